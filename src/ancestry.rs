@@ -0,0 +1,285 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+use crate::commit::Commit;
+use crate::commit_graph::CommitGraph;
+use crate::hash::ObjectId;
+use crate::odb::ObjectDatabase;
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+// NOTE: generation of a commit = 1 + the biggest generation of its parents (a root is 1)
+// so an ancestor always has a smaller generation than its descendants, a walk looking
+// for X can stop at the commits under the generation of X
+// it comes from the commit-graph, a commit that is not in there is INFINITY like git,
+// nothing is pruned for it (a commit in the graph never has a parent outside the graph)
+pub(crate) const GENERATION_INFINITY: u64 = u64::MAX;
+
+// NOTE: questions about the history, every commit is parsed only once
+// (only the tree, the parents, the committer time and the generation are kept)
+// commits in the commit-graph are not parsed at all, everything comes from the graph
+// merge_bases(a, b)           the best common ancestors, more than one for criss-cross merges
+// merge_bases_many(a, [b..])  same but with a merge of all the b as the other side
+// octopus([a, b, c..])        the common ancestors of all of them
+// is_ancestor(a, b)           can a be reached from b
+// fork_point(a, [r..])        where a forked from a ref, r are the values the ref had
+pub(crate) struct Ancestry<'a> {
+    db: &'a dyn ObjectDatabase,
+    graph: Option<CommitGraph>,
+    commits: HashMap<ObjectId, Node>,
+}
+
+struct Node {
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    time: i64,
+    generation: u64,
+}
+
+impl<'a> Ancestry<'a> {
+    pub(crate) fn new(db: &'a dyn ObjectDatabase) -> anyhow::Result<Self> {
+        Ok(Self {
+            db,
+            graph: CommitGraph::current(db.format())?,
+            commits: HashMap::new(),
+        })
+    }
+
+    pub(crate) fn graph(&self) -> Option<&CommitGraph> {
+        self.graph.as_ref()
+    }
+
+    pub(crate) fn tree(&mut self, commit: ObjectId) -> anyhow::Result<ObjectId> {
+        Ok(self.load(commit)?.tree)
+    }
+
+    pub(crate) fn parents(&mut self, commit: ObjectId) -> anyhow::Result<Vec<ObjectId>> {
+        Ok(self.load(commit)?.parents.clone())
+    }
+
+    pub(crate) fn time(&mut self, commit: ObjectId) -> anyhow::Result<i64> {
+        Ok(self.load(commit)?.time)
+    }
+
+    pub(crate) fn generation(&mut self, commit: ObjectId) -> anyhow::Result<u64> {
+        Ok(self.load(commit)?.generation)
+    }
+
+    fn load(&mut self, commit: ObjectId) -> anyhow::Result<&Node> {
+        if !self.commits.contains_key(&commit) {
+            let in_graph = match &self.graph {
+                Some(graph) => graph.commit(commit)?,
+                None => None,
+            };
+            let node = match in_graph {
+                Some(found) => Node {
+                    tree: found.tree,
+                    parents: found.parents,
+                    time: found.time,
+                    generation: found.generation,
+                },
+                None => {
+                    let parsed = Commit::read(self.db, &commit.to_string())?;
+                    Node {
+                        tree: parsed.tree,
+                        parents: parsed.parents,
+                        time: parsed.committer.time,
+                        generation: GENERATION_INFINITY,
+                    }
+                }
+            };
+            self.commits.insert(commit, node);
+        }
+        Ok(&self.commits[&commit])
+    }
+
+    pub(crate) fn merge_bases(
+        &mut self,
+        one: ObjectId,
+        two: ObjectId,
+    ) -> anyhow::Result<Vec<ObjectId>> {
+        self.merge_bases_many(one, &[two])
+    }
+
+    // NOTE: same walk as git (paint_down_to_common), the highest generation first then
+    // the newest, a commit reached from both sides is a candidate, and what is under it
+    // is stale, the candidates that are ancestors of another candidate are dropped at the end
+    pub(crate) fn merge_bases_many(
+        &mut self,
+        one: ObjectId,
+        twos: &[ObjectId],
+    ) -> anyhow::Result<Vec<ObjectId>> {
+        if twos.contains(&one) {
+            return Ok(vec![one]);
+        }
+        let candidates = self.paint_down(one, twos)?;
+        self.remove_redundant(candidates)
+    }
+
+    // the merge bases of the first two, then the merge bases of those with the third...
+    // the ones under another one are dropped at the end
+    pub(crate) fn octopus(&mut self, commits: &[ObjectId]) -> anyhow::Result<Vec<ObjectId>> {
+        let Some((&first, rest)) = commits.split_first() else {
+            return Ok(Vec::new());
+        };
+        let mut result = vec![first];
+        for &next in rest {
+            let mut bases = Vec::new();
+            for &commit in &result {
+                for base in self.merge_bases(next, commit)? {
+                    if !bases.contains(&base) {
+                        bases.push(base);
+                    }
+                }
+            }
+            result = bases;
+        }
+        self.remove_redundant(result)
+    }
+
+    // NOTE: a plain walk down from the descendant, what is under the generation of the
+    // ancestor can not lead to it
+    pub(crate) fn is_ancestor(
+        &mut self,
+        ancestor: ObjectId,
+        descendant: ObjectId,
+    ) -> anyhow::Result<bool> {
+        let min_generation = self.generation(ancestor)?;
+        let mut seen = HashSet::new();
+        let mut stack = vec![descendant];
+        while let Some(commit) = stack.pop() {
+            if commit == ancestor {
+                return Ok(true);
+            }
+            if !seen.insert(commit) || self.generation(commit)? < min_generation {
+                continue;
+            }
+            stack.extend(self.parents(commit)?);
+        }
+        Ok(false)
+    }
+
+    // NOTE: the merge base of the commit with every value the ref had (its reflog)
+    // it's only a fork point when there is a single one and the ref was pointing to it,
+    // so a rebased upstream still gives the commit we started from
+    pub(crate) fn fork_point(
+        &mut self,
+        commit: ObjectId,
+        history: &[ObjectId],
+    ) -> anyhow::Result<Option<ObjectId>> {
+        if history.is_empty() {
+            return Ok(None);
+        }
+        match self.merge_bases_many(commit, history)?.as_slice() {
+            [base] if history.contains(base) => Ok(Some(*base)),
+            _ => Ok(None),
+        }
+    }
+
+    fn paint_down(&mut self, one: ObjectId, twos: &[ObjectId]) -> anyhow::Result<Vec<ObjectId>> {
+        let mut flags: HashMap<ObjectId, u8> = HashMap::new();
+        let mut queue = Queue::default();
+        flags.insert(one, PARENT1);
+        queue.push(self.load(one)?, one);
+        for &two in twos {
+            *flags.entry(two).or_default() |= PARENT2;
+            queue.push(self.load(two)?, two);
+        }
+        let mut result = Vec::new();
+        while queue
+            .heap
+            .iter()
+            .any(|(_, _, _, commit)| flags[commit] & STALE == 0)
+        {
+            let Some(commit) = queue.pop() else {
+                break;
+            };
+            let mut paint = flags[&commit] & (PARENT1 | PARENT2 | STALE);
+            if paint == PARENT1 | PARENT2 {
+                if flags[&commit] & RESULT == 0 {
+                    *flags.get_mut(&commit).unwrap() |= RESULT;
+                    result.push(commit);
+                }
+                paint |= STALE;
+            }
+            for parent in self.parents(commit)? {
+                let parent_flags = flags.entry(parent).or_default();
+                if *parent_flags & paint == paint {
+                    continue;
+                }
+                *parent_flags |= paint;
+                queue.push(self.load(parent)?, parent);
+            }
+        }
+        // the newest first, a commit seen twice in the queue was only added once
+        result.sort_by_key(|commit| Reverse(self.commits[commit].time));
+        Ok(result)
+    }
+
+    // a candidate reachable from another candidate is not a best common ancestor
+    fn remove_redundant(&mut self, candidates: Vec<ObjectId>) -> anyhow::Result<Vec<ObjectId>> {
+        if candidates.len() < 2 {
+            return Ok(candidates);
+        }
+        let mut min_generation = GENERATION_INFINITY;
+        for &candidate in &candidates {
+            min_generation = min_generation.min(self.generation(candidate)?);
+        }
+        let mut redundant = vec![false; candidates.len()];
+        for (i, &candidate) in candidates.iter().enumerate() {
+            if redundant[i] {
+                continue;
+            }
+            let reached = self.walk_down(candidate, min_generation)?;
+            for (j, other) in candidates.iter().enumerate() {
+                redundant[j] |= j != i && reached.contains(other);
+            }
+        }
+        Ok(candidates
+            .into_iter()
+            .zip(redundant)
+            .filter(|(_, redundant)| !redundant)
+            .map(|(commit, _)| commit)
+            .collect())
+    }
+
+    // every commit under start, no need to go under the smallest generation we look for
+    fn walk_down(
+        &mut self,
+        start: ObjectId,
+        min_generation: u64,
+    ) -> anyhow::Result<HashSet<ObjectId>> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![start];
+        while let Some(commit) = stack.pop() {
+            if self.generation(commit)? < min_generation || !seen.insert(commit) {
+                continue;
+            }
+            stack.extend(self.parents(commit)?);
+        }
+        Ok(seen)
+    }
+}
+
+// NOTE: the highest generation first, then the newest, then the first pushed
+// (same order as git, the merge bases come out in the same order when commits have same time)
+#[derive(Default)]
+struct Queue {
+    heap: BinaryHeap<(u64, i64, Reverse<usize>, ObjectId)>,
+    pushed: usize,
+}
+
+impl Queue {
+    fn push(&mut self, node: &Node, commit: ObjectId) {
+        self.heap
+            .push((node.generation, node.time, Reverse(self.pushed), commit));
+        self.pushed += 1;
+    }
+
+    fn pop(&mut self) -> Option<ObjectId> {
+        self.heap.pop().map(|(_, _, _, commit)| commit)
+    }
+}
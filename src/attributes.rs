@@ -0,0 +1,118 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use globset::GlobBuilder;
+
+// NOTE: .gitattributes give attributes to paths, one pattern per line
+// *.txt   text eol=lf        -> text is Set, eol is Value("lf")
+// *.png   -text              -> text is Unset
+// *.bin   binary             -> macro for "-diff -merge -text"
+// vendor/* !text             -> text go back to unspecified (removed from the map)
+// pattern without '/' match the file name in any dir below the .gitattributes,
+// with a '/' it match the path relative to the dir of the .gitattributes
+// files read in this order, later match win:
+//   <worktree>/.gitattributes, then the .gitattributes of every dir down to the file,
+//   then .git/info/attributes
+// TODO: no [attr] macro definitions and no core.attributesFile
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum State {
+    Set,
+    Unset,
+    Value(String),
+}
+
+pub(crate) type Attributes = BTreeMap<String, State>;
+
+// NOTE: path is relative to the worktree root with '/' separators ("src/main.rs")
+pub(crate) fn lookup(git_dir: &Path, path: &str) -> anyhow::Result<Attributes> {
+    let worktree = git_dir.parent().unwrap_or(Path::new(""));
+    let mut files = vec![(String::new(), worktree.join(".gitattributes"))];
+    if let Some((dirs, _)) = path.rsplit_once('/') {
+        let mut dir = String::new();
+        for component in dirs.split('/') {
+            if !dir.is_empty() {
+                dir.push('/');
+            }
+            dir.push_str(component);
+            files.push((dir.clone(), worktree.join(&dir).join(".gitattributes")));
+        }
+    }
+    files.push((String::new(), git_dir.join("info/attributes")));
+
+    let mut attributes = Attributes::new();
+    for (base, file) in files {
+        let content = match fs::read_to_string(&file) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", file.display())),
+        };
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("[attr]") {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let Some(pattern) = words.next() else {
+                continue;
+            };
+            if pattern.starts_with('!') {
+                eprintln!("Negative patterns are ignored in git attributes");
+                continue;
+            }
+            if !matches(&base, pattern, path) {
+                continue;
+            }
+            for word in words {
+                apply(&mut attributes, word);
+            }
+        }
+    }
+    Ok(attributes)
+}
+
+fn apply(attributes: &mut Attributes, word: &str) {
+    if word == "binary" {
+        attributes.insert("binary".to_string(), State::Set);
+        for name in ["diff", "merge", "text"] {
+            attributes.insert(name.to_string(), State::Unset);
+        }
+    } else if let Some(name) = word.strip_prefix('-') {
+        attributes.insert(name.to_string(), State::Unset);
+    } else if let Some(name) = word.strip_prefix('!') {
+        attributes.remove(name);
+    } else if let Some((name, value)) = word.split_once('=') {
+        attributes.insert(name.to_string(), State::Value(value.to_string()));
+    } else {
+        attributes.insert(word.to_string(), State::Set);
+    }
+}
+
+fn matches(base: &str, pattern: &str, path: &str) -> bool {
+    let relative = if base.is_empty() {
+        path
+    } else {
+        match path
+            .strip_prefix(base)
+            .and_then(|rest| rest.strip_prefix('/'))
+        {
+            Some(relative) => relative,
+            None => return false,
+        }
+    };
+    // "dir/" only match directories, attributes are only looked up for files
+    if pattern.ends_with('/') {
+        return false;
+    }
+    let (pattern, text) = if pattern.contains('/') {
+        (pattern.trim_start_matches('/'), relative)
+    } else {
+        let name = relative.rsplit('/').next().unwrap_or(relative);
+        (pattern, name)
+    };
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .is_ok_and(|glob| glob.compile_matcher().is_match(text))
+}
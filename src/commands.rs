@@ -1,8 +1,29 @@
 pub(crate) mod cat_file;
+pub(crate) mod cherry_pick;
+pub(crate) mod clone;
+pub(crate) mod commit;
+pub(crate) mod commit_graph;
 pub(crate) mod commit_tree;
+pub(crate) mod count_objects;
+pub(crate) mod fetch;
+pub(crate) mod fsck;
+pub(crate) mod gc;
 pub(crate) mod hash_object;
-pub(crate) mod ls_tree;
-pub(crate) mod write_tree;
-pub(crate) mod commit;
+pub(crate) mod init;
+pub(crate) mod log;
 pub(crate) mod ls_file;
+pub(crate) mod ls_tree;
+pub(crate) mod merge;
+pub(crate) mod merge_base;
+pub(crate) mod mktree;
+pub(crate) mod mv;
+pub(crate) mod prune;
+pub(crate) mod push;
+pub(crate) mod rebase;
+pub(crate) mod reset;
+pub(crate) mod restore;
+pub(crate) mod revert;
+pub(crate) mod rm;
+pub(crate) mod stash;
 pub(crate) mod update_index;
+pub(crate) mod write_tree;
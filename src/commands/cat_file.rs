@@ -1,15 +1,37 @@
 use anyhow::Context;
 
-use crate::objects::{self, Kind};
+use crate::objects::Kind;
+use crate::odb::{self, ObjectDatabase};
 
 // NOTE: it's use to read the blob object file "cat-file -p hash" it will uncompress the file
 // content
 // run test
 // /path/to/your_program.sh cat-file -p 3b18e512dba79e4c8300dd08aeb37f8e728b8dad
 // hello world
-pub(crate) fn invoke(pretty_print: bool, object_hash: String) -> anyhow::Result<()> {
-    anyhow::ensure!(pretty_print, "Please provide the flag 'p'");
-    let mut object = objects::Object::read(&object_hash)?;
+// -t print only the kind, -s print only the size (only the header is read for both)
+// short hash also work: cat-file -p 3b18e5
+pub(crate) fn invoke(
+    pretty_print: bool,
+    show_type: bool,
+    show_size: bool,
+    object_hash: String,
+) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        pretty_print || show_type || show_size,
+        "Please provide the flag 'p'"
+    );
+    let db = odb::open()?;
+    let object_hash = odb::resolve_prefix(&db, &object_hash)?;
+    if show_type || show_size {
+        let (kind, size) = db.read_header(&object_hash)?;
+        if show_type {
+            println!("{kind}");
+        } else {
+            println!("{size}");
+        }
+        return Ok(());
+    }
+    let mut object = db.read(&object_hash)?;
     match object.kind {
         Kind::Blob => {
             let stdout = std::io::stdout();
@@ -0,0 +1,33 @@
+use crate::sequencer::{self, Action};
+
+// NOTE: apply the changes of existing commits on top of HEAD, a new commit for each one
+// (same author and message), see crate::sequencer
+// cargo run -- cherry-pick feature~2 main..feature
+// A..B            the commits of B that are not in A, the oldest first
+// -n              only the index and the files, no commit (MERGE_MSG has the message)
+// -x              add "(cherry picked from commit <hash>)" to the message
+// -m N            pick a merge, the change is the one from its parent N
+// -e              edit the message before the commit
+// --continue      commit the resolved conflict and go on with the next commits
+// --skip          forget the commit that stopped and go on
+// --abort         back to where it started
+pub(crate) fn invoke(
+    options: sequencer::Options,
+    sequence: Option<Sequence>,
+    commits: &[String],
+) -> anyhow::Result<()> {
+    match sequence {
+        Some(Sequence::Continue) => sequencer::resume(),
+        Some(Sequence::Skip) => sequencer::skip(Action::Pick),
+        Some(Sequence::Abort) => sequencer::abort(),
+        None => sequencer::start(Action::Pick, options, commits),
+    }
+}
+
+// --continue / --skip / --abort, shared with revert
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Sequence {
+    Continue,
+    Skip,
+    Abort,
+}
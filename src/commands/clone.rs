@@ -0,0 +1,281 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::commands::init::{format_config, init_repo};
+use crate::commit::Commit;
+use crate::config::{self, Config};
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::hooks::Hooks;
+use crate::index::Index;
+use crate::odb::{self, ObjectDatabase};
+use crate::refs::{self, Head};
+use crate::remote;
+use crate::worktree;
+
+// NOTE: clone the repository that is on the same disk
+// cargo run -- clone ../other-repo my-copy
+// --shared           -> don't copy any object, .git/objects/info/alternates point to the source
+// --reference <repo> -> borrow objects from <repo> (alternates), copy only what <repo> doesn't have
+// without them every object of the source get copied (packs as they are, loose one by one)
+// branches of the source become refs/remotes/origin/*, HEAD branch get checked out
+// file:///some/where is the same as the path
+pub(crate) fn invoke(
+    shared: bool,
+    reference: Option<PathBuf>,
+    repository: &Path,
+    directory: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    let url = repository;
+    let repository = match url.to_str().and_then(|url| url.strip_prefix("file://")) {
+        Some(path) => Path::new(path),
+        None => url,
+    };
+    let src_git_dir = find_git_dir(repository)?;
+    check_ref_names(&src_git_dir)?;
+    let src_objects = fs::canonicalize(src_git_dir.join("objects"))
+        .context("Failed to find the objects dir of the source repository")?;
+    let directory = match directory {
+        Some(directory) => directory,
+        None => default_directory(repository)?,
+    };
+    if directory.exists() {
+        anyhow::ensure!(
+            fs::read_dir(&directory)?.next().is_none(),
+            "destination path '{}' already exists and is not an empty directory.",
+            directory.display()
+        );
+    }
+    eprintln!("Cloning into '{}'...", directory.display());
+    // NOTE: a clone that fails halfway leaves nothing behind, the directory goes away when
+    // we made it, when it was already there (empty) only what we put in it
+    let created = !directory.exists();
+    let result = clone_into(
+        shared,
+        reference,
+        url,
+        &src_git_dir,
+        src_objects,
+        &directory,
+    );
+    if result.is_err() {
+        let _ = if created {
+            fs::remove_dir_all(&directory)
+        } else {
+            remove_contents(&directory)
+        };
+    }
+    result
+}
+
+fn clone_into(
+    shared: bool,
+    reference: Option<PathBuf>,
+    url: &Path,
+    src_git_dir: &Path,
+    src_objects: PathBuf,
+    directory: &Path,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(directory)
+        .with_context(|| format!("Failed to create {}", directory.display()))?;
+    let git_dir = directory.join(".git");
+    let object_format =
+        ObjectFormat::from_config(&Config::load_from(&src_git_dir.join("config"))?)?;
+    init_repo(&git_dir, object_format)?;
+    let objects_dir = git_dir.join("objects");
+
+    let mut alternates = Vec::new();
+    if shared {
+        alternates.push(src_objects.clone());
+    }
+    if let Some(reference) = &reference {
+        let reference_objects = fs::canonicalize(find_git_dir(reference)?.join("objects"))
+            .context("Failed to find the objects dir of the reference repository")?;
+        alternates.push(reference_objects);
+    }
+    if !alternates.is_empty() {
+        fs::create_dir_all(objects_dir.join("info"))?;
+        let mut content = String::new();
+        for alternate in &alternates {
+            content.push_str(&format!("{}\n", alternate.display()));
+        }
+        fs::write(objects_dir.join("info/alternates"), content)
+            .context("Failed to write objects/info/alternates")?;
+    }
+    if !shared {
+        copy_objects(src_git_dir, &objects_dir, reference.is_none())?;
+    }
+
+    // NOTE: a file:// url is kept as it is, a path becomes absolute (remote.origin.url and
+    // "clone: from <url>" in the reflogs)
+    let url = match url.to_str().filter(|url| url.starts_with("file://")) {
+        Some(url) => url.to_string(),
+        None => fs::canonicalize(url)?.display().to_string(),
+    };
+    let message = format!("clone: from {url}");
+    let src_head = refs::read_head(src_git_dir)?;
+    let mut branch = None;
+    for (name, hash) in refs::list(src_git_dir, "refs/")? {
+        if let Some(name) = name.strip_prefix("refs/heads/") {
+            let tracking = format!("refs/remotes/origin/{name}");
+            refs::update(&git_dir, &tracking, &hash, &message)?;
+        } else if name.starts_with("refs/tags/") {
+            refs::update(&git_dir, &name, &hash, &message)?;
+        }
+    }
+    let head_commit = match &src_head {
+        Head::Symbolic(name) => {
+            let commit = refs::resolve(src_git_dir, name)?;
+            if let Some(name) = name.strip_prefix("refs/heads/") {
+                let full = format!("refs/heads/{name}");
+                refs::write_symbolic(&git_dir, "HEAD", &full, &message)?;
+                if let Some(commit) = &commit {
+                    refs::update(&git_dir, &full, commit, &message)?;
+                    refs::write_symbolic(
+                        &git_dir,
+                        "refs/remotes/origin/HEAD",
+                        &format!("refs/remotes/origin/{name}"),
+                        &message,
+                    )?;
+                }
+                branch = Some(name.to_string());
+            }
+            commit
+        }
+        Head::Detached(commit) => {
+            refs::update(&git_dir, "HEAD", commit, &message)?;
+            Some(commit.clone())
+        }
+    };
+    write_config(&git_dir, object_format, &url, branch.as_deref())?;
+
+    let Some(head_commit) = head_commit else {
+        eprintln!("warning: You appear to have cloned an empty repository.");
+        return Ok(());
+    };
+    let db = odb::open_at(&objects_dir)?;
+    let tree = Commit::read(&db, &head_commit)?.tree.to_string();
+    let mut index = Index::new(db.format());
+    worktree::checkout_tree(&db, &tree, directory, &mut index)?;
+    index.write(&git_dir.join("index"))?;
+    // NOTE: nothing was checked out before, so the old HEAD is the all zero hash
+    // the clone is done by now, a hook that can't run is only a warning
+    let zero = "0".repeat(head_commit.len());
+    if let Err(e) = Hooks::open(&git_dir)?.run("post-checkout", &[&zero, &head_commit, "1"], &[]) {
+        eprintln!("warning: post-checkout hook failed: {e:#}");
+    }
+    Ok(())
+}
+
+fn remove_contents(directory: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() && !path.is_symlink() {
+            fs::remove_dir_all(&path)?;
+        } else {
+            fs::remove_file(&path)?;
+        }
+    }
+    Ok(())
+}
+
+// NOTE: every ref of the source becomes a file here and HEAD's branch goes in .git/config,
+// a name that is not a valid ref name (../../x, a "]" and a newline) stops the clone
+// before anything is created
+fn check_ref_names(src_git_dir: &Path) -> anyhow::Result<()> {
+    let mut names: Vec<String> = refs::list(src_git_dir, "refs/")?
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    if let Head::Symbolic(name) = refs::read_head(src_git_dir)? {
+        names.push(name);
+    }
+    for name in &names {
+        refs::check_refname_format(name).context("refusing to clone")?;
+    }
+    Ok(())
+}
+
+// NOTE: <repo>/.git for the normal repo, <repo> itself when it's a bare repo
+pub(crate) fn find_git_dir(repository: &Path) -> anyhow::Result<PathBuf> {
+    let dot_git = repository.join(".git");
+    if dot_git.join("objects").is_dir() {
+        return Ok(dot_git);
+    }
+    anyhow::ensure!(
+        repository.join("objects").is_dir() && repository.join("HEAD").is_file(),
+        "repository '{}' does not exist",
+        repository.display()
+    );
+    Ok(repository.to_path_buf())
+}
+
+// ../some/project.git -> project
+fn default_directory(repository: &Path) -> anyhow::Result<PathBuf> {
+    let canonical = fs::canonicalize(repository)
+        .with_context(|| format!("repository '{}' does not exist", repository.display()))?;
+    let name = canonical
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("can not guess the directory name, please give one")?;
+    Ok(PathBuf::from(name.strip_suffix(".git").unwrap_or(name)))
+}
+
+// NOTE: packs are copied as they are (only when we copy everything),
+// then every object of the refs the destination still can't see get copied as loose object.
+// with --reference the destination already see the reference objects through alternates
+fn copy_objects(src_git_dir: &Path, objects_dir: &Path, copy_packs: bool) -> anyhow::Result<()> {
+    let src_objects = src_git_dir.join("objects");
+    let src_pack_dir = src_objects.join("pack");
+    if copy_packs && src_pack_dir.is_dir() {
+        let pack_dir = objects_dir.join("pack");
+        fs::create_dir_all(&pack_dir)?;
+        for entry in fs::read_dir(&src_pack_dir)? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|ext| ext == "pack" || ext == "idx")
+            {
+                fs::copy(&path, pack_dir.join(path.file_name().unwrap()))
+                    .with_context(|| format!("Failed to copy {}", path.display()))?;
+            }
+        }
+    }
+    let src_db = odb::open_at(&src_objects)?;
+    let db = odb::open_at(objects_dir)?;
+    let mut wants: Vec<String> = refs::list(src_git_dir, "refs/")?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect();
+    if let Head::Detached(hash) = refs::read_head(src_git_dir)? {
+        wants.push(hash);
+    }
+    let wants = wants
+        .iter()
+        .map(|hash| ObjectId::from_hex(hash))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let missing = remote::missing_objects(&src_db, &db, &wants)?;
+    remote::copy_objects(&src_db, &db, &missing)
+}
+
+fn write_config(
+    git_dir: &Path,
+    object_format: ObjectFormat,
+    url: &str,
+    branch: Option<&str>,
+) -> anyhow::Result<()> {
+    let mut config = format_config(object_format);
+    config.push_str(&format!(
+        "[remote \"origin\"]\n\turl = {}\n\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+        config::escape_value(url)
+    ));
+    if let Some(branch) = branch {
+        config.push_str(&format!(
+            "[branch {}]\n\tremote = origin\n\tmerge = {}\n",
+            config::escape_subsection(branch)?,
+            config::escape_value(&format!("refs/heads/{branch}"))
+        ));
+    }
+    fs::write(git_dir.join("config"), config).context("Failed to write .git/config")
+}
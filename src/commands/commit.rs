@@ -1,6 +1,20 @@
-use crate::commands::{commit_tree::write_commit, write_tree::write_tree_for};
+use std::fs;
+use std::path::Path;
+
 use anyhow::Context;
-use std::path::PathBuf;
+
+use crate::commands::commit_tree::read_message_file;
+use crate::commands::write_tree::write_tree_for;
+use crate::commit::{self, Cleanup, Commit, Signature};
+use crate::config::Config;
+use crate::editor;
+use crate::hash::ObjectId;
+use crate::hooks::Hooks;
+use crate::index::{Index, IndexEntry};
+use crate::objects::Object;
+use crate::odb::{self, Abbrev, CompositeDb, ObjectDatabase};
+use crate::refs::{self, Head};
+use crate::tree::Tree;
 
 // NOTE: it will add your latest commit to the list
 // it will create the new commit object
@@ -8,31 +22,374 @@ use std::path::PathBuf;
 // so when you run git log you will see the new commit
 // and show child based on the parent commit
 // cargo run -- commit -m "commit message"
-pub fn invoke(message: &str) -> anyhow::Result<()> {
-    // to commit we need value same as the commit-tree
-    //value of head=ref: refs/heads/master
-    let head_ref = std::fs::read_to_string(".git/HEAD")
-        .with_context(|| format!("Failed to read the head "))?;
-    let Some(head_ref) = head_ref.strip_prefix("ref: ") else {
-        anyhow::bail!("Refusing to commit onto detached HEAD")
-    };
-    let head_ref = head_ref.trim();
-    let parent_sha = std::fs::read_to_string(format!("./.git/{head_ref}"))
-        .with_context(|| format!("Failed to read the parent commit hash:{}", head_ref))?;
-    let parent_sha = parent_sha.trim();
-
-    let Some(tree_hash) = write_tree_for(&PathBuf::from("."))? else {
-        eprintln!("Not commiting the empty tree");
-        return Ok(());
-    };
-    let tree_hash = hex::encode(tree_hash);
-
-    let commit_hash = write_commit(&tree_hash, Some(parent_sha), &message)
-        .with_context(|| format!("Failed to generate commit hash"))?;
-    let commit_hash = hex::encode(commit_hash);
-
-    std::fs::write(format!(".git/{head_ref}"), &commit_hash)
-        .with_context(|| format!("Failed to update the HEAD ref at :{}", head_ref))?;
-    eprintln!("HEAD is now at {}", commit_hash);
+// the tree is the .git/index (what update-index staged), a repository that never had an index
+// commit the working tree as it is
+// -a              stage the changes of the tracked files first (deleted ones are removed)
+// --amend         replace HEAD: same parents, same author, HEAD message in the editor
+// -m msg / -F f   the message (every -m is a paragraph, -F - is stdin), no editor
+// -t file         start the editor with this template (commit.template), unchanged = abort
+// -e / --no-edit  force / skip the editor
+// --cleanup mode  see commit::Cleanup (commit.cleanup)
+// --author "Name <email>", --date <date>  override the author
+// --signoff       add the Signed-off-by trailer of the committer
+// -n / --no-verify  skip the pre-commit and commit-msg hooks
+// -q              no "[main 1a2b3c4] subject" line
+// during a merge (.git/MERGE_HEAD) the commit gets the other commit as the second parent
+// and .git/MERGE_MSG as the message, the conflicts have to be resolved first
+// after a cherry-pick conflict (.git/CHERRY_PICK_HEAD) the author is the one of the picked commit
+// without --allow-empty, a commit with the same tree as its parent is refused
+pub(crate) fn invoke(options: Options) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let config = Config::load()?;
+    let db = odb::open()?;
+    let hooks = Hooks::open(git_dir)?;
+
+    let head = refs::read_head(git_dir)?;
+    let head_commit = match &head {
+        Head::Symbolic(name) => refs::resolve(git_dir, name)?,
+        Head::Detached(hash) => Some(hash.clone()),
+    };
+    let head_commit = head_commit
+        .map(|hash| ObjectId::from_hex(&hash))
+        .transpose()?;
+    let old = head_commit
+        .map(|hash| Commit::read(&db, &hash.to_string()))
+        .transpose()?;
+    anyhow::ensure!(
+        !options.amend || old.is_some(),
+        "You have nothing to amend."
+    );
+    let merge_heads = match fs::read_to_string(git_dir.join("MERGE_HEAD")) {
+        Ok(content) => content
+            .lines()
+            .map(ObjectId::from_hex)
+            .collect::<anyhow::Result<Vec<_>>>()?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e).context("Failed to read MERGE_HEAD"),
+    };
+    anyhow::ensure!(
+        !options.amend || merge_heads.is_empty(),
+        "You are in the middle of a merge -- cannot amend."
+    );
+
+    let index_path = git_dir.join("index");
+    if options.all && index_path.exists() {
+        let (mut index, lock) = Index::lock(&index_path)?;
+        stage_tracked(&db, &config, &mut index)?;
+        index.write_locked(lock)?;
+    }
+    // NOTE: the hook can still change the index, so the tree is written after it
+    if !options.no_verify {
+        anyhow::ensure!(
+            hooks.run("pre-commit", &[], &[])?,
+            "pre-commit hook refused the commit"
+        );
+    }
+    if index_path.exists() {
+        anyhow::ensure!(
+            Index::read(&index_path)?
+                .entries
+                .iter()
+                .all(|e| e.stage() == 0),
+            "Committing is not possible because you have unmerged files."
+        );
+    }
+    let tree = write_tree(&db, &index_path)?;
+    let mut parents = match (&old, head_commit) {
+        (Some(old), _) if options.amend => old.parents.clone(),
+        (_, Some(head_commit)) => vec![head_commit],
+        _ => Vec::new(),
+    };
+    parents.extend(merge_heads);
+    // NOTE: amend is compared with the parent of HEAD (the commit would become empty),
+    // amending a merge is always allowed
+    if !options.allow_empty && parents.len() <= 1 {
+        let parent_tree = match parents.first() {
+            Some(parent) => Commit::read(&db, &parent.to_string())?.tree,
+            None => Tree::default().object().hash(db.format())?,
+        };
+        if tree == parent_tree {
+            if options.amend {
+                anyhow::bail!(
+                    "You asked to amend the most recent commit, but doing so would make\n\
+                     it empty. You can repeat your command with --allow-empty, or you can\n\
+                     remove the commit entirely with \"git reset HEAD^\"."
+                );
+            }
+            if old.is_none() {
+                anyhow::bail!("nothing to commit (create/copy files and use \"git add\" to track)");
+            }
+            anyhow::bail!("nothing to commit, working tree clean");
+        }
+    }
+
+    // NOTE: a cherry-pick stopped on a conflict keeps the author of the picked commit
+    let picked = fs::read_to_string(git_dir.join("CHERRY_PICK_HEAD")).ok();
+    let mut author = match (&old, picked) {
+        (Some(old), _) if options.amend => old.author.clone(),
+        (_, Some(picked)) => Commit::read(&db, picked.trim())?.author,
+        _ => Signature::author(&config)?,
+    };
+    if let Some(ident) = &options.author {
+        author = author.with_ident(ident)?;
+    }
+    if let Some(date) = &options.date {
+        (author.time, author.timezone) = commit::parse_date(date)?;
+    }
+    let committer = Signature::committer(&config)?;
+    let message = message(&options, &config, &hooks, git_dir, old.as_ref(), &committer)?;
+
+    let commit = Commit {
+        tree,
+        parents,
+        author,
+        committer,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message: message.into_bytes(),
+    };
+    let commit_hash = commit
+        .object()
+        .write_to(&db)
+        .context("Failed to generate commit hash")?
+        .to_string();
+
+    // NOTE: "commit: <subject>" in the reflog, "commit (initial)", "commit (amend)" and
+    // "commit (merge)" for those, a cherry-pick or a rebase says it was them (reflog_action)
+    let action = match &options.reflog_action {
+        Some(action) => action.as_str(),
+        None if options.amend => "commit (amend)",
+        None if old.is_none() => "commit (initial)",
+        None if commit.parents.len() > 1 => "commit (merge)",
+        None => "commit",
+    };
+    let reflog = format!("{action}: {}", commit.subject());
+    let branch = match &head {
+        Head::Symbolic(name) => {
+            refs::update(git_dir, name, &commit_hash, &reflog)
+                .with_context(|| format!("Failed to update the HEAD ref at :{name}"))?;
+            name.strip_prefix("refs/heads/").unwrap_or(name).to_string()
+        }
+        Head::Detached(_) => {
+            refs::update(git_dir, "HEAD", &commit_hash, &reflog)?;
+            "detached HEAD".to_string()
+        }
+    };
+    for file in [
+        "MERGE_HEAD",
+        "MERGE_MSG",
+        "MERGE_MODE",
+        "CHERRY_PICK_HEAD",
+        "REVERT_HEAD",
+    ] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+    // NOTE: the commit is already made, a post-commit hook that can't run is only a warning
+    if let Err(e) = hooks.run("post-commit", &[], &[]) {
+        eprintln!("warning: post-commit hook failed: {e:#}");
+    }
+    if !options.quiet {
+        let root = if commit.parents.is_empty() {
+            " (root-commit)"
+        } else {
+            ""
+        };
+        println!(
+            "[{branch}{root} {}] {}",
+            Abbrev::new(&db, 7)?.abbrev(&commit_hash),
+            commit.subject()
+        );
+    }
+    // NOTE: the commit is already made, a gc --auto that fails is only a warning
+    if let Err(e) = crate::commands::gc::invoke(true, None) {
+        eprintln!("warning: gc --auto failed: {e:#}");
+    }
     Ok(())
 }
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) messages: Vec<String>,
+    pub(crate) file: Option<String>,
+    pub(crate) template: Option<String>,
+    pub(crate) edit: bool,
+    pub(crate) no_edit: bool,
+    pub(crate) amend: bool,
+    pub(crate) all: bool,
+    pub(crate) cleanup: Option<Cleanup>,
+    pub(crate) allow_empty: bool,
+    pub(crate) author: Option<String>,
+    pub(crate) date: Option<String>,
+    pub(crate) signoff: bool,
+    pub(crate) no_verify: bool,
+    pub(crate) quiet: bool,
+    // what the reflog says made the commit ("cherry-pick", "rebase (pick)"), "commit" when None
+    pub(crate) reflog_action: Option<String>,
+}
+
+fn write_tree(db: &CompositeDb, index_path: &Path) -> anyhow::Result<ObjectId> {
+    if !index_path.exists() {
+        return match write_tree_for(Path::new("."))? {
+            Some(tree) => Ok(tree),
+            None => Tree::default().object().write_to(db),
+        };
+    }
+    Index::read(index_path)?.write_tree(db)
+}
+
+// NOTE: -a, only the files already in the index, new files are left alone
+// the stat in the index tell if the file changed, only those are hashed again
+// a conflicted path (stages 1-3) is taken from the worktree as it is, the conflict is resolved
+fn stage_tracked(db: &CompositeDb, config: &Config, index: &mut Index) -> anyhow::Result<()> {
+    let symlinks = config.get_bool("core.symlinks")?.unwrap_or(true);
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    let mut previous: Option<&str> = None;
+    for entry in &index.entries {
+        // one time per path, the stages of a conflict come one after the other
+        if previous.replace(&entry.path) == Some(entry.path.as_str()) {
+            continue;
+        }
+        // submodule is committed by its own repository
+        if entry.mode == 0o160000 {
+            continue;
+        }
+        let metadata = match fs::symlink_metadata(&entry.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                removed.push(entry.path.clone());
+                continue;
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("Failed to read stat for :{}", entry.path));
+            }
+        };
+        let fresh = IndexEntry::from_metadata(&entry.path, &metadata, entry.hash, 0);
+        let same_stat = (fresh.mtime, fresh.ctime, fresh.size, fresh.ino, fresh.mode)
+            == (entry.mtime, entry.ctime, entry.size, entry.ino, entry.mode);
+        if same_stat && entry.stage() == 0 {
+            continue;
+        }
+        let hash = Object::blob_from_file(&entry.path)?
+            .write_to(db)
+            .context("Create the hash of the blob")?;
+        let mut fresh = IndexEntry::from_metadata(&entry.path, &metadata, hash, 0);
+        if entry.mode == 0o120000 && !symlinks && metadata.is_file() {
+            fresh.mode = 0o120000;
+        }
+        changed.push(fresh);
+    }
+    for path in removed {
+        index.remove(&path);
+    }
+    for entry in changed {
+        index.remove(&entry.path);
+        index.add(entry);
+    }
+    Ok(())
+}
+
+fn message(
+    options: &Options,
+    config: &Config,
+    hooks: &Hooks,
+    git_dir: &Path,
+    old: Option<&Commit>,
+    committer: &Signature,
+) -> anyhow::Result<String> {
+    let template = options
+        .template
+        .as_deref()
+        .or(config.get("commit.template"));
+    // the source tells prepare-commit-msg where the message comes from
+    let (mut message, source, template) = if !options.messages.is_empty() {
+        let paragraphs: Vec<String> = options
+            .messages
+            .iter()
+            .map(|paragraph| format!("{}\n", paragraph.trim_end_matches('\n')))
+            .collect();
+        (paragraphs.join("\n"), Some("message"), None)
+    } else if let Some(file) = &options.file {
+        (read_message_file(file)?, Some("message"), None)
+    } else if let Ok(merge_message) = fs::read_to_string(git_dir.join("MERGE_MSG")) {
+        (merge_message, Some("merge"), None)
+    } else if let (Some(old), true) = (old, options.amend) {
+        (old.message_text(), Some("commit"), None)
+    } else if let Some(template) = template {
+        let template =
+            fs::read_to_string(template).with_context(|| format!("could not read '{template}'"))?;
+        (template.clone(), Some("template"), Some(template))
+    } else {
+        (String::new(), None, None)
+    };
+    let given = source == Some("message");
+    if options.signoff {
+        let signoff = format!(
+            "Signed-off-by: {} <{}>",
+            String::from_utf8_lossy(&committer.name),
+            String::from_utf8_lossy(&committer.email)
+        );
+        message = commit::add_signoff(&message, &signoff);
+    }
+
+    let comment = config
+        .get("core.commentChar")
+        .and_then(|comment| comment.chars().next())
+        .unwrap_or('#');
+    let cleanup = match (options.cleanup, config.get("commit.cleanup")) {
+        (Some(cleanup), _) => cleanup,
+        (None, Some(cleanup)) => cleanup.parse()?,
+        (None, None) => Cleanup::Default,
+    };
+    let edit = options.edit || (!given && !options.no_edit);
+    let cleanup = cleanup.resolve(edit);
+
+    // NOTE: the message is always written to .git/COMMIT_EDITMSG, even without the editor,
+    // the hooks edit it there and it is read back after them
+    let edit_file = git_dir.join("COMMIT_EDITMSG");
+    if edit {
+        if !message.is_empty() && !message.ends_with('\n') {
+            message.push('\n');
+        }
+        message = format!("{message}\n{}", cleanup.editor_help(comment));
+    }
+    fs::write(&edit_file, &message)
+        .with_context(|| format!("Failed to write {}", edit_file.display()))?;
+
+    // "<file> message", "<file> template" or "<file> commit HEAD" for --amend
+    let hook_file = edit_file.to_string_lossy();
+    let mut hook_args = vec![hook_file.as_ref()];
+    hook_args.extend(source);
+    if source == Some("commit") {
+        hook_args.push("HEAD");
+    }
+    anyhow::ensure!(
+        hooks.run("prepare-commit-msg", &hook_args, &[])?,
+        "prepare-commit-msg hook refused the commit"
+    );
+    if edit {
+        editor::launch(&editor::editor(config), &edit_file)?;
+    }
+    if !options.no_verify {
+        anyhow::ensure!(
+            hooks.run("commit-msg", &[&hook_file], &[])?,
+            "commit-msg hook refused the commit"
+        );
+    }
+    let message = fs::read_to_string(&edit_file)
+        .with_context(|| format!("Failed to read {}", edit_file.display()))?;
+
+    let message = cleanup.apply(&message, comment);
+    if let Some(template) = template {
+        anyhow::ensure!(
+            message != cleanup.apply(&template, comment),
+            "Aborting commit; you did not edit the message from the template."
+        );
+    }
+    anyhow::ensure!(
+        !message.trim().is_empty(),
+        "Aborting commit due to empty commit message."
+    );
+    Ok(message)
+}
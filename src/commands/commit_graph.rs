@@ -0,0 +1,86 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commit_graph::{self, CommitGraph};
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, CompositeDb, ObjectDatabase};
+use crate::refs::{self, Head};
+use crate::revision;
+
+// NOTE: write or check .git/objects/info/commit-graph (see crate::commit_graph)
+// cargo run -- commit-graph write --reachable --changed-paths
+// write                 every commit in the object database (loose and packed)
+// write --reachable     only the commits reachable from the refs and HEAD
+// write --changed-paths add the bloom filters used by log -- <path>, an existing graph
+//                       with filters keep them
+// verify                compare the graph with the commit objects, exit code 1 on a problem
+pub(crate) fn write(reachable: bool, changed_paths: bool) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let tips = if reachable {
+        reachable_tips(&db, git_dir)?
+    } else {
+        all_commits(&db)?
+    };
+    if tips.is_empty() {
+        return Ok(());
+    }
+    let objects_dir = odb::objects_dir();
+    let changed_paths = changed_paths
+        || CommitGraph::open(&commit_graph::path(&objects_dir), db.format())
+            .ok()
+            .flatten()
+            .is_some_and(|graph| graph.has_changed_paths());
+    commit_graph::write(&db, &objects_dir, tips, changed_paths)?;
+    Ok(())
+}
+
+pub(crate) fn verify() -> anyhow::Result<()> {
+    let db = odb::open()?;
+    let path = commit_graph::path(&odb::objects_dir());
+    let Some(graph) = CommitGraph::open(&path, db.format())? else {
+        return Ok(());
+    };
+    let errors = commit_graph::verify(&db, &graph)?;
+    for error in &errors {
+        eprintln!("error: {error}");
+    }
+    if !errors.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// NOTE: tips of the history, the tags are peeled, refs to a tree or a blob are skipped
+pub(crate) fn reachable_tips(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<Vec<ObjectId>> {
+    let mut hashes: Vec<String> = refs::list(git_dir, "refs/")?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect();
+    if let Head::Detached(hash) = refs::read_head(git_dir)? {
+        hashes.push(hash);
+    }
+    let mut tips = Vec::new();
+    for hash in hashes {
+        let id = ObjectId::from_hex(&hash)?;
+        if let Ok(commit) = revision::peel(db, id, Kind::Commit) {
+            tips.push(commit);
+        }
+    }
+    Ok(tips)
+}
+
+fn all_commits(db: &CompositeDb) -> anyhow::Result<Vec<ObjectId>> {
+    let mut commits = Vec::new();
+    for id in db.iter()? {
+        let (kind, _) = db
+            .read_header(&id.to_string())
+            .with_context(|| format!("Failed to read {id}"))?;
+        if kind == Kind::Commit {
+            commits.push(id);
+        }
+    }
+    Ok(commits)
+}
@@ -1,54 +1,107 @@
-use crate::objects::{Kind, Object};
+use std::io::Read;
+use std::path::Path;
+
 use anyhow::Context;
-use chrono::Local;
-use ini::Ini;
-use std::{io::Cursor, path::PathBuf};
+
+use crate::commit::{Commit, Signature};
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, ObjectDatabase};
+use crate::revision;
 
 // NOTE: it's use to write the commit object
 // cargo run -- commit-tree <tree_sha> -p <parent_commit_sha> -m <commit_message>
 // it's only write the commit object it will not show any thing in the git log or git show command
+// -p can be given many times (merge commit), the same parent twice is ignored
+// message: every -m is a paragraph ("-m a -m b" -> "a\n\nb\n"), then the -F files as they are
+// (-F - is stdin), stdin when there is neither -m nor -F
+// tree and parents can be any revision: HEAD, main~1, v1^{tree}
 pub(crate) fn invoke(
-    tree_sha: String,
-    parent_commit_sha: Option<String>,
-    commit_message: String,
+    tree: &str,
+    parents: &[String],
+    messages: &[String],
+    message_files: &[String],
 ) -> anyhow::Result<()> {
-    let hash = write_commit(&tree_sha, parent_commit_sha.as_deref(), &commit_message)?;
-    println!("{}", hex::encode(hash));
-    Ok(())
-}
+    let db = odb::open()?;
+    let git_dir = Path::new(".git");
+    let tree = revision::peel(&db, revision::resolve(&db, git_dir, tree)?, Kind::Tree)
+        .with_context(|| format!("{tree} is not a valid 'tree' object"))?;
+    let mut parent_ids: Vec<ObjectId> = Vec::new();
+    for parent in parents {
+        let id = revision::resolve(&db, git_dir, parent)?;
+        anyhow::ensure!(
+            db.read_header(&id.to_string())?.0 == Kind::Commit,
+            "{id} is not a valid 'commit' object"
+        );
+        if parent_ids.contains(&id) {
+            eprintln!("error: duplicate parent {id} ignored");
+            continue;
+        }
+        parent_ids.push(id);
+    }
 
-pub(crate) fn write_commit(
-    tree_sha: &str,
-    parent_commit_sha: Option<&str>,
-    commit_message: &str,
-) -> anyhow::Result<[u8; 20]> {
-    let mut commit_object = Vec::new();
-    commit_object.extend(format!("tree {}\n", tree_sha).as_bytes());
-    if let Some(commit) = parent_commit_sha {
-        commit_object.extend(format!("parent {}\n", commit).as_bytes());
+    let mut message = String::new();
+    for paragraph in messages {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(paragraph);
+        if !message.ends_with('\n') {
+            message.push('\n');
+        }
+    }
+    for file in message_files {
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(&read_message_file(file)?);
     }
-    // TODO: currently only support the local username email not global
-    // if you need to access global try to read the ~/.gitconfig
-    let git_config = Ini::load_from_file(&PathBuf::from(".git/config"))
-        .context("Reading config file. use git config user.name name and user.email email")?;
-    let username = git_config.get_from(Some("user"), "name").unwrap_or("none");
-    let email = git_config.get_from(Some("user"), "email").unwrap_or("none");
+    if messages.is_empty() && message_files.is_empty() {
+        message = read_message_file("-")?;
+    }
+
+    let hash = write_commit(&db, tree, parent_ids, message)?;
+    println!("{hash}");
+    Ok(())
+}
 
-    let time = Local::now().format("%s %z");
-    commit_object.extend(
-        format!("author {username} <{email}> {time}\ncommitter {username} <{email}> {time}\n\n")
-            .as_bytes(),
-    );
-    commit_object.extend(format!("{}\n", commit_message).as_bytes());
-    let hash = Object {
-        kind: Kind::Commit,
-        expected_size: commit_object.len() as u64,
-        reader: Cursor::new(commit_object),
+// NOTE: "-" is stdin
+pub(crate) fn read_message_file(file: &str) -> anyhow::Result<String> {
+    let mut message = String::new();
+    if file == "-" {
+        std::io::stdin()
+            .read_to_string(&mut message)
+            .context("could not read log from standard input")?;
+    } else {
+        message = std::fs::read_to_string(file)
+            .with_context(|| format!("could not read log file '{file}'"))?;
     }
-    .write_to_object()
-    .context("Failed to write the commit objects")?;
+    Ok(message)
+}
 
-    Ok(hash)
+// NOTE: author/committer from GIT_AUTHOR_* / GIT_COMMITTER_* or user.name and user.email
+pub(crate) fn write_commit(
+    db: &dyn ObjectDatabase,
+    tree: ObjectId,
+    parents: Vec<ObjectId>,
+    message: String,
+) -> anyhow::Result<ObjectId> {
+    let config = Config::load()?;
+    let commit = Commit {
+        tree,
+        parents,
+        author: Signature::author(&config)?,
+        committer: Signature::committer(&config)?,
+        encoding: None,
+        extra_headers: Vec::new(),
+        gpgsig: None,
+        message: message.into_bytes(),
+    };
+    commit
+        .object()
+        .write_to(db)
+        .context("Failed to write the commit objects")
 }
 // TEST:  https://app.codecrafters.io/courses/git/stages/jm9
 //$ mkdir test_dir && cd test_dir
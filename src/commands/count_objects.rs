@@ -0,0 +1,168 @@
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::hash::ObjectFormat;
+use crate::odb::{self, ObjectDatabase, PackDb};
+
+// NOTE: how much space the object database use
+// count-objects          -> "3 objects, 12 kilobytes" (loose objects only)
+// count-objects -v
+// count: 3               -> loose objects
+// size: 12               -> disk usage of the loose objects in KiB
+// in-pack: 7             -> objects in all the packs
+// packs: 1
+// size-pack: 1           -> .pack + .idx in KiB
+// prune-packable: 0      -> loose objects that are also in a pack (can be deleted)
+// garbage: 0             -> files in the objects dir that are not objects or packs
+// size-garbage: 0        -> length of those files in KiB
+// alternate: /abs/path   -> one line per alternate object store
+// -H print the sizes as "12.00 KiB" instead of the KiB number
+// disk usage is the allocated blocks, not the file length (small file still take 4 KiB),
+// the garbage is counted by its length like git
+pub(crate) fn invoke(verbose: bool, human_readable: bool) -> anyhow::Result<()> {
+    let objects_dir = odb::objects_dir();
+    let format = ObjectFormat::current()?;
+    let packs = PackDb::open_all(&objects_dir, format)?;
+    let mut garbage = Garbage {
+        report: verbose,
+        ..Default::default()
+    };
+
+    let mut count = 0u64;
+    let mut size = 0u64;
+    let mut prune_packable = 0u64;
+    for fanout in 0..=255u8 {
+        let dir = objects_dir.join(format!("{fanout:02x}"));
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+        };
+        for entry in entries {
+            let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+            let metadata = entry.metadata()?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let is_object = name.len() == format.hex_len() - 2
+                && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'));
+            if !is_object {
+                garbage.add(&entry.path(), &metadata, "garbage found");
+                continue;
+            }
+            count += 1;
+            size += disk_usage(&metadata);
+            let hash = format!("{fanout:02x}{name}");
+            if packs.iter().any(|pack| pack.contains(&hash)) {
+                prune_packable += 1;
+            }
+        }
+    }
+
+    let mut in_pack = 0u64;
+    let mut size_pack = 0u64;
+    for pack in &packs {
+        in_pack += pack.len() as u64;
+        for path in [
+            pack.pack_path().to_path_buf(),
+            pack.pack_path().with_extension("idx"),
+        ] {
+            size_pack += fs::metadata(&path)
+                .with_context(|| format!("Failed to read stat for :{}", path.display()))?
+                .len();
+        }
+    }
+    pack_garbage(&objects_dir.join("pack"), &mut garbage)?;
+
+    let show = |bytes: u64| {
+        if human_readable {
+            humanise(bytes)
+        } else {
+            (bytes / 1024).to_string()
+        }
+    };
+    if !verbose {
+        if human_readable {
+            println!("{count} objects, {}", humanise(size));
+        } else {
+            println!("{count} objects, {} kilobytes", size / 1024);
+        }
+        return Ok(());
+    }
+    println!("count: {count}");
+    println!("size: {}", show(size));
+    println!("in-pack: {in_pack}");
+    println!("packs: {}", packs.len());
+    println!("size-pack: {}", show(size_pack));
+    println!("prune-packable: {prune_packable}");
+    println!("garbage: {}", garbage.count);
+    println!("size-garbage: {}", show(garbage.size));
+    for alternate in odb::read_alternates(&objects_dir)? {
+        let alternate = fs::canonicalize(&alternate).unwrap_or(alternate);
+        println!("alternate: {}", alternate.display());
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct Garbage {
+    report: bool,
+    count: u64,
+    size: u64,
+}
+
+impl Garbage {
+    fn add(&mut self, path: &Path, metadata: &fs::Metadata, reason: &str) {
+        if self.report {
+            eprintln!("warning: {reason}: {}", path.display());
+        }
+        self.count += 1;
+        self.size += metadata.len();
+    }
+}
+
+// NOTE: a pack is the .pack and .idx pair, with optional .keep/.bitmap/.rev/... next to them
+// anything else in objects/pack (tmp_pack_ of a killed fetch, an .idx alone) is garbage
+fn pack_garbage(pack_dir: &Path, garbage: &mut Garbage) -> anyhow::Result<()> {
+    let entries = match fs::read_dir(pack_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", pack_dir.display())),
+    };
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", pack_dir.display()))?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or("");
+        let known = matches!(
+            extension,
+            "pack" | "idx" | "keep" | "bitmap" | "promisor" | "rev" | "mtimes"
+        );
+        if !known || !metadata.is_file() {
+            garbage.add(&path, &metadata, "garbage found");
+        } else if !path.with_extension("pack").is_file() {
+            garbage.add(&path, &metadata, "no corresponding .pack");
+        } else if !path.with_extension("idx").is_file() {
+            garbage.add(&path, &metadata, "no corresponding .idx");
+        }
+    }
+    Ok(())
+}
+
+fn disk_usage(metadata: &fs::Metadata) -> u64 {
+    metadata.blocks() * 512
+}
+
+// NOTE: same as git -H: "512 bytes", "1.63 KiB", "12.00 MiB", "1.50 GiB"
+// (integer math with the same rounding as git so the numbers match)
+fn humanise(bytes: u64) -> String {
+    for (shift, unit, round) in [(30, "GiB", 5368709), (20, "MiB", 5243), (10, "KiB", 5)] {
+        if bytes > 1 << shift {
+            let x = bytes + round;
+            let fraction = ((x & ((1 << shift) - 1)) * 100) >> shift;
+            return format!("{}.{fraction:02} {unit}", x >> shift);
+        }
+    }
+    format!("{bytes} bytes")
+}
@@ -0,0 +1,307 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, ObjectDatabase};
+use crate::reachable;
+use crate::refs::{self, Head};
+use crate::remote::{self, Refspec, Remote};
+use crate::revision;
+
+// NOTE: get the refs (and the objects they need) of another repository on the disk
+// cargo run -- fetch                      the remote of the current branch (or origin),
+//                                         with its remote.<name>.fetch refspecs
+// cargo run -- fetch origin side          only side, into FETCH_HEAD
+// cargo run -- fetch ../other main:x      main of ../other becomes refs/heads/x
+// a ref only moves forward unless it is forced (-f, or "+" in the refspec), an existing tag
+// never moves without -f, the current branch is never fetched into
+// tags of the remote pointing to something we have now come along
+// every fetched ref goes to .git/FETCH_HEAD, the one to merge first
+pub(crate) fn invoke(remote: Option<&str>, refspecs: &[String], force: bool) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let config = Config::load()?;
+    let name = match remote {
+        Some(name) => name.to_string(),
+        None => remote::default_name(git_dir, &config)?,
+    };
+    let remote = Remote::open(&config, &name)?;
+    let remote_db = odb::open_at(&remote.git_dir.join("objects"))?;
+    let db = odb::open()?;
+    let remote_refs = valid_refs(refs::list(&remote.git_dir, "refs/")?);
+
+    let mut fetched: Vec<Fetched> = Vec::new();
+    if refspecs.is_empty() {
+        let merge = match refs::read_head(git_dir)? {
+            Head::Symbolic(head) => head.strip_prefix("refs/heads/").and_then(|branch| {
+                (config.get(&format!("branch.{branch}.remote")) == Some(name.as_str()))
+                    .then(|| config.get(&format!("branch.{branch}.merge")))
+                    .flatten()
+            }),
+            Head::Detached(_) => None,
+        };
+        for refspec in &remote.fetch {
+            for (src, hash) in &remote_refs {
+                if let Some(dst) = refspec.map(src) {
+                    fetched.push(Fetched {
+                        src: src.clone(),
+                        hash: hash.clone(),
+                        dst: Some(dst),
+                        force: refspec.force,
+                        merge: merge == Some(src.as_str()),
+                    });
+                }
+            }
+        }
+    } else {
+        for refspec in refspecs {
+            let refspec = Refspec::parse(refspec)?;
+            if refspec.is_pattern() {
+                for (src, hash) in &remote_refs {
+                    if let Some(dst) = refspec.map(src) {
+                        fetched.push(Fetched {
+                            src: src.clone(),
+                            hash: hash.clone(),
+                            dst: Some(dst),
+                            force: refspec.force,
+                            merge: false,
+                        });
+                    }
+                }
+                continue;
+            }
+            let src = revision::dwim_ref(&remote.git_dir, &refspec.src)?
+                .with_context(|| format!("couldn't find remote ref {}", refspec.src))?;
+            let hash = refs::resolve(&remote.git_dir, &src)?
+                .with_context(|| format!("couldn't find remote ref {}", refspec.src))?;
+            let dst = match &refspec.dst {
+                Some(dst) => Some(local_name(git_dir, dst, &src)?),
+                None => None,
+            };
+            fetched.push(Fetched {
+                src,
+                hash,
+                dst,
+                force: refspec.force,
+                merge: true,
+            });
+        }
+    }
+    for dst in fetched.iter().filter_map(|fetched| fetched.dst.as_ref()) {
+        refs::check_refname_format(dst)?;
+    }
+    if let Head::Symbolic(head) = refs::read_head(git_dir)? {
+        let cwd = std::env::current_dir().context("Failed to get the current dir")?;
+        for dst in fetched.iter().filter_map(|fetched| fetched.dst.as_ref()) {
+            anyhow::ensure!(
+                *dst != head,
+                "refusing to fetch into branch '{dst}' checked out at '{}'",
+                cwd.display()
+            );
+        }
+    }
+
+    let wants = fetched
+        .iter()
+        .map(|fetched| ObjectId::from_hex(&fetched.hash))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let missing = remote::missing_objects(&remote_db, &db, &wants)?;
+    remote::copy_objects(&remote_db, &db, &missing)?;
+    if fetched.iter().any(|fetched| fetched.dst.is_some()) {
+        follow_tags(&remote_db, &db, git_dir, &remote_refs, &mut fetched)?;
+    }
+
+    // NOTE: the reflog says what was run, like "fetch origin +side:x: forced-update"
+    let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+    let abbrev = Abbrev::new(&db, 7)?;
+    let mut lines: Vec<(char, String, &str, String, &str)> = Vec::new();
+    let mut rejected = false;
+    for fetched in &fetched {
+        let from = remote::short_name(&fetched.src);
+        let Some(dst) = &fetched.dst else {
+            let kind = if fetched.src.starts_with("refs/heads/") {
+                "branch"
+            } else if fetched.src.starts_with("refs/tags/") {
+                "tag"
+            } else {
+                ""
+            };
+            lines.push(('*', kind.to_string(), from, "FETCH_HEAD".to_string(), ""));
+            continue;
+        };
+        let to = remote::short_name(dst).to_string();
+        let new = &fetched.hash;
+        let old = refs::resolve(git_dir, dst)?;
+        let is_tag = dst.starts_with("refs/tags/");
+        let (flag, summary, reason, message) = match &old {
+            Some(old) if old == new => continue,
+            None => {
+                let message = if is_tag {
+                    "storing tag"
+                } else {
+                    "storing head"
+                };
+                ('*', remote::new_ref_kind(dst).to_string(), "", message)
+            }
+            Some(_) if is_tag && force => ('t', "[tag update]".to_string(), "", "updating tag"),
+            Some(_) if is_tag => {
+                rejected = true;
+                let reason = "  (would clobber existing tag)";
+                lines.push(('!', "[rejected]".to_string(), from, to, reason));
+                continue;
+            }
+            Some(old) if remote::is_fast_forward(&db, old, new)? => (
+                ' ',
+                remote::range(&abbrev, old, new, false),
+                "",
+                "fast-forward",
+            ),
+            Some(old) if force || fetched.force => (
+                '+',
+                remote::range(&abbrev, old, new, true),
+                "  (forced update)",
+                "forced-update",
+            ),
+            Some(_) => {
+                rejected = true;
+                let reason = "  (non-fast-forward)";
+                lines.push(('!', "[rejected]".to_string(), from, to, reason));
+                continue;
+            }
+        };
+        refs::update(git_dir, dst, new, &format!("{command}: {message}"))?;
+        lines.push((flag, summary, from, to, reason));
+    }
+    write_fetch_head(git_dir, &remote, &fetched)?;
+
+    if !lines.is_empty() {
+        eprintln!("From {}", remote.url);
+        let width = lines
+            .iter()
+            .map(|(_, _, from, _, _)| from.len())
+            .max()
+            .unwrap_or(0)
+            .max(10);
+        for (flag, summary, from, to, reason) in lines {
+            eprintln!(" {flag} {summary:<17} {from:<width$} -> {to}{reason}");
+        }
+    }
+    if rejected {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// NOTE: the names come from the other repository (its packed-refs can say anything), one
+// that is not a valid ref name is left out like git does, it would become a path here
+pub(crate) fn valid_refs(refs: Vec<(String, String)>) -> Vec<(String, String)> {
+    refs.into_iter()
+        .filter(|(name, _)| match refs::check_refname_format(name) {
+            Ok(()) => true,
+            Err(_) => {
+                eprintln!("warning: ignoring ref with broken name {name}");
+                false
+            }
+        })
+        .collect()
+}
+
+// a remote ref and where it goes
+struct Fetched {
+    src: String,
+    hash: String,
+    // None is FETCH_HEAD only
+    dst: Option<String>,
+    force: bool,
+    // "not-for-merge" in FETCH_HEAD when false
+    merge: bool,
+}
+
+// NOTE: x -> the local ref it already is, or refs/heads/x (refs/tags/x when a tag is fetched)
+fn local_name(git_dir: &Path, dst: &str, src: &str) -> anyhow::Result<String> {
+    if dst.starts_with("refs/") || dst == "HEAD" {
+        return Ok(dst.to_string());
+    }
+    if let Some(existing) = revision::dwim_ref(git_dir, dst)? {
+        return Ok(existing);
+    }
+    let prefix = if src.starts_with("refs/tags/") {
+        "refs/tags/"
+    } else {
+        "refs/heads/"
+    };
+    Ok(format!("{prefix}{dst}"))
+}
+
+// NOTE: a tag of the remote that we don't have comes along when what it points to is here
+// now (it's part of what was fetched, or was already here), never the other tags
+fn follow_tags(
+    remote_db: &dyn ObjectDatabase,
+    db: &dyn ObjectDatabase,
+    git_dir: &Path,
+    remote_refs: &[(String, String)],
+    fetched: &mut Vec<Fetched>,
+) -> anyhow::Result<()> {
+    for (name, hash) in remote_refs {
+        if !name.starts_with("refs/tags/")
+            || refs::resolve(git_dir, name)?.is_some()
+            || fetched
+                .iter()
+                .any(|fetched| fetched.dst.as_ref() == Some(name))
+        {
+            continue;
+        }
+        let target = match remote_db.read_header(hash)? {
+            (Kind::Tag, _) if !db.contains(hash) => {
+                let mut content = Vec::new();
+                std::io::Read::read_to_end(&mut remote_db.read(hash)?.reader, &mut content)
+                    .with_context(|| format!("Failed to read tag {hash}"))?;
+                reachable::links(Kind::Tag, &content, db.format())?
+                    .first()
+                    .map(|target| target.to_string())
+            }
+            _ => Some(hash.clone()),
+        };
+        if !target.is_some_and(|target| db.contains(&target)) {
+            continue;
+        }
+        let missing = remote::missing_objects(remote_db, db, &[ObjectId::from_hex(hash)?])?;
+        remote::copy_objects(remote_db, db, &missing)?;
+        fetched.push(Fetched {
+            src: name.clone(),
+            hash: hash.clone(),
+            dst: Some(name.clone()),
+            force: false,
+            merge: false,
+        });
+    }
+    Ok(())
+}
+
+// NOTE: <hash>\t[not-for-merge]\tbranch 'main' of <url>
+// git pull (and merge FETCH_HEAD) take the lines without not-for-merge
+fn write_fetch_head(git_dir: &Path, remote: &Remote, fetched: &[Fetched]) -> anyhow::Result<()> {
+    let mut content = String::new();
+    for merge in [true, false] {
+        for fetched in fetched.iter().filter(|fetched| fetched.merge == merge) {
+            let what = if let Some(name) = fetched.src.strip_prefix("refs/heads/") {
+                format!("branch '{name}'")
+            } else if let Some(name) = fetched.src.strip_prefix("refs/tags/") {
+                format!("tag '{name}'")
+            } else if let Some(name) = fetched.src.strip_prefix("refs/remotes/") {
+                format!("remote-tracking branch '{name}'")
+            } else {
+                format!("'{}'", fetched.src)
+            };
+            let not_for_merge = if merge { "" } else { "not-for-merge" };
+            content.push_str(&format!(
+                "{}\t{not_for_merge}\t{what} of {}\n",
+                fetched.hash, remote.url
+            ));
+        }
+    }
+    fs::write(git_dir.join("FETCH_HEAD"), content).context("Failed to write FETCH_HEAD")
+}
@@ -0,0 +1,281 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::index::Index;
+use crate::objects::Kind;
+use crate::odb::{self, LooseDb, ObjectDatabase, PackDb};
+use crate::refs::{self, Head};
+use crate::validate::{self, Problem};
+
+// NOTE: check the repository is not broken, in two steps
+// 1. every local object (loose and packed) is read: it has to inflate, the size in the header
+//    has to match and the content has to hash to its name. trees, commits and tags are also
+//    parsed and checked (entry order and modes, tree/parent/author/committer lines, ...)
+// 2. connectivity: starting from HEAD, the refs, the reflogs and the index follow every link
+//    (commit -> tree + parents, tree -> entries, tag -> object), everything has to exist
+// output is the same as git
+// error in tree <hash>: treeNotSorted: not properly sorted
+// missing blob <hash>
+// dangling commit <hash>      -> unreachable and nothing else point to it
+// --unreachable print every unreachable object, not only the dangling ones
+// --lost-found write the dangling objects into .git/lost-found/{commit,other}/<hash>
+//   (blob get its content, the rest only the hash)
+// --connectivity-only skip step 1 (blobs are not even read), only follow the links
+pub(crate) fn invoke(
+    unreachable: bool,
+    lost_found: bool,
+    connectivity_only: bool,
+) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let format = db.format();
+    let mut fsck = Fsck {
+        format,
+        connectivity_only,
+        objects: BTreeMap::new(),
+        corrupt: HashSet::new(),
+        errors: 0,
+    };
+
+    let objects_dir = odb::objects_dir();
+    let mut backends: Vec<Box<dyn ObjectDatabase>> =
+        vec![Box::new(LooseDb::new(&objects_dir, format))];
+    for pack in PackDb::open_all(&objects_dir, format)? {
+        backends.push(Box::new(pack));
+    }
+    for backend in &backends {
+        for hash in backend.iter()? {
+            // same object can be loose and packed, check it once
+            if fsck.objects.contains_key(&hash) || fsck.corrupt.contains(&hash) {
+                continue;
+            }
+            if let Some(checked) = fsck.check(backend.as_ref(), hash) {
+                fsck.objects.insert(hash, checked);
+            }
+        }
+    }
+
+    // link to an object of the wrong kind (tree entry pointing to a commit, ...)
+    for (hash, checked) in &fsck.objects {
+        for (expected, link) in &checked.links {
+            if let Some(target) = fsck.objects.get(link)
+                && target.kind != *expected
+            {
+                println!(
+                    "error: object {link} is a {}, not a {expected}",
+                    target.kind
+                );
+                println!("error in {} {hash}: broken links", checked.kind);
+                fsck.errors += 1;
+            }
+        }
+    }
+
+    let reachable = fsck.connectivity(&db, git_dir)?;
+
+    let unreachable_objects: Vec<(&ObjectId, &Checked)> = fsck
+        .objects
+        .iter()
+        .filter(|(hash, _)| !reachable.contains(*hash))
+        .collect();
+    // dangling: unreachable and not used by any other unreachable object
+    let referenced: HashSet<ObjectId> = unreachable_objects
+        .iter()
+        .flat_map(|(_, checked)| checked.links.iter().map(|(_, hash)| *hash))
+        .collect();
+    for (hash, checked) in unreachable_objects {
+        let dangling = !referenced.contains(hash);
+        if unreachable {
+            println!("unreachable {} {hash}", checked.kind);
+        } else if dangling {
+            println!("dangling {} {hash}", checked.kind);
+        }
+        if lost_found && dangling {
+            write_lost_found(&db, git_dir, checked.kind, hash)?;
+        }
+    }
+
+    anyhow::ensure!(fsck.errors == 0, "fsck found {} error(s)", fsck.errors);
+    Ok(())
+}
+
+struct Checked {
+    kind: Kind,
+    // every object this one point to, with the kind it has to be
+    links: Vec<(Kind, ObjectId)>,
+}
+
+struct Fsck {
+    format: ObjectFormat,
+    connectivity_only: bool,
+    // every local object that could be read, with its links
+    objects: BTreeMap<ObjectId, Checked>,
+    // already reported, don't complain again when we find a link to it
+    corrupt: HashSet<ObjectId>,
+    errors: usize,
+}
+
+impl Fsck {
+    fn check(&mut self, db: &dyn ObjectDatabase, hash: ObjectId) -> Option<Checked> {
+        let mut problems = Vec::new();
+        match self.parse(db, hash, &mut problems) {
+            Ok(checked) => {
+                for problem in problems {
+                    if !problem.warning {
+                        self.errors += 1;
+                    }
+                    println!(
+                        "{} in {} {hash}: {}: {}",
+                        if problem.warning { "warning" } else { "error" },
+                        checked.kind,
+                        problem.id,
+                        problem.message
+                    );
+                }
+                Some(checked)
+            }
+            Err(e) => {
+                println!("error: {hash}: object corrupt: {e:#}");
+                self.errors += 1;
+                self.corrupt.insert(hash);
+                None
+            }
+        }
+    }
+
+    fn parse(
+        &self,
+        db: &dyn ObjectDatabase,
+        hash: ObjectId,
+        problems: &mut Vec<Problem>,
+    ) -> anyhow::Result<Checked> {
+        let hex = hash.to_string();
+        let (kind, _) = db.read_header(&hex)?;
+        if kind == Kind::Blob {
+            // the reader check the hash once everything went through
+            if !self.connectivity_only {
+                let mut object = db.read(&hex)?;
+                std::io::copy(&mut object.reader, &mut std::io::sink())?;
+            }
+            return Ok(Checked {
+                kind,
+                links: Vec::new(),
+            });
+        }
+        let mut content = Vec::new();
+        db.read(&hex)?.reader.read_to_end(&mut content)?;
+        let (links, found) = validate::object(kind, &content, self.format);
+        problems.extend(found);
+        if self.connectivity_only {
+            problems.clear();
+        }
+        Ok(Checked { kind, links })
+    }
+
+    // NOTE: walk from every root and return everything we could reach
+    fn connectivity(
+        &mut self,
+        db: &dyn ObjectDatabase,
+        git_dir: &Path,
+    ) -> anyhow::Result<HashSet<ObjectId>> {
+        let mut queue: Vec<(Option<Kind>, ObjectId)> = Vec::new();
+
+        let mut refs = refs::list(git_dir, "refs/")?;
+        match refs::read_head(git_dir)? {
+            Head::Symbolic(name) => {
+                if refs::resolve(git_dir, &name)?.is_none() {
+                    let branch = name.strip_prefix("refs/heads/").unwrap_or(&name);
+                    println!("notice: HEAD points to an unborn branch ({branch})");
+                }
+            }
+            Head::Detached(hash) => refs.push(("HEAD".to_string(), hash)),
+        }
+        for (name, hash) in refs {
+            match ObjectId::from_hex(&hash) {
+                Ok(id) if db.contains(&hash) => queue.push((None, id)),
+                _ => {
+                    println!("error: {name}: invalid sha1 pointer {hash}");
+                    self.errors += 1;
+                }
+            }
+        }
+
+        for (name, hash) in refs::reflog_entries(git_dir)? {
+            match ObjectId::from_hex(&hash) {
+                Ok(id) if db.contains(&hash) => queue.push((None, id)),
+                _ => {
+                    println!("error: {name}: invalid reflog entry {hash}");
+                    self.errors += 1;
+                }
+            }
+        }
+
+        let index = Index::read(&git_dir.join("index"))?;
+        for entry in &index.entries {
+            // submodule commit live in the other repository
+            if entry.mode & 0o170000 != 0o160000 {
+                queue.push((Some(Kind::Blob), entry.hash));
+            }
+        }
+
+        let mut reachable = HashSet::new();
+        while let Some((expected, hash)) = queue.pop() {
+            if !reachable.insert(hash) {
+                continue;
+            }
+            let links = match self.objects.get(&hash) {
+                Some(checked) => checked.links.clone(),
+                None if self.corrupt.contains(&hash) => continue,
+                // not local, but one of the alternates can have it
+                None if db.contains(&hash.to_string()) => {
+                    match self.parse(db, hash, &mut Vec::new()) {
+                        Ok(checked) => checked.links,
+                        Err(e) => {
+                            println!("error: {hash}: object corrupt: {e:#}");
+                            self.errors += 1;
+                            self.corrupt.insert(hash);
+                            continue;
+                        }
+                    }
+                }
+                None => {
+                    let kind = expected.map_or("object".to_string(), |kind| kind.to_string());
+                    println!("missing {kind} {hash}");
+                    self.errors += 1;
+                    continue;
+                }
+            };
+            queue.extend(links.into_iter().map(|(kind, hash)| (Some(kind), hash)));
+        }
+        Ok(reachable)
+    }
+}
+
+fn write_lost_found(
+    db: &dyn ObjectDatabase,
+    git_dir: &Path,
+    kind: Kind,
+    hash: &ObjectId,
+) -> anyhow::Result<()> {
+    let dir = git_dir.join("lost-found").join(match kind {
+        Kind::Commit => "commit",
+        _ => "other",
+    });
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    let path = dir.join(hash.to_string());
+    let mut file =
+        fs::File::create(&path).with_context(|| format!("Failed to create {}", path.display()))?;
+    if kind == Kind::Blob {
+        std::io::copy(&mut db.read(&hash.to_string())?.reader, &mut file)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    } else {
+        std::io::Write::write_all(&mut file, format!("{hash}\n").as_bytes())
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commands::{commit_graph, prune};
+use crate::config::Config;
+use crate::odb;
+
+// NOTE: housekeeping of the repository
+// gc                       -> prune the unreachable loose objects older than gc.pruneExpire
+// gc --prune=now           -> override gc.pruneExpire (default 2.weeks.ago, "never" to keep all)
+// gc --auto                -> do nothing unless there are too many loose objects, see gc.auto
+// then the commit-graph is written again with what is reachable (gc.writeCommitGraph)
+// commit run `gc --auto` after itself, same as git
+// TODO: we can't write packs yet, so gc does not repack, it only prune
+pub(crate) fn invoke(auto: bool, prune: Option<String>) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let config = Config::load()?;
+    if auto {
+        if !too_many_loose_objects(&config)? {
+            return Ok(());
+        }
+        eprintln!("Auto pruning the repository for optimum performance.");
+    }
+    let expire = prune
+        .as_deref()
+        .or(config.get("gc.pruneExpire"))
+        .unwrap_or("2.weeks.ago");
+    prune::prune(git_dir, prune::parse_expire(expire)?, false, false)?;
+    if config.get_bool("gc.writeCommitGraph")?.unwrap_or(true) {
+        commit_graph::write(true, false)?;
+    }
+    Ok(())
+}
+
+// NOTE: gc.auto is the number of loose objects that start `gc --auto` (default 6700, 0 = never)
+// counting all of them would be slow, git only count objects/17 and guess the rest
+// (hashes are random so every fanout dir has about 1/256 of them)
+const DEFAULT_GC_AUTO: i64 = 6700;
+fn too_many_loose_objects(config: &Config) -> anyhow::Result<bool> {
+    let threshold = match config.get("gc.auto") {
+        Some(value) => value
+            .parse::<i64>()
+            .with_context(|| format!("bad numeric config value '{value}' for 'gc.auto'"))?,
+        None => DEFAULT_GC_AUTO,
+    };
+    if threshold <= 0 {
+        return Ok(false);
+    }
+    let per_dir = (threshold + 255) / 256;
+    let fanout = odb::objects_dir().join("17");
+    let count = match fs::read_dir(&fanout) {
+        Ok(entries) => entries.count() as i64,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", fanout.display())),
+    };
+    Ok(count > per_dir)
+}
@@ -1,8 +1,15 @@
 use anyhow::Context;
 // use flate2::read::ZlibEncoder; // my code
-use std::path::Path;
+use std::fs;
+use std::io::{BufRead, Cursor, Read};
+use std::path::{Path, PathBuf};
 
-use crate::objects::Object;
+use crate::config::Config;
+use crate::convert::Filters;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{Kind, Object};
+use crate::odb::{self, CompositeDb};
+use crate::validate;
 
 // NOTE: it's use to write the object file currenly only support the blob file
 // run test
@@ -15,53 +22,179 @@ use crate::objects::Object;
 // NOTE: file content store as in location .git/objects/<hash - first 2>/<hash - rest>
 // blob <size>\0<content>
 // content is the zlib compressed data
-pub(crate) fn invoke(write: bool, file_path: &Path) -> anyhow::Result<()> {
-    let object = Object::blob_from_file(file_path)?;
-    let hash = if write {
-        object
-            .write_to_object()
-            .context("failed to write the blob object")?
+// GIT_OBJECT_DIRECTORY=/some/other/objects will write into that store instead of .git/objects
+//
+// NOTE: more than one object at once, in this order
+// --stdin              -> the content of stdin
+// <file>...            -> every file
+// --stdin-paths        -> every file named on stdin (one per line), for pipelines
+// -t tree|commit|tag   -> the content must be a valid object of that kind (same checks as fsck)
+//                         --literally skip the check, to create broken objects on purpose
+// blob content go through the clean filter and the CRLF -> LF conversion of .gitattributes,
+// as `git add` would store it. the path of the file is used for the attributes,
+// --path <file> use another one (and is the only way to get filters for --stdin),
+// --no-filters hash the bytes as they are
+pub(crate) fn invoke(options: Options, file_paths: &[PathBuf]) -> anyhow::Result<()> {
+    if options.write {
+        let db = odb::open()?;
+        hash_all(&options, Some(&db), file_paths)
     } else {
-        object
-            .write(std::io::sink())
-            .context("failed to get the hash of the blob object")?
+        hash_all(&options, None, file_paths)
+    }
+}
+
+pub(crate) struct Options {
+    pub(crate) kind: Kind,
+    pub(crate) write: bool,
+    pub(crate) stdin: bool,
+    pub(crate) stdin_paths: bool,
+    pub(crate) literally: bool,
+    pub(crate) no_filters: bool,
+    pub(crate) path: Option<String>,
+}
+
+fn hash_all(
+    options: &Options,
+    db: Option<&CompositeDb>,
+    file_paths: &[PathBuf],
+) -> anyhow::Result<()> {
+    let hasher = Hasher {
+        options,
+        db,
+        format: ObjectFormat::current()?,
+        config: Config::load()?,
     };
-    println!("{}", hex::encode(hash));
+    if options.stdin {
+        let mut content = Vec::new();
+        std::io::stdin()
+            .lock()
+            .read_to_end(&mut content)
+            .context("Failed to read stdin")?;
+        let size = content.len() as u64;
+        let hash = hasher.hash(Cursor::new(content), size, options.path.as_deref())?;
+        println!("{hash}");
+    }
+    for file_path in file_paths {
+        println!("{}", hasher.hash_file(file_path)?);
+    }
+    if options.stdin_paths {
+        for line in std::io::stdin().lock().lines() {
+            let line = line.context("Failed to read stdin")?;
+            println!("{}", hasher.hash_file(Path::new(&line))?);
+        }
+    }
     Ok(())
-    //
-    //
-    // this is my code  its working but not optimize
-    // let f = fs::File::open(file_path).context("File is not exits")?;
-    // let mut b = BufReader::new(f);
-    // let mut buffer = Vec::new();
-    // b.read_to_end(&mut buffer)
-    //     .context("Read the encoded file")?;
-    // // add the bolb <size>\0<content>
-    // let size = buffer.len();
-    // let mut header = format!("blob {}", size).into_bytes();
-    // header.push(0);
-    // let mut new_buf = Vec::with_capacity(size + header.len());
-    // new_buf.extend_from_slice(&header);
-    // new_buf.extend_from_slice(&buffer);
-    //
-    // // calculate the hash
-    // let mut hasher = Sha1::new();
-    // hasher.update(&new_buf);
-    // let result = format!("{:x}", hasher.finalize());
-    // println!("hash:{result}");
-    // let mut z = ZlibEncoder::new(&new_buf[..], Compression::fast());
-    // buffer.clear();
-    // z.read_to_end(&mut buffer).context("some")?;
-    //
-    // fs::create_dir_all(format!(".git/objects/{}", &result[..2]))
-    //     .context("Failed to create parent dir for .git/objects/")?;
-    // fs::write(
-    //     format!(".git/objects/{}/{}", &result[..2], &result[2..]),
-    //     &buffer[..],
-    // )
-    // .context("Failed to create file in .git/objects")?;
 }
 
+struct Hasher<'a> {
+    options: &'a Options,
+    db: Option<&'a CompositeDb>,
+    format: ObjectFormat,
+    config: Config,
+}
+
+impl Hasher<'_> {
+    fn hash_file(&self, file_path: &Path) -> anyhow::Result<ObjectId> {
+        let stat = fs::metadata(file_path)
+            .with_context(|| format!("Failed to read stat for :{}", file_path.display()))?;
+        let file = fs::File::open(file_path)
+            .with_context(|| format!("failed to open the file:{}", file_path.display()))?;
+        let path = match &self.options.path {
+            Some(path) => path.clone(),
+            None => file_path.to_string_lossy().into_owned(),
+        };
+        self.hash(file, stat.len(), Some(&path))
+    }
+
+    fn hash(
+        &self,
+        mut reader: impl Read,
+        size: u64,
+        path: Option<&str>,
+    ) -> anyhow::Result<ObjectId> {
+        let kind = self.options.kind;
+        let filters = match path {
+            Some(path) if kind == Kind::Blob && !self.options.no_filters => {
+                Some(Filters::for_path(Path::new(".git"), &self.config, path)?)
+            }
+            _ => None,
+        };
+        // nothing to convert or check, stream it (file can be bigger than the memory)
+        if kind == Kind::Blob && filters.as_ref().is_none_or(Filters::is_noop) {
+            return self.store(Object {
+                kind,
+                expected_size: size,
+                reader,
+            });
+        }
+        let mut content = Vec::new();
+        reader
+            .read_to_end(&mut content)
+            .context("Failed to read the content")?;
+        if let Some(filters) = filters {
+            content = filters.to_git(content)?;
+        }
+        if !self.options.literally {
+            let (_, problems) = validate::object(kind, &content, self.format);
+            let mut malformed = false;
+            for problem in problems.iter().filter(|problem| !problem.warning) {
+                eprintln!(
+                    "error: object fails fsck: {}: {}",
+                    problem.id, problem.message
+                );
+                malformed = true;
+            }
+            anyhow::ensure!(!malformed, "refusing to create malformed object");
+        }
+        self.store(Object {
+            kind,
+            expected_size: content.len() as u64,
+            reader: Cursor::new(content),
+        })
+    }
+
+    fn store(&self, object: Object<impl Read>) -> anyhow::Result<ObjectId> {
+        match self.db {
+            Some(db) => object
+                .write_to(db)
+                .context("failed to write the blob object"),
+            None => object
+                .hash(self.format)
+                .context("failed to get the hash of the blob object"),
+        }
+    }
+}
+// this is my code  its working but not optimize
+// let f = fs::File::open(file_path).context("File is not exits")?;
+// let mut b = BufReader::new(f);
+// let mut buffer = Vec::new();
+// b.read_to_end(&mut buffer)
+//     .context("Read the encoded file")?;
+// // add the bolb <size>\0<content>
+// let size = buffer.len();
+// let mut header = format!("blob {}", size).into_bytes();
+// header.push(0);
+// let mut new_buf = Vec::with_capacity(size + header.len());
+// new_buf.extend_from_slice(&header);
+// new_buf.extend_from_slice(&buffer);
+//
+// // calculate the hash
+// let mut hasher = Sha1::new();
+// hasher.update(&new_buf);
+// let result = format!("{:x}", hasher.finalize());
+// println!("hash:{result}");
+// let mut z = ZlibEncoder::new(&new_buf[..], Compression::fast());
+// buffer.clear();
+// z.read_to_end(&mut buffer).context("some")?;
+//
+// fs::create_dir_all(format!(".git/objects/{}", &result[..2]))
+//     .context("Failed to create parent dir for .git/objects/")?;
+// fs::write(
+//     format!(".git/objects/{}/{}", &result[..2], &result[2..]),
+//     &buffer[..],
+// )
+// .context("Failed to create file in .git/objects")?;
+
 // TEST:
 // The tester will first initialize a new git repository using your program:
 // $ mkdir test_dir && cd test_dir
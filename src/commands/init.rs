@@ -0,0 +1,43 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::hash::ObjectFormat;
+
+// NOTE: create the empty repository
+// .git/objects  -> all the objects
+// .git/refs     -> branches and tags
+// .git/HEAD     -> current branch
+// .git/config   -> repository format (sha1 or sha256 objects)
+// cargo run -- init
+// cargo run -- init --object-format=sha256
+pub(crate) fn invoke(object_format: ObjectFormat) -> anyhow::Result<()> {
+    init_repo(Path::new(".git"), object_format)?;
+    println!("Initialized git directory");
+    Ok(())
+}
+
+pub(crate) fn init_repo(git_dir: &Path, object_format: ObjectFormat) -> anyhow::Result<()> {
+    fs::create_dir(git_dir).with_context(|| format!("Failed to create {}", git_dir.display()))?;
+    fs::create_dir(git_dir.join("objects")).context("Failed to create the objects dir")?;
+    fs::create_dir(git_dir.join("refs")).context("Failed to create the refs dir")?;
+    fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").context("Failed to write HEAD")?;
+    fs::write(git_dir.join("config"), format_config(object_format))
+        .context("Failed to write .git/config")?;
+    Ok(())
+}
+
+// NOTE: sha256 repo need the repositoryformatversion 1 otherwise the real git
+// will ignore the [extensions] section
+pub(crate) fn format_config(object_format: ObjectFormat) -> String {
+    match object_format {
+        ObjectFormat::Sha1 => {
+            "[core]\n\trepositoryformatversion = 0\n\tfilemode = true\n\tbare = false\n".to_string()
+        }
+        ObjectFormat::Sha256 => format!(
+            "[core]\n\trepositoryformatversion = 1\n\tfilemode = true\n\tbare = false\n\
+             [extensions]\n\tobjectformat = {object_format}\n"
+        ),
+    }
+}
@@ -0,0 +1,198 @@
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commit::{self, Commit};
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, CompositeDb};
+use crate::revision;
+use crate::revwalk::RevWalk;
+use crate::tree;
+
+// NOTE: the history from HEAD (or the given commits), newest first
+// cargo run -- log --oneline -n 5 main -- src/
+// commit <hash>
+// Merge: <abbrev> <abbrev>       (only merges)
+// Author: Name <email>
+// Date:   Mon Oct 19 12:00:00 2026 +0200
+//
+//     message, indented by 4
+// --oneline    "<abbrev> <subject>"
+// -n N         stop after N commits
+// -- paths     only the commits that changed one of the paths, with the same history
+//              simplification as git: a merge that has the path like one of its parents
+//              is not shown and only that parent is followed
+// the bloom filters of the commit-graph tell most of the commits did not change the path
+// without comparing the trees
+pub(crate) fn invoke(
+    options: Options,
+    revisions: &[String],
+    paths: &[String],
+) -> anyhow::Result<()> {
+    let db = odb::open()?;
+    let git_dir = Path::new(".git");
+    let mut walk = RevWalk::new(&db)?;
+    let revisions = if revisions.is_empty() {
+        vec!["HEAD".to_string()]
+    } else {
+        revisions.to_vec()
+    };
+    for name in &revisions {
+        let commit = revision::resolve(&db, git_dir, name)
+            .and_then(|id| revision::peel(&db, id, Kind::Commit))
+            .with_context(|| format!("bad revision '{name}'"))?;
+        walk.push(commit)?;
+    }
+    let paths: Vec<&str> = paths
+        .iter()
+        .map(|path| path.trim_end_matches('/'))
+        .collect();
+    let abbrev = Abbrev::new(&db, 7)?;
+    let mut stdout = std::io::stdout().lock();
+    let mut shown = 0;
+    while let Some(id) = walk.pop() {
+        if options.max_count.is_some_and(|max| shown >= max) {
+            break;
+        }
+        let parents = walk.ancestry().parents(id)?;
+        let (show, follow) = if paths.is_empty() {
+            (true, parents.clone())
+        } else {
+            simplify(&db, &mut walk, id, &parents, &paths)?
+        };
+        for parent in follow {
+            walk.push(parent)?;
+        }
+        if !show {
+            continue;
+        }
+        let commit = Commit::read(&db, &id.to_string())?;
+        let hex = id.to_string();
+        if options.oneline {
+            writeln!(
+                stdout,
+                "{} {}",
+                abbrev.abbrev(&hex),
+                subject(&commit.message_text())
+            )?;
+        } else {
+            if shown > 0 {
+                writeln!(stdout)?;
+            }
+            write_medium(&mut stdout, &abbrev, &hex, &commit)?;
+        }
+        shown += 1;
+    }
+    Ok(())
+}
+
+pub(crate) struct Options {
+    pub(crate) oneline: bool,
+    pub(crate) max_count: Option<usize>,
+}
+
+// NOTE: (show the commit, parents to follow)
+// TREESAME = the paths are the same in the commit and the parent
+// no parent: shown when it has the path. one parent: shown when not TREESAME.
+// merge: TREESAME to one parent -> hidden, only that parent is followed
+fn simplify(
+    db: &CompositeDb,
+    walk: &mut RevWalk,
+    commit: ObjectId,
+    parents: &[ObjectId],
+    paths: &[&str],
+) -> anyhow::Result<(bool, Vec<ObjectId>)> {
+    let tree = walk.ancestry().tree(commit)?;
+    if parents.is_empty() {
+        for path in paths {
+            if tree::entry_at(db, tree, path)?.is_some() {
+                return Ok((true, Vec::new()));
+            }
+        }
+        return Ok((false, Vec::new()));
+    }
+    for (i, &parent) in parents.iter().enumerate() {
+        // the filter is about the first parent only
+        let surely_same = i == 0
+            && walk.ancestry().graph().is_some_and(|graph| {
+                paths
+                    .iter()
+                    .all(|path| graph.maybe_changed(commit, path) == Some(false))
+            });
+        let parent_tree = walk.ancestry().tree(parent)?;
+        if surely_same || same_paths(db, tree, parent_tree, paths)? {
+            return Ok((false, vec![parent]));
+        }
+    }
+    Ok((true, parents.to_vec()))
+}
+
+fn same_paths(
+    db: &CompositeDb,
+    one: ObjectId,
+    two: ObjectId,
+    paths: &[&str],
+) -> anyhow::Result<bool> {
+    if one == two {
+        return Ok(true);
+    }
+    for path in paths {
+        if tree::entry_at(db, one, path)? != tree::entry_at(db, two, path)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn write_medium(
+    out: &mut impl Write,
+    abbrev: &Abbrev,
+    hex: &str,
+    commit: &Commit,
+) -> anyhow::Result<()> {
+    writeln!(out, "commit {hex}")?;
+    if commit.parents.len() > 1 {
+        let parents: Vec<String> = commit
+            .parents
+            .iter()
+            .map(|parent| abbrev.abbrev(&parent.to_string()).to_string())
+            .collect();
+        writeln!(out, "Merge: {}", parents.join(" "))?;
+    }
+    let author = &commit.author;
+    writeln!(
+        out,
+        "Author: {} <{}>",
+        commit.decode(&author.name),
+        commit.decode(&author.email)
+    )?;
+    writeln!(
+        out,
+        "Date:   {}",
+        commit::format_date(author.time, &author.timezone)
+    )?;
+    writeln!(out)?;
+    let message = commit.message_text();
+    let lines: Vec<&str> = message.lines().collect();
+    let start = lines.iter().position(|line| !line.trim().is_empty());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty());
+    if let (Some(start), Some(end)) = (start, end) {
+        for line in &lines[start..=end] {
+            writeln!(out, "    {}", line.trim_end())?;
+        }
+    }
+    Ok(())
+}
+
+// the first paragraph on one line
+fn subject(message: &str) -> String {
+    let lines: Vec<&str> = message
+        .lines()
+        .skip_while(|line| line.trim().is_empty())
+        .take_while(|line| !line.trim().is_empty())
+        .map(str::trim)
+        .collect();
+    lines.join(" ")
+}
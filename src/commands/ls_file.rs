@@ -1,48 +1,14 @@
-use std::{
-    fs::File,
-    io::{BufRead, BufReader, Read},
-};
+use std::path::Path;
 
-use anyhow::Context;
+use crate::index::Index;
 
 // NOTE:: this command is use to read the .git/index file
 // cargo run -- ls-files --stage
 pub(crate) fn invoke(stage: bool, _: bool) -> anyhow::Result<()> {
-    // NOTE: .git/index file get store as binary
-    //
-    //you might to think read whole file in one go.
-    let f = std::fs::File::open(".git/index").context("Open the .git/index file.")?;
-    let mut reader = BufReader::new(f);
-    let mut header = [0u8; 4];
-    reader
-        .read_exact(&mut header)
-        .context("Reading the header")?;
-    assert_eq!(&header[..], b"DIRC");
-
-    let _ = read_be(&mut reader).context("Reading version of the .git/index")?;
-    let num_of_entries = read_be(&mut reader).context("Reading entry from the .git/index")?;
-    let mut file_path = Vec::with_capacity(256);
-    let mut stats = [0u8; 62];
-    for i in 0..num_of_entries {
-        file_path.clear();
-        reader
-            .read_exact(&mut stats)
-            .with_context(|| format!("Reading the stats for {} entry", i))?;
-        eprintln!("{:?}", &stats[0..=3]);
-        let file_path_bytes_count = reader
-            .read_until(0, &mut file_path)
-            .with_context(|| format!("Reading file path for entry {}", i))?;
-        if file_path.contains(&b'\0') {
-            file_path.pop();
-        }
-        let padding = (8 - ((62 + file_path_bytes_count) % 8)) % 8;
-        if padding > 0 {
-            reader
-                .seek_relative(padding as i64)
-                .context("Reading padding bytes")?;
-        }
+    // NOTE: .git/index file get store as binary, the format live in crate::index
+    let index = Index::read(Path::new(".git/index"))?;
+    for entry in &index.entries {
         if stage {
-            let hash = hex::encode(&stats[40..=59]);
             // NOTE: flag have 2 bytes let say it's [0, 15] →  0x000F  →  decimal 15
             // represent this in binary 0010 0000 0000 1000
             //15 14 13 12 11 ............ 0
@@ -51,35 +17,20 @@ pub(crate) fn invoke(stage: bool, _: bool) -> anyhow::Result<()> {
             // to extract the 12 and 13 bit we need to move this bit in to right (do the right
             // shift) stage = (flags >> 12) & 0b11 <- 0b11 will get only uses exactly 2 bits
             // 0000 0000 0000 0010 &0b11 -> 10 return only first 2 bits
-            //
-            //
-            //
-            let flags = u16::from_be_bytes(stats[60..=61].try_into().unwrap());
-            let flags = ((flags >> 12) & 0b11) as u8;
-
             println!(
                 "{:o} {} {:?}\t{}",
-                u32::from_be_bytes(stats[24..=27].try_into().unwrap()), // mode
-                hash,                                                   // hash
-                flags,                                                  // stage
-                str::from_utf8(&file_path)?                             // path
+                entry.mode,              // mode
+                hex::encode(entry.hash), // hash
+                entry.stage(),           // stage
+                entry.path               // path
             );
         } else {
-            println!("{}", str::from_utf8(&file_path)?)
+            println!("{}", entry.path)
         }
     }
-
-    // let stdout = std::io::stdout();
-    // let mut stdout = stdout.lock();
-    // stdout.write_all(&header)?;
     Ok(())
 }
 
-fn read_be(r: &mut BufReader<File>) -> anyhow::Result<u32> {
-    let mut buf = [0u8; 4];
-    r.read_exact(&mut buf)?;
-    Ok(u32::from_be_bytes(buf))
-}
 //NOTE: you need to read byte by byte first 12 is the header
 // DIRC 4 bytes
 // version 4 bytes u32 big endian
@@ -96,7 +47,7 @@ fn read_be(r: &mut BufReader<File>) -> anyhow::Result<u32> {
 // | uid        | 4            |
 // | gid        | 4            |
 // | file_size  | 4            |
-// | sha1       | 20           |
+// | hash       | 20 (32)      |  // 32 bytes in the sha256 repo
 // | flags      | 2            |
 // | path       | N            |
 // | path       |              |
@@ -105,7 +56,7 @@ fn read_be(r: &mut BufReader<File>) -> anyhow::Result<u32> {
 // if the total size of each entry is not multiple of 8 the needs to add the padding
 //padding = (8 - (entry_size_raw % 8)) % 8
 // path_length = 8
-// raw_size = 62 + 9 = 71
+// raw_size = 62 + 9 = 71 (74 + 9 = 83 with the sha256 hash)
 // 71 % 8 = 7
 // padding = 1
 // padding is \0 null bytes
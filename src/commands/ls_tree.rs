@@ -1,59 +1,208 @@
-use std::io::Read;
-use std::io::prelude::*;
+use std::io::Write;
+use std::path::Path;
 
 use anyhow::Context;
 
-use crate::objects::{self, Kind};
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, CompositeDb, ObjectDatabase};
+use crate::quote;
+use crate::revision;
+use crate::tree::{Tree, TreeEntry};
 
 // NOTE: it's use to list all the files and directories in the hash tree object
 // git ls-tree --name-only tree_hash
-pub(crate) fn invoke(name_only: bool, tree_object: String) -> anyhow::Result<()> {
-    // print!("{}, {:?} ", name_only, tree_object);
-    let mut object = objects::Object::read(&tree_object)?;
-    match object.kind {
-        Kind::Tree => {
-            let mut read_bytes: u64 = 0;
-            let mut mode = Vec::new();
-            let mut file_name = Vec::new();
-            let mut hashbuf = [0; 20];
-            // let stdout = std::io::stdout();
-            // let mut stdout = stdout.lock();
-            // std::io::copy(&mut object.reader, &mut stdout)?;
-            while object.expected_size > read_bytes {
-                mode.clear();
-                file_name.clear();
-                read_bytes += object.reader.read_until(b' ', &mut mode)? as u64;
-                read_bytes += object.reader.read_until(0, &mut file_name)? as u64;
-
-                if let Some(&b' ') = mode.last() {
-                    mode.pop();
-                }
-                if let Some(&0) = file_name.last() {
-                    file_name.pop();
+// tree-ish can be anything that lead to a tree: tree hash, commit, tag, HEAD~2, main:src
+// 100644 blob 3b18e512dba79e4c8300dd08aeb37f8e728b8dad	hello.txt
+// -r          go into the sub trees (only the blobs are printed)
+// -t          print the trees we go into too
+// -d          only the trees (-d -r imply -t)
+// -l          blob size before the name ("-" for trees and submodules)
+// -z          NUL after each entry and names not quoted ("tab\there" otherwise)
+// --abbrev=n  shortest unique hash of at least n chars
+// --format    %(objectmode) %(objecttype) %(objectname) %(objectsize) %(objectsize:padded)
+//             %(path), %% and %xx (hex byte)
+// paths after the tree-ish only list those entries, "dir/" list what is inside dir
+// (plain prefixes, no wildcards, same as git)
+// the type comes from the mode, the objects of the entries are only read for -l
+pub(crate) fn invoke(options: Options, tree_ish: &str, paths: &[String]) -> anyhow::Result<()> {
+    let db = odb::open()?;
+    let id = revision::resolve(&db, Path::new(".git"), tree_ish)?;
+    let tree = revision::peel(&db, id, Kind::Tree)
+        .with_context(|| format!("not a tree object: {tree_ish}"))?;
+    let format = match &options.format {
+        Some(format) => format.as_str(),
+        None if options.name_only => "%(path)",
+        None if options.object_only => "%(objectname)",
+        None if options.long => {
+            "%(objectmode) %(objecttype) %(objectname) %(objectsize:padded)%x09%(path)"
+        }
+        None => "%(objectmode) %(objecttype) %(objectname)%x09%(path)",
+    };
+    let abbrev = options
+        .abbrev
+        .map(|min_len| Abbrev::new(&db, min_len))
+        .transpose()?;
+    let paths = paths
+        .iter()
+        .map(|path| match path.trim_start_matches("./") {
+            "." => String::new(),
+            path => path.to_string(),
+        })
+        .collect();
+    let mut lister = Lister {
+        db: &db,
+        // -d -r imply -t, same as git
+        show_trees: options.show_trees || (options.trees_only && options.recursive),
+        options: &options,
+        paths,
+        format,
+        abbrev,
+        out: std::io::stdout().lock(),
+    };
+    lister.list(tree, b"")?;
+    lister.out.flush().context("Failed to write to stdout")
+}
+
+pub(crate) struct Options {
+    pub(crate) recursive: bool,
+    pub(crate) show_trees: bool,
+    pub(crate) trees_only: bool,
+    pub(crate) long: bool,
+    pub(crate) nul_terminated: bool,
+    pub(crate) name_only: bool,
+    pub(crate) object_only: bool,
+    pub(crate) abbrev: Option<usize>,
+    pub(crate) format: Option<String>,
+}
+
+struct Lister<'a, W> {
+    db: &'a CompositeDb,
+    options: &'a Options,
+    show_trees: bool,
+    paths: Vec<String>,
+    format: &'a str,
+    abbrev: Option<Abbrev>,
+    out: W,
+}
+
+impl<W: Write> Lister<'_, W> {
+    fn list(&mut self, tree: ObjectId, base: &[u8]) -> anyhow::Result<()> {
+        for entry in Tree::read(self.db, &tree.to_string())?.entries {
+            let mut path = base.to_vec();
+            path.extend(&entry.name);
+            let (matched, leads) = self.match_paths(&path);
+            // a blob is never on the way to a path
+            if !(matched || leads && entry.is_tree()) {
+                continue;
+            }
+            if entry.is_tree() {
+                if self.options.recursive || leads {
+                    if self.show_trees {
+                        self.show(&entry, &path)?;
+                    }
+                    path.push(b'/');
+                    self.list(entry.hash, &path)?;
+                    continue;
                 }
-                object
-                    .reader
-                    .read_exact(&mut hashbuf)
-                    .context("Failed to read the hash")?;
-                read_bytes += 20;
-
-                if !name_only {
-                    let hash = hex::encode(&hashbuf);
-                    let object =
-                        objects::Object::read(&hash).context("Failed to identify the hex")?;
-                    print!(
-                        "{:0>6} {} ",
-                        format!("{}", std::str::from_utf8(&mode)?),
-                        object.kind,
-                    );
-                    print!("{}", hash);
-                    print!("    ");
+            } else if self.options.trees_only {
+                continue;
+            }
+            self.show(&entry, &path)?;
+        }
+        Ok(())
+    }
+
+    // matched: the path is one of the paths or inside one of them
+    // leads: one of the paths is inside this one (so it's a tree we have to go into)
+    fn match_paths(&self, path: &[u8]) -> (bool, bool) {
+        if self.paths.is_empty() {
+            return (true, false);
+        }
+        let mut matched = false;
+        let mut leads = false;
+        for spec in &self.paths {
+            let spec = spec.as_bytes();
+            let trimmed = spec.strip_suffix(b"/").unwrap_or(spec);
+            matched |= trimmed.is_empty()
+                || path == trimmed
+                || (path.starts_with(trimmed) && path[trimmed.len()] == b'/');
+            leads |= spec.len() > path.len() && spec.starts_with(path) && spec[path.len()] == b'/';
+        }
+        (matched, leads)
+    }
+
+    fn show(&mut self, entry: &TreeEntry, path: &[u8]) -> anyhow::Result<()> {
+        let mut line = Vec::new();
+        let mut rest = self.format;
+        while let Some(percent) = rest.find('%') {
+            line.extend(&rest.as_bytes()[..percent]);
+            rest = &rest[percent + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                line.push(b'%');
+                rest = after;
+            } else if let Some(byte) = rest
+                .strip_prefix('x')
+                .and_then(|hex| hex.get(..2))
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                line.push(byte);
+                rest = &rest[3..];
+            } else if let Some((atom, after)) =
+                rest.strip_prefix('(').and_then(|atom| atom.split_once(')'))
+            {
+                self.expand(&mut line, atom, entry, path)?;
+                rest = after;
+            } else {
+                anyhow::bail!("bad ls-tree format: element '{rest}' does not start with '('");
+            }
+        }
+        line.extend(rest.as_bytes());
+        let terminator = if self.options.nul_terminated {
+            0
+        } else {
+            b'\n'
+        };
+        line.push(terminator);
+        self.out
+            .write_all(&line)
+            .context("Failed to write to stdout")
+    }
+
+    fn expand(
+        &self,
+        line: &mut Vec<u8>,
+        atom: &str,
+        entry: &TreeEntry,
+        path: &[u8],
+    ) -> anyhow::Result<()> {
+        match atom {
+            "objectmode" => line.extend(format!("{:06o}", entry.mode).as_bytes()),
+            "objecttype" => line.extend(entry.kind().to_string().as_bytes()),
+            "objectname" => {
+                let hash = entry.hash.to_string();
+                let hash = match &self.abbrev {
+                    Some(abbrev) => abbrev.abbrev(&hash),
+                    None => &hash,
+                };
+                line.extend(hash.as_bytes());
+            }
+            "objectsize" | "objectsize:padded" => {
+                let size = match entry.kind() {
+                    Kind::Blob => self.db.read_header(&entry.hash.to_string())?.1.to_string(),
+                    _ => "-".to_string(),
+                };
+                if atom == "objectsize" {
+                    line.extend(size.as_bytes());
+                } else {
+                    line.extend(format!("{size:>7}").as_bytes());
                 }
-                println!("{}", std::str::from_utf8(&file_name)?);
             }
+            "path" if self.options.nul_terminated => line.extend(path),
+            "path" => line.extend(quote::quote_c(path).as_bytes()),
+            _ => anyhow::bail!("bad ls-tree format: %({atom})"),
         }
-        _ => anyhow::bail!("we do not yet know how to print a '{:?}'", &object.kind),
+        Ok(())
     }
-    Ok(())
 }
 // TEST: https://app.codecrafters.io/courses/git/stages/kp1
@@ -0,0 +1,299 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::ancestry::Ancestry;
+use crate::commands;
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::diff;
+use crate::hash::ObjectId;
+use crate::hooks::Hooks;
+use crate::index::{Index, IndexEntry};
+use crate::merge::{self, ConflictStyle};
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev};
+use crate::refs::{self, Head};
+use crate::revision;
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: merge a branch (or any commit) into the current one
+// cargo run -- merge feature
+// - already in the history of HEAD      -> "Already up to date."
+// - HEAD is in its history               -> fast-forward, HEAD just move (no commit)
+// - otherwise the three-way merge (crate::merge) and a merge commit with both parents
+// conflicts stop before the commit: markers in the files, stages 1-3 in the index,
+// .git/MERGE_HEAD (the other commit) and .git/MERGE_MSG (the message to use),
+// fix the files, update-index them and run commit (or merge --continue)
+// --ff-only        refuse anything else than a fast-forward
+// --no-ff          always create the merge commit
+// --no-commit      stop before the commit like there was a conflict
+// --abort          go back to HEAD, the files not touched by the merge keep their changes
+// merge.conflictStyle = merge | diff3 | zdiff3 is how the conflicts are written
+pub(crate) fn invoke(options: Options, commit: Option<&str>) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    if options.abort {
+        return abort(git_dir);
+    }
+    if options.continue_merge {
+        anyhow::ensure!(
+            git_dir.join("MERGE_HEAD").exists(),
+            "There is no merge in progress (MERGE_HEAD missing)."
+        );
+        return commands::commit::invoke(commands::commit::Options::default());
+    }
+    anyhow::ensure!(
+        !git_dir.join("MERGE_HEAD").exists(),
+        "You have not concluded your merge (MERGE_HEAD exists).\n\
+         Please, commit your changes before you merge."
+    );
+    let Some(name) = commit else {
+        anyhow::bail!("No commit specified to merge.");
+    };
+
+    let db = odb::open()?;
+    let config = Config::load()?;
+    let theirs = revision::resolve(&db, git_dir, name)
+        .and_then(|id| revision::peel(&db, id, Kind::Commit))
+        .with_context(|| format!("{name} - not something we can merge"))?;
+    let head = refs::read_head(git_dir)?;
+    let head_commit = match &head {
+        Head::Symbolic(name) => refs::resolve(git_dir, name)?,
+        Head::Detached(hash) => Some(hash.clone()),
+    };
+    let head_commit = head_commit
+        .map(|hash| ObjectId::from_hex(&hash))
+        .transpose()?;
+    let hooks = Hooks::open(git_dir)?;
+
+    // NOTE: nothing to merge with on an unborn branch, it just take the other commit
+    let Some(ours) = head_commit else {
+        let tree = Commit::read(&db, &theirs.to_string())?.tree;
+        worktree::update(
+            &db,
+            &Files::new(),
+            &entries(&tree::files(&db, tree)?),
+            &BTreeMap::new(),
+            "merge",
+        )?;
+        move_head(
+            git_dir,
+            &head,
+            &theirs,
+            &format!("merge {name}: Fast-forward"),
+        )?;
+        return Ok(());
+    };
+
+    let bases = Ancestry::new(&db)?.merge_bases(ours, theirs)?;
+    if bases.contains(&theirs) {
+        println!("Already up to date.");
+        return Ok(());
+    }
+    let head_files = tree::files(&db, Commit::read(&db, &ours.to_string())?.tree)?;
+    if bases == [ours] && !options.no_ff {
+        let abbrev = Abbrev::new(&db, 7)?;
+        let (ours_hex, theirs_hex) = (ours.to_string(), theirs.to_string());
+        println!(
+            "Updating {}..{}",
+            abbrev.abbrev(&ours_hex),
+            abbrev.abbrev(&theirs_hex)
+        );
+        let theirs_files = tree::files(&db, Commit::read(&db, &theirs_hex)?.tree)?;
+        worktree::update(
+            &db,
+            &head_files,
+            &entries(&theirs_files),
+            &BTreeMap::new(),
+            "merge",
+        )?;
+        println!("Fast-forward");
+        fs::write(git_dir.join("ORIG_HEAD"), format!("{ours}\n"))
+            .context("Failed to write ORIG_HEAD")?;
+        move_head(
+            git_dir,
+            &head,
+            &theirs,
+            &format!("merge {name}: Fast-forward"),
+        )?;
+        print!("{}", diff::stat(&db, &head_files, &theirs_files)?);
+        post_merge(&hooks);
+        return Ok(());
+    }
+    anyhow::ensure!(!options.ff_only, "Not possible to fast-forward, aborting.");
+    anyhow::ensure!(
+        !bases.is_empty() || options.allow_unrelated_histories,
+        "refusing to merge unrelated histories"
+    );
+
+    // NOTE: the merge needs an index with nothing staged, what is staged would be lost
+    let index = Index::read(&git_dir.join("index"))?;
+    let index_files = index.files();
+    let mut staged: Vec<&String> = index_files.keys().chain(head_files.keys()).collect();
+    staged.sort_unstable();
+    staged.dedup();
+    staged
+        .retain(|path| index_files.get(*path) != head_files.get(*path) || index.is_unmerged(path));
+    let staged: Vec<&str> = staged.into_iter().map(String::as_str).collect();
+    anyhow::ensure!(
+        staged.is_empty(),
+        "Your local changes to the following files would be overwritten by merge:\n  {}\n\
+         Merge with strategy ort failed.",
+        staged.join("\n  ")
+    );
+
+    let style = match config.get("merge.conflictStyle") {
+        Some(style) => style.parse()?,
+        None => ConflictStyle::default(),
+    };
+    let result = merge::merge_commits(&db, ours, theirs, "HEAD", name, style)?;
+    worktree::update(&db, &head_files, &result.entries, &result.contents, "merge")?;
+    for message in &result.messages {
+        println!("{message}");
+    }
+    fs::write(git_dir.join("ORIG_HEAD"), format!("{ours}\n"))
+        .context("Failed to write ORIG_HEAD")?;
+
+    let mut message = if options.messages.is_empty() {
+        default_message(git_dir, &head, name)?
+    } else {
+        let paragraphs: Vec<String> = options
+            .messages
+            .iter()
+            .map(|paragraph| format!("{}\n", paragraph.trim_end_matches('\n')))
+            .collect();
+        paragraphs.join("\n")
+    };
+    if !result.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in &result.conflicts {
+            message.push_str(&format!("#\t{path}\n"));
+        }
+    }
+    fs::write(git_dir.join("MERGE_HEAD"), format!("{theirs}\n"))
+        .context("Failed to write MERGE_HEAD")?;
+    fs::write(git_dir.join("MERGE_MSG"), &message).context("Failed to write MERGE_MSG")?;
+    let mode = if options.no_ff { "no-ff" } else { "" };
+    fs::write(git_dir.join("MERGE_MODE"), mode).context("Failed to write MERGE_MODE")?;
+    anyhow::ensure!(
+        result.is_clean(),
+        "Automatic merge failed; fix conflicts and then commit the result."
+    );
+    if options.no_commit {
+        println!("Automatic merge went well; stopped before committing as requested");
+        return Ok(());
+    }
+
+    // the editor only when someone is there to use it, same as git
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    let edit = options.edit || (interactive && options.messages.is_empty() && !options.no_edit);
+    commands::commit::invoke(commands::commit::Options {
+        no_edit: !edit,
+        no_verify: options.no_verify,
+        quiet: true,
+        reflog_action: Some(format!("merge {name}")),
+        ..Default::default()
+    })?;
+    println!("Merge made by the 'ort' strategy.");
+    let merged_files = tree::files(&db, result.tree(&db)?)?;
+    print!("{}", diff::stat(&db, &head_files, &merged_files)?);
+    post_merge(&hooks);
+    Ok(())
+}
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) ff_only: bool,
+    pub(crate) no_ff: bool,
+    pub(crate) no_commit: bool,
+    pub(crate) messages: Vec<String>,
+    pub(crate) edit: bool,
+    pub(crate) no_edit: bool,
+    pub(crate) no_verify: bool,
+    pub(crate) allow_unrelated_histories: bool,
+    pub(crate) abort: bool,
+    pub(crate) continue_merge: bool,
+}
+
+// NOTE: same as git reset --merge, the index and the files the merge changed go back to
+// HEAD, the other files keep what was not committed
+fn abort(git_dir: &Path) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        git_dir.join("MERGE_HEAD").exists(),
+        "There is no merge to abort (MERGE_HEAD missing)."
+    );
+    let db = odb::open()?;
+    let head = revision::resolve(&db, git_dir, "HEAD")?;
+    let head_files = tree::files(&db, Commit::read(&db, &head.to_string())?.tree)?;
+    let index = Index::read(&git_dir.join("index"))?;
+    worktree::update(
+        &db,
+        &index.files(),
+        &entries(&head_files),
+        &BTreeMap::new(),
+        "merge",
+    )?;
+    for file in ["MERGE_HEAD", "MERGE_MSG", "MERGE_MODE"] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+    Ok(())
+}
+
+// NOTE: the merge is already done when the hook runs, a hook that can't run is only a warning
+fn post_merge(hooks: &Hooks) {
+    if let Err(e) = hooks.run("post-merge", &["0"], &[]) {
+        eprintln!("warning: post-merge hook failed: {e:#}");
+    }
+}
+
+pub(crate) fn entries(files: &Files) -> Vec<IndexEntry> {
+    files
+        .iter()
+        .map(|(path, &(mode, hash))| IndexEntry::new(path, mode, hash, 0))
+        .collect()
+}
+
+// the branch HEAD is on moves (and both reflogs get the line), a detached HEAD moves itself
+pub(crate) fn move_head(
+    git_dir: &Path,
+    head: &Head,
+    commit: &ObjectId,
+    message: &str,
+) -> anyhow::Result<()> {
+    match head {
+        Head::Symbolic(name) => refs::update(git_dir, name, &commit.to_string(), message),
+        Head::Detached(_) => refs::update(git_dir, "HEAD", &commit.to_string(), message),
+    }
+}
+
+// "Merge branch 'feature'", "Merge tag 'v1'", "Merge remote-tracking branch 'origin/main'",
+// "Merge commit '1a2b3c'", with " into <branch>" when it's not main or master
+fn default_message(git_dir: &Path, head: &Head, name: &str) -> anyhow::Result<String> {
+    let mut message = match revision::dwim_ref(git_dir, name)? {
+        Some(full) if full.starts_with("refs/heads/") => {
+            format!("Merge branch '{}'", &full["refs/heads/".len()..])
+        }
+        Some(full) if full.starts_with("refs/tags/") => {
+            format!("Merge tag '{}'", &full["refs/tags/".len()..])
+        }
+        Some(full) if full.starts_with("refs/remotes/") => {
+            format!(
+                "Merge remote-tracking branch '{}'",
+                &full["refs/remotes/".len()..]
+            )
+        }
+        _ => format!("Merge commit '{name}'"),
+    };
+    if let Head::Symbolic(current) = head {
+        let branch = current.strip_prefix("refs/heads/").unwrap_or(current);
+        if branch != "main" && branch != "master" {
+            message.push_str(&format!(" into {branch}"));
+        }
+    }
+    message.push('\n');
+    Ok(message)
+}
@@ -0,0 +1,115 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::ancestry::Ancestry;
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, CompositeDb};
+use crate::refs;
+use crate::revision;
+
+// NOTE: the best common ancestor of commits, what a merge would use as the base
+// cargo run -- merge-base main feature
+// A B C...       the merge base of A and a (virtual) merge of B, C...
+// --all          every best common ancestor (criss-cross merges have more than one)
+// --octopus      the common ancestor of all the commits, for a merge of all of them
+// --is-ancestor A B     no output, exit 0 when A is in the history of B, 1 otherwise
+// --fork-point ref [commit]   where commit (HEAD) forked from ref, using the reflog of ref
+//                so it's still found after ref was rebased or reset
+// nothing found is exit code 1 without output, same as git
+pub(crate) fn invoke(options: Options, commits: &[String]) -> anyhow::Result<()> {
+    let db = odb::open()?;
+    let git_dir = Path::new(".git");
+    let mut ancestry = Ancestry::new(&db)?;
+    if options.is_ancestor {
+        anyhow::ensure!(
+            commits.len() == 2,
+            "--is-ancestor takes exactly two commits"
+        );
+        let ancestor = commit(&db, git_dir, &commits[0])?;
+        let descendant = commit(&db, git_dir, &commits[1])?;
+        if !ancestry.is_ancestor(ancestor, descendant)? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let bases = if options.fork_point {
+        anyhow::ensure!(
+            (1..=2).contains(&commits.len()),
+            "--fork-point takes a ref and at most one commit"
+        );
+        let Some(full) = revision::dwim_ref(git_dir, &commits[0])? else {
+            anyhow::bail!("No such ref: '{}'", commits[0]);
+        };
+        let derived = commit(&db, git_dir, commits.get(1).map_or("HEAD", String::as_str))?;
+        let history = ref_history(&db, git_dir, &full)?;
+        ancestry
+            .fork_point(derived, &history)?
+            .into_iter()
+            .collect()
+    } else if options.octopus {
+        let commits = commits
+            .iter()
+            .map(|name| commit(&db, git_dir, name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ancestry.octopus(&commits)?
+    } else {
+        anyhow::ensure!(commits.len() >= 2, "merge-base needs at least two commits");
+        let one = commit(&db, git_dir, &commits[0])?;
+        let twos = commits[1..]
+            .iter()
+            .map(|name| commit(&db, git_dir, name))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        ancestry.merge_bases_many(one, &twos)?
+    };
+    if bases.is_empty() {
+        std::process::exit(1);
+    }
+    let shown = if options.all { bases.len() } else { 1 };
+    for base in &bases[..shown] {
+        println!("{base}");
+    }
+    Ok(())
+}
+
+pub(crate) struct Options {
+    pub(crate) all: bool,
+    pub(crate) octopus: bool,
+    pub(crate) is_ancestor: bool,
+    pub(crate) fork_point: bool,
+}
+
+fn commit(db: &CompositeDb, git_dir: &Path, name: &str) -> anyhow::Result<ObjectId> {
+    revision::resolve(db, git_dir, name)
+        .and_then(|id| revision::peel(db, id, Kind::Commit))
+        .with_context(|| format!("Not a valid commit name {name}"))
+}
+
+// every commit the ref pointed to, the current value when there is no reflog
+// entries that are gone (pruned) or are not commits are skipped like git
+fn ref_history(db: &CompositeDb, git_dir: &Path, name: &str) -> anyhow::Result<Vec<ObjectId>> {
+    let mut hashes = Vec::new();
+    for (i, entry) in refs::reflog(git_dir, name)?.into_iter().enumerate() {
+        if i == 0 {
+            hashes.push(entry.old);
+        }
+        hashes.push(entry.new);
+    }
+    if hashes.is_empty() {
+        hashes.extend(refs::resolve(git_dir, name)?);
+    }
+    let mut history = Vec::new();
+    for hash in hashes {
+        let Ok(id) = ObjectId::from_hex(&hash) else {
+            continue;
+        };
+        let Ok(id) = revision::peel(db, id, Kind::Commit) else {
+            continue;
+        };
+        if !history.contains(&id) {
+            history.push(id);
+        }
+    }
+    Ok(history)
+}
@@ -0,0 +1,106 @@
+use std::io::{BufRead, Read};
+
+use anyhow::Context;
+
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, CompositeDb, ObjectDatabase};
+use crate::quote;
+use crate::tree::{self, TreeBuilder};
+
+// NOTE: build a tree object from ls-tree lines on stdin, the opposite of ls-tree
+// <mode> SP <type> SP <hash> TAB <name>
+// 100644 blob 3b18e512dba79e4c8300dd08aeb37f8e728b8dad	hello.txt
+// lines can be in any order, the tree is sorted the git way
+// -z          lines end with NUL instead of LF (and names are never quoted)
+// --missing   don't check that the objects exist (the type still has to match the mode)
+// --batch     many trees, a blank line end each one, one hash printed per tree
+pub(crate) fn invoke(nul_terminated: bool, missing: bool, batch: bool) -> anyhow::Result<()> {
+    let db = odb::open()?;
+    let terminator = if nul_terminated { 0 } else { b'\n' };
+    let mut stdin = std::io::stdin().lock();
+    let mut end_of_input = false;
+    while !end_of_input {
+        let mut builder = TreeBuilder::default();
+        loop {
+            let mut line = Vec::new();
+            if stdin
+                .by_ref()
+                .read_until(terminator, &mut line)
+                .context("Failed to read stdin")?
+                == 0
+            {
+                end_of_input = true;
+                break;
+            }
+            if line.last() == Some(&terminator) {
+                line.pop();
+            }
+            if line.is_empty() {
+                anyhow::ensure!(
+                    batch,
+                    "input format error: (blank line only valid in batch mode)"
+                );
+                break;
+            }
+            let line = String::from_utf8(line).context("mktree input is not UTF-8")?;
+            add_line(&db, &mut builder, &line, nul_terminated, missing)?;
+        }
+        // blank line at the very end of a batch is not one more empty tree
+        if batch && end_of_input && builder.is_empty() {
+            break;
+        }
+        let hash = builder
+            .build()
+            .object()
+            .write_to(&db)
+            .context("Failed to write the tree object")?;
+        println!("{hash}");
+    }
+    Ok(())
+}
+
+fn add_line(
+    db: &CompositeDb,
+    builder: &mut TreeBuilder,
+    line: &str,
+    nul_terminated: bool,
+    missing: bool,
+) -> anyhow::Result<()> {
+    let format_error = || format!("input format error: {line}");
+    let (info, name) = line.split_once('\t').with_context(format_error)?;
+    let mut fields = info.splitn(3, ' ');
+    let (Some(mode), Some(kind), Some(hash)) = (fields.next(), fields.next(), fields.next()) else {
+        anyhow::bail!(format_error());
+    };
+    let mode = u32::from_str_radix(mode, 8).with_context(format_error)?;
+    let hash = ObjectId::from_hex(hash).with_context(format_error)?;
+    let name = if nul_terminated {
+        name.as_bytes().to_vec()
+    } else {
+        quote::unquote_c(name)?
+    };
+    let display_name = String::from_utf8_lossy(&name);
+    anyhow::ensure!(!name.contains(&b'/'), "path {display_name} contains slash");
+
+    let mode_kind = tree::kind_of_mode(mode);
+    let kind: Kind = kind.parse().with_context(format_error)?;
+    anyhow::ensure!(
+        kind == mode_kind,
+        "entry '{display_name}' object type ({kind}) doesn't match mode type ({mode_kind})"
+    );
+    // NOTE: submodule commit live in another repository, nothing to check here
+    if !missing && mode_kind != Kind::Commit {
+        let hex = hash.to_string();
+        anyhow::ensure!(
+            db.contains(&hex),
+            "entry '{display_name}' object {hex} is unavailable"
+        );
+        let (actual, _) = db.read_header(&hex)?;
+        anyhow::ensure!(
+            actual == kind,
+            "entry '{display_name}' object {hex} is a {actual} but specified type was ({kind})"
+        );
+    }
+    builder.insert(mode, name, hash)
+}
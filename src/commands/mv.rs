@@ -0,0 +1,149 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::index::{Index, IndexEntry};
+
+// NOTE: rename tracked files (or dirs) and their index entries
+// cargo run -- mv old new
+// cargo run -- mv a b dir          move them into the existing dir
+// every move is checked first, nothing is renamed when one of them is refused
+// (-k skips the refused ones instead), the index is written once at the end
+// -f          overwrite the destination when it exists
+// -n          only show what would be done
+// -v          show the renames
+pub(crate) fn invoke(options: Options, paths: &[String]) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let [sources @ .., destination] = paths else {
+        anyhow::bail!("usage: mv [<options>] <source>... <destination>");
+    };
+    anyhow::ensure!(
+        !sources.is_empty(),
+        "usage: mv [<options>] <source>... <destination>"
+    );
+    let index_path = git_dir.join("index");
+    let (mut index, lock) = Index::lock(&index_path)?;
+
+    let destination = destination.trim_end_matches('/');
+    let into_dir = Path::new(destination).is_dir();
+    anyhow::ensure!(
+        into_dir || sources.len() == 1,
+        "destination '{destination}' is not a directory"
+    );
+
+    // (source, destination) of every move that is allowed
+    let mut moves: Vec<(String, String)> = Vec::new();
+    for source in sources {
+        let source = source.trim_end_matches('/');
+        let target = if into_dir {
+            let name = Path::new(source).file_name().with_context(|| {
+                format!("bad source, source={source}, destination={destination}")
+            })?;
+            format!("{destination}/{}", name.to_string_lossy())
+        } else {
+            destination.to_string()
+        };
+        if options.dry_run {
+            println!("Checking rename of '{source}' to '{target}'");
+        }
+        match check(&index, source, &target, &moves, options.force) {
+            Ok(()) => moves.push((source.to_string(), target)),
+            Err(_) if options.skip_errors => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    for (source, target) in &moves {
+        if options.verbose || options.dry_run {
+            println!("Renaming {source} to {target}");
+        }
+        if options.dry_run {
+            continue;
+        }
+        if options.force && fs::symlink_metadata(target).is_ok_and(|m| !m.is_dir()) {
+            fs::remove_file(target).with_context(|| format!("Failed to remove {target}"))?;
+        }
+        fs::rename(source, target).with_context(|| format!("renaming '{source}' failed"))?;
+        rename_entries(&mut index, source, target)?;
+    }
+    if !options.dry_run {
+        index.write_locked(lock)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) force: bool,
+    pub(crate) skip_errors: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) verbose: bool,
+}
+
+// the same errors (and words) as git mv
+fn check(
+    index: &Index,
+    source: &str,
+    target: &str,
+    moves: &[(String, String)],
+    force: bool,
+) -> anyhow::Result<()> {
+    let names = format!("source={source}, destination={target}");
+    let metadata = fs::symlink_metadata(source);
+    anyhow::ensure!(metadata.is_ok(), "bad source, {names}");
+    let under = format!("{source}/");
+    let tracked = if metadata.is_ok_and(|m| m.is_dir()) {
+        anyhow::ensure!(
+            target != source && !target.starts_with(&under),
+            "can not move directory into itself, {names}"
+        );
+        index
+            .entries
+            .iter()
+            .any(|entry| entry.path.starts_with(&under))
+    } else {
+        index.entries.iter().any(|entry| entry.path == source)
+    };
+    anyhow::ensure!(tracked, "not under version control, {names}");
+    anyhow::ensure!(!index.is_unmerged(source), "conflicted, {names}");
+    if let Ok(existing) = fs::symlink_metadata(target) {
+        anyhow::ensure!(force && !existing.is_dir(), "destination exists, {names}");
+    }
+    if let Some(parent) = Path::new(target).parent()
+        && !parent.as_os_str().is_empty()
+    {
+        anyhow::ensure!(
+            parent.is_dir(),
+            "destination directory does not exist, {names}"
+        );
+    }
+    anyhow::ensure!(
+        !moves.iter().any(|(_, other)| other == target),
+        "multiple sources for the same target, {names}"
+    );
+    Ok(())
+}
+
+// NOTE: the entry (or every entry under the dir) moves to the new path with the stat of
+// the renamed file, a tracked file that was overwritten loses its entry
+fn rename_entries(index: &mut Index, source: &str, target: &str) -> anyhow::Result<()> {
+    let under = format!("{source}/");
+    let moved: Vec<IndexEntry> = index
+        .entries
+        .iter()
+        .filter(|entry| entry.path == source || entry.path.starts_with(&under))
+        .cloned()
+        .collect();
+    index.remove(target);
+    for entry in moved {
+        index.remove(&entry.path);
+        let path = format!("{target}{}", &entry.path[source.len()..]);
+        let metadata = fs::symlink_metadata(&path)
+            .with_context(|| format!("Failed to read stat for :{path}"))?;
+        let mut renamed = IndexEntry::from_metadata(&path, &metadata, entry.hash, 0);
+        renamed.mode = entry.mode;
+        index.add(renamed);
+    }
+    Ok(())
+}
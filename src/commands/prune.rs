@@ -0,0 +1,156 @@
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use anyhow::Context;
+use chrono::{Local, NaiveDate, NaiveDateTime, TimeZone};
+
+use crate::commit_graph::CommitGraph;
+use crate::odb::{self, LooseDb, ObjectDatabase};
+use crate::reachable;
+
+// NOTE: remove the loose objects nothing can reach anymore
+// every commit write blobs and trees, amend/reset/etc leave the old ones behind
+// reachable = from HEAD, refs, reflogs and the index (see reachable::roots)
+// only loose objects older than --expire are removed, so an object that was just written
+// by some other command (and is not in a ref yet) survive
+// prune --dry-run                 -> only print "<hash> <kind>" of what would be removed
+// prune --expire 2.weeks.ago      -> keep everything younger than two weeks
+// also remove the tmp_obj_ files a killed hash-object/commit left in .git/objects
+pub(crate) fn invoke(expire: Option<String>, dry_run: bool, verbose: bool) -> anyhow::Result<()> {
+    // without --expire every unreachable object goes, same as git
+    let expire = match expire {
+        Some(expire) => parse_expire(&expire)?,
+        None => Some(SystemTime::now()),
+    };
+    prune(Path::new(".git"), expire, dry_run, verbose)?;
+    Ok(())
+}
+
+// NOTE: expire None is "never", nothing is removed
+pub(crate) fn prune(
+    git_dir: &Path,
+    expire: Option<SystemTime>,
+    dry_run: bool,
+    verbose: bool,
+) -> anyhow::Result<usize> {
+    let Some(expire) = expire else {
+        return Ok(0);
+    };
+    let db = odb::open()?;
+    let graph = CommitGraph::current(db.format())?;
+    let reachable = reachable::mark(&db, graph.as_ref(), reachable::roots(git_dir)?)?;
+
+    let objects_dir = odb::objects_dir();
+    let loose = LooseDb::new(&objects_dir, db.format());
+    let mut pruned = 0;
+    for hash in loose.iter()? {
+        if reachable.contains(&hash) {
+            continue;
+        }
+        let hex = hash.to_string();
+        let path = loose.object_path(&hex)?;
+        if !older_than(&path, expire)? {
+            continue;
+        }
+        if dry_run || verbose {
+            match loose.read_header(&hex) {
+                Ok((kind, _)) => println!("{hex} {kind}"),
+                Err(_) => println!("{hex} unknown"),
+            }
+        }
+        pruned += 1;
+        if dry_run {
+            continue;
+        }
+        fs::remove_file(&path).with_context(|| format!("Failed to remove {}", path.display()))?;
+        // fanout dir is left empty most of the time, git remove it too (fail if not empty)
+        if let Some(parent) = path.parent() {
+            let _ = fs::remove_dir(parent);
+        }
+    }
+
+    for entry in fs::read_dir(&objects_dir)
+        .with_context(|| format!("Failed to read {}", objects_dir.display()))?
+    {
+        let entry = entry?;
+        if !entry.file_name().to_string_lossy().starts_with("tmp_") {
+            continue;
+        }
+        let path = entry.path();
+        if !entry.file_type()?.is_file() || !older_than(&path, expire)? {
+            continue;
+        }
+        println!("Removing stale temporary file {}", path.display());
+        if !dry_run {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(pruned)
+}
+
+fn older_than(path: &Path, expire: SystemTime) -> anyhow::Result<bool> {
+    let modified = fs::symlink_metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to read stat for :{}", path.display()))?;
+    Ok(modified < expire)
+}
+
+// NOTE: the small part of git approxidate we need for the expire dates
+// now / all           -> everything
+// never / false       -> nothing (None)
+// 2.weeks.ago         -> also "2 weeks ago", second/minute/hour/day/week/month/year(s)
+// 2024-01-31          -> local midnight, "2024-01-31 12:00:00" also work
+// @1700000000         -> unix timestamp
+pub(crate) fn parse_expire(value: &str) -> anyhow::Result<Option<SystemTime>> {
+    let value = value.trim().to_ascii_lowercase();
+    match value.as_str() {
+        "now" | "all" => return Ok(Some(SystemTime::now())),
+        "never" | "false" => return Ok(None),
+        _ => {}
+    }
+    if let Some(seconds) = value.strip_prefix('@') {
+        let seconds = seconds
+            .parse::<u64>()
+            .with_context(|| format!("invalid expire date '{value}'"))?;
+        return Ok(Some(SystemTime::UNIX_EPOCH + Duration::from_secs(seconds)));
+    }
+    if let Some(relative) = value
+        .strip_suffix(".ago")
+        .or_else(|| value.strip_suffix(" ago"))
+    {
+        let Some((count, unit)) = relative.split_once(['.', ' ']) else {
+            anyhow::bail!("invalid expire date '{value}'");
+        };
+        let count = count
+            .parse::<u64>()
+            .with_context(|| format!("invalid expire date '{value}'"))?;
+        let unit = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            "year" => 365 * 24 * 60 * 60,
+            _ => anyhow::bail!("invalid expire date '{value}'"),
+        };
+        let ago = Duration::from_secs(count.saturating_mul(unit));
+        return Ok(Some(
+            SystemTime::now()
+                .checked_sub(ago)
+                .unwrap_or(SystemTime::UNIX_EPOCH),
+        ));
+    }
+    let date = NaiveDateTime::parse_from_str(&value, "%Y-%m-%d %H:%M:%S").or_else(|_| {
+        NaiveDate::parse_from_str(&value, "%Y-%m-%d").map(|date| date.and_time(Default::default()))
+    });
+    let Ok(date) = date else {
+        anyhow::bail!("invalid expire date '{value}'");
+    };
+    let Some(date) = Local.from_local_datetime(&date).earliest() else {
+        anyhow::bail!("invalid expire date '{value}'");
+    };
+    Ok(Some(date.into()))
+}
@@ -0,0 +1,455 @@
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::hooks::Hooks;
+use crate::odb::{self, Abbrev, ObjectDatabase};
+use crate::refs::{self, Head};
+use crate::remote::{self, Refspec, Remote};
+use crate::revision;
+
+// NOTE: update refs of another repository on the disk with ours (and copy the objects they need)
+// cargo run -- push                         the current branch to the same name on its remote
+// cargo run -- push origin main             main to main
+// cargo run -- push ../bare.git HEAD~1:x    any commit to refs/heads/x of ../bare.git
+// cargo run -- push origin :old             delete old
+// a ref only moves forward:
+// -f (or "+" in the refspec)       move it anyway
+// --force-with-lease               move it anyway, but only when it is still where our
+//                                  remote-tracking ref says it is (nobody pushed meanwhile)
+// --force-with-lease=main          the same, only for main
+// --force-with-lease=main:<rev>    only when main of the remote is <rev>
+// the checked out branch of a non-bare remote is refused (receive.denyCurrentBranch)
+// the remote-tracking refs of the pushed refs follow
+// the pre-push hook sees every update first and can stop the push (not with --no-verify)
+pub(crate) fn invoke(
+    remote: Option<&str>,
+    refspecs: &[String],
+    force: bool,
+    force_with_lease: Option<&str>,
+    no_verify: bool,
+) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let config = Config::load()?;
+    let db = odb::open()?;
+    let name = match remote {
+        Some(name) => name.to_string(),
+        None => remote::default_name(git_dir, &config)?,
+    };
+    let remote = Remote::open(&config, &name)?;
+    let remote_db = odb::open_at(&remote.git_dir.join("objects"))?;
+
+    let mut pushed: Vec<Pushed> = Vec::new();
+    if refspecs.is_empty() {
+        pushed.push(current_branch(git_dir, &config, &remote)?);
+    }
+    for refspec in refspecs {
+        let refspec = Refspec::parse(refspec)?;
+        if refspec.is_pattern() {
+            for (src, hash) in refs::list(git_dir, "refs/")? {
+                if let Some(dst) = refspec.map(&src) {
+                    pushed.push(Pushed {
+                        from: remote::short_name(&src).to_string(),
+                        local: src.clone(),
+                        hash: Some(hash),
+                        dst,
+                        force: refspec.force,
+                    });
+                }
+            }
+            continue;
+        }
+        pushed.push(resolve(&db, git_dir, &remote, &refspec)?);
+    }
+    // NOTE: dst becomes a path in the other repository, check it before anything is copied
+    for pushed in &pushed {
+        refs::check_refname_format(&pushed.dst)?;
+    }
+
+    let lease = match force_with_lease {
+        Some(lease) => Some(parse_lease(&db, git_dir, lease)?),
+        None => None,
+    };
+    let remote_head = match refs::read_head(&remote.git_dir)? {
+        Head::Symbolic(head) if !is_bare(&remote.git_dir)? => Some(head),
+        _ => None,
+    };
+    let deny_current = Config::load_from(&remote.git_dir.join("config"))?
+        .get("receive.denyCurrentBranch")
+        .is_none_or(|value| !matches!(value, "ignore" | "warn" | "false"));
+
+    // NOTE: git words the hint for the ref named like our current branch differently
+    let current = match refs::read_head(git_dir)? {
+        Head::Symbolic(head) => Some(head),
+        Head::Detached(_) => None,
+    };
+    let abbrev = Abbrev::new(&db, 7)?;
+    // (flag, summary, from -> to, reason)
+    let mut lines: Vec<(char, String, String, String)> = Vec::new();
+    let mut accepted: Vec<(&Pushed, Option<String>)> = Vec::new();
+    let mut hint = None;
+    let mut failed = false;
+    for pushed in &pushed {
+        let dst = &pushed.dst;
+        let to = remote::short_name(dst);
+        let names = match &pushed.hash {
+            Some(_) => format!("{} -> {to}", pushed.from),
+            None => to.to_string(),
+        };
+        let old = refs::resolve(&remote.git_dir, dst)?;
+        if old == pushed.hash {
+            if old.is_none() {
+                eprintln!("error: unable to delete '{to}': remote ref does not exist");
+                failed = true;
+            }
+            continue;
+        }
+        let reject = |reason: &str| {
+            (
+                '!',
+                "[rejected]".to_string(),
+                names.clone(),
+                reason.to_string(),
+            )
+        };
+        // NOTE: None when the ref has no lease, Some(None) when it must not exist
+        let expected = match &lease {
+            Some(lease) => lease.expected(dst, &remote)?,
+            None => None,
+        };
+        if let Some(expected) = &expected
+            && *expected != old
+        {
+            lines.push(reject(" (stale info)"));
+            continue;
+        }
+        let forced = force || pushed.force || expected.is_some();
+        let line = match (&old, &pushed.hash) {
+            (None, Some(_)) => (
+                '*',
+                remote::new_ref_kind(dst).to_string(),
+                names,
+                String::new(),
+            ),
+            (Some(_), None) => ('-', "[deleted]".to_string(), names, String::new()),
+            (Some(_), Some(_)) if dst.starts_with("refs/tags/") && !forced => {
+                hint.get_or_insert(
+                    "hint: Updates were rejected because the tag already exists in the remote.",
+                );
+                lines.push(reject(" (already exists)"));
+                continue;
+            }
+            (Some(old), Some(_)) if !db.contains(old) && !forced => {
+                hint.get_or_insert(
+                    "hint: Updates were rejected because the remote contains work that you do\n\
+                     hint: not have locally. This is usually caused by another repository pushing\n\
+                     hint: to the same ref. You may want to first integrate the remote changes\n\
+                     hint: (e.g., 'git pull ...') before pushing again.",
+                );
+                lines.push(reject(" (fetch first)"));
+                continue;
+            }
+            (Some(old), Some(new)) if remote::is_fast_forward(&db, old, new)? => (
+                ' ',
+                remote::range(&abbrev, old, new, false),
+                names,
+                String::new(),
+            ),
+            (Some(old), Some(new)) if forced => (
+                '+',
+                remote::range(&abbrev, old, new, true),
+                names,
+                " (forced update)".to_string(),
+            ),
+            (Some(_), Some(_)) => {
+                hint.get_or_insert(if current.as_ref() == Some(dst) {
+                    "hint: Updates were rejected because the tip of your current branch is behind\n\
+                     hint: its remote counterpart. Integrate the remote changes (e.g.\n\
+                     hint: 'git pull ...') before pushing again."
+                } else {
+                    "hint: Updates were rejected because a pushed branch tip is behind its remote\n\
+                     hint: counterpart. Check out this branch and integrate the remote changes\n\
+                     hint: (e.g. 'git pull ...') before pushing again."
+                });
+                lines.push(reject(" (non-fast-forward)"));
+                continue;
+            }
+            (None, None) => unreachable!("a missing ref is never deleted"),
+        };
+        // NOTE: the remote only says no once the update itself is fine
+        if deny_current && remote_head.as_deref() == Some(dst.as_str()) {
+            let reason = match pushed.hash {
+                Some(_) => " (branch is currently checked out)",
+                None => " (deletion of the current branch prohibited)",
+            };
+            eprintln!("remote: error: refusing to update checked out branch: {dst}");
+            let (_, _, names, _) = line;
+            lines.push((
+                '!',
+                "[remote rejected]".to_string(),
+                names,
+                reason.to_string(),
+            ));
+            continue;
+        }
+        lines.push(line);
+        accepted.push((pushed, old));
+    }
+
+    // NOTE: one line per update, "(delete)" and a zero hash on our side for a deletion,
+    // a zero hash on the remote side for a new ref
+    if !no_verify && !accepted.is_empty() {
+        let mut input = String::new();
+        for (pushed, old) in &accepted {
+            let zero = "0".repeat(db.format().hex_len());
+            let (local, new) = match &pushed.hash {
+                Some(new) => (pushed.local.as_str(), new),
+                None => ("(delete)", &zero),
+            };
+            let old = old.as_ref().unwrap_or(&zero);
+            input.push_str(&format!("{local} {new} {} {old}\n", pushed.dst));
+        }
+        let hooks = Hooks::open(git_dir)?;
+        anyhow::ensure!(
+            hooks.run("pre-push", &[&remote.name, &remote.url], input.as_bytes())?,
+            "failed to push some refs to '{}'",
+            remote.url
+        );
+    }
+
+    let wants = accepted
+        .iter()
+        .filter_map(|(pushed, _)| pushed.hash.as_deref())
+        .map(ObjectId::from_hex)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let missing = remote::missing_objects(&db, &remote_db, &wants)?;
+    remote::copy_objects(&db, &remote_db, &missing)?;
+    for (pushed, _) in &accepted {
+        let dst = &pushed.dst;
+        let tracking = remote.tracking_ref(dst);
+        match &pushed.hash {
+            Some(new) => {
+                refs::update(&remote.git_dir, dst, new, "push")?;
+                if let Some(tracking) = tracking {
+                    refs::update(git_dir, &tracking, new, "update by push")?;
+                }
+            }
+            None => {
+                refs::delete(&remote.git_dir, dst)?;
+                if let Some(tracking) = tracking {
+                    refs::delete(git_dir, &tracking)?;
+                }
+            }
+        }
+    }
+
+    if !lines.is_empty() {
+        eprintln!("To {}", remote.url);
+    }
+    for (flag, summary, names, reason) in lines {
+        failed |= flag == '!';
+        eprintln!(" {flag} {summary:<17} {names}{reason}");
+    }
+    if failed {
+        if let Some(hint) = hint {
+            eprintln!("{hint}");
+            eprintln!("hint: See the 'Note about fast-forwards' in 'git push --help' for details.");
+        }
+        anyhow::bail!("failed to push some refs to '{}'", remote.url);
+    }
+    if accepted.is_empty() {
+        eprintln!("Everything up-to-date");
+    }
+    Ok(())
+}
+
+// one ref of the remote and what it becomes
+struct Pushed {
+    // what the user called it, "main" or "HEAD~1"
+    from: String,
+    // the full name of our ref (HEAD stays HEAD), or "HEAD~1" when it is not one, for the
+    // pre-push hook
+    local: String,
+    // None deletes the ref
+    hash: Option<String>,
+    dst: String,
+    force: bool,
+}
+
+// NOTE: push.default = simple, the current branch goes to the branch with the same name,
+// and when it has an upstream on that remote the upstream must have the same name
+fn current_branch(git_dir: &Path, config: &Config, remote: &Remote) -> anyhow::Result<Pushed> {
+    let Head::Symbolic(head) = refs::read_head(git_dir)? else {
+        anyhow::bail!(
+            "You are not currently on a branch.\n\
+             To push the history leading to the current (detached HEAD)\n\
+             state now, use\n\n    git push {} HEAD:<name-of-remote-branch>\n",
+            remote.name
+        );
+    };
+    let branch = remote::short_name(&head);
+    let hash = refs::resolve(git_dir, &head)?
+        .with_context(|| format!("src refspec {branch} does not match any"))?;
+    if config.get(&format!("branch.{branch}.remote")) == Some(remote.name.as_str())
+        && let Some(merge) = config.get(&format!("branch.{branch}.merge"))
+    {
+        anyhow::ensure!(
+            merge == head,
+            "The upstream branch of your current branch does not match\n\
+             the name of your current branch.  To push to the upstream branch\n\
+             on the remote, use\n\n    git push {} HEAD:{}\n\n\
+             To push to the branch of the same name on the remote, use\n\n    git push {} HEAD\n",
+            remote.name,
+            remote::short_name(merge),
+            remote.name
+        );
+    }
+    Ok(Pushed {
+        from: branch.to_string(),
+        local: head.clone(),
+        hash: Some(hash),
+        dst: head,
+        force: false,
+    })
+}
+
+// NOTE: src is one of our refs (then dst can be left out, same name there) or any revision,
+// dst is completed from what the remote has, or the kind of ref src is
+fn resolve(
+    db: &dyn ObjectDatabase,
+    git_dir: &Path,
+    remote: &Remote,
+    refspec: &Refspec,
+) -> anyhow::Result<Pushed> {
+    let src = &refspec.src;
+    if src.is_empty() {
+        let dst = refspec.dst.as_deref().unwrap_or_default();
+        let dst = match revision::dwim_ref(&remote.git_dir, dst)? {
+            Some(existing) => existing,
+            None if dst.starts_with("refs/") => dst.to_string(),
+            None => format!("refs/heads/{dst}"),
+        };
+        return Ok(Pushed {
+            from: String::new(),
+            local: String::new(),
+            hash: None,
+            dst,
+            force: refspec.force,
+        });
+    }
+    let local = revision::dwim_ref(git_dir, src)?;
+    let (full, hash) = match local.clone() {
+        Some(full) => {
+            let hash = refs::resolve(git_dir, &full)?
+                .with_context(|| format!("src refspec {src} does not match any"))?;
+            // HEAD is the branch it points to
+            let full = match (full.as_str(), refs::read_head(git_dir)?) {
+                ("HEAD", Head::Symbolic(head)) => Some(head),
+                ("HEAD", Head::Detached(_)) => None,
+                _ => Some(full),
+            };
+            (full, hash)
+        }
+        None => {
+            let hash = revision::resolve(db, git_dir, src)
+                .map_err(|_| anyhow::anyhow!("src refspec {src} does not match any"))?;
+            (None, hash.to_string())
+        }
+    };
+    let dst = match (refspec.dst.as_deref(), &full) {
+        (None, Some(full)) => full.clone(),
+        (Some(dst), _) if dst.starts_with("refs/") => dst.to_string(),
+        (Some(dst), full) => match revision::dwim_ref(&remote.git_dir, dst)? {
+            Some(existing) => existing,
+            None => match full.as_deref() {
+                Some(full) if full.starts_with("refs/tags/") => format!("refs/tags/{dst}"),
+                Some(full) if full.starts_with("refs/heads/") => format!("refs/heads/{dst}"),
+                _ => anyhow::bail!(
+                    "The destination you provided is not a full refname (i.e.,\n\
+                     starting with \"refs/\"). Unable to push {src}."
+                ),
+            },
+        },
+        (None, None) => anyhow::bail!(
+            "The destination you provided is not a full refname (i.e.,\n\
+             starting with \"refs/\"). Unable to push {src}."
+        ),
+    };
+    Ok(Pushed {
+        from: src.clone(),
+        local: local.unwrap_or_else(|| src.clone()),
+        hash: Some(hash),
+        dst,
+        force: refspec.force,
+    })
+}
+
+// NOTE: "" is every ref, "main" one ref, "main:<rev>" one ref at a given commit
+// (an empty <rev> means the ref must not exist on the remote yet)
+fn parse_lease(db: &dyn ObjectDatabase, git_dir: &Path, lease: &str) -> anyhow::Result<Lease> {
+    if lease.is_empty() {
+        return Ok(Lease {
+            name: None,
+            expected: None,
+        });
+    }
+    let Some((name, expected)) = lease.split_once(':') else {
+        return Ok(Lease {
+            name: Some(lease.to_string()),
+            expected: None,
+        });
+    };
+    let expected = match expected {
+        "" => None,
+        rev => Some(
+            revision::resolve(db, git_dir, rev)
+                .with_context(|| format!("cannot parse expected object name '{rev}'"))?
+                .to_string(),
+        ),
+    };
+    Ok(Lease {
+        name: Some(name.to_string()),
+        expected: Some(expected),
+    })
+}
+
+// --force-with-lease[=<name>[:<expected>]]
+struct Lease {
+    // None is every ref
+    name: Option<String>,
+    // None is "where the remote-tracking ref is", Some(None) is "not there"
+    expected: Option<Option<String>>,
+}
+
+impl Lease {
+    // the value the remote ref must have for the push to go on, None when the lease does
+    // not cover this ref
+    fn expected(&self, dst: &str, remote: &Remote) -> anyhow::Result<Option<Option<String>>> {
+        if let Some(name) = &self.name
+            && name != dst
+            && name != remote::short_name(dst)
+        {
+            return Ok(None);
+        }
+        match &self.expected {
+            Some(expected) => Ok(Some(expected.clone())),
+            // no remote-tracking ref is the same as expecting a ref that is not there
+            None => Ok(Some(match remote.tracking_ref(dst) {
+                Some(tracking) => refs::resolve(Path::new(".git"), &tracking)?,
+                None => None,
+            })),
+        }
+    }
+}
+
+// NOTE: core.bare of its config, a <repo>/.git dir is never bare
+fn is_bare(git_dir: &Path) -> anyhow::Result<bool> {
+    if git_dir.file_name().is_some_and(|name| name == ".git") {
+        return Ok(false);
+    }
+    Ok(Config::load_from(&git_dir.join("config"))?
+        .get_bool("core.bare")?
+        .unwrap_or(true))
+}
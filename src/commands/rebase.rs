@@ -0,0 +1,923 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+
+use crate::ancestry::Ancestry;
+use crate::commands;
+use crate::commands::merge::entries;
+use crate::commit::{Cleanup, Commit};
+use crate::config::Config;
+use crate::diff;
+use crate::editor;
+use crate::hash::ObjectId;
+use crate::index::Index;
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, CompositeDb};
+use crate::refs::{self, Head};
+use crate::revision;
+use crate::revwalk;
+use crate::sequencer::{self, Action, Merged};
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: replay the commits of the current branch on top of another commit
+// cargo run -- rebase main
+// cargo run -- rebase --onto main next topic
+// <upstream>        the commits of HEAD that are not in upstream are replayed on it, the
+//                   oldest first, merges are left out (branch.<name>.merge when not given)
+// --onto <newbase>  replayed on newbase instead of upstream
+// <branch>          that branch is checked out first
+// -i                the todo list goes through the editor first (GIT_SEQUENCE_EDITOR,
+//                   sequence.editor, then the commit editor):
+//   p, pick    use the commit               r, reword  use it and edit the message
+//   e, edit    use it and stop to amend it  s, squash  meld it into the previous one
+//   f, fixup   like squash, the previous message is kept    d, drop  leave it out
+//   x, exec    run the rest of the line in the shell, stop when it fails
+//   b, break   stop here
+// --autosquash      "fixup! <subject>" and "squash! <subject>" go right after their target
+//                   (rebase.autoSquash)
+// --exec <cmd>      "exec <cmd>" after every commit
+// --reapply-cherry-picks  a commit whose change is already in upstream (same patch id, it
+//                   was cherry-picked there) is picked too, it is left out by default
+// a commit whose parent is already HEAD is not picked again, HEAD just move to it,
+// a commit that became empty is dropped
+// HEAD is detached while it runs, the branch only move at the end
+// state in .git/rebase-merge: head-name, onto, orig-head, git-rebase-todo, done, and when
+// it stopped: message (of the commit), amend (edit), message-squash (squash/fixup chain)
+// a conflict, edit, break or a failed exec stop it, then --continue, --skip or --abort
+pub(crate) fn invoke(
+    options: Options,
+    upstream: Option<&str>,
+    branch: Option<&str>,
+) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let state = git_dir.join("rebase-merge");
+    if options.continue_rebase || options.skip || options.abort {
+        anyhow::ensure!(state.exists(), "No rebase in progress?");
+        let db = odb::open()?;
+        if options.abort {
+            return abort(&db, git_dir);
+        }
+        if options.skip {
+            skip(&db, git_dir)?;
+        } else {
+            resume(&db, git_dir)?;
+        }
+        return run(&db, git_dir);
+    }
+    anyhow::ensure!(
+        !state.exists(),
+        "It seems that there is already a rebase-merge directory, and\n\
+         I wonder if you are in the middle of another rebase.  If that is the\n\
+         case, please try\n\tgit rebase (--continue | --abort | --skip)\n\
+         If that is not the case, please\n\trm -fr \".git/rebase-merge\"\n\
+         and run me again.  I am stopping in case you still have something\n\
+         valuable there."
+    );
+
+    let db = odb::open()?;
+    let config = Config::load()?;
+    let resolve = |name: &str| {
+        revision::resolve(&db, git_dir, name)
+            .and_then(|id| revision::peel(&db, id, Kind::Commit))
+            .with_context(|| format!("invalid upstream '{name}'"))
+    };
+    if let Some(branch) = branch {
+        switch_to(&db, git_dir, branch)?;
+    }
+    let head = revision::resolve(&db, git_dir, "HEAD")?;
+    ensure_clean(&db, git_dir, head)?;
+    let head_name = match refs::read_head(git_dir)? {
+        Head::Symbolic(name) => name,
+        Head::Detached(_) => "detached HEAD".to_string(),
+    };
+    let upstream_name = match upstream {
+        Some(upstream) => upstream.to_string(),
+        None => tracked_upstream(&config, &head_name)?,
+    };
+    let upstream = resolve(&upstream_name)?;
+    let onto_name = options.onto.as_deref().unwrap_or(&upstream_name);
+    let onto = resolve(onto_name)?;
+
+    let mut ancestry = Ancestry::new(&db)?;
+    if !options.interactive
+        && options.exec.is_empty()
+        && ancestry.merge_bases(upstream, head)? == [onto]
+        && ancestry.is_ancestor(onto, head)?
+    {
+        let name = head_name.strip_prefix("refs/heads/").unwrap_or(&head_name);
+        println!("Current branch {name} is up to date.");
+        return Ok(());
+    }
+
+    // the merges are left out, like git without --rebase-merges
+    let mut commits = revwalk::range(&db, upstream, head)?;
+    commits.reverse();
+    let applied = if options.reapply_cherry_picks {
+        HashSet::new()
+    } else {
+        upstream_patch_ids(&db, &mut ancestry, head, upstream)?
+    };
+    let abbrev = Abbrev::new(&db, 7)?;
+    let mut skipped = false;
+    let mut todo = Vec::new();
+    for commit in commits {
+        if ancestry.parents(commit)?.len() > 1 {
+            continue;
+        }
+        if !applied.is_empty() && patch_id(&db, commit)?.is_some_and(|id| applied.contains(&id)) {
+            eprintln!(
+                "warning: skipped previously applied commit {}",
+                abbrev.abbrev(&commit.to_string())
+            );
+            skipped = true;
+            continue;
+        }
+        todo.push(Line {
+            todo: Todo::Pick,
+            commit: Some(commit),
+            rest: subject(&db, commit)?,
+        });
+    }
+    let autosquash = options.autosquash
+        || (options.interactive && config.get_bool("rebase.autoSquash")?.unwrap_or(false));
+    if skipped {
+        eprintln!(
+            "hint: use --reapply-cherry-picks to include skipped commits\n\
+             hint: Disable this message with \"git config advice.skippedCherryPicks false\""
+        );
+    }
+    if autosquash && options.interactive {
+        todo = rearrange_squash(todo);
+    }
+    if !options.exec.is_empty() {
+        todo = add_exec(todo, &options.exec);
+    }
+
+    fs::create_dir_all(&state).context("Failed to create .git/rebase-merge")?;
+    if options.interactive {
+        let mut content = format_todo(Some(&abbrev), &todo);
+        content.push_str(&todo_help(
+            abbrev.abbrev(&upstream.to_string()),
+            abbrev.abbrev(&head.to_string()),
+            abbrev.abbrev(&onto.to_string()),
+            todo.len(),
+        ));
+        let todo_path = state.join("git-rebase-todo");
+        fs::write(&todo_path, content).context("Failed to write git-rebase-todo")?;
+        let edited = editor::launch(&editor::sequence_editor(&config), &todo_path)
+            .and_then(|_| read_todo(&db, git_dir, &todo_path));
+        todo = match edited {
+            Ok(todo) => todo,
+            Err(e) => {
+                let _ = fs::remove_dir_all(&state);
+                return Err(e);
+            }
+        };
+        if todo.is_empty() {
+            fs::remove_dir_all(&state).context("Failed to remove .git/rebase-merge")?;
+            anyhow::bail!("nothing to do");
+        }
+    }
+    fs::write(state.join("head-name"), format!("{head_name}\n"))
+        .context("Failed to write rebase-merge/head-name")?;
+    fs::write(state.join("onto"), format!("{onto}\n"))
+        .context("Failed to write rebase-merge/onto")?;
+    fs::write(state.join("orig-head"), format!("{head}\n"))
+        .context("Failed to write rebase-merge/orig-head")?;
+    fs::write(state.join("done"), "").context("Failed to write rebase-merge/done")?;
+    write_todo(&db, &state, &todo)?;
+    fs::write(git_dir.join("ORIG_HEAD"), format!("{head}\n"))
+        .context("Failed to write ORIG_HEAD")?;
+
+    checkout(&db, git_dir, onto)?;
+    let message = format!("rebase (start): checkout {onto_name}");
+    refs::update(git_dir, "HEAD", &onto.to_string(), &message)?;
+    run(&db, git_dir)
+}
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) onto: Option<String>,
+    pub(crate) interactive: bool,
+    pub(crate) autosquash: bool,
+    pub(crate) exec: Vec<String>,
+    pub(crate) reapply_cherry_picks: bool,
+    pub(crate) continue_rebase: bool,
+    pub(crate) skip: bool,
+    pub(crate) abort: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Todo {
+    Pick,
+    Reword,
+    Edit,
+    Squash,
+    Fixup,
+    Drop,
+    Exec,
+    Break,
+}
+
+impl Todo {
+    fn name(self) -> &'static str {
+        match self {
+            Todo::Pick => "pick",
+            Todo::Reword => "reword",
+            Todo::Edit => "edit",
+            Todo::Squash => "squash",
+            Todo::Fixup => "fixup",
+            Todo::Drop => "drop",
+            Todo::Exec => "exec",
+            Todo::Break => "break",
+        }
+    }
+
+    fn parse(word: &str) -> Option<Todo> {
+        match word {
+            "p" | "pick" => Some(Todo::Pick),
+            "r" | "reword" => Some(Todo::Reword),
+            "e" | "edit" => Some(Todo::Edit),
+            "s" | "squash" => Some(Todo::Squash),
+            "f" | "fixup" => Some(Todo::Fixup),
+            "d" | "drop" => Some(Todo::Drop),
+            "x" | "exec" => Some(Todo::Exec),
+            "b" | "break" => Some(Todo::Break),
+            _ => None,
+        }
+    }
+
+    fn is_squash(self) -> bool {
+        matches!(self, Todo::Squash | Todo::Fixup)
+    }
+}
+
+// one line of the todo, rest is the subject (or the command of exec)
+#[derive(Debug, Clone)]
+struct Line {
+    todo: Todo,
+    commit: Option<ObjectId>,
+    rest: String,
+}
+
+// NOTE: the todo one line at a time, the line goes to done before it runs so a stop
+// leave it there (--continue look at it to know what it was doing)
+fn run(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let state = git_dir.join("rebase-merge");
+    loop {
+        let mut todo = read_todo(db, git_dir, &state.join("git-rebase-todo"))?;
+        if todo.is_empty() {
+            return finish(db, git_dir);
+        }
+        let line = todo.remove(0);
+        let done = fs::read_to_string(state.join("done")).unwrap_or_default();
+        let abbrev = Abbrev::new(db, 7)?;
+        fs::write(
+            state.join("done"),
+            format!("{done}{}", format_todo(None, std::slice::from_ref(&line))),
+        )
+        .context("Failed to write rebase-merge/done")?;
+        write_todo(db, &state, &todo)?;
+        let last_squash = !todo.first().is_some_and(|next| next.todo.is_squash());
+        if !line.todo.is_squash() {
+            let _ = fs::remove_file(state.join("message-squash"));
+        }
+
+        match (line.todo, line.commit) {
+            (Todo::Drop, _) => {}
+            (Todo::Break, _) => {
+                let head = revision::resolve(db, git_dir, "HEAD")?;
+                println!(
+                    "Stopped at {} ({})",
+                    abbrev.abbrev(&head.to_string()),
+                    subject(db, head)?
+                );
+                return Ok(());
+            }
+            (Todo::Exec, _) => {
+                println!("Executing: {}", line.rest);
+                let status = Command::new("sh")
+                    .arg("-c")
+                    .arg(&line.rest)
+                    .status()
+                    .with_context(|| format!("Failed to run {}", line.rest))?;
+                anyhow::ensure!(
+                    status.success(),
+                    "execution failed: {}\n\
+                     You can fix the problem, and then run\n\n  git rebase --continue\n",
+                    line.rest
+                );
+            }
+            (Todo::Squash | Todo::Fixup, Some(commit)) => {
+                add_to_squash(db, git_dir, line.todo, commit)?;
+                let merged = sequencer::merge_commit(
+                    db,
+                    git_dir,
+                    Action::Pick,
+                    commit,
+                    sequencer::Options::default(),
+                )?;
+                if !merged.result.is_clean() {
+                    return stop_on_conflict(git_dir, &merged, commit);
+                }
+                squash(db, git_dir, last_squash, line.todo)?;
+            }
+            (todo, Some(commit)) => {
+                if !pick(db, git_dir, commit, todo)? {
+                    continue;
+                }
+                if todo == Todo::Reword {
+                    reword(db, git_dir)?;
+                }
+                if todo == Todo::Edit {
+                    return stop_to_amend(db, git_dir, commit);
+                }
+            }
+            (_, None) => anyhow::bail!("missing commit for {}", line.todo.name()),
+        }
+    }
+}
+
+// NOTE: HEAD get the change of the commit, with its author and message
+// false when it was dropped because it became empty
+// "rebase (pick): subject" in the reflog ("rebase (edit)" for an edit...)
+fn pick(db: &CompositeDb, git_dir: &Path, commit: ObjectId, todo: Todo) -> anyhow::Result<bool> {
+    let head = revision::resolve(db, git_dir, "HEAD")?;
+    let picked = Commit::read(db, &commit.to_string())?;
+    if picked.parents == [head] {
+        checkout(db, git_dir, commit)?;
+        refs::update(git_dir, "HEAD", &commit.to_string(), "rebase: fast-forward")?;
+        return Ok(true);
+    }
+    let merged = sequencer::merge_commit(
+        db,
+        git_dir,
+        Action::Pick,
+        commit,
+        sequencer::Options::default(),
+    )?;
+    if !merged.result.is_clean() {
+        return stop_on_conflict(git_dir, &merged, commit).map(|()| false);
+    }
+    let parent_tree = match picked.parents.first() {
+        Some(parent) => Some(Commit::read(db, &parent.to_string())?.tree),
+        None => None,
+    };
+    let was_empty = parent_tree == Some(picked.tree);
+    if merged.result.tree(db)? == merged.head_tree && !was_empty {
+        return Ok(false);
+    }
+    let action = format!("rebase ({})", todo.name());
+    commit_as(&picked, picked.message_text(), true, action)?;
+    Ok(true)
+}
+
+// a new commit from the index with the author and the message of another one
+fn commit_as(picked: &Commit, message: String, quiet: bool, action: String) -> anyhow::Result<()> {
+    let author = &picked.author;
+    commands::commit::invoke(commands::commit::Options {
+        messages: vec![message],
+        author: Some(format!(
+            "{} <{}>",
+            picked.decode(&author.name),
+            picked.decode(&author.email)
+        )),
+        date: Some(format!("@{} {}", author.time, author.timezone)),
+        cleanup: Some(Cleanup::Verbatim),
+        allow_empty: true,
+        no_edit: true,
+        no_verify: true,
+        quiet,
+        reflog_action: Some(action),
+        ..Default::default()
+    })
+}
+
+fn reword(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    commands::commit::invoke(commands::commit::Options {
+        amend: true,
+        edit: true,
+        allow_empty: true,
+        no_verify: true,
+        reflog_action: Some("rebase (reword)".to_string()),
+        ..Default::default()
+    })?;
+    sequencer::summary(db, git_dir, true)
+}
+
+// NOTE: the message of a squash/fixup chain is built in message-squash like git
+// # This is a combination of 2 commits.
+// # This is the 1st commit message:
+// <message of HEAD>
+// # This is the commit message #2:        (fixup: "will be skipped" and commented)
+// <message of the commit>
+fn add_to_squash(
+    db: &CompositeDb,
+    git_dir: &Path,
+    todo: Todo,
+    commit: ObjectId,
+) -> anyhow::Result<()> {
+    let path = git_dir.join("rebase-merge").join("message-squash");
+    let (count, body) = match fs::read_to_string(&path) {
+        Ok(content) => {
+            let count = content
+                .lines()
+                .next()
+                .and_then(|line| line.strip_prefix("# This is a combination of "))
+                .and_then(|rest| rest.split(' ').next())
+                .and_then(|count| count.parse::<usize>().ok())
+                .context("bad rebase-merge/message-squash")?;
+            let body = content.split_once('\n').map(|(_, body)| body).unwrap_or("");
+            (count, body.to_string())
+        }
+        Err(_) => {
+            let head = Commit::read(db, &revision::resolve(db, git_dir, "HEAD")?.to_string())?;
+            (
+                1,
+                format!(
+                    "# This is the 1st commit message:\n\n{}",
+                    head.message_text()
+                ),
+            )
+        }
+    };
+    let message = Commit::read(db, &commit.to_string())?.message_text();
+    let count = count + 1;
+    let added = match todo {
+        Todo::Squash => format!("\n# This is the commit message #{count}:\n\n{message}"),
+        _ => {
+            let commented: Vec<String> = message
+                .lines()
+                .map(|line| format!("# {line}").trim_end().to_string())
+                .collect();
+            format!(
+                "\n# The commit message #{count} will be skipped:\n\n{}\n",
+                commented.join("\n")
+            )
+        }
+    };
+    fs::write(
+        &path,
+        format!("# This is a combination of {count} commits.\n{body}{added}"),
+    )
+    .context("Failed to write rebase-merge/message-squash")
+}
+
+// HEAD is amended with what the index has now, the editor only for the last one of a chain
+// that has a squash in it
+fn squash(db: &CompositeDb, git_dir: &Path, last: bool, todo: Todo) -> anyhow::Result<()> {
+    let path = git_dir.join("rebase-merge").join("message-squash");
+    let message =
+        fs::read_to_string(&path).context("Failed to read rebase-merge/message-squash")?;
+    let edit = last && message.contains("\n# This is the commit message #");
+    commands::commit::invoke(commands::commit::Options {
+        messages: vec![message],
+        amend: true,
+        edit,
+        no_edit: !edit,
+        cleanup: Some(Cleanup::Strip),
+        allow_empty: true,
+        no_verify: true,
+        quiet: !edit,
+        reflog_action: Some(format!("rebase ({})", todo.name())),
+        ..Default::default()
+    })?;
+    if edit {
+        sequencer::summary(db, git_dir, true)?;
+    }
+    if last {
+        let _ = fs::remove_file(&path);
+    }
+    Ok(())
+}
+
+// the output of the merge is only shown when it did not work, like git
+fn stop_on_conflict(git_dir: &Path, merged: &Merged, commit: ObjectId) -> anyhow::Result<()> {
+    for message in &merged.result.messages {
+        println!("{message}");
+    }
+    let state = git_dir.join("rebase-merge");
+    fs::write(state.join("message"), &merged.commit.message)
+        .context("Failed to write rebase-merge/message")?;
+    fs::write(git_dir.join("MERGE_MSG"), &merged.commit.message)
+        .context("Failed to write MERGE_MSG")?;
+    fs::write(git_dir.join("REBASE_HEAD"), format!("{commit}\n"))
+        .context("Failed to write REBASE_HEAD")?;
+    let (short, subject) = (&merged.short, &merged.subject);
+    anyhow::bail!(
+        "could not apply {short}... {subject}\n\
+         hint: Resolve all conflicts manually, mark them as resolved with\n\
+         hint: \"git add/rm <conflicted_files>\", then run \"git rebase --continue\".\n\
+         hint: You can instead skip this commit: run \"git rebase --skip\".\n\
+         hint: To abort and get back to the state before \"git rebase\", run \"git rebase --abort\"."
+    )
+}
+
+fn stop_to_amend(db: &CompositeDb, git_dir: &Path, commit: ObjectId) -> anyhow::Result<()> {
+    let head = revision::resolve(db, git_dir, "HEAD")?;
+    fs::write(
+        git_dir.join("rebase-merge").join("amend"),
+        format!("{head}\n"),
+    )
+    .context("Failed to write rebase-merge/amend")?;
+    fs::write(git_dir.join("REBASE_HEAD"), format!("{commit}\n"))
+        .context("Failed to write REBASE_HEAD")?;
+    println!(
+        "Stopped at {}...  {}\n\
+         You can amend the commit now, with\n\n  git commit --amend \n\n\
+         Once you are satisfied with your changes, run\n\n  git rebase --continue",
+        Abbrev::new(db, 7)?.abbrev(&commit.to_string()),
+        subject(db, commit)?
+    );
+    Ok(())
+}
+
+// NOTE: --continue, what stopped is finished first:
+// a conflict -> the resolved index is committed (amended for a squash/fixup)
+// edit       -> the staged changes are amended into the commit
+fn resume(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let state = git_dir.join("rebase-merge");
+    let index = Index::read(&git_dir.join("index"))?;
+    anyhow::ensure!(
+        index.entries.iter().all(|e| e.stage() == 0),
+        "You must edit all merge conflicts and then\nmark them as resolved using git add"
+    );
+    let head = revision::resolve(db, git_dir, "HEAD")?;
+    let head_tree = Commit::read(db, &head.to_string())?.tree;
+    let staged = index.write_tree(db)? != head_tree;
+    let amend = fs::read_to_string(state.join("amend")).ok();
+    let stopped = fs::read_to_string(git_dir.join("REBASE_HEAD")).ok();
+
+    if let Some(amend) = amend {
+        if staged && amend.trim() == head.to_string() {
+            commands::commit::invoke(commands::commit::Options {
+                amend: true,
+                no_edit: true,
+                no_verify: true,
+                quiet: true,
+                reflog_action: Some("rebase (continue)".to_string()),
+                ..Default::default()
+            })?;
+        }
+    } else if let Some(stopped) = stopped {
+        let picked = Commit::read(db, stopped.trim())?;
+        let done = read_todo(db, git_dir, &state.join("done"))?;
+        let todo = read_todo(db, git_dir, &state.join("git-rebase-todo"))?;
+        match done.last().map(|line| line.todo) {
+            Some(current) if current.is_squash() => {
+                let last = !todo.first().is_some_and(|next| next.todo.is_squash());
+                squash(db, git_dir, last, current)?;
+            }
+            current => {
+                let message = fs::read_to_string(state.join("message"))
+                    .unwrap_or_else(|_| picked.message_text());
+                if staged {
+                    commit_as(&picked, message, false, "rebase (continue)".to_string())?;
+                    sequencer::summary(db, git_dir, false)?;
+                    if current == Some(Todo::Reword) {
+                        reword(db, git_dir)?;
+                    }
+                }
+            }
+        }
+    }
+    for file in ["amend", "message"] {
+        let _ = fs::remove_file(state.join(file));
+    }
+    for file in ["REBASE_HEAD", "MERGE_MSG"] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+    Ok(())
+}
+
+// NOTE: --skip, the commit that stopped is forgotten, the index and the files go back to HEAD
+fn skip(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let state = git_dir.join("rebase-merge");
+    let head = revision::resolve(db, git_dir, "HEAD")?;
+    sequencer::reset_merge(db, git_dir, head)?;
+    for file in ["amend", "message"] {
+        let _ = fs::remove_file(state.join(file));
+    }
+    for file in ["REBASE_HEAD", "MERGE_MSG"] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+    Ok(())
+}
+
+// NOTE: --abort, the files and HEAD go back to where it started, the branch never moved
+fn abort(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let state = git_dir.join("rebase-merge");
+    let orig_head = fs::read_to_string(state.join("orig-head"))
+        .context("Failed to read rebase-merge/orig-head")?;
+    let orig_head = ObjectId::from_hex(orig_head.trim())?;
+    let head_name = fs::read_to_string(state.join("head-name"))
+        .context("Failed to read rebase-merge/head-name")?;
+    let head_name = head_name.trim();
+    sequencer::reset_merge(db, git_dir, orig_head)?;
+    if head_name.starts_with("refs/") {
+        let message = format!("rebase (abort): returning to {head_name}");
+        refs::write_symbolic(git_dir, "HEAD", head_name, &message)?;
+    } else {
+        let message = format!("rebase (abort): returning to {orig_head}");
+        refs::update(git_dir, "HEAD", &orig_head.to_string(), &message)?;
+    }
+    for file in ["REBASE_HEAD", "MERGE_MSG"] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+    fs::remove_dir_all(&state).context("Failed to remove .git/rebase-merge")
+}
+
+// the branch get the new HEAD and HEAD is back on it
+fn finish(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let state = git_dir.join("rebase-merge");
+    let head_name = fs::read_to_string(state.join("head-name"))
+        .context("Failed to read rebase-merge/head-name")?;
+    let head_name = head_name.trim();
+    if head_name.starts_with("refs/") {
+        let onto =
+            fs::read_to_string(state.join("onto")).context("Failed to read rebase-merge/onto")?;
+        let head = revision::resolve(db, git_dir, "HEAD")?;
+        let message = format!("rebase (finish): {head_name} onto {}", onto.trim());
+        refs::update(git_dir, head_name, &head.to_string(), &message)?;
+        let message = format!("rebase (finish): returning to {head_name}");
+        refs::write_symbolic(git_dir, "HEAD", head_name, &message)?;
+    }
+    fs::remove_dir_all(&state).context("Failed to remove .git/rebase-merge")?;
+    eprintln!("Successfully rebased and updated {head_name}.");
+    Ok(())
+}
+
+// index and files from HEAD to the commit, HEAD itself is not moved
+fn checkout(db: &CompositeDb, git_dir: &Path, commit: ObjectId) -> anyhow::Result<()> {
+    let files = tree::files(db, Commit::read(db, &commit.to_string())?.tree)?;
+    let index = Index::read(&git_dir.join("index"))?;
+    worktree::update(
+        db,
+        &index.files(),
+        &entries(&files),
+        &BTreeMap::new(),
+        "checkout",
+    )
+}
+
+// "rebase <upstream> <branch>" start with the branch checked out
+fn switch_to(db: &CompositeDb, git_dir: &Path, branch: &str) -> anyhow::Result<()> {
+    let full = format!("refs/heads/{branch}");
+    let commit = match refs::resolve(git_dir, &full)? {
+        Some(hash) => ObjectId::from_hex(&hash)?,
+        None => revision::resolve(db, git_dir, branch)
+            .and_then(|id| revision::peel(db, id, Kind::Commit))
+            .with_context(|| format!("no such branch/commit '{branch}'"))?,
+    };
+    if revision::resolve(db, git_dir, "HEAD").ok() != Some(commit) {
+        checkout(db, git_dir, commit)?;
+    }
+    let message = format!("rebase (start): checkout {branch}");
+    if refs::resolve(git_dir, &full)?.is_some() {
+        refs::write_symbolic(git_dir, "HEAD", &full, &message)
+    } else {
+        refs::update(git_dir, "HEAD", &commit.to_string(), &message)
+    }
+}
+
+// nothing staged and no tracked file changed
+fn ensure_clean(db: &CompositeDb, git_dir: &Path, head: ObjectId) -> anyhow::Result<()> {
+    let index = Index::read(&git_dir.join("index"))?;
+    let head_files = tree::files(db, Commit::read(db, &head.to_string())?.tree)?;
+    let mut unstaged = false;
+    for entry in &index.entries {
+        unstaged |= worktree::is_modified(Path::new("."), entry, index.format)?;
+    }
+    anyhow::ensure!(
+        !unstaged,
+        "cannot rebase: You have unstaged changes.\nPlease commit or stash them."
+    );
+    anyhow::ensure!(
+        index.files() == head_files,
+        "cannot rebase: Your index contains uncommitted changes.\nPlease commit or stash them."
+    );
+    Ok(())
+}
+
+// branch.<name>.merge of the current branch, as a remote-tracking branch
+fn tracked_upstream(config: &Config, head_name: &str) -> anyhow::Result<String> {
+    let branch = head_name.strip_prefix("refs/heads/").unwrap_or(head_name);
+    let merge = config.get(&format!("branch.{branch}.merge"));
+    let remote = config.get(&format!("branch.{branch}.remote"));
+    match (
+        remote,
+        merge.and_then(|merge| merge.strip_prefix("refs/heads/")),
+    ) {
+        (Some("."), Some(merge)) => Ok(merge.to_string()),
+        (Some(remote), Some(merge)) => Ok(format!("refs/remotes/{remote}/{merge}")),
+        _ => anyhow::bail!(
+            "There is no tracking information for the current branch.\n\
+             Please specify which branch you want to rebase against."
+        ),
+    }
+}
+
+// NOTE: the patch ids of the commits of upstream that are not in HEAD (merges left out)
+fn upstream_patch_ids(
+    db: &CompositeDb,
+    ancestry: &mut Ancestry,
+    head: ObjectId,
+    upstream: ObjectId,
+) -> anyhow::Result<HashSet<ObjectId>> {
+    let mut ids = HashSet::new();
+    for commit in revwalk::range(db, head, upstream)? {
+        if ancestry.parents(commit)?.len() > 1 {
+            continue;
+        }
+        ids.extend(patch_id(db, commit)?);
+    }
+    Ok(ids)
+}
+
+// the change of a commit against its first parent, None for an empty one
+fn patch_id(db: &CompositeDb, commit: ObjectId) -> anyhow::Result<Option<ObjectId>> {
+    let commit = Commit::read(db, &commit.to_string())?;
+    let parent_files = match commit.parents.first() {
+        Some(parent) => tree::files(db, Commit::read(db, &parent.to_string())?.tree)?,
+        None => Files::new(),
+    };
+    diff::patch_id(db, &parent_files, &tree::files(db, commit.tree)?)
+}
+
+fn subject(db: &CompositeDb, commit: ObjectId) -> anyhow::Result<String> {
+    Ok(Commit::read(db, &commit.to_string())?.subject())
+}
+
+// NOTE: "fixup! <subject>" (or squash!) moves right after the commit it names, by subject,
+// by hash, or by the start of the subject, the ones for the same commit keep their order
+fn rearrange_squash(todo: Vec<Line>) -> Vec<Line> {
+    let mut targets: Vec<Option<usize>> = vec![None; todo.len()];
+    for (i, line) in todo.iter().enumerate() {
+        let mut wanted = match line.rest.split_once(' ') {
+            Some(("fixup!" | "squash!", rest)) => rest,
+            _ => continue,
+        };
+        while let Some(rest) = wanted
+            .strip_prefix("fixup! ")
+            .or_else(|| wanted.strip_prefix("squash! "))
+        {
+            wanted = rest;
+        }
+        let earlier = &todo[..i];
+        let found = earlier
+            .iter()
+            .position(|other| other.rest == wanted)
+            .or_else(|| {
+                earlier.iter().position(|other| {
+                    wanted.len() >= 4
+                        && other
+                            .commit
+                            .is_some_and(|commit| commit.to_string().starts_with(wanted))
+                })
+            })
+            .or_else(|| {
+                earlier
+                    .iter()
+                    .position(|other| other.rest.starts_with(wanted))
+            });
+        if let Some(found) = found {
+            // a fixup of a fixup goes with the first target
+            let root = targets[found].unwrap_or(found);
+            targets[i] = Some(root);
+        }
+    }
+    let mut ordered = Vec::new();
+    for (i, line) in todo.iter().enumerate() {
+        if targets[i].is_some() {
+            continue;
+        }
+        ordered.push(line.clone());
+        for (j, fixup) in todo.iter().enumerate() {
+            if targets[j] == Some(i) {
+                let todo = if fixup.rest.starts_with("squash!") {
+                    Todo::Squash
+                } else {
+                    Todo::Fixup
+                };
+                ordered.push(Line {
+                    todo,
+                    ..fixup.clone()
+                });
+            }
+        }
+    }
+    ordered
+}
+
+// "exec <cmd>" after every commit, after the fixups of a commit
+fn add_exec(todo: Vec<Line>, commands: &[String]) -> Vec<Line> {
+    let mut with_exec = Vec::new();
+    for (i, line) in todo.iter().enumerate() {
+        with_exec.push(line.clone());
+        let next_squash = todo.get(i + 1).is_some_and(|next| next.todo.is_squash());
+        if line.commit.is_some() && !next_squash {
+            for command in commands {
+                with_exec.push(Line {
+                    todo: Todo::Exec,
+                    commit: None,
+                    rest: command.clone(),
+                });
+            }
+        }
+    }
+    with_exec
+}
+
+// the todo has abbreviated hashes, done the full ones (abbrev None)
+fn format_todo(abbrev: Option<&Abbrev>, todo: &[Line]) -> String {
+    let mut content = String::new();
+    for line in todo {
+        match line.commit {
+            Some(commit) => content.push_str(&format!(
+                "{} {} {}\n",
+                line.todo.name(),
+                match abbrev {
+                    Some(abbrev) => abbrev.abbrev(&commit.to_string()).to_string(),
+                    None => commit.to_string(),
+                },
+                line.rest
+            )),
+            None if line.rest.is_empty() => content.push_str(&format!("{}\n", line.todo.name())),
+            None => content.push_str(&format!("{} {}\n", line.todo.name(), line.rest)),
+        }
+    }
+    content
+}
+
+fn write_todo(db: &CompositeDb, state: &Path, todo: &[Line]) -> anyhow::Result<()> {
+    let abbrev = Abbrev::new(db, 7)?;
+    fs::write(
+        state.join("git-rebase-todo"),
+        format_todo(Some(&abbrev), todo),
+    )
+    .context("Failed to write rebase-merge/git-rebase-todo")
+}
+
+fn read_todo(db: &CompositeDb, git_dir: &Path, path: &Path) -> anyhow::Result<Vec<Line>> {
+    let content =
+        fs::read_to_string(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let mut todo = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let invalid = || format!("invalid line {}: {line}", number + 1);
+        let todo_kind = Todo::parse(word).with_context(invalid)?;
+        let rest = rest.trim();
+        match todo_kind {
+            Todo::Exec | Todo::Break => todo.push(Line {
+                todo: todo_kind,
+                commit: None,
+                rest: rest.to_string(),
+            }),
+            _ => {
+                let (name, subject) = rest.split_once(' ').unwrap_or((rest, ""));
+                let commit = revision::resolve(db, git_dir, name)
+                    .and_then(|id| revision::peel(db, id, Kind::Commit))
+                    .with_context(invalid)?;
+                todo.push(Line {
+                    todo: todo_kind,
+                    commit: Some(commit),
+                    rest: subject.to_string(),
+                });
+            }
+        }
+    }
+    Ok(todo)
+}
+
+fn todo_help(upstream: &str, head: &str, onto: &str, count: usize) -> String {
+    format!(
+        "\n# Rebase {upstream}..{head} onto {onto} ({count} command{})\n\
+         #\n\
+         # Commands:\n\
+         # p, pick <commit> = use commit\n\
+         # r, reword <commit> = use commit, but edit the commit message\n\
+         # e, edit <commit> = use commit, but stop for amending\n\
+         # s, squash <commit> = use commit, but meld into previous commit\n\
+         # f, fixup <commit> = like \"squash\" but keep only the previous\n\
+         #                    commit's log message\n\
+         # x, exec <command> = run command (the rest of the line) using shell\n\
+         # b, break = stop here (continue rebase later with 'git rebase --continue')\n\
+         # d, drop <commit> = remove commit\n\
+         #\n\
+         # These lines can be re-ordered; they are executed from top to bottom.\n\
+         #\n\
+         # If you remove a line here THAT COMMIT WILL BE LOST.\n\
+         #\n\
+         # However, if you remove everything, the rebase will be aborted.\n\
+         #\n",
+        if count == 1 { "" } else { "s" }
+    )
+}
@@ -0,0 +1,308 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commands::merge::{entries, move_head};
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::index::{Index, IndexEntry};
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, CompositeDb};
+use crate::pathspec;
+use crate::quote;
+use crate::refs::{self, Head};
+use crate::revision;
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: move the current branch (or the detached HEAD) to another commit
+// cargo run -- reset --hard HEAD~1
+//                     HEAD  index  worktree
+// --soft              moved
+// --mixed (default)   moved  reset
+// --hard              moved  reset  reset, every local change is lost
+// --keep              moved  reset  only the files that differ between HEAD and the commit,
+//                                   refused when one of them has local changes
+// --merge             moved  reset  only the files where the index differs from the commit,
+//                                   refused when one of them has unstaged changes
+//                                   (what is left after a conflicted merge)
+// the old HEAD goes to ORIG_HEAD, HEAD and the branch get "reset: moving to <rev>" in their
+// reflog and a merge/cherry-pick/revert in progress is forgotten
+// reset [<tree-ish>] [--] <paths>   only the index entries of the paths, from the tree
+// (HEAD by default), the branch does not move
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Mode {
+    Soft,
+    Mixed,
+    Hard,
+    Keep,
+    Merge,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Soft => "soft",
+            Mode::Mixed => "mixed",
+            Mode::Hard => "hard",
+            Mode::Keep => "keep",
+            Mode::Merge => "merge",
+        }
+    }
+}
+
+// NOTE: without "--" the first argument is the revision when it is one, the rest are paths
+pub(crate) fn invoke(
+    mode: Option<Mode>,
+    quiet: bool,
+    args: &[String],
+    paths: &[String],
+) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let (revision, paths) = match (args, paths.is_empty()) {
+        ([], _) => (None, paths.to_vec()),
+        ([revision], false) => (Some(revision.as_str()), paths.to_vec()),
+        (_, false) => anyhow::bail!("only one revision can be given before '--'"),
+        ([first, rest @ ..], true) => {
+            if revision::resolve(&db, git_dir, first).is_ok() {
+                (Some(first.as_str()), rest.to_vec())
+            } else {
+                for path in args {
+                    anyhow::ensure!(
+                        Path::new(path).exists(),
+                        "ambiguous argument '{path}': unknown revision or path not in the working tree.\n\
+                         Use '--' to separate paths from revisions, like this:\n\
+                         'git <command> [<revision>...] -- [<file>...]'"
+                    );
+                }
+                (None, args.to_vec())
+            }
+        }
+    };
+
+    if !paths.is_empty() {
+        match mode {
+            Some(Mode::Mixed) => {
+                eprintln!(
+                    "warning: --mixed with paths is deprecated; use 'git reset -- <paths>' instead."
+                )
+            }
+            Some(mode) => anyhow::bail!("Cannot do {} reset with paths.", mode.name()),
+            None => {}
+        }
+        return reset_paths(&db, git_dir, revision, &paths, quiet);
+    }
+
+    let mode = mode.unwrap_or(Mode::Mixed);
+    anyhow::ensure!(
+        mode != Mode::Soft || !git_dir.join("MERGE_HEAD").exists(),
+        "Cannot do a soft reset in the middle of a merge."
+    );
+    let name = revision.unwrap_or("HEAD");
+    let head = refs::read_head(git_dir)?;
+    let head_commit = match &head {
+        Head::Symbolic(name) => refs::resolve(git_dir, name)?,
+        Head::Detached(hash) => Some(hash.clone()),
+    };
+    let head_commit = head_commit
+        .map(|hash| ObjectId::from_hex(&hash))
+        .transpose()?;
+    // NOTE: "reset" on an unborn branch empties the index, there is nothing to move
+    let target = match (revision, head_commit) {
+        (None, None) => None,
+        _ => Some(
+            revision::resolve(&db, git_dir, name)
+                .and_then(|id| revision::peel(&db, id, Kind::Commit))
+                .with_context(|| format!("Could not parse object '{name}'."))?,
+        ),
+    };
+    let target_files = match target {
+        Some(commit) => tree::files(&db, Commit::read(&db, &commit.to_string())?.tree)?,
+        None => Files::new(),
+    };
+    let head_files = match head_commit {
+        Some(commit) => tree::files(&db, Commit::read(&db, &commit.to_string())?.tree)?,
+        None => Files::new(),
+    };
+
+    let index_path = git_dir.join("index");
+    match mode {
+        Mode::Soft => {}
+        Mode::Mixed => {
+            let (mut index, lock) = Index::lock(&index_path)?;
+            index.set_files(&target_files, |_| true);
+            worktree::refresh(&mut index)?;
+            index.write_locked(lock)?;
+        }
+        Mode::Hard => hard(&db, &index_path, &target_files)?,
+        Mode::Keep => {
+            worktree::update(
+                &db,
+                &head_files,
+                &entries(&target_files),
+                &BTreeMap::new(),
+                "reset",
+            )
+            .with_context(|| format!("Could not reset index file to revision '{name}'."))?;
+            // staged changes of the files that stay the same become unstaged ones
+            let (mut index, lock) = Index::lock(&index_path)?;
+            index.set_files(&target_files, |_| true);
+            worktree::refresh(&mut index)?;
+            index.write_locked(lock)?;
+        }
+        Mode::Merge => {
+            let index = Index::read(&index_path)?;
+            worktree::update(
+                &db,
+                &index.files(),
+                &entries(&target_files),
+                &BTreeMap::new(),
+                "reset",
+            )
+            .with_context(|| format!("Could not reset index file to revision '{name}'."))?;
+        }
+    }
+
+    if let Some(target) = target {
+        let message = format!("reset: moving to {name}");
+        if let Some(old) = head_commit {
+            fs::write(git_dir.join("ORIG_HEAD"), format!("{old}\n"))
+                .context("Failed to write ORIG_HEAD")?;
+        }
+        move_head(git_dir, &head, &target, &message)?;
+    }
+    remove_branch_state(git_dir);
+
+    if quiet {
+        return Ok(());
+    }
+    match (mode, target) {
+        (Mode::Hard, Some(target)) => {
+            let hex = target.to_string();
+            let commit = Commit::read(&db, &hex)?;
+            println!(
+                "HEAD is now at {} {}",
+                Abbrev::new(&db, 7)?.abbrev(&hex),
+                commit.subject()
+            );
+        }
+        (Mode::Mixed, _) => print_unstaged(&Index::read(&index_path)?)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+// NOTE: the index entries of the paths (a dir is everything under it) come from the tree,
+// paths not in the tree leave the index, the worktree is not touched
+fn reset_paths(
+    db: &CompositeDb,
+    git_dir: &Path,
+    revision: Option<&str>,
+    paths: &[String],
+    quiet: bool,
+) -> anyhow::Result<()> {
+    let name = revision.unwrap_or("HEAD");
+    let tree = match revision::resolve(db, git_dir, name) {
+        Ok(id) => Some(
+            revision::peel(db, id, Kind::Tree)
+                .with_context(|| format!("Could not parse object '{name}'."))?,
+        ),
+        // unborn branch, the paths are removed from the index
+        Err(_) if revision.is_none() && refs::resolve(git_dir, "HEAD")?.is_none() => None,
+        Err(e) => return Err(e),
+    };
+    let files = match tree {
+        Some(tree) => tree::files(db, tree)?,
+        None => Files::new(),
+    };
+    let index_path = git_dir.join("index");
+    let (mut index, lock) = Index::lock(&index_path)?;
+    index.set_files(&files, |file| pathspec::matches(paths, file));
+    worktree::refresh(&mut index)?;
+    index.write_locked(lock)?;
+    if !quiet {
+        print_unstaged(&index)?;
+    }
+    Ok(())
+}
+
+// NOTE: every file and index entry becomes the commit, even with local changes
+// tracked files that are not in the commit are deleted, untracked ones stay
+fn hard(db: &CompositeDb, index_path: &Path, files: &Files) -> anyhow::Result<()> {
+    let worktree = Path::new(".");
+    let (mut index, lock) = Index::lock(index_path)?;
+    let symlinks = Config::load()?.get_bool("core.symlinks")?.unwrap_or(true);
+    let mut old: BTreeMap<String, IndexEntry> = BTreeMap::new();
+    for entry in index.entries.drain(..) {
+        old.entry(entry.path.clone()).or_insert(entry);
+    }
+    for path in old.keys().filter(|path| !files.contains_key(*path)) {
+        worktree::remove_path(worktree, path)?;
+    }
+    for (path, &(mode, hash)) in files {
+        let same = match old.get(path) {
+            Some(entry) => {
+                entry.stage() == 0
+                    && entry.mode == mode
+                    && entry.hash == hash
+                    && !worktree::is_modified(worktree, entry, index.format)?
+            }
+            None => false,
+        };
+        if same {
+            index.add(old[path].clone());
+        } else {
+            index.add(worktree::checkout_file(
+                db, worktree, path, mode, hash, symlinks,
+            )?);
+        }
+    }
+    index.write_locked(lock)
+}
+
+// NOTE: whatever was going on (merge, cherry-pick, revert) is over once HEAD moved away
+fn remove_branch_state(git_dir: &Path) {
+    for file in [
+        "MERGE_HEAD",
+        "MERGE_MSG",
+        "MERGE_MODE",
+        "MERGE_RR",
+        "AUTO_MERGE",
+        "SQUASH_MSG",
+        "CHERRY_PICK_HEAD",
+        "REVERT_HEAD",
+    ] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+}
+
+// "M\tpath" for a change that is not staged, "D\tpath" for a deleted file
+fn print_unstaged(index: &Index) -> anyhow::Result<()> {
+    let worktree = Path::new(".");
+    let mut unstaged = Vec::new();
+    for entry in index.entries.iter().filter(|entry| entry.stage() == 0) {
+        if !worktree::is_modified(worktree, entry, index.format)? {
+            continue;
+        }
+        let status = if fs::symlink_metadata(worktree.join(&entry.path)).is_ok() {
+            'M'
+        } else {
+            'D'
+        };
+        unstaged.push(format!(
+            "{status}\t{}",
+            quote::quote_c(entry.path.as_bytes())
+        ));
+    }
+    if !unstaged.is_empty() {
+        println!("Unstaged changes after reset:");
+        for line in unstaged {
+            println!("{line}");
+        }
+    }
+    Ok(())
+}
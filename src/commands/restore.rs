@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::index::Index;
+use crate::objects::Kind;
+use crate::odb;
+use crate::pathspec;
+use crate::refs;
+use crate::revision;
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: put back the content of files from somewhere else
+// cargo run -- restore f               worktree from the index (throw away unstaged changes)
+// cargo run -- restore --staged f      index from HEAD (unstage, the file keeps its changes)
+// cargo run -- restore -SW f           both from HEAD
+// --source=<tree-ish>                  from that tree instead, files the tree does not have
+//                                      are deleted (from the index and/or the worktree)
+pub(crate) fn invoke(options: Options, paths: &[String]) -> anyhow::Result<()> {
+    anyhow::ensure!(!paths.is_empty(), "you must specify path(s) to restore");
+    let git_dir = Path::new(".git");
+    let worktree_path = Path::new(".");
+    let db = odb::open()?;
+    let restore_worktree = options.worktree || !options.staged;
+
+    let index_path = git_dir.join("index");
+    let (mut index, lock) = Index::lock(&index_path)?;
+    // None is the index itself, the default source of the worktree
+    let source: Option<Files> = match (&options.source, options.staged) {
+        (Some(name), _) => {
+            let tree = revision::resolve(&db, git_dir, name)
+                .and_then(|id| revision::peel(&db, id, Kind::Tree))
+                .map_err(|_| anyhow::anyhow!("could not resolve {name}"))?;
+            Some(tree::files(&db, tree)?)
+        }
+        (None, true) => match refs::resolve(git_dir, "HEAD")? {
+            Some(hash) => {
+                let head = Commit::read(&db, &hash)?;
+                Some(tree::files(&db, head.tree)?)
+            }
+            None => Some(Files::new()),
+        },
+        (None, false) => None,
+    };
+
+    for pathspec in paths {
+        let known = index
+            .entries
+            .iter()
+            .any(|entry| pathspec::matches_one(pathspec, &entry.path))
+            || source.as_ref().is_some_and(|files| {
+                files
+                    .keys()
+                    .any(|path| pathspec::matches_one(pathspec, path))
+            });
+        anyhow::ensure!(
+            known,
+            "pathspec '{pathspec}' did not match any file(s) known to git"
+        );
+    }
+    let selected = |path: &str| pathspec::matches(paths, path);
+
+    if restore_worktree {
+        let symlinks = Config::load()?.get_bool("core.symlinks")?.unwrap_or(true);
+        match &source {
+            Some(files) => {
+                // the tracked files that the source does not have go away
+                let tracked: Vec<String> = index
+                    .entries
+                    .iter()
+                    .map(|entry| entry.path.clone())
+                    .filter(|path| selected(path) && !files.contains_key(path))
+                    .collect();
+                for path in tracked {
+                    worktree::remove_path(worktree_path, &path)?;
+                }
+                for (path, &(mode, hash)) in files.iter().filter(|(path, _)| selected(path)) {
+                    worktree::checkout_file(&db, worktree_path, path, mode, hash, symlinks)?;
+                }
+            }
+            None => {
+                if let Some(entry) = index
+                    .entries
+                    .iter()
+                    .find(|entry| entry.stage() != 0 && selected(&entry.path))
+                {
+                    anyhow::bail!("path '{}' is unmerged", entry.path);
+                }
+                let entries: Vec<_> = index
+                    .entries
+                    .iter()
+                    .filter(|entry| selected(&entry.path))
+                    .cloned()
+                    .collect();
+                for entry in entries {
+                    if !worktree::is_modified(worktree_path, &entry, index.format)? {
+                        continue;
+                    }
+                    // fresh stat, the file is the same as the index again
+                    let fresh = worktree::checkout_file(
+                        &db,
+                        worktree_path,
+                        &entry.path,
+                        entry.mode,
+                        entry.hash,
+                        symlinks,
+                    )?;
+                    index.add(fresh);
+                }
+            }
+        }
+    }
+    if options.staged
+        && let Some(files) = &source
+    {
+        index.set_files(files, selected);
+    }
+    worktree::refresh(&mut index)?;
+    index.write_locked(lock)
+}
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) staged: bool,
+    pub(crate) worktree: bool,
+    pub(crate) source: Option<String>,
+}
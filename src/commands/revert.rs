@@ -0,0 +1,28 @@
+use std::io::IsTerminal;
+
+use crate::commands::cherry_pick::Sequence;
+use crate::sequencer::{self, Action};
+
+// NOTE: undo existing commits with new commits on top of HEAD, see crate::sequencer
+// cargo run -- revert HEAD~1
+// message: Revert "<subject>" + "This reverts commit <hash>."
+// A..B            the commits of B that are not in A, the newest first
+// -n              only the index and the files, no commit
+// -m N            revert a merge, the changes it brought compared to its parent N
+// -e / --no-edit  the message goes through the editor, by default only when someone is there
+// --continue / --skip / --abort   same as cherry-pick
+pub(crate) fn invoke(
+    mut options: sequencer::Options,
+    no_edit: bool,
+    sequence: Option<Sequence>,
+    commits: &[String],
+) -> anyhow::Result<()> {
+    let interactive = std::io::stdin().is_terminal() && std::io::stdout().is_terminal();
+    options.edit |= interactive && !no_edit;
+    match sequence {
+        Some(Sequence::Continue) => sequencer::resume(),
+        Some(Sequence::Skip) => sequencer::skip(Action::Revert),
+        Some(Sequence::Abort) => sequencer::abort(),
+        None => sequencer::start(Action::Revert, options, commits),
+    }
+}
@@ -0,0 +1,155 @@
+use std::path::Path;
+
+use crate::commit::Commit;
+use crate::index::Index;
+use crate::odb;
+use crate::pathspec;
+use crate::quote;
+use crate::refs;
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: stop tracking files, and delete them unless --cached
+// cargo run -- rm f
+// -r          a dir removes every tracked file under it
+// --cached    only the index entry goes, the file stays (becomes untracked)
+// -f          skip the checks, without it nothing is removed when a file would lose
+//             something that is not committed:
+//             - staged content different from both the file and HEAD (even with --cached)
+//             - changes staged in the index (without --cached)
+//             - local modifications of the file (without --cached)
+pub(crate) fn invoke(options: Options, paths: &[String]) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !paths.is_empty(),
+        "No pathspec was given. Which files should I remove?"
+    );
+    let git_dir = Path::new(".git");
+    let worktree_path = Path::new(".");
+    let db = odb::open()?;
+    let index_path = git_dir.join("index");
+    let (mut index, lock) = Index::lock(&index_path)?;
+
+    let mut removed: Vec<String> = Vec::new();
+    for pathspec in paths {
+        let mut matched: Vec<&str> = index
+            .entries
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .filter(|path| pathspec::matches_one(pathspec, path))
+            .collect();
+        matched.dedup();
+        anyhow::ensure!(
+            !matched.is_empty(),
+            "pathspec '{pathspec}' did not match any files"
+        );
+        let is_file = matched
+            .iter()
+            .any(|path| *path == pathspec.trim_end_matches('/'));
+        anyhow::ensure!(
+            options.recursive || is_file,
+            "not removing '{}' recursively without -r",
+            pathspec.trim_end_matches('/')
+        );
+        removed.extend(matched.into_iter().map(str::to_string));
+    }
+    removed.sort();
+    removed.dedup();
+
+    if !options.force {
+        let head: Option<Files> = match refs::resolve(git_dir, "HEAD")? {
+            Some(hash) => Some(tree::files(&db, Commit::read(&db, &hash)?.tree)?),
+            None => None,
+        };
+        let (mut both, mut staged, mut local) = (Vec::new(), Vec::new(), Vec::new());
+        for path in &removed {
+            // a conflict is removed as it is
+            if index.is_unmerged(path) {
+                continue;
+            }
+            let Some(entry) = index.entries.iter().find(|entry| entry.path == *path) else {
+                continue;
+            };
+            // a file already deleted (or a dir in its place) has nothing to lose
+            let local_changes = std::fs::symlink_metadata(worktree_path.join(path))
+                .is_ok_and(|metadata| !metadata.is_dir())
+                && worktree::is_modified(worktree_path, entry, index.format)?;
+            // without a HEAD everything in the index is staged
+            let staged_changes = head
+                .as_ref()
+                .is_none_or(|files| files.get(path.as_str()) != Some(&(entry.mode, entry.hash)));
+            if local_changes && staged_changes {
+                both.push(path.as_str());
+            } else if !options.cached {
+                if staged_changes {
+                    staged.push(path.as_str());
+                }
+                if local_changes {
+                    local.push(path.as_str());
+                }
+            }
+        }
+        let mut errors = Vec::new();
+        if !both.is_empty() {
+            errors.push(refusal(
+                &both,
+                "staged content different from both the\nfile and the HEAD",
+                "(use -f to force removal)",
+            ));
+        }
+        if !staged.is_empty() {
+            errors.push(refusal(
+                &staged,
+                "changes staged in the index",
+                "(use --cached to keep the file, or -f to force removal)",
+            ));
+        }
+        if !local.is_empty() {
+            errors.push(refusal(
+                &local,
+                "local modifications",
+                "(use --cached to keep the file, or -f to force removal)",
+            ));
+        }
+        anyhow::ensure!(errors.is_empty(), "{}", errors.join("\n"));
+    }
+
+    for path in &removed {
+        if !options.quiet {
+            println!("rm '{}'", quote::quote_c(path.as_bytes()));
+        }
+        index.remove(path);
+    }
+    if !options.dry_run {
+        index.write_locked(lock)?;
+        if !options.cached {
+            for path in &removed {
+                worktree::remove_path(worktree_path, path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub(crate) struct Options {
+    pub(crate) cached: bool,
+    pub(crate) recursive: bool,
+    pub(crate) force: bool,
+    pub(crate) dry_run: bool,
+    pub(crate) quiet: bool,
+}
+
+// "the following file has local modifications:\n    f\n(use ...)", plural for many files
+fn refusal(paths: &[&str], what: &str, hint: &str) -> String {
+    let (files, has) = if paths.len() == 1 {
+        ("file", "has")
+    } else {
+        ("files", "have")
+    };
+    let mut message = format!("the following {files} {has} {what}:\n");
+    for path in paths {
+        message.push_str(&format!("    {path}\n"));
+    }
+    message.push_str(hint);
+    message
+}
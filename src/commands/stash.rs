@@ -0,0 +1,501 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commands::commit_tree::write_commit;
+use crate::commands::merge::entries;
+use crate::commands::reset::{self, Mode};
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::diff;
+use crate::hash::ObjectId;
+use crate::index::{Index, IndexEntry};
+use crate::merge::{self, ConflictStyle, Labels};
+use crate::objects::Object;
+use crate::odb::{self, Abbrev, CompositeDb, ObjectDatabase};
+use crate::pathspec;
+use crate::refs::{self, Head};
+use crate::revision;
+use crate::tree::{self, Files};
+use crate::worktree;
+
+// NOTE: put the local changes away and get back a clean worktree, same objects as git:
+//   w  "WIP on main: 1a2b3c4 subject"    tree = the tracked files as they are in the worktree
+//   |\  parents = HEAD, i (and u)
+//   | i  "index on main: 1a2b3c4 subject"  tree = the index, parent = HEAD
+//   u    "untracked files on main: ..."    tree = the untracked files (-u), no parent
+// refs/stash points to the last w, its reflog is the list (stash@{0} is the newest)
+// cargo run -- stash push -m "half done" -u -- src
+// cargo run -- stash list | show -p | apply | pop | drop | branch <name> | clear
+// <stash> is stash@{N}, or N alone, stash@{0} by default
+const STASH: &str = "refs/stash";
+
+pub(crate) fn push(options: PushOptions, paths: &[String]) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let worktree_path = Path::new(".");
+    let db = odb::open()?;
+    let head = revision::resolve(&db, git_dir, "HEAD")
+        .map_err(|_| anyhow::anyhow!("You do not have the initial commit yet"))?;
+    let head_commit = Commit::read(&db, &head.to_string())?;
+    let head_files = tree::files(&db, head_commit.tree)?;
+    let index_path = git_dir.join("index");
+    let index = Index::read(&index_path)?;
+    if let Some(entry) = index.entries.iter().find(|entry| entry.stage() != 0) {
+        anyhow::bail!("{}: needs merge\ncould not save index tree", entry.path);
+    }
+    let index_files = index.files();
+    let selected = |path: &str| paths.is_empty() || pathspec::matches(paths, path);
+    let untracked: Vec<String> = if options.include_untracked {
+        worktree::untracked(worktree_path, &index)?
+            .into_iter()
+            .filter(|path| selected(path))
+            .collect()
+    } else {
+        Vec::new()
+    };
+    for pathspec in paths {
+        let known = index_files
+            .keys()
+            .chain(head_files.keys())
+            .chain(&untracked)
+            .any(|path| pathspec::matches_one(pathspec, path));
+        anyhow::ensure!(
+            known,
+            "pathspec '{pathspec}' did not match any file(s) known to git\n\
+             Did you forget to 'git add'?"
+        );
+    }
+
+    // the tracked files with what the worktree has now
+    let mut worktree_files = index_files.clone();
+    for entry in index.entries.iter().filter(|entry| selected(&entry.path)) {
+        let path = worktree_path.join(&entry.path);
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+            worktree_files.remove(&entry.path);
+            continue;
+        };
+        if !worktree::is_modified(worktree_path, entry, index.format)? {
+            continue;
+        }
+        let hash = Object::blob_from_file(&path)?.write_to(&db)?;
+        let mut mode = IndexEntry::from_metadata(&entry.path, &metadata, hash, 0).mode;
+        if entry.mode == 0o120000 && metadata.is_file() {
+            mode = 0o120000;
+        }
+        worktree_files.insert(entry.path.clone(), (mode, hash));
+    }
+    let changed = |one: &Files, two: &Files| {
+        one.keys()
+            .chain(two.keys())
+            .filter(|path| selected(path))
+            .any(|path| one.get(path) != two.get(path))
+    };
+    if !changed(&index_files, &head_files)
+        && !changed(&worktree_files, &index_files)
+        && untracked.is_empty()
+    {
+        println!("No local changes to save");
+        return Ok(());
+    }
+
+    let branch = match refs::read_head(git_dir)? {
+        Head::Symbolic(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(_) => "(no branch)".to_string(),
+    };
+    let hex = head.to_string();
+    let on = format!(
+        "{branch}: {} {}",
+        Abbrev::new(&db, 7)?.abbrev(&hex),
+        head_commit.subject()
+    );
+    let index_commit = write_commit(
+        &db,
+        index.write_tree(&db)?,
+        vec![head],
+        format!("index on {on}\n"),
+    )?;
+    let mut parents = vec![head, index_commit];
+    if !untracked.is_empty() {
+        let mut files = Files::new();
+        for path in &untracked {
+            let full = worktree_path.join(path);
+            let metadata = std::fs::symlink_metadata(&full)
+                .with_context(|| format!("Failed to read stat for :{path}"))?;
+            let hash = Object::blob_from_file(&full)?.write_to(&db)?;
+            let mode = IndexEntry::from_metadata(path, &metadata, hash, 0).mode;
+            files.insert(path.clone(), (mode, hash));
+        }
+        parents.push(write_commit(
+            &db,
+            write_tree(&db, &files)?,
+            Vec::new(),
+            format!("untracked files on {on}\n"),
+        )?);
+    }
+    let message = match &options.message {
+        Some(message) => format!("On {branch}: {message}"),
+        None => format!("WIP on {on}"),
+    };
+    let stash = write_commit(
+        &db,
+        write_tree(&db, &worktree_files)?,
+        parents,
+        message.clone(),
+    )?;
+    refs::update(git_dir, STASH, &stash.to_string(), &message)?;
+    if !options.quiet {
+        println!("Saved working directory and index state {message}");
+    }
+
+    if paths.is_empty() {
+        reset::invoke(Some(Mode::Hard), true, &[], &[])?;
+    } else {
+        // only the paths go back to HEAD, in the index and in the worktree
+        let symlinks = Config::load()?.get_bool("core.symlinks")?.unwrap_or(true);
+        let (mut index, lock) = Index::lock(&index_path)?;
+        for path in index_files.keys().filter(|path| selected(path)) {
+            if !head_files.contains_key(path) {
+                worktree::remove_path(worktree_path, path)?;
+            }
+        }
+        for (path, &(mode, hash)) in head_files.iter().filter(|(path, _)| selected(path)) {
+            if worktree_files.get(path) != Some(&(mode, hash)) {
+                worktree::checkout_file(&db, worktree_path, path, mode, hash, symlinks)?;
+            }
+        }
+        index.set_files(&head_files, selected);
+        worktree::refresh(&mut index)?;
+        index.write_locked(lock)?;
+    }
+    for path in &untracked {
+        worktree::remove_path(worktree_path, path)?;
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+pub(crate) struct PushOptions {
+    pub(crate) message: Option<String>,
+    pub(crate) include_untracked: bool,
+    pub(crate) quiet: bool,
+}
+
+pub(crate) fn list() -> anyhow::Result<()> {
+    let log = refs::reflog(Path::new(".git"), STASH)?;
+    for (i, entry) in log.iter().rev().enumerate() {
+        println!("stash@{{{i}}}: {}", entry.message);
+    }
+    Ok(())
+}
+
+// NOTE: what the stash changed from the commit it was made on, --stat by default
+pub(crate) fn show(patch: bool, stat: bool, stash: Option<&str>) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let stash = find(&db, git_dir, stash)?;
+    let base = tree::files(&db, Commit::read(&db, &stash.base.to_string())?.tree)?;
+    let files = tree::files(&db, stash.commit.tree)?;
+    if stat || !patch {
+        print!("{}", diff::diff_stat(&db, &base, &files)?);
+    }
+    if stat && patch {
+        println!();
+    }
+    if patch {
+        print!("{}", diff::patch(&db, &base, &files)?);
+    }
+    Ok(())
+}
+
+// NOTE: the changes of the stash merged into the worktree (three-way, the base is the
+// commit the stash was made on), the index stays what it was but the new files are added
+// a conflict leaves the markers and the stages like merge does, exit code 1
+pub(crate) fn apply(stash: Option<&str>, quiet: bool) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let stash = find(&db, git_dir, stash)?;
+    if !restore(&db, git_dir, &stash, quiet)? {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+// NOTE: apply then drop, the stash is kept when the apply did not go well
+pub(crate) fn pop(stash: Option<&str>, quiet: bool) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let stash = find(&db, git_dir, stash)?;
+    anyhow::ensure!(
+        stash.position.is_some(),
+        "'{}' is not a stash reference",
+        stash.name
+    );
+    match restore(&db, git_dir, &stash, quiet) {
+        Ok(true) => remove(git_dir, &stash, quiet),
+        Ok(false) => {
+            println!("The stash entry is kept in case you need it again.");
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("error: {e:#}");
+            println!("The stash entry is kept in case you need it again.");
+            std::process::exit(1);
+        }
+    }
+}
+
+pub(crate) fn drop(stash: Option<&str>, quiet: bool) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let db = odb::open()?;
+    let stash = find(&db, git_dir, stash)?;
+    remove(git_dir, &stash, quiet)
+}
+
+// NOTE: a new branch from the commit the stash was made on, with the index and the files
+// of the stash (there is nothing to merge there), the stash is dropped
+pub(crate) fn branch(name: &str, stash: Option<&str>) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let worktree_path = Path::new(".");
+    let db = odb::open()?;
+    let stash = find(&db, git_dir, stash)?;
+    let full = format!("refs/heads/{name}");
+    anyhow::ensure!(
+        refs::resolve(git_dir, &full)?.is_none(),
+        "a branch named '{name}' already exists"
+    );
+    let untracked = untracked_files(&db, &stash)?;
+    check_untracked(worktree_path, &untracked)?;
+
+    let index_path = git_dir.join("index");
+    let index = Index::read(&index_path)?;
+    let files = tree::files(&db, stash.commit.tree)?;
+    worktree::update(
+        &db,
+        &index.files(),
+        &entries(&files),
+        &BTreeMap::new(),
+        "checkout",
+    )?;
+    let index_tree = Commit::read(&db, &stash.commit.parents[1].to_string())?.tree;
+    let (mut index, lock) = Index::lock(&index_path)?;
+    index.set_files(&tree::files(&db, index_tree)?, |_| true);
+    worktree::refresh(&mut index)?;
+    index.write_locked(lock)?;
+    write_untracked(&db, worktree_path, &untracked)?;
+
+    let previous = match refs::read_head(git_dir)? {
+        Head::Symbolic(name) => name
+            .strip_prefix("refs/heads/")
+            .unwrap_or(&name)
+            .to_string(),
+        Head::Detached(hash) => hash,
+    };
+    let base = stash.base.to_string();
+    refs::update(
+        git_dir,
+        &full,
+        &base,
+        &format!("branch: Created from {base}"),
+    )?;
+    refs::write_symbolic(
+        git_dir,
+        "HEAD",
+        &full,
+        &format!("checkout: moving from {previous} to {name}"),
+    )?;
+    println!("Switched to a new branch '{name}'");
+    if stash.position.is_some() {
+        remove(git_dir, &stash, false)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn clear() -> anyhow::Result<()> {
+    refs::delete(Path::new(".git"), STASH)
+}
+
+// a stash commit and where it comes from
+struct Stash {
+    // "stash@{1}" as given, "refs/stash@{1}" for the default and a number
+    name: String,
+    // in the reflog, 0 is the newest, None for a commit given by its hash
+    position: Option<usize>,
+    commit: Commit,
+    id: ObjectId,
+    // the commit it was made on (first parent)
+    base: ObjectId,
+}
+
+fn find(db: &CompositeDb, git_dir: &Path, stash: Option<&str>) -> anyhow::Result<Stash> {
+    anyhow::ensure!(
+        refs::resolve(git_dir, STASH)?.is_some(),
+        "No stash entries found."
+    );
+    let number = |text: &str| text.parse::<usize>().ok();
+    let (name, position) = match stash {
+        None => ("refs/stash@{0}".to_string(), Some(0)),
+        Some(text) if number(text).is_some() => (format!("refs/stash@{{{text}}}"), number(text)),
+        Some(text) => {
+            let position = text
+                .strip_prefix("stash@{")
+                .or_else(|| text.strip_prefix("refs/stash@{"))
+                .and_then(|rest| rest.strip_suffix('}'))
+                .and_then(number);
+            (text.to_string(), position)
+        }
+    };
+    let id = match position {
+        Some(position) => {
+            let log = refs::reflog(git_dir, STASH)?;
+            let Some(entry) = log.iter().rev().nth(position) else {
+                anyhow::bail!("log for '{STASH}' only has {} entries", log.len());
+            };
+            ObjectId::from_hex(&entry.new)?
+        }
+        None => revision::resolve(db, git_dir, &name)
+            .map_err(|_| anyhow::anyhow!("{name} is not a valid reference"))?,
+    };
+    let commit = Commit::read(db, &id.to_string())
+        .with_context(|| format!("'{name}' is not a stash-like commit"))?;
+    anyhow::ensure!(
+        matches!(commit.parents.len(), 2 | 3),
+        "'{name}' is not a stash-like commit"
+    );
+    Ok(Stash {
+        name,
+        position,
+        base: commit.parents[0],
+        commit,
+        id,
+    })
+}
+
+// true when it went well, false on a conflict
+fn restore(db: &CompositeDb, git_dir: &Path, stash: &Stash, quiet: bool) -> anyhow::Result<bool> {
+    let worktree_path = Path::new(".");
+    let index_path = git_dir.join("index");
+    let index = Index::read(&index_path)?;
+    anyhow::ensure!(
+        !index.entries.iter().any(|entry| entry.stage() != 0),
+        "Cannot apply a stash in the middle of a merge"
+    );
+    let untracked = untracked_files(db, stash)?;
+    check_untracked(worktree_path, &untracked)?;
+
+    let base_tree = Commit::read(db, &stash.base.to_string())?.tree;
+    if base_tree == stash.commit.tree {
+        println!("Already up to date.");
+        write_untracked(db, worktree_path, &untracked)?;
+        return Ok(true);
+    }
+    let current = index.files();
+    let style = match Config::load()?.get("merge.conflictStyle") {
+        Some(style) => style.parse()?,
+        None => ConflictStyle::default(),
+    };
+    let labels = Labels {
+        ours: "Updated upstream".to_string(),
+        base: "Stash base".to_string(),
+        theirs: "Stashed changes".to_string(),
+    };
+    let result = merge::merge_trees(
+        db,
+        base_tree,
+        index.write_tree(db)?,
+        stash.commit.tree,
+        labels,
+        style,
+    )?;
+    worktree::update(db, &current, &result.entries, &result.contents, "merge")?;
+    if !quiet || !result.is_clean() {
+        for message in &result.messages {
+            println!("{message}");
+        }
+    }
+    write_untracked(db, worktree_path, &untracked)?;
+    if !result.is_clean() {
+        return Ok(false);
+    }
+
+    // the stashed changes are not staged again, only the files that are new
+    let (mut index, lock) = Index::lock(&index_path)?;
+    let mut files = current.clone();
+    for (path, entry) in index.files() {
+        if !current.contains_key(&path) {
+            files.insert(path, entry);
+        }
+    }
+    index.set_files(&files, |_| true);
+    worktree::refresh(&mut index)?;
+    index.write_locked(lock)?;
+    Ok(true)
+}
+
+// NOTE: the entry leaves the reflog (the next one now starts where the dropped one started),
+// the last one going removes refs/stash
+fn remove(git_dir: &Path, stash: &Stash, quiet: bool) -> anyhow::Result<()> {
+    let Some(position) = stash.position else {
+        anyhow::bail!("'{}' is not a stash reference", stash.name);
+    };
+    let mut log = refs::reflog(git_dir, STASH)?;
+    let index = log.len() - 1 - position;
+    let dropped = log.remove(index);
+    if let Some(next) = log.get_mut(index) {
+        next.old = dropped.old;
+    }
+    match log.last() {
+        // the update adds a line to the log, the whole log is replaced after it
+        Some(last) => {
+            refs::update(git_dir, STASH, &last.new, &last.message)?;
+            refs::write_reflog(git_dir, STASH, &log)?;
+        }
+        None => refs::delete(git_dir, STASH)?,
+    }
+    if !quiet {
+        println!("Dropped {} ({})", stash.name, stash.id);
+    }
+    Ok(())
+}
+
+// the third parent, -u
+fn untracked_files(db: &CompositeDb, stash: &Stash) -> anyhow::Result<Files> {
+    match stash.commit.parents.get(2) {
+        Some(commit) => tree::files(db, Commit::read(db, &commit.to_string())?.tree),
+        None => Ok(Files::new()),
+    }
+}
+
+fn check_untracked(worktree_path: &Path, files: &Files) -> anyhow::Result<()> {
+    let existing: Vec<&String> = files
+        .keys()
+        .filter(|path| std::fs::symlink_metadata(worktree_path.join(path)).is_ok())
+        .collect();
+    if !existing.is_empty() {
+        for path in existing {
+            eprintln!("{path} already exists, no checkout");
+        }
+        anyhow::bail!("could not restore untracked files from stash");
+    }
+    Ok(())
+}
+
+// back in the worktree only, they stay untracked
+fn write_untracked(db: &CompositeDb, worktree_path: &Path, files: &Files) -> anyhow::Result<()> {
+    let symlinks = Config::load()?.get_bool("core.symlinks")?.unwrap_or(true);
+    for (path, &(mode, hash)) in files {
+        worktree::checkout_file(db, worktree_path, path, mode, hash, symlinks)?;
+    }
+    Ok(())
+}
+
+fn write_tree(db: &dyn ObjectDatabase, files: &Files) -> anyhow::Result<ObjectId> {
+    let index = Index {
+        format: db.format(),
+        entries: entries(files),
+    };
+    index.write_tree(db)
+}
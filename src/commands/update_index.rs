@@ -1,102 +1,48 @@
-use std::{
-    fs::{self, OpenOptions},
-    io::Write,
-    os::unix::fs::MetadataExt,
-    path::Path,
-};
+use std::{fs, path::Path};
 
 use anyhow::Context;
-use sha1::{Digest, Sha1};
 
+use crate::config::Config;
+use crate::index::{Index, IndexEntry};
 use crate::objects::Object;
-const READER_VERSION: u32 = 2;
-pub(crate) fn invoke(add: bool, file_path: Option<String>) -> anyhow::Result<()> {
-    eprintln!("{} {:?} ", add, file_path);
-    if let Some(file_path) = file_path {
-        let mut buf: Vec<u8> = Vec::with_capacity(128);
-        // header
-        buf.extend(b"DIRC");
-        buf.extend(READER_VERSION.to_be_bytes());
-        buf.extend((1 as u32).to_be_bytes());
-        // entry start;
-        let metadata = fs::metadata(&file_path).context("Reading metadata for the file")?;
-        // all the meatadata if the value is greater then u32::MAX it will get truncated
-        buf.extend((metadata.ctime() as u32).to_be_bytes());
-        buf.extend((metadata.ctime_nsec() as u32).to_be_bytes());
-        buf.extend((metadata.mtime() as u32).to_be_bytes());
-        buf.extend((metadata.mtime_nsec() as u32).to_be_bytes());
-        buf.extend((metadata.dev() as u32).to_be_bytes());
-        buf.extend((metadata.ino() as u32).to_be_bytes());
-        buf.extend(metadata.mode().to_be_bytes());
-        buf.extend(metadata.uid().to_be_bytes());
-        buf.extend(metadata.gid().to_be_bytes());
-        buf.extend((metadata.size() as u32).to_be_bytes());
-        let object = Object::blob_from_file(&file_path)?;
-        let sha1 = object
-            .write(std::io::sink())
-            .context("Create the hash of the blob")?;
-        buf.extend(sha1);
-        // NOTE: you need to handle the merge conflict related stage
-        let flag = build_flag(0, file_path.as_bytes().len()); // here we don't need string length but we need bytes len
-        buf.extend(flag.to_be_bytes());
-        buf.extend(file_path.as_bytes());
-        buf.extend(b"\0");
-        // one file total length should be multiply of 8
-        let padding = vec![0; (8 - (buf.len() % 8)) % 8];
-        buf.extend(padding);
-        // entry end;
-        let mut hasher = Sha1::new();
-        hasher.update(&buf);
-        let hash = hasher.finalize();
-        buf.extend(hash);
-        write_atomic_index(buf)?;
-    }
-    Ok(())
-}
-fn build_flag(stage: u16, path_length: usize) -> u16 {
-    let stage = stage & 0b11; // enforce 2 bits
-    let path_length = std::cmp::min(path_length as u16, 0x0FFF); // enforce 12 bits
-    (stage << 12) | path_length // we shift 12 bit of the stage so it will make room for the path
-    // length
-}
 
-fn write_atomic_index(buf: Vec<u8>) -> anyhow::Result<()> {
-    let index_dir = Path::new(".git");
-    let index_lock_file = index_dir.join("index.lock");
-    let index_file = index_dir.join("index");
+// NOTE: add or refresh one file in the .git/index
+// cargo run -- update-index --add file.txt
+// the blob get written into .git/objects, the entry keep the stat of the file so git can
+// later see if the file changed without reading it.
+// without --add the file have to be in the index already
+pub(crate) fn invoke(add: bool, file_path: Option<String>) -> anyhow::Result<()> {
+    let Some(file_path) = file_path else {
+        return Ok(());
+    };
+    let index_path = Path::new(".git/index");
+    let (mut index, lock) = Index::lock(index_path)?;
+    anyhow::ensure!(
+        add || index.entries.iter().any(|e| e.path == file_path),
+        "{file_path}: cannot add to the index - missing --add option?"
+    );
+    let metadata = fs::symlink_metadata(&file_path).context("Reading metadata for the file")?;
+    let hash = Object::blob_from_file(&file_path)?
+        .write_to_object()
+        .context("Create the hash of the blob")?;
+    let mut entry = IndexEntry::from_metadata(&file_path, &metadata, hash, 0);
+    // NOTE: with core.symlinks = false the symlink was checked out as a plain file
+    // holding the target, it stays a symlink in the index
+    let was_symlink = index
+        .entries
+        .iter()
+        .any(|e| e.path == file_path && e.mode == 0o120000);
+    if was_symlink
+        && metadata.is_file()
+        && Config::load()?.get_bool("core.symlinks")? == Some(false)
     {
-        let mut tmp = OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(index_lock_file)
-            .context("Create the .git/index.lock file.")?;
-        tmp.write_all(&buf[..])
-            .context("Write the .git/index.lock file.")?;
-        tmp.sync_all().context("Sync all .git/index.lock file.")?;
+        entry.mode = 0o120000;
     }
-    fs::rename(".git/index.lock", index_file)
-        .context("Rename the .git/index.lock file to .git/index file")?;
+    // NOTE: adding a conflicted file resolves it, the stages 1-3 go away
+    index.remove(&file_path);
+    index.add(entry);
+    index.write_locked(lock)?;
     Ok(())
-    // NOTE:
-    // We use `OpenOptions` instead of `File::open` / `File::create` because writing
-    // `.git/index` must be done atomically and exclusively.
-    //
-    // - `OpenOptions::create_new(true)` maps to `O_CREAT | O_EXCL` and guarantees
-    //   that `.git/index.lock` is created ONLY if it does not already exist.
-    //   This prevents concurrent writers and avoids race conditions.
-    //
-    // - The index is written fully to `index.lock`, NOT directly to `index`.
-    //   Writing directly risks partial writes and repository corruption.
-    //
-    // - `sync_all()` (fsync) is required to force file contents + metadata
-    //   from the OS page cache to stable storage before renaming.
-    //   Without this, a crash or power loss could leave a corrupted index.
-    //
-    // Correct order (same as Git core):
-    //   write → fsync(file) → rename(index.lock → index) → fsync(.git dir)
-    //
-    // This guarantees atomicity, durability, and corruption-free updates.
 }
 
 // NOTE: you need to read byte by byte first 12 is the header
@@ -116,7 +62,7 @@ fn write_atomic_index(buf: Vec<u8>) -> anyhow::Result<()> {
 // | uid        | 4            |
 // | gid        | 4            |
 // | file_size  | 4            |
-// | sha1       | 20           |
+// | hash       | 20 (32)      |  // 32 bytes in the sha256 repo
 // | flags      | 2            |
 // | path       | N            |
 // | path       |              |
@@ -125,7 +71,7 @@ fn write_atomic_index(buf: Vec<u8>) -> anyhow::Result<()> {
 // if the total size of each entry is not multiple of 8 the needs to add the padding
 // padding = (8 - (entry_size_raw % 8)) % 8
 // path_length = 8
-// raw_size = 62 + 9 = 71
+// raw_size = 62 + 9 = 71 (74 + 9 = 83 with the sha256 hash)
 // 71 % 8 = 7
 // padding = 1
 // padding is \0 null bytes
@@ -0,0 +1,506 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Context;
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeZone};
+
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::objects::{Kind, Object};
+use crate::odb::ObjectDatabase;
+
+// NOTE: commit object content
+// tree <hash>
+// parent <hash>                         (zero or more, first one is the branch we were on)
+// author Name <email> 1700000000 +0100
+// committer Name <email> 1700000000 +0100
+// encoding ISO-8859-1                   (optional, message is UTF-8 when missing)
+// mergetag object ...                   (any other header, kept as they are)
+// gpgsig -----BEGIN PGP SIGNATURE-----  (value on many lines, the next ones start with ' ')
+//  ...
+//  -----END PGP SIGNATURE-----
+//
+// message
+// parse(serialize(commit)) == commit and serialize(parse(bytes)) == bytes
+// for everything git writes (headers in the order above)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Commit {
+    pub(crate) tree: ObjectId,
+    pub(crate) parents: Vec<ObjectId>,
+    pub(crate) author: Signature,
+    pub(crate) committer: Signature,
+    pub(crate) encoding: Option<String>,
+    pub(crate) extra_headers: Vec<(String, Vec<u8>)>,
+    pub(crate) gpgsig: Option<Vec<u8>>,
+    // NOTE: bytes, the message (and the names) are in the encoding of the header,
+    // message_text() is the UTF-8 of it
+    pub(crate) message: Vec<u8>,
+}
+
+impl Commit {
+    pub(crate) fn read(db: &dyn ObjectDatabase, hash: &str) -> anyhow::Result<Commit> {
+        let mut object = db.read(hash)?;
+        anyhow::ensure!(
+            object.kind == Kind::Commit,
+            "{hash} is a {} not a commit",
+            object.kind
+        );
+        let mut content = Vec::with_capacity(object.expected_size as usize);
+        object
+            .reader
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read the commit {hash}"))?;
+        Commit::parse(&content).with_context(|| format!("bad commit object {hash}"))
+    }
+
+    pub(crate) fn parse(content: &[u8]) -> anyhow::Result<Commit> {
+        let (headers, message) = match content.windows(2).position(|pair| pair == b"\n\n") {
+            Some(at) => (&content[..at], &content[at + 2..]),
+            None => (content.strip_suffix(b"\n").unwrap_or(content), &[][..]),
+        };
+        let headers = parse_headers(headers)?;
+        let mut headers = headers.into_iter().peekable();
+
+        let hash = |value: &[u8], what: &str| {
+            std::str::from_utf8(value)
+                .map_err(anyhow::Error::from)
+                .and_then(ObjectId::from_hex)
+                .with_context(|| format!("bad {what} hash"))
+        };
+        let Some((_, tree)) = headers.next_if(|(name, _)| name == "tree") else {
+            anyhow::bail!("missing tree header");
+        };
+        let tree = hash(&tree, "tree")?;
+        let mut parents = Vec::new();
+        while let Some((_, parent)) = headers.next_if(|(name, _)| name == "parent") {
+            parents.push(hash(&parent, "parent")?);
+        }
+        let Some((_, author)) = headers.next_if(|(name, _)| name == "author") else {
+            anyhow::bail!("missing author header");
+        };
+        let Some((_, committer)) = headers.next_if(|(name, _)| name == "committer") else {
+            anyhow::bail!("missing committer header");
+        };
+        // an encoding that is not even UTF-8 stays with the other headers, it is written
+        // back at the same place
+        let encoding = headers
+            .next_if(|(name, value)| name == "encoding" && std::str::from_utf8(value).is_ok())
+            .map(|(_, value)| String::from_utf8_lossy(&value).into_owned());
+        let mut extra_headers: Vec<(String, Vec<u8>)> = headers.collect();
+        let gpgsig = match extra_headers.last() {
+            Some((name, _)) if name == "gpgsig" => extra_headers.pop().map(|(_, value)| value),
+            _ => None,
+        };
+        Ok(Commit {
+            tree,
+            parents,
+            author: Signature::parse(&author)?,
+            committer: Signature::parse(&committer)?,
+            encoding,
+            extra_headers,
+            gpgsig,
+            message: message.to_vec(),
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        push_header(&mut content, "tree", self.tree.to_string().as_bytes());
+        for parent in &self.parents {
+            push_header(&mut content, "parent", parent.to_string().as_bytes());
+        }
+        push_header(&mut content, "author", &self.author.to_bytes());
+        push_header(&mut content, "committer", &self.committer.to_bytes());
+        if let Some(encoding) = &self.encoding {
+            push_header(&mut content, "encoding", encoding.as_bytes());
+        }
+        for (name, value) in &self.extra_headers {
+            push_header(&mut content, name, value);
+        }
+        if let Some(gpgsig) = &self.gpgsig {
+            push_header(&mut content, "gpgsig", gpgsig);
+        }
+        content.push(b'\n');
+        content.extend_from_slice(&self.message);
+        content
+    }
+
+    pub(crate) fn object(&self) -> Object<impl Read> {
+        let content = self.to_bytes();
+        Object {
+            kind: Kind::Commit,
+            expected_size: content.len() as u64,
+            reader: Cursor::new(content),
+        }
+    }
+
+    // NOTE: like git we re-encode to UTF-8 to show a message or to make a new commit of it.
+    // latin-1 is the one we know (every byte is the same code point), anything else is
+    // read as UTF-8 and a bad byte becomes U+FFFD
+    pub(crate) fn decode(&self, bytes: &[u8]) -> String {
+        let latin1 = self.encoding.as_deref().is_some_and(|encoding| {
+            ["iso-8859-1", "iso8859-1", "latin1", "latin-1"]
+                .iter()
+                .any(|name| encoding.eq_ignore_ascii_case(name))
+        });
+        if latin1 {
+            bytes.iter().map(|&b| b as char).collect()
+        } else {
+            String::from_utf8_lossy(bytes).into_owned()
+        }
+    }
+
+    pub(crate) fn message_text(&self) -> String {
+        self.decode(&self.message)
+    }
+
+    // first line of the message
+    pub(crate) fn subject(&self) -> String {
+        self.message_text().lines().next().unwrap_or("").to_string()
+    }
+}
+
+// "name value" lines, a line starting with ' ' continue the value of the previous one
+fn parse_headers(headers: &[u8]) -> anyhow::Result<Vec<(String, Vec<u8>)>> {
+    let mut parsed: Vec<(String, Vec<u8>)> = Vec::new();
+    for line in headers.split(|&b| b == b'\n') {
+        if let Some(continuation) = line.strip_prefix(b" ") {
+            let Some((_, value)) = parsed.last_mut() else {
+                anyhow::bail!("continuation line before any header");
+            };
+            value.push(b'\n');
+            value.extend_from_slice(continuation);
+            continue;
+        }
+        let (name, value) = match line.iter().position(|&b| b == b' ') {
+            Some(at) => (&line[..at], &line[at + 1..]),
+            None => (line, &[][..]),
+        };
+        parsed.push((String::from_utf8_lossy(name).into_owned(), value.to_vec()));
+    }
+    Ok(parsed)
+}
+
+fn push_header(content: &mut Vec<u8>, name: &str, value: &[u8]) {
+    content.extend_from_slice(name.as_bytes());
+    content.push(b' ');
+    for &b in value {
+        content.push(b);
+        if b == b'\n' {
+            content.push(b' ');
+        }
+    }
+    content.push(b'\n');
+}
+
+// NOTE: who and when, "Name <email> <unix time> <+hhmm>"
+// timezone is kept as written ("+0530", "-0000") so the commit hash does not change,
+// name and email are bytes for the same reason (Commit::decode to show them)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Signature {
+    pub(crate) name: Vec<u8>,
+    pub(crate) email: Vec<u8>,
+    pub(crate) time: i64,
+    pub(crate) timezone: String,
+}
+
+// for the reflog and messages, lossy when the name is not UTF-8
+impl std::fmt::Display for Signature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.to_bytes()))
+    }
+}
+
+impl Signature {
+    pub(crate) fn parse(value: &[u8]) -> anyhow::Result<Signature> {
+        let shown = String::from_utf8_lossy(value);
+        let (Some(open), Some(close)) = (
+            value.iter().position(|&b| b == b'<'),
+            value.iter().rposition(|&b| b == b'>'),
+        ) else {
+            anyhow::bail!("bad signature '{shown}'");
+        };
+        anyhow::ensure!(open < close, "bad signature '{shown}'");
+        let date = String::from_utf8_lossy(&value[close + 1..]);
+        let mut date = date.split_whitespace();
+        let (Some(time), Some(timezone)) = (date.next(), date.next()) else {
+            anyhow::bail!("bad date in signature '{shown}'");
+        };
+        Ok(Signature {
+            name: value[..open].trim_ascii_end().to_vec(),
+            email: value[open + 1..close].to_vec(),
+            time: time
+                .parse()
+                .with_context(|| format!("bad date in signature '{shown}'"))?,
+            timezone: timezone.to_string(),
+        })
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.name.clone();
+        bytes.extend_from_slice(b" <");
+        bytes.extend_from_slice(&self.email);
+        bytes.extend_from_slice(format!("> {} {}", self.time, self.timezone).as_bytes());
+        bytes
+    }
+}
+
+impl Signature {
+    // NOTE: GIT_AUTHOR_NAME, GIT_AUTHOR_EMAIL, GIT_AUTHOR_DATE then user.name / user.email
+    // (GIT_COMMITTER_* for the committer), the date is now when not set
+    pub(crate) fn author(config: &Config) -> anyhow::Result<Signature> {
+        Signature::from_env(config, "AUTHOR")
+    }
+
+    pub(crate) fn committer(config: &Config) -> anyhow::Result<Signature> {
+        Signature::from_env(config, "COMMITTER")
+    }
+
+    fn from_env(config: &Config, role: &str) -> anyhow::Result<Signature> {
+        let lookup = |variable: &str, key: &str| {
+            std::env::var(format!("GIT_{role}_{variable}"))
+                .ok()
+                .or_else(|| config.get(key).map(str::to_string))
+                // TODO: currently only support the local username email not global
+                // if you need to access global try to read the ~/.gitconfig
+                .unwrap_or_else(|| "none".to_string())
+        };
+        let (time, timezone) = match std::env::var(format!("GIT_{role}_DATE")) {
+            Ok(date) => parse_date(&date)?,
+            Err(_) => now(),
+        };
+        Ok(Signature {
+            name: lookup("NAME", "user.name").into_bytes(),
+            email: lookup("EMAIL", "user.email").into_bytes(),
+            time,
+            timezone,
+        })
+    }
+}
+
+impl Signature {
+    // NOTE: --author "A U Thor <author@example.com>", the date stay the same
+    pub(crate) fn with_ident(&self, ident: &str) -> anyhow::Result<Signature> {
+        let (Some(open), Some(close)) = (ident.find('<'), ident.rfind('>')) else {
+            anyhow::bail!("--author '{ident}' is not 'Name <email>'");
+        };
+        anyhow::ensure!(open < close, "--author '{ident}' is not 'Name <email>'");
+        Ok(Signature {
+            name: ident[..open].trim().as_bytes().to_vec(),
+            email: ident.as_bytes()[open + 1..close].to_vec(),
+            ..self.clone()
+        })
+    }
+}
+
+fn now() -> (i64, String) {
+    let now = Local::now();
+    (now.timestamp(), now.format("%z").to_string())
+}
+
+// NOTE: the dates git accept in GIT_AUTHOR_DATE and --date
+// 1700000000 +0100 / @1700000000 [+0100]   -> unix time (git internal format)
+// 2023-11-14T22:13:20[+01:00] / 2023-11-14 22:13:20 [+0100]   -> ISO 8601, local time without zone
+// Tue, 14 Nov 2023 22:13:20 +0100          -> RFC 2822
+// now
+pub(crate) fn parse_date(value: &str) -> anyhow::Result<(i64, String)> {
+    let value = value.trim();
+    if value == "now" {
+        return Ok(now());
+    }
+    let unix = value.strip_prefix('@').unwrap_or(value);
+    let mut words = unix.split_whitespace();
+    if let Some(Ok(time)) = words.next().map(str::parse::<i64>) {
+        let timezone = match words.next() {
+            Some(timezone) if parse_timezone(timezone).is_some() => timezone.to_string(),
+            Some(_) => anyhow::bail!("invalid date format: {value}"),
+            None => "+0000".to_string(),
+        };
+        return Ok((time, timezone));
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(value) {
+        return Ok(from_datetime(date));
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%#z",
+        "%Y-%m-%d %H:%M:%S %#z",
+        "%Y-%m-%d %H:%M:%S%#z",
+    ] {
+        if let Ok(date) = DateTime::parse_from_str(value, format) {
+            return Ok(from_datetime(date));
+        }
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S"] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(value, format) {
+            let Some(date) = Local.from_local_datetime(&date).earliest() else {
+                anyhow::bail!("invalid date format: {value}");
+            };
+            return Ok(from_datetime(date.fixed_offset()));
+        }
+    }
+    anyhow::bail!("invalid date format: {value}")
+}
+
+fn from_datetime(date: DateTime<FixedOffset>) -> (i64, String) {
+    (date.timestamp(), date.format("%z").to_string())
+}
+
+// "+0100" -> 60 minutes
+pub(crate) fn parse_timezone(timezone: &str) -> Option<i32> {
+    let (sign, digits) = match timezone.as_bytes().first()? {
+        b'+' => (1, &timezone[1..]),
+        b'-' => (-1, &timezone[1..]),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i32 = digits[..2].parse().ok()?;
+    let minutes: i32 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 60 + minutes))
+}
+
+// "Mon Oct 19 12:00:00 2026 +0200", in the timezone of the signature
+pub(crate) fn format_date(time: i64, timezone: &str) -> String {
+    let minutes = parse_timezone(timezone).unwrap_or(0);
+    let offset = FixedOffset::east_opt(minutes * 60).unwrap_or(FixedOffset::east_opt(0).unwrap());
+    match DateTime::from_timestamp(time, 0) {
+        Some(date) => format!(
+            "{} {timezone}",
+            date.with_timezone(&offset).format("%a %b %-d %H:%M:%S %Y")
+        ),
+        None => format!("{time} {timezone}"),
+    }
+}
+
+// NOTE: what happen to the message before it is stored (--cleanup, commit.cleanup)
+// strip       -> remove the comment lines ("# ..."), then whitespace
+// whitespace  -> trailing spaces, leading/trailing empty lines, many empty lines become one
+// verbatim    -> keep it as it is
+// scissors    -> whitespace, and everything from the "# --- >8 ---" line is dropped
+// default     -> strip when the editor was used, whitespace otherwise
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Cleanup {
+    Strip,
+    Whitespace,
+    Verbatim,
+    Scissors,
+    Default,
+}
+
+impl std::str::FromStr for Cleanup {
+    type Err = anyhow::Error;
+    fn from_str(mode: &str) -> anyhow::Result<Self> {
+        match mode {
+            "strip" => Ok(Cleanup::Strip),
+            "whitespace" => Ok(Cleanup::Whitespace),
+            "verbatim" => Ok(Cleanup::Verbatim),
+            "scissors" => Ok(Cleanup::Scissors),
+            "default" => Ok(Cleanup::Default),
+            _ => anyhow::bail!("Invalid cleanup mode {mode}"),
+        }
+    }
+}
+
+impl Cleanup {
+    // the mode that is really used, Default and Scissors depend on the editor
+    pub(crate) fn resolve(self, edited: bool) -> Cleanup {
+        match self {
+            Cleanup::Default if edited => Cleanup::Strip,
+            Cleanup::Default => Cleanup::Whitespace,
+            Cleanup::Scissors if !edited => Cleanup::Whitespace,
+            mode => mode,
+        }
+    }
+
+    // help text after the message in the editor
+    pub(crate) fn editor_help(self, comment: char) -> String {
+        match self {
+            Cleanup::Strip => format!(
+                "{comment} Please enter the commit message for your changes. Lines starting\n\
+                 {comment} with '{comment}' will be ignored, and an empty message aborts the commit.\n"
+            ),
+            Cleanup::Scissors => format!(
+                "{}\n\
+                 {comment} Do not modify or remove the line above.\n\
+                 {comment} Everything below it will be ignored.\n",
+                scissors_line(comment)
+            ),
+            _ => format!(
+                "{comment} Please enter the commit message for your changes. Lines starting\n\
+                 {comment} with '{comment}' will be kept; you may remove them yourself if you want to.\n\
+                 {comment} An empty message aborts the commit.\n"
+            ),
+        }
+    }
+
+    pub(crate) fn apply(self, message: &str, comment: char) -> String {
+        match self {
+            Cleanup::Verbatim => message.to_string(),
+            Cleanup::Strip => stripspace(message, Some(comment)),
+            Cleanup::Scissors => {
+                let scissors = scissors_line(comment);
+                let end = message
+                    .split_inclusive('\n')
+                    .take_while(|line| line.trim_end() != scissors)
+                    .map(str::len)
+                    .sum();
+                stripspace(&message[..end], None)
+            }
+            Cleanup::Whitespace | Cleanup::Default => stripspace(message, None),
+        }
+    }
+}
+
+fn scissors_line(comment: char) -> String {
+    format!("{comment} ------------------------ >8 ------------------------")
+}
+
+// NOTE: git stripspace
+fn stripspace(message: &str, comment: Option<char>) -> String {
+    let mut cleaned = String::new();
+    let mut empty_lines = false;
+    for line in message.split('\n') {
+        if comment.is_some_and(|comment| line.starts_with(comment)) {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            empty_lines = !cleaned.is_empty();
+            continue;
+        }
+        if empty_lines {
+            cleaned.push('\n');
+            empty_lines = false;
+        }
+        cleaned.push_str(line);
+        cleaned.push('\n');
+    }
+    cleaned
+}
+
+// NOTE: "Signed-off-by: Name <email>" trailer at the end of the message
+// goes right under the other trailers of the last paragraph, after an empty line otherwise
+// (the subject is never a trailer), nothing happen when it is already the last line
+pub(crate) fn add_signoff(message: &str, signoff: &str) -> String {
+    let body = message.trim_end();
+    let paragraphs: Vec<&str> = body.split("\n\n").collect();
+    let last = paragraphs.last().copied().unwrap_or("");
+    if last.lines().last() == Some(signoff) {
+        return format!("{body}\n");
+    }
+    let is_trailer = |line: &str| {
+        line.split_once(": ").is_some_and(|(token, _)| {
+            !token.is_empty()
+                && token
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+        })
+    };
+    if body.is_empty() {
+        format!("\n{signoff}\n")
+    } else if paragraphs.len() > 1 && last.lines().all(is_trailer) {
+        format!("{body}\n{signoff}\n")
+    } else {
+        format!("{body}\n\n{signoff}\n")
+    }
+}
@@ -0,0 +1,678 @@
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::hash::{Hasher, ObjectFormat, ObjectId};
+use crate::odb::{self, ObjectDatabase};
+use crate::tree;
+
+// NOTE: .git/objects/info/commit-graph, what a walk of the history need about every commit
+// without inflating the commit objects (same file as git, version 1)
+// "CGPH" | version 1 | hash version (1 sha1, 2 sha256) | chunk count | base graphs (0)
+// chunk table: (id u32, offset u64) per chunk, then a 0 id with the end offset
+// OIDF  fanout, 256 x u32, how many commits have a first byte <= i
+// OIDL  the sorted commit hashes, the position in here is how commits point to each other
+// CDAT  per commit: tree hash | parent 1 | parent 2 | generation << 2 + time >> 32 | time
+//       a parent is a position, 0x70000000 is no parent, 0x80000000 | i means the parents
+//       after the first are in EDGE from i (octopus merge)
+// EDGE  positions, the last one of a list has 0x80000000 set
+// BIDX  per commit the end of its bloom filter in BDAT (u32)
+// BDAT  hash version 1 | hash count 7 | bits per entry 10, then the filters
+// the last bytes are the hash of everything before
+// generation is the topological level (1 for a root, max of the parents + 1)
+// TODO: split graphs (info/commit-graphs/commit-graph-chain) are not read
+const SIGNATURE: &[u8] = b"CGPH";
+const VERSION: u8 = 1;
+const CHUNK_OID_FANOUT: u32 = 0x4f49_4446;
+const CHUNK_OID_LOOKUP: u32 = 0x4f49_444c;
+const CHUNK_COMMIT_DATA: u32 = 0x4344_4154;
+const CHUNK_EXTRA_EDGES: u32 = 0x4544_4745;
+const CHUNK_BLOOM_INDEXES: u32 = 0x4249_4458;
+const CHUNK_BLOOM_DATA: u32 = 0x4244_4154;
+const PARENT_NONE: u32 = 0x7000_0000;
+const EXTRA_EDGES: u32 = 0x8000_0000;
+const LAST_EDGE: u32 = 0x8000_0000;
+const GENERATION_MAX: u64 = 0x3fff_ffff;
+const TIME_MAX: i64 = (1 << 34) - 1;
+
+// NOTE: bloom filter of the paths a commit changed (compared to its first parent)
+// a path not in the filter was surely not changed, so log -- path skips the tree diff
+// every changed file and its parent dirs are added, 10 bits per path, 7 hashes
+// a commit with more than 512 changed paths get the "everything changed" filter
+const BLOOM_VERSION: u32 = 1;
+const BLOOM_HASHES: u32 = 7;
+const BLOOM_BITS_PER_ENTRY: u32 = 10;
+const BLOOM_MAX_CHANGED_PATHS: usize = 512;
+
+pub(crate) struct CommitGraph {
+    data: Vec<u8>,
+    format: ObjectFormat,
+    count: usize,
+    fanout: usize,
+    oids: usize,
+    commits: usize,
+    edges: Option<(usize, usize)>,
+    bloom: Option<Bloom>,
+}
+
+struct Bloom {
+    indexes: usize,
+    filters: (usize, usize),
+    hashes: u32,
+}
+
+pub(crate) struct GraphCommit {
+    pub(crate) tree: ObjectId,
+    pub(crate) parents: Vec<ObjectId>,
+    pub(crate) generation: u64,
+    pub(crate) time: i64,
+}
+
+pub(crate) fn path(objects_dir: &Path) -> PathBuf {
+    objects_dir.join("info/commit-graph")
+}
+
+impl CommitGraph {
+    // NOTE: graph of the repository in the current dir, None when there is none
+    // or core.commitGraph = false, a broken file is reported and not used (same as git)
+    pub(crate) fn current(format: ObjectFormat) -> anyhow::Result<Option<Self>> {
+        let config = Config::load()?;
+        if config.get_bool("core.commitGraph")? == Some(false) {
+            return Ok(None);
+        }
+        let read_bloom = config
+            .get_bool("commitGraph.readChangedPaths")?
+            .unwrap_or(true);
+        match Self::open(&path(&odb::objects_dir()), format) {
+            Ok(Some(mut graph)) => {
+                if !read_bloom {
+                    graph.bloom = None;
+                }
+                Ok(Some(graph))
+            }
+            Ok(None) => Ok(None),
+            Err(e) => {
+                eprintln!("error: {e:#}");
+                Ok(None)
+            }
+        }
+    }
+
+    pub(crate) fn open(path: &Path, format: ObjectFormat) -> anyhow::Result<Option<Self>> {
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+        };
+        Self::parse(data, format).map(Some)
+    }
+
+    pub(crate) fn parse(data: Vec<u8>, format: ObjectFormat) -> anyhow::Result<Self> {
+        let hash_len = format.raw_len();
+        anyhow::ensure!(
+            data.len() >= 8 + 12 + hash_len,
+            "commit-graph file is too small"
+        );
+        anyhow::ensure!(
+            data.starts_with(SIGNATURE),
+            "commit-graph signature {:08X} does not match signature {:08X}",
+            be_u32(&data, 0)?,
+            u32::from_be_bytes(SIGNATURE.try_into().unwrap())
+        );
+        anyhow::ensure!(
+            data[4] == VERSION,
+            "commit-graph version {:X} does not match version {VERSION:X}",
+            data[4]
+        );
+        let hash_version = match format {
+            ObjectFormat::Sha1 => 1,
+            ObjectFormat::Sha256 => 2,
+        };
+        anyhow::ensure!(
+            data[5] == hash_version,
+            "commit-graph hash version {:X} does not match version {hash_version:X}",
+            data[5]
+        );
+        anyhow::ensure!(data[7] == 0, "commit-graph chains are not supported");
+
+        // (id, start, end) of every chunk, the end is the start of the next one
+        let chunk_count = data[6] as usize;
+        let end_of_chunks = data.len() - hash_len;
+        let mut table = Vec::with_capacity(chunk_count + 1);
+        for i in 0..=chunk_count {
+            let at = 8 + i * 12;
+            let id = be_u32(&data, at)?;
+            let offset = be_u64(&data, at + 4)? as usize;
+            anyhow::ensure!(
+                offset <= end_of_chunks,
+                "commit-graph improper chunk offset {offset:08x}"
+            );
+            table.push((id, offset));
+        }
+        anyhow::ensure!(
+            table[chunk_count].0 == 0,
+            "commit-graph chunk table is not terminated"
+        );
+        let mut chunks = HashMap::new();
+        for pair in table.windows(2) {
+            let ((id, start), (_, end)) = (pair[0], pair[1]);
+            anyhow::ensure!(start <= end, "commit-graph improper chunk offset {end:08x}");
+            chunks.insert(id, (start, end));
+        }
+        let chunk = |id: u32, name: &str| {
+            chunks
+                .get(&id)
+                .copied()
+                .with_context(|| format!("commit-graph required {name} chunk missing or corrupted"))
+        };
+
+        let (fanout, fanout_end) = chunk(CHUNK_OID_FANOUT, "OID fanout")?;
+        anyhow::ensure!(
+            fanout_end - fanout == 256 * 4,
+            "commit-graph OID fanout chunk is wrong size"
+        );
+        let count = be_u32(&data, fanout + 255 * 4)? as usize;
+        let (oids, oids_end) = chunk(CHUNK_OID_LOOKUP, "OID lookup")?;
+        anyhow::ensure!(
+            oids_end - oids == count * hash_len,
+            "commit-graph OID lookup chunk is the wrong size"
+        );
+        let (commits, commits_end) = chunk(CHUNK_COMMIT_DATA, "commit data")?;
+        anyhow::ensure!(
+            commits_end - commits == count * (hash_len + 16),
+            "commit-graph commit data chunk is wrong size"
+        );
+        let edges = chunks.get(&CHUNK_EXTRA_EDGES).copied();
+
+        // filters we don't know how to read are ignored, only the log is slower
+        let bloom = match (
+            chunks.get(&CHUNK_BLOOM_INDEXES),
+            chunks.get(&CHUNK_BLOOM_DATA),
+        ) {
+            (Some(&(indexes, indexes_end)), Some(&(filters, filters_end)))
+                if indexes_end - indexes == count * 4
+                    && filters_end - filters >= 12
+                    && be_u32(&data, filters)? == BLOOM_VERSION =>
+            {
+                Some(Bloom {
+                    indexes,
+                    filters: (filters + 12, filters_end),
+                    hashes: be_u32(&data, filters + 4)?,
+                })
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            data,
+            format,
+            count,
+            fanout,
+            oids,
+            commits,
+            edges,
+            bloom,
+        })
+    }
+
+    pub(crate) fn has_changed_paths(&self) -> bool {
+        self.bloom.is_some()
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.count
+    }
+
+    pub(crate) fn oid(&self, position: usize) -> anyhow::Result<ObjectId> {
+        anyhow::ensure!(
+            position < self.count,
+            "commit-graph has a position {position} out of range"
+        );
+        let hash_len = self.format.raw_len();
+        let at = self.oids + position * hash_len;
+        ObjectId::from_bytes(&self.data[at..at + hash_len])
+    }
+
+    // the fanout gives the range of commits with the same first byte, then a binary search
+    pub(crate) fn position(&self, id: ObjectId) -> Option<usize> {
+        let first = id.as_bytes()[0] as usize;
+        let fanout = |i: usize| {
+            be_u32(&self.data, self.fanout + i * 4)
+                .ok()
+                .map(|n| n as usize)
+        };
+        let start = if first == 0 { 0 } else { fanout(first - 1)? };
+        let end = fanout(first)?.min(self.count);
+        let hash_len = self.format.raw_len();
+        let (mut low, mut high) = (start, end);
+        while low < high {
+            let middle = (low + high) / 2;
+            let at = self.oids + middle * hash_len;
+            match self.data[at..at + hash_len].cmp(id.as_bytes()) {
+                std::cmp::Ordering::Equal => return Some(middle),
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        None
+    }
+
+    pub(crate) fn commit(&self, id: ObjectId) -> anyhow::Result<Option<GraphCommit>> {
+        match self.position(id) {
+            Some(position) => self.commit_at(position).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn commit_at(&self, position: usize) -> anyhow::Result<GraphCommit> {
+        let hash_len = self.format.raw_len();
+        let at = self.commits + position * (hash_len + 16);
+        let tree = ObjectId::from_bytes(&self.data[at..at + hash_len])?;
+        let first = be_u32(&self.data, at + hash_len)?;
+        let second = be_u32(&self.data, at + hash_len + 4)?;
+        let generation = be_u32(&self.data, at + hash_len + 8)?;
+        let time = be_u32(&self.data, at + hash_len + 12)?;
+
+        let mut parents = Vec::new();
+        if first != PARENT_NONE {
+            parents.push(self.oid(first as usize)?);
+        }
+        if second != PARENT_NONE && second & EXTRA_EDGES == 0 {
+            parents.push(self.oid(second as usize)?);
+        } else if second != PARENT_NONE {
+            let Some((edges, edges_end)) = self.edges else {
+                anyhow::bail!("commit-graph has no extra edges chunk");
+            };
+            let mut at = edges + (second & !EXTRA_EDGES) as usize * 4;
+            loop {
+                anyhow::ensure!(at < edges_end, "commit-graph extra edges out of bounds");
+                let edge = be_u32(&self.data, at)?;
+                parents.push(self.oid((edge & !LAST_EDGE) as usize)?);
+                if edge & LAST_EDGE != 0 {
+                    break;
+                }
+                at += 4;
+            }
+        }
+        Ok(GraphCommit {
+            tree,
+            parents,
+            generation: (generation >> 2) as u64,
+            time: ((generation as i64 & 3) << 32) | time as i64,
+        })
+    }
+
+    // NOTE: Some(false) when the commit surely did not change the path,
+    // None when there is no filter to tell (not in the graph, no bloom chunk)
+    pub(crate) fn maybe_changed(&self, id: ObjectId, path: &str) -> Option<bool> {
+        let bloom = self.bloom.as_ref()?;
+        let position = self.position(id)?;
+        let index = |i: usize| be_u32(&self.data, bloom.indexes + i * 4).ok();
+        let start = if position == 0 {
+            0
+        } else {
+            index(position - 1)?
+        };
+        let end = index(position)?;
+        let (filters, filters_end) = bloom.filters;
+        let filter = self
+            .data
+            .get(filters + start as usize..filters + end as usize)
+            .filter(|_| filters + end as usize <= filters_end)?;
+        if filter.is_empty() {
+            return None;
+        }
+        let bits = filter.len() as u64 * 8;
+        let contains = bloom_key(path.trim_end_matches('/'), bloom.hashes)
+            .iter()
+            .all(|&hash| {
+                let bit = hash as u64 % bits;
+                filter[(bit / 8) as usize] & (1 << (bit % 8)) != 0
+            });
+        Some(contains)
+    }
+}
+
+// NOTE: write the graph of the commits and everything under them
+// the parents of a commit have to be in the same file, so the history is walked to the roots
+pub(crate) fn write(
+    db: &dyn ObjectDatabase,
+    objects_dir: &Path,
+    tips: Vec<ObjectId>,
+    changed_paths: bool,
+) -> anyhow::Result<usize> {
+    let format = db.format();
+    let hash_len = format.raw_len();
+    let mut commits: HashMap<ObjectId, Commit> = HashMap::new();
+    let mut stack = tips;
+    while let Some(id) = stack.pop() {
+        if commits.contains_key(&id) {
+            continue;
+        }
+        let commit = Commit::read(db, &id.to_string())?;
+        stack.extend(commit.parents.iter().copied());
+        commits.insert(id, commit);
+    }
+    let mut ids: Vec<ObjectId> = commits.keys().copied().collect();
+    ids.sort_unstable();
+    let positions: HashMap<ObjectId, u32> = ids
+        .iter()
+        .enumerate()
+        .map(|(position, &id)| (id, position as u32))
+        .collect();
+    let generations = generations(&commits)?;
+
+    let mut fanout = Vec::with_capacity(256 * 4);
+    for byte in 0..=255u8 {
+        let count = ids.partition_point(|id| id.as_bytes()[0] <= byte);
+        fanout.extend((count as u32).to_be_bytes());
+    }
+    let mut lookup = Vec::with_capacity(ids.len() * hash_len);
+    let mut data = Vec::with_capacity(ids.len() * (hash_len + 16));
+    let mut edges = Vec::new();
+    for id in &ids {
+        lookup.extend(id.as_bytes());
+        let commit = &commits[id];
+        data.extend(commit.tree.as_bytes());
+        let parents: Vec<u32> = commit.parents.iter().map(|p| positions[p]).collect();
+        data.extend(
+            parents
+                .first()
+                .copied()
+                .unwrap_or(PARENT_NONE)
+                .to_be_bytes(),
+        );
+        let second = match parents.len() {
+            0 | 1 => PARENT_NONE,
+            2 => parents[1],
+            _ => {
+                let start = (edges.len() / 4) as u32 | EXTRA_EDGES;
+                for (i, &parent) in parents[1..].iter().enumerate() {
+                    let last = if i == parents.len() - 2 { LAST_EDGE } else { 0 };
+                    edges.extend((parent | last).to_be_bytes());
+                }
+                start
+            }
+        };
+        data.extend(second.to_be_bytes());
+        let time = commit.committer.time.clamp(0, TIME_MAX);
+        let generation = (generations[id] as u32) << 2 | (time >> 32) as u32;
+        data.extend(generation.to_be_bytes());
+        data.extend((time as u32).to_be_bytes());
+    }
+
+    let mut chunks = vec![
+        (CHUNK_OID_FANOUT, fanout),
+        (CHUNK_OID_LOOKUP, lookup),
+        (CHUNK_COMMIT_DATA, data),
+    ];
+    if !edges.is_empty() {
+        chunks.push((CHUNK_EXTRA_EDGES, edges));
+    }
+    if changed_paths {
+        let mut indexes = Vec::with_capacity(ids.len() * 4);
+        let mut filters = Vec::new();
+        for header in [BLOOM_VERSION, BLOOM_HASHES, BLOOM_BITS_PER_ENTRY] {
+            filters.extend(header.to_be_bytes());
+        }
+        for id in &ids {
+            let commit = &commits[id];
+            let parent_tree = commit.parents.first().map(|parent| commits[parent].tree);
+            let paths = tree::changed_paths(db, parent_tree, Some(commit.tree))?;
+            filters.extend(bloom_filter(&paths));
+            indexes.extend(((filters.len() - 12) as u32).to_be_bytes());
+        }
+        chunks.push((CHUNK_BLOOM_INDEXES, indexes));
+        chunks.push((CHUNK_BLOOM_DATA, filters));
+    }
+
+    let mut content = Vec::new();
+    content.extend(SIGNATURE);
+    content.extend([
+        VERSION,
+        if format == ObjectFormat::Sha1 { 1 } else { 2 },
+        chunks.len() as u8,
+        0,
+    ]);
+    let mut offset = (8 + (chunks.len() + 1) * 12) as u64;
+    for (id, chunk) in &chunks {
+        content.extend(id.to_be_bytes());
+        content.extend(offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    content.extend(0u32.to_be_bytes());
+    content.extend(offset.to_be_bytes());
+    for (_, chunk) in &chunks {
+        content.extend(chunk);
+    }
+    let checksum = Hasher::digest(format, &content)?;
+    content.extend(checksum.as_bytes());
+
+    // written next to it first so a reader never see half a file, read-only like git
+    let path = path(objects_dir);
+    let info = path.parent().unwrap();
+    fs::create_dir_all(info).with_context(|| format!("Failed to create {}", info.display()))?;
+    let tmp_path = info.join("tmp_commit_graph");
+    fs::write(&tmp_path, &content)
+        .with_context(|| format!("Failed to write {}", tmp_path.display()))?;
+    fs::set_permissions(&tmp_path, fs::Permissions::from_mode(0o444))
+        .context("Failed to make the commit-graph read-only")?;
+    fs::rename(&tmp_path, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(ids.len())
+}
+
+// topological levels, without recursion (a long history would blow the stack)
+fn generations(commits: &HashMap<ObjectId, Commit>) -> anyhow::Result<HashMap<ObjectId, u64>> {
+    let mut generations: HashMap<ObjectId, u64> = HashMap::new();
+    for &id in commits.keys() {
+        let mut stack = vec![id];
+        while let Some(&top) = stack.last() {
+            if generations.contains_key(&top) {
+                stack.pop();
+                continue;
+            }
+            let parents = &commits[&top].parents;
+            let missing: Vec<ObjectId> = parents
+                .iter()
+                .filter(|p| !generations.contains_key(p))
+                .copied()
+                .collect();
+            if missing.is_empty() {
+                let max = parents.iter().map(|p| generations[p]).max().unwrap_or(0);
+                generations.insert(top, (max + 1).min(GENERATION_MAX));
+                stack.pop();
+            } else {
+                anyhow::ensure!(
+                    stack.len() <= commits.len(),
+                    "cycle in the history at {top}"
+                );
+                stack.extend(missing);
+            }
+        }
+    }
+    Ok(generations)
+}
+
+// NOTE: check the file against the objects, every problem is one line (same text as git)
+pub(crate) fn verify(db: &dyn ObjectDatabase, graph: &CommitGraph) -> anyhow::Result<Vec<String>> {
+    let mut errors = Vec::new();
+    let hash_len = graph.format.raw_len();
+    let (content, checksum) = graph.data.split_at(graph.data.len() - hash_len);
+    let bad_checksum = Hasher::digest(graph.format, content)?.as_bytes() != checksum;
+    if bad_checksum {
+        errors.push("the commit-graph file has incorrect checksum and is likely corrupt".into());
+    }
+
+    let mut ids = Vec::with_capacity(graph.len());
+    for position in 0..graph.len() {
+        ids.push(graph.oid(position)?);
+    }
+    for pair in ids.windows(2) {
+        if pair[0] >= pair[1] {
+            errors.push(format!(
+                "commit-graph has incorrect OID order: {} then {}",
+                pair[0], pair[1]
+            ));
+        }
+    }
+    for byte in 0..=255u8 {
+        let expected = ids.iter().filter(|id| id.as_bytes()[0] <= byte).count();
+        let found = be_u32(&graph.data, graph.fanout + byte as usize * 4)? as usize;
+        if found != expected {
+            errors.push(format!(
+                "commit-graph has incorrect fanout value: fanout[{byte}] = {found} != {expected}"
+            ));
+        }
+    }
+    // a bad checksum alone still let us check the commits, a bad order or fanout does not
+    if errors.len() > usize::from(bad_checksum) {
+        return Ok(errors);
+    }
+
+    for (position, &id) in ids.iter().enumerate() {
+        let Ok(commit) = Commit::read(db, &id.to_string()) else {
+            errors.push(format!(
+                "failed to parse commit {id} from object database for commit-graph"
+            ));
+            continue;
+        };
+        let graph_commit = graph.commit_at(position)?;
+        if graph_commit.tree != commit.tree {
+            errors.push(format!(
+                "root tree OID for commit {id} in commit-graph is {} != {}",
+                graph_commit.tree, commit.tree
+            ));
+        }
+        for i in 0..graph_commit.parents.len().max(commit.parents.len()) {
+            match (graph_commit.parents.get(i), commit.parents.get(i)) {
+                (Some(found), Some(expected)) if found != expected => errors.push(format!(
+                    "commit-graph parent for {id} is {found} != {expected}"
+                )),
+                (Some(_), None) => {
+                    errors.push(format!(
+                        "commit-graph parent list for commit {id} is too long"
+                    ));
+                    break;
+                }
+                (None, Some(_)) => {
+                    errors.push(format!(
+                        "commit-graph parent list for commit {id} terminates early"
+                    ));
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let mut max_parent = 0;
+        for parent in &graph_commit.parents {
+            if let Some(parent) = graph.commit(*parent)? {
+                max_parent = max_parent.max(parent.generation);
+            }
+        }
+        let expected = (max_parent + 1).min(GENERATION_MAX);
+        if graph_commit.generation < expected {
+            errors.push(format!(
+                "commit-graph generation for commit {id} is {} < {expected}",
+                graph_commit.generation
+            ));
+        }
+        if graph_commit.time != commit.committer.time.clamp(0, TIME_MAX) {
+            errors.push(format!(
+                "commit date for commit {id} in commit-graph is {} != {}",
+                graph_commit.time, commit.committer.time
+            ));
+        }
+    }
+    Ok(errors)
+}
+
+// NOTE: one byte per 8 bits, at least one byte, the hashes of every key set their bit
+fn bloom_filter(paths: &[String]) -> Vec<u8> {
+    if paths.len() > BLOOM_MAX_CHANGED_PATHS {
+        return vec![0xff];
+    }
+    let mut keys: Vec<&str> = Vec::new();
+    for path in paths {
+        keys.push(path);
+        // "a/b/c" also add "a/b" and "a"
+        let mut rest = path.as_str();
+        while let Some((parent, _)) = rest.rsplit_once('/') {
+            keys.push(parent);
+            rest = parent;
+        }
+    }
+    keys.sort_unstable();
+    keys.dedup();
+    let len = (keys.len() * BLOOM_BITS_PER_ENTRY as usize)
+        .div_ceil(8)
+        .max(1);
+    let mut filter = vec![0u8; len];
+    let bits = len as u64 * 8;
+    for key in keys {
+        for hash in bloom_key(key, BLOOM_HASHES) {
+            let bit = hash as u64 % bits;
+            filter[(bit / 8) as usize] |= 1 << (bit % 8);
+        }
+    }
+    filter
+}
+
+// hash i = murmur3(seed 0) + i * murmur3(seed 1), same as git
+fn bloom_key(path: &str, hashes: u32) -> Vec<u32> {
+    let first = murmur3(0x293a_e76f, path.as_bytes());
+    let second = murmur3(0x7e64_6e2c, path.as_bytes());
+    (0..hashes)
+        .map(|i| first.wrapping_add(i.wrapping_mul(second)))
+        .collect()
+}
+
+// NOTE: murmur3 32 bits, the version 1 filters of git read the bytes as signed char,
+// a path with a byte >= 0x80 has to be hashed with that same mistake to match git
+fn murmur3(seed: u32, data: &[u8]) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+    let byte = |b: u8| b as i8 as u32;
+    let mut hash = seed;
+    let mut blocks = data.chunks_exact(4);
+    for block in &mut blocks {
+        let mut k =
+            byte(block[0]) | byte(block[1]) << 8 | byte(block[2]) << 16 | byte(block[3]) << 24;
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+        hash = hash
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+    let tail = blocks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, &b) in tail.iter().enumerate() {
+            k ^= byte(b) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        hash ^= k;
+    }
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^= hash >> 16;
+    hash
+}
+
+fn be_u32(data: &[u8], at: usize) -> anyhow::Result<u32> {
+    let raw = data.get(at..at + 4).context("commit-graph is truncated")?;
+    Ok(u32::from_be_bytes(raw.try_into().unwrap()))
+}
+
+fn be_u64(data: &[u8], at: usize) -> anyhow::Result<u64> {
+    let raw = data.get(at..at + 8).context("commit-graph is truncated")?;
+    Ok(u64::from_be_bytes(raw.try_into().unwrap()))
+}
@@ -0,0 +1,212 @@
+use std::path::Path;
+
+use anyhow::Context;
+use ini::Ini;
+
+// NOTE: read only view of .git/config
+// git name the keys "section.key" or "section.subsection.key"
+// [core]
+//     fsync = loose-object,index     -> core.fsync
+// [remote "origin"]
+//     url = ../other                 -> remote.origin.url
+// section and key are case insensitive, subsection is not
+// TODO: currently only support the local config not the global ~/.gitconfig
+pub(crate) struct Config {
+    ini: Ini,
+}
+
+impl Config {
+    pub(crate) fn load() -> anyhow::Result<Self> {
+        Self::load_from(Path::new(".git/config"))
+    }
+    // NOTE: missing config file is just the empty config
+    pub(crate) fn load_from(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self { ini: Ini::new() });
+        }
+        let ini = Ini::load_from_file(path)
+            .with_context(|| format!("Reading config file {}", path.display()))?;
+        Ok(Self { ini })
+    }
+    // NOTE: last value win when the key is repeated, same as git
+    pub(crate) fn get(&self, name: &str) -> Option<&str> {
+        self.get_all(name).last().copied()
+    }
+    pub(crate) fn get_all(&self, name: &str) -> Vec<&str> {
+        let Some((section, key)) = name.rsplit_once('.') else {
+            return Vec::new();
+        };
+        let (section, subsection) = match section.split_once('.') {
+            Some((section, subsection)) => (section, Some(subsection)),
+            None => (section, None),
+        };
+        let mut values = Vec::new();
+        for (name, properties) in self.ini.iter() {
+            let Some(name) = name else {
+                continue;
+            };
+            if !section_matches(name, section, subsection) {
+                continue;
+            }
+            for (k, v) in properties.iter() {
+                if k.eq_ignore_ascii_case(key) {
+                    values.push(v);
+                }
+            }
+        }
+        values
+    }
+    // NOTE: number with optional k, m, g suffix (512m -> 512 * 1024 * 1024)
+    pub(crate) fn get_size(&self, name: &str) -> anyhow::Result<Option<u64>> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+        let lower = value.to_ascii_lowercase();
+        let (number, unit) = match lower.as_bytes().last() {
+            Some(b'k') => (&lower[..lower.len() - 1], 1024),
+            Some(b'm') => (&lower[..lower.len() - 1], 1024 * 1024),
+            Some(b'g') => (&lower[..lower.len() - 1], 1024 * 1024 * 1024),
+            _ => (lower.as_str(), 1),
+        };
+        let number = number
+            .trim()
+            .parse::<u64>()
+            .with_context(|| format!("bad numeric config value '{value}' for '{name}'"))?;
+        Ok(Some(number * unit))
+    }
+    // NOTE: true/yes/on/1 and false/no/off/0, anything else is an error like in git
+    pub(crate) fn get_bool(&self, name: &str) -> anyhow::Result<Option<bool>> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+        match value.to_ascii_lowercase().as_str() {
+            "true" | "yes" | "on" | "1" | "" => Ok(Some(true)),
+            "false" | "no" | "off" | "0" => Ok(Some(false)),
+            _ => anyhow::bail!("bad boolean config value '{value}' for '{name}'"),
+        }
+    }
+}
+
+// ini section name for [remote "origin"] is `remote "origin"`
+// the old style [remote.origin] is also accepted by git
+fn section_matches(name: &str, section: &str, subsection: Option<&str>) -> bool {
+    let name = name.trim();
+    let (name_section, name_subsection) = match name.split_once(char::is_whitespace) {
+        Some((s, sub)) => (s, Some(sub.trim().trim_matches('"'))),
+        None => match name.split_once('.') {
+            Some((s, sub)) => (s, Some(sub)),
+            None => (name, None),
+        },
+    };
+    name_section.eq_ignore_ascii_case(section) && name_subsection == subsection
+}
+
+// NOTE: for writing a config, names and urls can come from another repository
+// [branch "a\"b"] -> subsection a"b, a newline can't be written in a subsection at all
+pub(crate) fn escape_subsection(name: &str) -> anyhow::Result<String> {
+    anyhow::ensure!(
+        !name.contains(['\n', '\0']),
+        "invalid config subsection '{}'",
+        name.escape_debug()
+    );
+    Ok(format!(
+        "\"{}\"",
+        name.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+// a value is quoted when spaces at the ends or ; and # (comments) would be lost otherwise
+pub(crate) fn escape_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+    if value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains([';', '#'])
+    {
+        format!("\"{escaped}\"")
+    } else {
+        escaped
+    }
+}
+
+// NOTE: blobs bigger than core.bigFileThreshold (default 512m) are never loaded in memory,
+// reads stream them straight from the loose file or the pack entry.
+// git also never try to delta them, we don't write deltas at all so nothing to do there
+const DEFAULT_BIG_FILE_THRESHOLD: u64 = 512 * 1024 * 1024;
+impl Config {
+    pub(crate) fn big_file_threshold(&self) -> anyhow::Result<u64> {
+        Ok(self
+            .get_size("core.bigFileThreshold")?
+            .unwrap_or(DEFAULT_BIG_FILE_THRESHOLD))
+    }
+}
+
+// NOTE: core.fsync say which files have to be fsync'ed before we trust them
+// core.fsync = loose-object,-index    (the "-" remove the component)
+// none, loose-object, packfile, pack-metadata, commit-graph, index, reference,
+// objects (loose-object + packfile), derived-metadata (pack-metadata + commit-graph),
+// committed (objects + reference), added (committed + index), all
+// the list is applied on top of the default, and our default is everything we write.
+// the old core.fsyncObjectFiles = false still turn off only the loose objects
+pub(crate) const FSYNC_LOOSE_OBJECT: u32 = 1 << 0;
+const FSYNC_PACKFILE: u32 = 1 << 1;
+const FSYNC_PACK_METADATA: u32 = 1 << 2;
+const FSYNC_COMMIT_GRAPH: u32 = 1 << 3;
+pub(crate) const FSYNC_INDEX: u32 = 1 << 4;
+pub(crate) const FSYNC_REFERENCE: u32 = 1 << 5;
+const FSYNC_OBJECTS: u32 = FSYNC_LOOSE_OBJECT | FSYNC_PACKFILE;
+const FSYNC_DERIVED_METADATA: u32 = FSYNC_PACK_METADATA | FSYNC_COMMIT_GRAPH;
+const FSYNC_COMMITTED: u32 = FSYNC_OBJECTS | FSYNC_REFERENCE;
+const FSYNC_ADDED: u32 = FSYNC_COMMITTED | FSYNC_INDEX;
+const FSYNC_ALL: u32 = FSYNC_ADDED | FSYNC_DERIVED_METADATA;
+
+impl Config {
+    pub(crate) fn fsync(&self, component: u32) -> anyhow::Result<bool> {
+        let mut positive = 0;
+        let mut negative = 0;
+        if self.get_bool("core.fsyncObjectFiles")? == Some(false) {
+            negative |= FSYNC_LOOSE_OBJECT;
+        }
+        if let Some(value) = self.get("core.fsync") {
+            for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+                if name == "none" {
+                    positive = 0;
+                    negative = FSYNC_ALL;
+                    continue;
+                }
+                let (negated, name) = match name.strip_prefix('-') {
+                    Some(name) => (true, name),
+                    None => (false, name),
+                };
+                let bits = match name {
+                    "loose-object" => FSYNC_LOOSE_OBJECT,
+                    "packfile" => FSYNC_PACKFILE,
+                    "pack-metadata" => FSYNC_PACK_METADATA,
+                    "commit-graph" => FSYNC_COMMIT_GRAPH,
+                    "index" => FSYNC_INDEX,
+                    "reference" => FSYNC_REFERENCE,
+                    "objects" => FSYNC_OBJECTS,
+                    "derived-metadata" => FSYNC_DERIVED_METADATA,
+                    "committed" => FSYNC_COMMITTED,
+                    "added" => FSYNC_ADDED,
+                    "all" => FSYNC_ALL,
+                    _ => {
+                        eprintln!("warning: ignoring unknown core.fsync component '{name}'");
+                        continue;
+                    }
+                };
+                if negated {
+                    negative |= bits;
+                    positive &= !bits;
+                } else {
+                    positive |= bits;
+                    negative &= !bits;
+                }
+            }
+        }
+        Ok(((FSYNC_ALL | positive) & !negative) & component != 0)
+    }
+}
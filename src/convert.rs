@@ -0,0 +1,167 @@
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+
+use crate::attributes::{self, State};
+use crate::config::Config;
+
+// NOTE: what happen to the worktree content before it is stored as a blob (git convert_to_git)
+// 1. clean filter: "filter=<driver>" attribute run filter.<driver>.clean with the content on
+//    stdin and store its stdout ("%f" in the command is replaced by the path).
+//    failing filter is an error only with filter.<driver>.required = true
+// 2. end of line: text files get their CRLF turned into LF
+//    text, eol=lf|crlf, crlf      -> always
+//    -text, binary, -crlf         -> never
+//    text=auto                    -> only when the content does not look binary
+//    nothing set                  -> core.autocrlf = true|input work like text=auto,
+//                                    false (the default) never convert
+pub(crate) struct Filters {
+    path: String,
+    clean: Option<CleanFilter>,
+    eol: Eol,
+}
+
+struct CleanFilter {
+    driver: String,
+    command: Option<String>,
+    required: bool,
+}
+
+#[derive(PartialEq)]
+enum Eol {
+    Keep,
+    Text,
+    Auto,
+}
+
+impl Filters {
+    pub(crate) fn for_path(git_dir: &Path, config: &Config, path: &str) -> anyhow::Result<Self> {
+        let path = path.trim_start_matches("./").to_string();
+        let attributes = attributes::lookup(git_dir, &path)?;
+
+        let clean = match attributes.get("filter") {
+            Some(State::Value(driver)) => Some(CleanFilter {
+                driver: driver.clone(),
+                command: config
+                    .get(&format!("filter.{driver}.clean"))
+                    .map(str::to_string),
+                required: config
+                    .get_bool(&format!("filter.{driver}.required"))?
+                    .unwrap_or(false),
+            }),
+            _ => None,
+        };
+
+        let eol = match (attributes.get("text"), attributes.get("crlf")) {
+            (Some(State::Unset), _) => Eol::Keep,
+            (Some(State::Value(value)), _) if value == "auto" => Eol::Auto,
+            (Some(_), _) => Eol::Text,
+            (None, Some(State::Unset)) => Eol::Keep,
+            (None, Some(_)) => Eol::Text,
+            (None, None) if matches!(attributes.get("eol"), Some(State::Value(_))) => Eol::Text,
+            (None, None) => match config.get("core.autocrlf") {
+                Some(value) if value.eq_ignore_ascii_case("input") => Eol::Auto,
+                _ if config.get_bool("core.autocrlf")? == Some(true) => Eol::Auto,
+                _ => Eol::Keep,
+            },
+        };
+        Ok(Self { path, clean, eol })
+    }
+
+    // NOTE: nothing to do, the caller can stream the file instead of loading it
+    pub(crate) fn is_noop(&self) -> bool {
+        self.clean.is_none() && self.eol == Eol::Keep
+    }
+
+    pub(crate) fn to_git(&self, content: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let content = match &self.clean {
+            Some(filter) => filter.run(&self.path, content)?,
+            None => content,
+        };
+        let convert = match self.eol {
+            Eol::Keep => false,
+            Eol::Text => true,
+            Eol::Auto => !is_binary(&content),
+        };
+        if !convert || !content.windows(2).any(|pair| pair == b"\r\n") {
+            return Ok(content);
+        }
+        let mut converted = Vec::with_capacity(content.len());
+        for (i, &byte) in content.iter().enumerate() {
+            if byte == b'\r' && content.get(i + 1) == Some(&b'\n') {
+                continue;
+            }
+            converted.push(byte);
+        }
+        Ok(converted)
+    }
+}
+
+impl CleanFilter {
+    fn run(&self, path: &str, content: Vec<u8>) -> anyhow::Result<Vec<u8>> {
+        let Some(command) = &self.command else {
+            anyhow::ensure!(
+                !self.required,
+                "{path}: clean filter '{}' failed",
+                self.driver
+            );
+            return Ok(content);
+        };
+        let command = command.replace("%f", &format!("'{}'", path.replace('\'', "'\\''")));
+        match run_filter(&command, &content) {
+            Ok(output) => Ok(output),
+            Err(e) if !self.required => {
+                eprintln!("error: external filter '{command}' failed: {e:#}");
+                Ok(content)
+            }
+            Err(e) => {
+                Err(e).with_context(|| format!("{path}: clean filter '{}' failed", self.driver))
+            }
+        }
+    }
+}
+
+fn run_filter(command: &str, content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run '{command}'"))?;
+    let mut stdin = child.stdin.take().context("filter stdin")?;
+    // feed stdin from another thread, a filter that write before reading everything
+    // would block forever on a full pipe otherwise
+    let output = std::thread::scope(|scope| {
+        scope.spawn(move || {
+            // filter is allowed to not read all its input
+            let _ = stdin.write_all(content);
+        });
+        child.wait_with_output()
+    })
+    .context("Failed to wait for the filter")?;
+    anyhow::ensure!(output.status.success(), "exited with {}", output.status);
+    Ok(output.stdout)
+}
+
+// NOTE: same guess as git for text=auto
+// NUL or lone CR -> binary, or too many non printable bytes (more than 1 in 128)
+fn is_binary(content: &[u8]) -> bool {
+    let mut printable = 0usize;
+    let mut non_printable = 0usize;
+    for (i, &byte) in content.iter().enumerate() {
+        match byte {
+            0 => return true,
+            b'\r' if content.get(i + 1) != Some(&b'\n') => return true,
+            127 => non_printable += 1,
+            b'\n' | b'\r' | b'\t' | 8 | 12 | 27 => printable += 1,
+            // DOS end of file marker at the very end is not counted
+            26 if i + 1 == content.len() => {}
+            1..32 => non_printable += 1,
+            _ => printable += 1,
+        }
+    }
+    (printable >> 7) < non_printable
+}
@@ -0,0 +1,565 @@
+use std::fmt::Write;
+use std::io::Read;
+use std::ops::Range;
+
+use anyhow::Context;
+
+use crate::hash::{Hasher, ObjectId};
+use crate::odb::{Abbrev, ObjectDatabase};
+use crate::tree::Files;
+
+// NOTE: line diff (Myers, the default algorithm of git)
+// the result is the list of changes, every change replace the lines old (range in a)
+// with the lines new (range in b), what is between two changes is the same in a and b
+// a = [x, y, z], b = [x, w, z] -> [Change { old: 1..2, new: 1..2 }]
+// an insertion has an empty old range, a deletion an empty new range
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Change {
+    pub(crate) old: Range<usize>,
+    pub(crate) new: Range<usize>,
+}
+
+// NOTE: the lines keep their "\n", the last one may not have it
+pub(crate) fn lines(text: &[u8]) -> Vec<&[u8]> {
+    text.split_inclusive(|&b| b == b'\n').collect()
+}
+
+// NOTE: git guess, NUL in the first 8000 bytes is a binary file
+pub(crate) fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(8000)].contains(&0)
+}
+
+pub(crate) fn diff<T: PartialEq>(a: &[T], b: &[T]) -> Vec<Change> {
+    let mut keep_a = vec![false; a.len()];
+    let mut keep_b = vec![false; b.len()];
+    compare(a, 0..a.len(), b, 0..b.len(), &mut keep_a, &mut keep_b);
+    slide_down(a, &mut keep_a);
+    slide_down(b, &mut keep_b);
+
+    // kept lines are paired in order, everything between two pairs is one change
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && keep_a[i] && keep_b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (old_start, new_start) = (i, j);
+        while i < a.len() && !keep_a[i] {
+            i += 1;
+        }
+        while j < b.len() && !keep_b[j] {
+            j += 1;
+        }
+        changes.push(Change {
+            old: old_start..i,
+            new: new_start..j,
+        });
+    }
+    changes
+}
+
+// divide and conquer on the middle snake, so the memory stay linear
+fn compare<T: PartialEq>(
+    a: &[T],
+    mut a_range: Range<usize>,
+    b: &[T],
+    mut b_range: Range<usize>,
+    keep_a: &mut [bool],
+    keep_b: &mut [bool],
+) {
+    while !a_range.is_empty() && !b_range.is_empty() && a[a_range.start] == b[b_range.start] {
+        keep_a[a_range.start] = true;
+        keep_b[b_range.start] = true;
+        a_range.start += 1;
+        b_range.start += 1;
+    }
+    while !a_range.is_empty() && !b_range.is_empty() && a[a_range.end - 1] == b[b_range.end - 1] {
+        keep_a[a_range.end - 1] = true;
+        keep_b[b_range.end - 1] = true;
+        a_range.end -= 1;
+        b_range.end -= 1;
+    }
+    if a_range.is_empty() || b_range.is_empty() {
+        return;
+    }
+    let Some((x, y)) = middle_snake(&a[a_range.clone()], &b[b_range.clone()]) else {
+        // nothing in common, it's all one change
+        return;
+    };
+    let (x, y) = (a_range.start + x, b_range.start + y);
+    compare(a, a_range.start..x, b, b_range.start..y, keep_a, keep_b);
+    compare(a, x..a_range.end, b, y..b_range.end, keep_a, keep_b);
+}
+
+// NOTE: forward and backward search at the same time (Myers paper, section 4b),
+// where they meet is a point of a shortest edit script, the split point of compare
+fn middle_snake<T: PartialEq>(a: &[T], b: &[T]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max_d = (n + m + 1) / 2;
+    let offset = max_d;
+    let length = (2 * max_d + 2) as usize;
+    let mut forward = vec![-1isize; length];
+    let mut backward = vec![-1isize; length];
+    forward[(offset + 1) as usize] = 0;
+    backward[(offset + 1) as usize] = 0;
+    let delta = n - m;
+    // with an odd delta the paths meet during the forward step
+    let odd = delta % 2 != 0;
+    let (mut k1_start, mut k1_end, mut k2_start, mut k2_end) = (0, 0, 0, 0);
+    for d in 0..max_d {
+        let mut k1 = -d + k1_start;
+        while k1 <= d - k1_end {
+            let k1_offset = (offset + k1) as usize;
+            let mut x1 = if k1 == -d || (k1 != d && forward[k1_offset - 1] < forward[k1_offset + 1])
+            {
+                forward[k1_offset + 1]
+            } else {
+                forward[k1_offset - 1] + 1
+            };
+            let mut y1 = x1 - k1;
+            while x1 < n && y1 < m && a[x1 as usize] == b[y1 as usize] {
+                x1 += 1;
+                y1 += 1;
+            }
+            forward[k1_offset] = x1;
+            if x1 > n {
+                k1_end += 2;
+            } else if y1 > m {
+                k1_start += 2;
+            } else if odd {
+                let k2_offset = offset + delta - k1;
+                if k2_offset >= 0
+                    && (k2_offset as usize) < length
+                    && backward[k2_offset as usize] != -1
+                    && x1 >= n - backward[k2_offset as usize]
+                {
+                    return Some((x1 as usize, y1 as usize));
+                }
+            }
+            k1 += 2;
+        }
+
+        let mut k2 = -d + k2_start;
+        while k2 <= d - k2_end {
+            let k2_offset = (offset + k2) as usize;
+            let mut x2 =
+                if k2 == -d || (k2 != d && backward[k2_offset - 1] < backward[k2_offset + 1]) {
+                    backward[k2_offset + 1]
+                } else {
+                    backward[k2_offset - 1] + 1
+                };
+            let mut y2 = x2 - k2;
+            while x2 < n && y2 < m && a[(n - x2 - 1) as usize] == b[(m - y2 - 1) as usize] {
+                x2 += 1;
+                y2 += 1;
+            }
+            backward[k2_offset] = x2;
+            if x2 > n {
+                k2_end += 2;
+            } else if y2 > m {
+                k2_start += 2;
+            } else if !odd {
+                let k1_offset = offset + delta - k2;
+                if k1_offset >= 0 && (k1_offset as usize) < length {
+                    let x1 = forward[k1_offset as usize];
+                    if x1 != -1 && x1 >= n - x2 {
+                        let y1 = offset + x1 - k1_offset;
+                        return Some((x1 as usize, y1 as usize));
+                    }
+                }
+            }
+            k2 += 2;
+        }
+    }
+    None
+}
+
+// NOTE: same as git, a group of added (or removed) lines that can move is put as low as
+// it can go, "a\nb\na\n" -> "a\nb\na\nb\na\n" adds the last "b\na\n" and not the middle one
+fn slide_down<T: PartialEq>(lines: &[T], keep: &mut [bool]) {
+    let mut start = 0;
+    while start < lines.len() {
+        if keep[start] {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < lines.len() && !keep[end] {
+            end += 1;
+        }
+        while end < lines.len() && lines[start] == lines[end] {
+            keep[start] = true;
+            keep[end] = false;
+            start += 1;
+            end += 1;
+            while end < lines.len() && !keep[end] {
+                end += 1;
+            }
+        }
+        start = end;
+    }
+}
+
+// NOTE: "git diff --stat" of two trees, what merge print after it's done
+//  a.txt | 2 +-
+//  b.bin | Bin 0 -> 5 bytes
+//  2 files changed, 1 insertion(+), 1 deletion(-)
+//  create mode 100644 b.bin
+// the bars are scaled down when they don't fit in 80 columns
+pub(crate) fn stat(db: &dyn ObjectDatabase, old: &Files, new: &Files) -> anyhow::Result<String> {
+    let (files, total, summary) = stat_parts(db, old, new)?;
+    Ok(files + &total + &summary)
+}
+
+// NOTE: "git diff --stat" alone, without the create/delete mode lines (stash show)
+pub(crate) fn diff_stat(
+    db: &dyn ObjectDatabase,
+    old: &Files,
+    new: &Files,
+) -> anyhow::Result<String> {
+    let (files, total, _) = stat_parts(db, old, new)?;
+    Ok(files + &total)
+}
+
+// NOTE: same without the line of every file, what the summary of a commit show
+//  1 file changed, 1 insertion(+)
+//  create mode 100644 b.bin
+pub(crate) fn short_stat(
+    db: &dyn ObjectDatabase,
+    old: &Files,
+    new: &Files,
+) -> anyhow::Result<String> {
+    let (_, total, summary) = stat_parts(db, old, new)?;
+    Ok(total + &summary)
+}
+
+// (the lines of the files, the total, the summary)
+fn stat_parts(
+    db: &dyn ObjectDatabase,
+    old: &Files,
+    new: &Files,
+) -> anyhow::Result<(String, String, String)> {
+    struct FileStat {
+        name: String,
+        added: usize,
+        removed: usize,
+        binary: Option<(usize, usize)>,
+    }
+    let mut stats = Vec::new();
+    let mut summary = String::new();
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort_unstable();
+    paths.dedup();
+    for path in paths {
+        let (old_entry, new_entry) = (old.get(path), new.get(path));
+        if old_entry == new_entry {
+            continue;
+        }
+        match (old_entry, new_entry) {
+            (None, Some((mode, _))) => writeln!(summary, " create mode {mode:06o} {path}")?,
+            (Some((mode, _)), None) => writeln!(summary, " delete mode {mode:06o} {path}")?,
+            (Some((old_mode, _)), Some((new_mode, _))) if old_mode != new_mode => writeln!(
+                summary,
+                " mode change {old_mode:06o} => {new_mode:06o} {path}"
+            )?,
+            _ => {}
+        }
+        let old_content = read_blob(db, old_entry.map(|(_, hash)| *hash))?;
+        let new_content = read_blob(db, new_entry.map(|(_, hash)| *hash))?;
+        let mut stat = FileStat {
+            name: path.clone(),
+            added: 0,
+            removed: 0,
+            binary: None,
+        };
+        if is_binary(&old_content) || is_binary(&new_content) {
+            stat.binary = Some((old_content.len(), new_content.len()));
+        } else if old_entry.map(|(_, hash)| hash) != new_entry.map(|(_, hash)| hash) {
+            for change in diff(&lines(&old_content), &lines(&new_content)) {
+                stat.removed += change.old.len();
+                stat.added += change.new.len();
+            }
+        }
+        stats.push(stat);
+    }
+    if stats.is_empty() {
+        return Ok((String::new(), String::new(), String::new()));
+    }
+
+    let max_change = stats.iter().map(|s| s.added + s.removed).max().unwrap_or(0);
+    let mut number_width = max_change.to_string().len();
+    let mut bin_width = 0;
+    for stat in &stats {
+        if let Some((before, after)) = stat.binary {
+            number_width = number_width.max("Bin".len());
+            bin_width = bin_width.max(format!("Bin {before} -> {after} bytes").len());
+        }
+    }
+    let mut name_width = stats.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    let width = 80;
+    let mut graph_width = if max_change + 4 > bin_width {
+        max_change
+    } else {
+        bin_width - 4
+    };
+    if name_width + number_width + 6 + graph_width > width {
+        if graph_width > (width * 3 / 8).saturating_sub(number_width + 6) {
+            graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+    let scale = |count: usize| {
+        if count == 0 || max_change <= graph_width {
+            count
+        } else {
+            1 + count * (graph_width - 1) / max_change
+        }
+    };
+
+    let mut out = String::new();
+    let (mut insertions, mut deletions) = (0, 0);
+    for stat in &stats {
+        // too long names keep their end: ".../deep/file.txt"
+        let name = if stat.name.len() > name_width {
+            let keep = name_width.saturating_sub(3);
+            let mut start = stat.name.len() - keep;
+            if let Some(slash) = stat.name[start..].find('/') {
+                start += slash;
+            }
+            format!("...{}", &stat.name[start..])
+        } else {
+            stat.name.clone()
+        };
+        if let Some((before, after)) = stat.binary {
+            writeln!(
+                out,
+                " {name:<name_width$} | {:>number_width$} {before} -> {after} bytes",
+                "Bin"
+            )?;
+            continue;
+        }
+        insertions += stat.added;
+        deletions += stat.removed;
+        let total = stat.added + stat.removed;
+        write!(out, " {name:<name_width$} | {total:>number_width$}")?;
+        if total > 0 {
+            write!(
+                out,
+                " {}{}",
+                "+".repeat(scale(stat.added)),
+                "-".repeat(scale(stat.removed))
+            )?;
+        }
+        out.push('\n');
+    }
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let mut total = String::new();
+    write!(
+        total,
+        " {} file{} changed",
+        stats.len(),
+        plural(stats.len())
+    )?;
+    if insertions > 0 || deletions == 0 {
+        write!(total, ", {insertions} insertion{}(+)", plural(insertions))?;
+    }
+    if deletions > 0 || insertions == 0 {
+        write!(total, ", {deletions} deletion{}(-)", plural(deletions))?;
+    }
+    total.push('\n');
+    Ok((out, total, summary))
+}
+
+// NOTE: "git diff -p" of two trees, a unified diff with 3 lines of context
+// diff --git a/f b/f
+// index 3b18e51..7a2a7b0 100644
+// --- a/f
+// +++ b/f
+// @@ -1,4 +1,4 @@ <function line>
+//  same
+// -old
+// +new
+// changes less than 6 lines apart share one hunk, the function line is the last line
+// before the hunk starting with a letter, '_' or '$' (the default of git)
+pub(crate) fn patch(db: &dyn ObjectDatabase, old: &Files, new: &Files) -> anyhow::Result<String> {
+    let abbrev = Abbrev::new(db, 7)?;
+    let short = |hash: Option<ObjectId>| match hash {
+        Some(hash) => abbrev.abbrev(&hash.to_string()).to_string(),
+        None => "0".repeat(7),
+    };
+    let mut out = String::new();
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort_unstable();
+    paths.dedup();
+    for path in paths {
+        let (old_entry, new_entry) = (old.get(path).copied(), new.get(path).copied());
+        if old_entry == new_entry {
+            continue;
+        }
+        let (old_hash, new_hash) = (old_entry.map(|e| e.1), new_entry.map(|e| e.1));
+        writeln!(out, "diff --git a/{path} b/{path}")?;
+        let mut index_mode = String::new();
+        match (old_entry, new_entry) {
+            (None, Some((mode, _))) => writeln!(out, "new file mode {mode:06o}")?,
+            (Some((mode, _)), None) => writeln!(out, "deleted file mode {mode:06o}")?,
+            (Some((old_mode, _)), Some((new_mode, _))) if old_mode != new_mode => {
+                writeln!(out, "old mode {old_mode:06o}\nnew mode {new_mode:06o}")?
+            }
+            (Some((mode, _)), Some(_)) => index_mode = format!(" {mode:06o}"),
+            (None, None) => {}
+        }
+        if old_hash == new_hash {
+            continue;
+        }
+        writeln!(
+            out,
+            "index {}..{}{index_mode}",
+            short(old_hash),
+            short(new_hash)
+        )?;
+        let old_name = match old_entry {
+            Some(_) => format!("a/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let new_name = match new_entry {
+            Some(_) => format!("b/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let old_content = read_blob(db, old_hash)?;
+        let new_content = read_blob(db, new_hash)?;
+        if is_binary(&old_content) || is_binary(&new_content) {
+            writeln!(out, "Binary files {old_name} and {new_name} differ")?;
+            continue;
+        }
+        writeln!(out, "--- {old_name}\n+++ {new_name}")?;
+        write_hunks(&mut out, &lines(&old_content), &lines(&new_content))?;
+    }
+    Ok(out)
+}
+
+// NOTE: the patch id of git, the same change made on top of another commit gives the same
+// id: the hunks are hashed without their "@@" lines and without any whitespace, the blob
+// hashes are left out (a binary file only has its hashes, so they stay)
+// None when nothing changed
+pub(crate) fn patch_id(
+    db: &dyn ObjectDatabase,
+    old: &Files,
+    new: &Files,
+) -> anyhow::Result<Option<ObjectId>> {
+    let mut patch = String::new();
+    let mut paths: Vec<&String> = old.keys().chain(new.keys()).collect();
+    paths.sort_unstable();
+    paths.dedup();
+    for path in paths {
+        let (old_entry, new_entry) = (old.get(path).copied(), new.get(path).copied());
+        if old_entry == new_entry {
+            continue;
+        }
+        let (old_mode, new_mode) = (old_entry.map(|e| e.0), new_entry.map(|e| e.0));
+        writeln!(patch, "{path} {old_mode:?} {new_mode:?}")?;
+        let (old_hash, new_hash) = (old_entry.map(|e| e.1), new_entry.map(|e| e.1));
+        if old_hash == new_hash {
+            continue;
+        }
+        let old_content = read_blob(db, old_hash)?;
+        let new_content = read_blob(db, new_hash)?;
+        if is_binary(&old_content) || is_binary(&new_content) {
+            writeln!(patch, "{old_hash:?}{new_hash:?}")?;
+            continue;
+        }
+        let mut hunks = String::new();
+        write_hunks(&mut hunks, &lines(&old_content), &lines(&new_content))?;
+        for line in hunks.lines().filter(|line| !line.starts_with("@@")) {
+            patch.extend(line.chars().filter(|c| !c.is_whitespace()));
+            patch.push('\n');
+        }
+    }
+    if patch.is_empty() {
+        return Ok(None);
+    }
+    Hasher::digest(db.format(), patch.as_bytes()).map(Some)
+}
+
+const CONTEXT: usize = 3;
+
+fn write_hunks(out: &mut String, a: &[&[u8]], b: &[&[u8]]) -> anyhow::Result<()> {
+    let changes = diff(a, b);
+    let mut i = 0;
+    while i < changes.len() {
+        let mut last = i;
+        while last + 1 < changes.len()
+            && changes[last + 1].old.start - changes[last].old.end <= 2 * CONTEXT
+        {
+            last += 1;
+        }
+        let old_start = changes[i].old.start.saturating_sub(CONTEXT);
+        let old_end = (changes[last].old.end + CONTEXT).min(a.len());
+        let new_start = changes[i].new.start - (changes[i].old.start - old_start);
+        let new_end = changes[last].new.end + (old_end - changes[last].old.end);
+        let range = |start: usize, len: usize| match len {
+            0 => format!("{start},0"),
+            1 => format!("{}", start + 1),
+            len => format!("{},{len}", start + 1),
+        };
+        write!(
+            out,
+            "@@ -{} +{} @@",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        )?;
+        if let Some(function) = a[..old_start].iter().rev().find(|line| {
+            line.first()
+                .is_some_and(|&c| c.is_ascii_alphabetic() || c == b'_' || c == b'$')
+        }) {
+            let function = function.trim_ascii_end();
+            let function = String::from_utf8_lossy(&function[..function.len().min(80)]);
+            write!(out, " {function}")?;
+        }
+        out.push('\n');
+
+        let mut old_line = old_start;
+        for change in &changes[i..=last] {
+            for line in &a[old_line..change.old.start] {
+                push_line(out, ' ', line);
+            }
+            for line in &a[change.old.clone()] {
+                push_line(out, '-', line);
+            }
+            for line in &b[change.new.clone()] {
+                push_line(out, '+', line);
+            }
+            old_line = change.old.end;
+        }
+        for line in &a[old_line..old_end] {
+            push_line(out, ' ', line);
+        }
+        i = last + 1;
+    }
+    Ok(())
+}
+
+fn push_line(out: &mut String, prefix: char, line: &[u8]) {
+    out.push(prefix);
+    out.push_str(&String::from_utf8_lossy(line));
+    if !line.ends_with(b"\n") {
+        out.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+fn read_blob(db: &dyn ObjectDatabase, hash: Option<ObjectId>) -> anyhow::Result<Vec<u8>> {
+    let Some(hash) = hash else {
+        return Ok(Vec::new());
+    };
+    let mut content = Vec::new();
+    db.read(&hash.to_string())?
+        .reader
+        .read_to_end(&mut content)
+        .with_context(|| format!("Failed to read the blob {hash}"))?;
+    Ok(content)
+}
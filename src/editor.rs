@@ -0,0 +1,45 @@
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+
+use crate::config::Config;
+
+// NOTE: editor for the messages, the first one that is set of
+// GIT_EDITOR, core.editor, VISUAL, EDITOR, then vi
+// it's a shell command ("code --wait" work), the file to edit is added at the end
+pub(crate) fn editor(config: &Config) -> String {
+    std::env::var("GIT_EDITOR")
+        .ok()
+        .or_else(|| config.get("core.editor").map(str::to_string))
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .unwrap_or_else(|| "vi".to_string())
+}
+
+// NOTE: the todo of rebase -i, GIT_SEQUENCE_EDITOR then sequence.editor, the editor otherwise
+pub(crate) fn sequence_editor(config: &Config) -> String {
+    std::env::var("GIT_SEQUENCE_EDITOR")
+        .ok()
+        .or_else(|| config.get("sequence.editor").map(str::to_string))
+        .unwrap_or_else(|| editor(config))
+}
+
+pub(crate) fn launch(editor: &str, file: &Path) -> anyhow::Result<()> {
+    // ":" is the "don't edit anything" editor of git
+    if editor == ":" {
+        return Ok(());
+    }
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{editor} \"$@\""))
+        .arg(editor)
+        .arg(file)
+        .status()
+        .with_context(|| format!("unable to start editor '{editor}'"))?;
+    anyhow::ensure!(
+        status.success(),
+        "There was a problem with the editor '{editor}'."
+    );
+    Ok(())
+}
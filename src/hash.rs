@@ -0,0 +1,159 @@
+use core::fmt;
+
+use anyhow::Context;
+use sha1_checked::{CollisionResult, Sha1};
+use sha2::{Digest, Sha256};
+
+use crate::config::Config;
+
+// NOTE: repository can use sha1 (20 bytes, 40 hex char) or sha256 (32 bytes, 64 hex char)
+// git init --object-format=sha256 write into .git/config
+// [core]
+//     repositoryformatversion = 1
+// [extensions]
+//     objectformat = sha256
+// every hash in the repo (objects, trees, index, refs, packs) then use the same size
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum ObjectFormat {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub(crate) fn from_config(config: &Config) -> anyhow::Result<Self> {
+        match config.get("extensions.objectFormat") {
+            None => Ok(ObjectFormat::Sha1),
+            Some(name) => name.parse(),
+        }
+    }
+    // NOTE: format of the repository in the current dir
+    pub(crate) fn current() -> anyhow::Result<Self> {
+        Self::from_config(&Config::load()?)
+    }
+    pub(crate) fn raw_len(self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+    pub(crate) fn hex_len(self) -> usize {
+        self.raw_len() * 2
+    }
+}
+
+impl fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectFormat::Sha1 => write!(f, "sha1"),
+            ObjectFormat::Sha256 => write!(f, "sha256"),
+        }
+    }
+}
+
+impl std::str::FromStr for ObjectFormat {
+    type Err = anyhow::Error;
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "sha1" => Ok(ObjectFormat::Sha1),
+            "sha256" => Ok(ObjectFormat::Sha256),
+            _ => anyhow::bail!("unknown object format '{name}'"),
+        }
+    }
+}
+
+// NOTE: raw hash of the object, big enough for both formats
+// only the first `len` bytes are used, so the sha1 hash is [20 bytes][12 zero]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub(crate) struct ObjectId {
+    bytes: [u8; 32],
+    len: u8,
+}
+
+impl ObjectId {
+    pub(crate) fn from_bytes(raw: &[u8]) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            raw.len() == 20 || raw.len() == 32,
+            "object id must be 20 or 32 bytes, got {}",
+            raw.len()
+        );
+        let mut bytes = [0; 32];
+        bytes[..raw.len()].copy_from_slice(raw);
+        Ok(Self {
+            bytes,
+            len: raw.len() as u8,
+        })
+    }
+    pub(crate) fn from_hex(hash: &str) -> anyhow::Result<Self> {
+        anyhow::ensure!(
+            hash.len() == 40 || hash.len() == 64,
+            "not a valid object name: '{hash}'"
+        );
+        let raw =
+            hex::decode(hash).with_context(|| format!("not a valid object name: '{hash}'"))?;
+        Self::from_bytes(&raw)
+    }
+    pub(crate) fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl AsRef<[u8]> for ObjectId {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl fmt::Display for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.as_bytes()))
+    }
+}
+
+impl fmt::Debug for ObjectId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ObjectId({self})")
+    }
+}
+
+// NOTE: sha1 is hashed with the collision detection (same sha1dc as the real git)
+// file crafted like SHAttered (two different content, same sha1) can't get into the repo,
+// we fail hard instead of returning the hash
+pub(crate) enum Hasher {
+    Sha1(Box<Sha1>),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub(crate) fn new(format: ObjectFormat) -> Self {
+        match format {
+            ObjectFormat::Sha1 => Hasher::Sha1(Box::new(Sha1::builder().safe_hash(false).build())),
+            ObjectFormat::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+    pub(crate) fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+    pub(crate) fn finalize(self) -> anyhow::Result<ObjectId> {
+        match self {
+            Hasher::Sha1(hasher) => match hasher.try_finalize() {
+                CollisionResult::Ok(hash) => ObjectId::from_bytes(&hash),
+                CollisionResult::Mitigated(hash) | CollisionResult::Collision(hash) => {
+                    anyhow::bail!(
+                        "SHA-1 appears to be part of a collision attack: {}",
+                        hex::encode(hash)
+                    )
+                }
+            },
+            Hasher::Sha256(hasher) => ObjectId::from_bytes(&hasher.finalize()),
+        }
+    }
+    pub(crate) fn digest(format: ObjectFormat, data: &[u8]) -> anyhow::Result<ObjectId> {
+        let mut hasher = Hasher::new(format);
+        hasher.update(data);
+        hasher.finalize()
+    }
+}
@@ -0,0 +1,94 @@
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::Context;
+
+use crate::config::Config;
+
+// NOTE: hooks are programs in .git/hooks (or core.hooksPath) named after the event
+// they run in the root of the worktree, their stdout goes to our stderr like git
+// a hook that is there but not executable is skipped with a hint (advice.ignoredHook)
+// pre-commit                     commit, before the tree is written, non zero = no commit
+// prepare-commit-msg file source [hash]   commit, before the editor
+// commit-msg file                commit, after the editor, non zero = no commit
+// post-commit                    commit, after HEAD moved
+// post-checkout old new 1        clone/checkout, after the worktree is written
+// post-merge squash              merge, after the merge commit (0 or 1 for --squash)
+// pre-push remote url            push, stdin "<local ref> <local hash> <remote ref> <remote hash>"
+//                                per ref, non zero = nothing pushed
+// reference-transaction state    every ref update, stdin "<old> <new> <ref>", "prepared"
+//                                can refuse the update, "committed" after it is written
+// --no-verify skip pre-commit and commit-msg (and pre-push)
+pub(crate) struct Hooks {
+    dir: PathBuf,
+    worktree: PathBuf,
+    advice: bool,
+}
+
+impl Hooks {
+    pub(crate) fn open(git_dir: &Path) -> anyhow::Result<Self> {
+        let config = Config::load_from(&git_dir.join("config"))?;
+        let worktree = match git_dir.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+        // relative core.hooksPath is from the root of the worktree, same as git
+        let dir = match config.get("core.hooksPath") {
+            Some(path) => worktree.join(path),
+            None => git_dir.join("hooks"),
+        };
+        let advice = config.get_bool("advice.ignoredHook")?.unwrap_or(true);
+        Ok(Self {
+            dir,
+            worktree,
+            advice,
+        })
+    }
+
+    // NOTE: Ok(true) when the hook succeeded or there is no hook to run
+    pub(crate) fn run(&self, name: &str, args: &[&str], input: &[u8]) -> anyhow::Result<bool> {
+        let path = self.dir.join(name);
+        let Ok(metadata) = fs::metadata(&path) else {
+            return Ok(true);
+        };
+        if !metadata.is_file() {
+            return Ok(true);
+        }
+        if metadata.permissions().mode() & 0o111 == 0 {
+            if self.advice {
+                eprintln!(
+                    "hint: The '{}' hook was ignored because it's not set as executable.\n\
+                     hint: You can disable this warning with `git config advice.ignoredHook false`.",
+                    path.display()
+                );
+            }
+            return Ok(true);
+        }
+        // the hook runs from the worktree, a relative path would not be found from there
+        let program = std::path::absolute(&path)
+            .with_context(|| format!("Failed to find the hook {}", path.display()))?;
+        let stdin = if input.is_empty() {
+            Stdio::null()
+        } else {
+            Stdio::piped()
+        };
+        let mut child = Command::new(&program)
+            .args(args)
+            .current_dir(&self.worktree)
+            .stdin(stdin)
+            .stdout(std::io::stderr())
+            .spawn()
+            .with_context(|| format!("cannot run {}", path.display()))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            // hook is allowed to not read its input
+            let _ = stdin.write_all(input);
+        }
+        let status = child
+            .wait()
+            .with_context(|| format!("Failed to wait for the hook {}", path.display()))?;
+        Ok(status.success())
+    }
+}
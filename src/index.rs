@@ -0,0 +1,385 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::Path;
+
+use crate::config::{Config, FSYNC_INDEX};
+use crate::hash::{Hasher, ObjectFormat, ObjectId};
+use crate::lockfile::{LockFile, write_atomic};
+use crate::odb::ObjectDatabase;
+use crate::tree::{Files, TreeBuilder};
+use anyhow::Context;
+
+// NOTE: the .git/index (staging area) in memory
+// header 12 bytes: DIRC | version u32 | number of entries u32
+// then every entry (all the number are big endian, anything bigger then u32 get truncated)
+// | ctime_sec | ctime_nsec | mtime_sec | mtime_nsec | dev | ino | mode | uid | gid | size | <- 4 bytes each
+// | hash 20 (sha256 repo 32) | flags 2 | (version 3: extended flags 2) | path | 1..8 \0 so the entry is multiple of 8 |
+// then the extensions (TREE, REUC, ...) and at the end hash of everything before it
+// extension: signature 4 | size u32 | data, a signature starting with A-Z is only a cache
+// we can drop, any other (link, sdir) changes what the index means and we refuse it
+//
+// flags: assume-valid 1 bit | extended 1 bit | stage 2 bits | path length 12 bits
+// extended flags: reserved 1 bit | skip-worktree 1 bit | intent-to-add 1 bit | unused 13 bits
+// entries are sorted by path bytes and then by stage
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const READER_VERSION: u32 = 2;
+// the version written when an entry has extended flags
+const EXTENDED_VERSION: u32 = 3;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct IndexEntry {
+    pub(crate) ctime: (u32, u32),
+    pub(crate) mtime: (u32, u32),
+    pub(crate) dev: u32,
+    pub(crate) ino: u32,
+    pub(crate) mode: u32,
+    pub(crate) uid: u32,
+    pub(crate) gid: u32,
+    pub(crate) size: u32,
+    pub(crate) hash: ObjectId,
+    pub(crate) flags: u16,
+    // skip-worktree, intent-to-add (version 3), kept as read
+    pub(crate) extended_flags: u16,
+    pub(crate) path: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Index {
+    pub(crate) format: ObjectFormat,
+    pub(crate) entries: Vec<IndexEntry>,
+}
+
+impl IndexEntry {
+    // NOTE: entry of a file that is not (yet) in the worktree, all the stat are 0
+    // (the stages of a conflict, or a file that is checked out later)
+    pub(crate) fn new(path: &str, mode: u32, hash: ObjectId, stage: u16) -> Self {
+        Self {
+            ctime: (0, 0),
+            mtime: (0, 0),
+            dev: 0,
+            ino: 0,
+            mode,
+            uid: 0,
+            gid: 0,
+            size: 0,
+            hash,
+            flags: build_flag(stage, path.len()),
+            extended_flags: 0,
+            path: path.to_string(),
+        }
+    }
+    // NOTE: entry for the file in the working tree with the given blob hash
+    pub(crate) fn from_metadata(
+        path: &str,
+        metadata: &fs::Metadata,
+        hash: ObjectId,
+        stage: u16,
+    ) -> Self {
+        Self {
+            ctime: (metadata.ctime() as u32, metadata.ctime_nsec() as u32),
+            mtime: (metadata.mtime() as u32, metadata.mtime_nsec() as u32),
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            mode: mode_from_metadata(metadata),
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+            hash,
+            flags: build_flag(stage, path.len()),
+            extended_flags: 0,
+            path: path.to_string(),
+        }
+    }
+    pub(crate) fn stage(&self) -> u16 {
+        (self.flags & FLAG_STAGE_MASK) >> 12
+    }
+}
+
+// NOTE: git only store 4 kind of the file mode, not the real permission bits
+pub(crate) fn mode_from_metadata(metadata: &fs::Metadata) -> u32 {
+    if metadata.file_type().is_symlink() {
+        0o120000
+    } else if metadata.is_dir() {
+        0o160000
+    } else if metadata.permissions().mode() & 0o111 != 0 {
+        0o100755
+    } else {
+        0o100644
+    }
+}
+
+pub(crate) fn build_flag(stage: u16, path_length: usize) -> u16 {
+    let stage = stage & 0b11; // enforce 2 bits
+    let path_length = std::cmp::min(path_length, FLAG_NAME_MASK as usize) as u16; // enforce 12 bits
+    (stage << 12) | path_length
+}
+
+impl Index {
+    pub(crate) fn new(format: ObjectFormat) -> Self {
+        Self {
+            format,
+            entries: Vec::new(),
+        }
+    }
+    // NOTE: a command that changes the index takes index.lock before reading it, nobody can
+    // change the index in between. write_locked puts the new one in place, a lock that is
+    // dropped leaves the index as it was
+    pub(crate) fn lock(path: &Path) -> anyhow::Result<(Self, LockFile)> {
+        let lock = LockFile::acquire(path)?;
+        Ok((Self::read(path)?, lock))
+    }
+    // NOTE: missing index file is the same as the empty index (fresh repo)
+    // hash size come from the repo format in the config next to the index
+    // every offset is checked, a truncated or lying file is an error
+    pub(crate) fn read(path: &Path) -> anyhow::Result<Self> {
+        let format =
+            ObjectFormat::from_config(&Config::load_from(&path.with_file_name("config"))?)?;
+        let hash_len = format.raw_len();
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::new(format)),
+            Err(e) => return Err(e).context("Open the .git/index file."),
+        };
+        anyhow::ensure!(data.len() >= 12 + hash_len, ".git/index file is too short");
+        let (content, checksum) = data.split_at(data.len() - hash_len);
+        anyhow::ensure!(
+            Hasher::digest(format, content)?.as_bytes() == checksum,
+            ".git/index file checksum does not match"
+        );
+        anyhow::ensure!(
+            &content[..4] == SIGNATURE,
+            ".git/index file has bad signature"
+        );
+        let version = be_u32(content, 4)?;
+        anyhow::ensure!(
+            version == 2 || version == 3,
+            ".git/index version {version} is not supported"
+        );
+        let num_of_entries = be_u32(content, 8)? as usize;
+        let stats_len = 40 + hash_len + 2;
+        // every entry is at least its stats and one nul
+        anyhow::ensure!(
+            num_of_entries <= content.len() / (stats_len + 1),
+            ".git/index file is truncated"
+        );
+        let mut entries = Vec::with_capacity(num_of_entries);
+        let mut at = 12;
+        for i in 0..num_of_entries {
+            let start = at;
+            let stats = content
+                .get(at..at + stats_len)
+                .with_context(|| format!("Reading the stats for {i} entry"))?;
+            let flags = u16::from_be_bytes(stats[stats_len - 2..].try_into().unwrap());
+            at += stats_len;
+            let mut extended_flags = 0;
+            if flags & FLAG_EXTENDED != 0 {
+                anyhow::ensure!(
+                    version >= EXTENDED_VERSION,
+                    "entry {i} has extended flags in a version {version} index"
+                );
+                let raw = content
+                    .get(at..at + 2)
+                    .with_context(|| format!("Reading the extended flags for entry {i}"))?;
+                extended_flags = u16::from_be_bytes(raw.try_into().unwrap());
+                at += 2;
+            }
+            let path_len = content
+                .get(at..)
+                .and_then(|rest| rest.iter().position(|&b| b == 0))
+                .with_context(|| format!("Reading file path for entry {i}"))?;
+            let path = std::str::from_utf8(&content[at..at + path_len])
+                .with_context(|| format!("file path for entry {i} is not valid UTF-8"))?
+                .to_string();
+            at += path_len;
+            // 1 to 8 nul bytes so the whole entry is multiple of 8
+            at = start + ((at - start + 8) & !7);
+            anyhow::ensure!(
+                at <= content.len(),
+                "Reading the padding of entry {i}: .git/index file is truncated"
+            );
+            entries.push(IndexEntry {
+                ctime: (be_u32(stats, 0)?, be_u32(stats, 4)?),
+                mtime: (be_u32(stats, 8)?, be_u32(stats, 12)?),
+                dev: be_u32(stats, 16)?,
+                ino: be_u32(stats, 20)?,
+                mode: be_u32(stats, 24)?,
+                uid: be_u32(stats, 28)?,
+                gid: be_u32(stats, 32)?,
+                size: be_u32(stats, 36)?,
+                hash: ObjectId::from_bytes(&stats[40..40 + hash_len])?,
+                flags: flags & !FLAG_EXTENDED,
+                extended_flags,
+                path,
+            });
+        }
+        // NOTE: the optional extensions (cache tree, resolve undo, ..) are only a cache,
+        // we drop them and git will rebuild them when it needs
+        while at < content.len() {
+            let name = content
+                .get(at..at + 4)
+                .context("Reading an extension: .git/index file is truncated")?;
+            let size = be_u32(content, at + 4)? as usize;
+            anyhow::ensure!(
+                name[0].is_ascii_uppercase(),
+                "index uses {} extension, which we do not understand",
+                String::from_utf8_lossy(name)
+            );
+            at = at + 8 + size;
+            anyhow::ensure!(
+                at <= content.len(),
+                "Reading the {} extension: .git/index file is truncated",
+                String::from_utf8_lossy(name)
+            );
+        }
+        Ok(Self { format, entries })
+    }
+    pub(crate) fn write(&self, path: &Path) -> anyhow::Result<()> {
+        let fsync = Config::load_from(&path.with_file_name("config"))?.fsync(FSYNC_INDEX)?;
+        write_atomic(path, &self.to_bytes()?, fsync)
+    }
+    // the index taken by Index::lock
+    pub(crate) fn write_locked(&self, lock: LockFile) -> anyhow::Result<()> {
+        let config = Config::load_from(&lock.path().with_file_name("config"))?;
+        lock.commit(&self.to_bytes()?, config.fsync(FSYNC_INDEX)?)
+    }
+    fn to_bytes(&self) -> anyhow::Result<Vec<u8>> {
+        let mut buf: Vec<u8> = Vec::with_capacity(12 + self.entries.len() * 80);
+        let version = if self.entries.iter().any(|e| e.extended_flags != 0) {
+            EXTENDED_VERSION
+        } else {
+            READER_VERSION
+        };
+        // header
+        buf.extend(SIGNATURE);
+        buf.extend(version.to_be_bytes());
+        buf.extend((self.entries.len() as u32).to_be_bytes());
+        for entry in &self.entries {
+            let start = buf.len();
+            buf.extend(entry.ctime.0.to_be_bytes());
+            buf.extend(entry.ctime.1.to_be_bytes());
+            buf.extend(entry.mtime.0.to_be_bytes());
+            buf.extend(entry.mtime.1.to_be_bytes());
+            buf.extend(entry.dev.to_be_bytes());
+            buf.extend(entry.ino.to_be_bytes());
+            buf.extend(entry.mode.to_be_bytes());
+            buf.extend(entry.uid.to_be_bytes());
+            buf.extend(entry.gid.to_be_bytes());
+            buf.extend(entry.size.to_be_bytes());
+            buf.extend(entry.hash.as_bytes());
+            if entry.extended_flags != 0 {
+                buf.extend((entry.flags | FLAG_EXTENDED).to_be_bytes());
+                buf.extend(entry.extended_flags.to_be_bytes());
+            } else {
+                buf.extend(entry.flags.to_be_bytes());
+            }
+            buf.extend(entry.path.as_bytes());
+            let len = buf.len() - start;
+            buf.resize(start + ((len + 8) & !7), 0);
+        }
+        let hash = Hasher::digest(self.format, &buf)?;
+        buf.extend(hash.as_bytes());
+        Ok(buf)
+    }
+    // NOTE: keep entries sorted by path and stage, replace the one with same path+stage
+    pub(crate) fn add(&mut self, entry: IndexEntry) {
+        let key = (entry.path.as_bytes(), entry.stage());
+        match self
+            .entries
+            .binary_search_by(|e| (e.path.as_bytes(), e.stage()).cmp(&key))
+        {
+            Ok(i) => self.entries[i] = entry,
+            Err(i) => self.entries.insert(i, entry),
+        }
+    }
+    // NOTE: drop every stage of the path
+    pub(crate) fn remove(&mut self, path: &str) {
+        self.entries.retain(|e| e.path != path);
+    }
+    // NOTE: the stage 0 entries, same shape as tree::files so they can be compared
+    pub(crate) fn files(&self) -> Files {
+        self.entries
+            .iter()
+            .filter(|e| e.stage() == 0)
+            .map(|e| (e.path.clone(), (e.mode, e.hash)))
+            .collect()
+    }
+    // NOTE: the index entries of the selected paths become the files, the stat of an entry
+    // that does not change is kept so the file is not hashed again
+    pub(crate) fn set_files(&mut self, files: &Files, selected: impl Fn(&str) -> bool) {
+        let old: BTreeMap<String, IndexEntry> = self
+            .entries
+            .iter()
+            .filter(|entry| entry.stage() == 0)
+            .map(|entry| (entry.path.clone(), entry.clone()))
+            .collect();
+        self.entries.retain(|entry| !selected(&entry.path));
+        for (path, &(mode, hash)) in files.iter().filter(|(path, _)| selected(path)) {
+            match old.get(path) {
+                Some(entry) if entry.mode == mode && entry.hash == hash => self.add(entry.clone()),
+                _ => self.add(IndexEntry::new(path, mode, hash, 0)),
+            }
+        }
+    }
+    pub(crate) fn is_unmerged(&self, path: &str) -> bool {
+        self.entries
+            .iter()
+            .any(|e| e.path == path && e.stage() != 0)
+    }
+    // NOTE: the tree objects of the staged content (git write-tree), one tree per directory
+    // conflicted entries (stage 1-3) have to be resolved first
+    pub(crate) fn write_tree(&self, db: &dyn ObjectDatabase) -> anyhow::Result<ObjectId> {
+        if let Some(entry) = self.entries.iter().find(|e| e.stage() != 0) {
+            anyhow::bail!(
+                "{}: unmerged, you need to resolve your current index first",
+                entry.path
+            );
+        }
+        let entries: Vec<&IndexEntry> = self.entries.iter().collect();
+        write_subtree(db, &entries, "")
+    }
+}
+
+// entries are sorted by path, so everything under "dir/" is one slice
+fn write_subtree(
+    db: &dyn ObjectDatabase,
+    entries: &[&IndexEntry],
+    prefix: &str,
+) -> anyhow::Result<ObjectId> {
+    let mut builder = TreeBuilder::default();
+    let mut i = 0;
+    while i < entries.len() {
+        let rest = &entries[i].path[prefix.len()..];
+        match rest.split_once('/') {
+            None => {
+                builder.insert(entries[i].mode, rest, entries[i].hash)?;
+                i += 1;
+            }
+            Some((dir, _)) => {
+                let dir_prefix = format!("{prefix}{dir}/");
+                let end = i + entries[i..]
+                    .iter()
+                    .take_while(|e| e.path.starts_with(&dir_prefix))
+                    .count();
+                let hash = write_subtree(db, &entries[i..end], &dir_prefix)?;
+                builder.insert(0o40000, dir, hash)?;
+                i = end;
+            }
+        }
+    }
+    builder
+        .build()
+        .object()
+        .write_to(db)
+        .context("Failed to write a tree object")
+}
+
+fn be_u32(data: &[u8], at: usize) -> anyhow::Result<u32> {
+    let raw = data
+        .get(at..at + 4)
+        .context(".git/index file is truncated")?;
+    Ok(u32::from_be_bytes(raw.try_into().unwrap()))
+}
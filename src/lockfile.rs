@@ -0,0 +1,77 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+// NOTE:
+// We use `OpenOptions` instead of `File::open` / `File::create` because writing
+// `.git/index` (or a ref) must be done atomically and exclusively.
+//
+// - `OpenOptions::create_new(true)` maps to `O_CREAT | O_EXCL` and guarantees
+//   that `.git/index.lock` is created ONLY if it does not already exist.
+//   This prevents concurrent writers and avoids race conditions.
+//
+// - The new content is written fully to `<file>.lock`, NOT directly to the file.
+//   Writing directly risks partial writes and repository corruption.
+//
+// Correct order (same as Git core):
+//   write → fsync(file) → rename(index.lock → index)
+// (the fsync can be turned off with core.fsync)
+//
+// the lock can be taken before the file is read, nobody else can change it until
+// commit, a lock that is dropped without commit is removed
+pub(crate) struct LockFile {
+    path: PathBuf,
+    lock: PathBuf,
+    file: Option<File>,
+}
+
+impl LockFile {
+    pub(crate) fn acquire(path: &Path) -> anyhow::Result<Self> {
+        let mut lock = path.as_os_str().to_owned();
+        lock.push(".lock");
+        let lock = PathBuf::from(lock);
+        let file = OpenOptions::new()
+            .create_new(true)
+            .write(true)
+            .open(&lock)
+            .with_context(|| format!("Unable to create '{}': File exists.", lock.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            lock,
+            file: Some(file),
+        })
+    }
+
+    // the file the lock is for
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn commit(mut self, buf: &[u8], fsync: bool) -> anyhow::Result<()> {
+        let mut file = self.file.take().expect("the lock is committed once");
+        let result = file
+            .write_all(buf)
+            .and_then(|_| if fsync { file.sync_all() } else { Ok(()) })
+            .and_then(|_| fs::rename(&self.lock, &self.path))
+            .with_context(|| format!("Failed to write {}", self.path.display()));
+        if result.is_err() {
+            // the lock is ours, don't leave it behind or every next command will fail
+            let _ = fs::remove_file(&self.lock);
+        }
+        result
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        if self.file.is_some() {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+pub(crate) fn write_atomic(path: &Path, buf: &[u8], fsync: bool) -> anyhow::Result<()> {
+    LockFile::acquire(path)?.commit(buf, fsync)
+}
@@ -1,9 +1,35 @@
 use clap::{Parser, Subcommand};
-use std::fs;
+use hash::ObjectFormat;
+use objects::Kind;
 use std::path::PathBuf;
 
+pub(crate) mod ancestry;
+pub(crate) mod attributes;
 pub(crate) mod commands;
+pub(crate) mod commit;
+pub(crate) mod commit_graph;
+pub(crate) mod config;
+pub(crate) mod convert;
+pub(crate) mod diff;
+pub(crate) mod editor;
+pub(crate) mod hash;
+pub(crate) mod hooks;
+pub(crate) mod index;
+pub(crate) mod lockfile;
+pub(crate) mod merge;
 pub(crate) mod objects;
+pub(crate) mod odb;
+pub(crate) mod pathspec;
+pub(crate) mod quote;
+pub(crate) mod reachable;
+pub(crate) mod refs;
+pub(crate) mod remote;
+pub(crate) mod revision;
+pub(crate) mod revwalk;
+pub(crate) mod sequencer;
+pub(crate) mod tree;
+pub(crate) mod validate;
+pub(crate) mod worktree;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -14,30 +40,76 @@ struct Args {
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Init,
+    Init {
+        #[arg(long = "object-format", value_name = "FORMAT", default_value = "sha1")]
+        object_format: ObjectFormat,
+    },
     // plumbing command
     CatFile {
         #[arg(short = 'p')]
         pretty_print: bool,
+        #[arg(short = 't', conflicts_with_all = ["pretty_print", "show_size"])]
+        show_type: bool,
+        #[arg(short = 's', conflicts_with = "pretty_print")]
+        show_size: bool,
         object_hash: String,
     },
     HashObject {
+        #[arg(short = 't', value_name = "TYPE", default_value = "blob")]
+        kind: Kind,
         #[arg(short = 'w')]
         write: bool,
-        file_path: PathBuf,
+        #[arg(long = "stdin")]
+        stdin: bool,
+        #[arg(long = "stdin-paths", conflicts_with_all = ["stdin", "file_paths"])]
+        stdin_paths: bool,
+        #[arg(long = "literally")]
+        literally: bool,
+        #[arg(long = "no-filters", conflicts_with = "path")]
+        no_filters: bool,
+        #[arg(long = "path", value_name = "FILE")]
+        path: Option<String>,
+        file_paths: Vec<PathBuf>,
     },
     LsTree {
-        #[arg(long = "name-only")]
+        #[arg(short = 'r')]
+        recursive: bool,
+        #[arg(short = 't')]
+        show_trees: bool,
+        #[arg(short = 'd')]
+        trees_only: bool,
+        #[arg(short = 'l', long = "long")]
+        long: bool,
+        #[arg(short = 'z')]
+        nul_terminated: bool,
+        #[arg(long = "name-only", visible_alias = "name-status")]
         name_only: bool,
-        tree_object: String,
+        #[arg(long = "object-only", conflicts_with_all = ["name_only", "long"])]
+        object_only: bool,
+        #[arg(long = "abbrev", value_name = "N", num_args = 0..=1, default_missing_value = "7", require_equals = true)]
+        abbrev: Option<usize>,
+        #[arg(long = "format", conflicts_with_all = ["name_only", "object_only", "long"])]
+        format: Option<String>,
+        tree_ish: String,
+        paths: Vec<String>,
     },
     WriteTree,
+    Mktree {
+        #[arg(short = 'z')]
+        nul_terminated: bool,
+        #[arg(long = "missing")]
+        missing: bool,
+        #[arg(long = "batch")]
+        batch: bool,
+    },
     CommitTree {
         tree_sha: String,
         #[arg(short = 'p', value_name = "PARENT_COMMIT")]
-        parent_commit_sha: Option<String>,
+        parent_commit_sha: Vec<String>,
         #[arg(short = 'm', value_name = "COMMIT_MESSAGE")]
-        commit_message: String,
+        commit_message: Vec<String>,
+        #[arg(short = 'F', value_name = "FILE")]
+        message_file: Vec<String>,
     },
     LsFiles {
         #[arg(short = 's', long = "stage")]
@@ -50,50 +122,708 @@ enum Commands {
         add: bool,
         file_path: Option<String>,
     },
+    Fsck {
+        #[arg(long = "unreachable")]
+        unreachable: bool,
+        #[arg(long = "lost-found")]
+        lost_found: bool,
+        #[arg(long = "connectivity-only")]
+        connectivity_only: bool,
+    },
+    CountObjects {
+        #[arg(short = 'v', long = "verbose")]
+        verbose: bool,
+        #[arg(short = 'H', long = "human-readable")]
+        human_readable: bool,
+    },
+    Prune {
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+        #[arg(short = 'v', long = "verbose")]
+        verbose: bool,
+        #[arg(long = "expire", value_name = "TIME")]
+        expire: Option<String>,
+    },
     // general commands
     Commit {
-        #[arg(short = 'm')]
-        message: String,
+        #[arg(short = 'm', long = "message")]
+        message: Vec<String>,
+        #[arg(short = 'F', long = "file", conflicts_with = "message")]
+        file: Option<String>,
+        #[arg(short = 't', long = "template")]
+        template: Option<String>,
+        #[arg(short = 'e', long = "edit")]
+        edit: bool,
+        #[arg(long = "no-edit", conflicts_with = "edit")]
+        no_edit: bool,
+        #[arg(long = "amend")]
+        amend: bool,
+        #[arg(short = 'a', long = "all")]
+        all: bool,
+        #[arg(long = "cleanup", value_name = "MODE")]
+        cleanup: Option<commit::Cleanup>,
+        #[arg(long = "allow-empty")]
+        allow_empty: bool,
+        #[arg(long = "author")]
+        author: Option<String>,
+        #[arg(long = "date")]
+        date: Option<String>,
+        #[arg(short = 's', long = "signoff")]
+        signoff: bool,
+        #[arg(short = 'n', long = "no-verify")]
+        no_verify: bool,
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+    },
+    Merge {
+        #[arg(long = "ff-only")]
+        ff_only: bool,
+        #[arg(long = "no-ff", conflicts_with = "ff_only")]
+        no_ff: bool,
+        #[arg(long = "no-commit")]
+        no_commit: bool,
+        #[arg(short = 'm', long = "message")]
+        message: Vec<String>,
+        #[arg(short = 'e', long = "edit")]
+        edit: bool,
+        #[arg(long = "no-edit", conflicts_with = "edit")]
+        no_edit: bool,
+        #[arg(long = "no-verify")]
+        no_verify: bool,
+        #[arg(long = "allow-unrelated-histories")]
+        allow_unrelated_histories: bool,
+        #[arg(long = "abort", conflicts_with = "continue_merge")]
+        abort: bool,
+        #[arg(long = "continue")]
+        continue_merge: bool,
+        commit: Option<String>,
+    },
+    MergeBase {
+        #[arg(short = 'a', long = "all")]
+        all: bool,
+        #[arg(long = "octopus")]
+        octopus: bool,
+        #[arg(long = "is-ancestor", conflicts_with_all = ["octopus", "fork_point"])]
+        is_ancestor: bool,
+        #[arg(long = "fork-point", conflicts_with = "octopus")]
+        fork_point: bool,
+        #[arg(required = true)]
+        commits: Vec<String>,
+    },
+    CherryPick {
+        #[arg(short = 'n', long = "no-commit")]
+        no_commit: bool,
+        #[arg(short = 'x')]
+        record_origin: bool,
+        #[arg(short = 'm', long = "mainline", value_name = "PARENT")]
+        mainline: Option<usize>,
+        #[arg(short = 'e', long = "edit")]
+        edit: bool,
+        #[arg(long = "continue", conflicts_with_all = ["skip", "abort"])]
+        continue_pick: bool,
+        #[arg(long = "skip", conflicts_with = "abort")]
+        skip: bool,
+        #[arg(long = "abort")]
+        abort: bool,
+        #[arg(required_unless_present_any = ["continue_pick", "skip", "abort"])]
+        commits: Vec<String>,
+    },
+    Revert {
+        #[arg(short = 'n', long = "no-commit")]
+        no_commit: bool,
+        #[arg(short = 'm', long = "mainline", value_name = "PARENT")]
+        mainline: Option<usize>,
+        #[arg(short = 'e', long = "edit")]
+        edit: bool,
+        #[arg(long = "no-edit", conflicts_with = "edit")]
+        no_edit: bool,
+        #[arg(long = "continue", conflicts_with_all = ["skip", "abort"])]
+        continue_revert: bool,
+        #[arg(long = "skip", conflicts_with = "abort")]
+        skip: bool,
+        #[arg(long = "abort")]
+        abort: bool,
+        #[arg(required_unless_present_any = ["continue_revert", "skip", "abort"])]
+        commits: Vec<String>,
+    },
+    Rebase {
+        #[arg(long = "onto", value_name = "NEWBASE")]
+        onto: Option<String>,
+        #[arg(short = 'i', long = "interactive")]
+        interactive: bool,
+        #[arg(long = "autosquash")]
+        autosquash: bool,
+        #[arg(short = 'x', long = "exec", value_name = "CMD")]
+        exec: Vec<String>,
+        #[arg(long = "reapply-cherry-picks")]
+        reapply_cherry_picks: bool,
+        #[arg(long = "continue", conflicts_with_all = ["skip", "abort"])]
+        continue_rebase: bool,
+        #[arg(long = "skip", conflicts_with = "abort")]
+        skip: bool,
+        #[arg(long = "abort")]
+        abort: bool,
+        upstream: Option<String>,
+        branch: Option<String>,
+    },
+    Reset {
+        #[arg(long = "soft", conflicts_with_all = ["mixed", "hard", "keep", "merge"])]
+        soft: bool,
+        #[arg(long = "mixed", conflicts_with_all = ["hard", "keep", "merge"])]
+        mixed: bool,
+        #[arg(long = "hard", conflicts_with_all = ["keep", "merge"])]
+        hard: bool,
+        #[arg(long = "keep", conflicts_with = "merge")]
+        keep: bool,
+        #[arg(long = "merge")]
+        merge: bool,
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+        args: Vec<String>,
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+    Restore {
+        #[arg(short = 'S', long = "staged")]
+        staged: bool,
+        #[arg(short = 'W', long = "worktree")]
+        worktree: bool,
+        #[arg(short = 's', long = "source", value_name = "TREE")]
+        source: Option<String>,
+        paths: Vec<String>,
+    },
+    Rm {
+        #[arg(long = "cached")]
+        cached: bool,
+        #[arg(short = 'r')]
+        recursive: bool,
+        #[arg(short = 'f', long = "force")]
+        force: bool,
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+        paths: Vec<String>,
+    },
+    Mv {
+        #[arg(short = 'f', long = "force")]
+        force: bool,
+        #[arg(short = 'k')]
+        skip_errors: bool,
+        #[arg(short = 'n', long = "dry-run")]
+        dry_run: bool,
+        #[arg(short = 'v', long = "verbose")]
+        verbose: bool,
+        #[arg(required = true)]
+        paths: Vec<String>,
+    },
+    #[command(args_conflicts_with_subcommands = true)]
+    Stash {
+        #[command(subcommand)]
+        action: Option<StashAction>,
+        #[command(flatten)]
+        push: StashPush,
+    },
+    Log {
+        #[arg(long = "oneline")]
+        oneline: bool,
+        #[arg(short = 'n', long = "max-count", value_name = "NUMBER")]
+        max_count: Option<usize>,
+        revisions: Vec<String>,
+        #[arg(last = true)]
+        paths: Vec<String>,
+    },
+    Clone {
+        #[arg(short = 's', long = "shared")]
+        shared: bool,
+        #[arg(long = "reference", value_name = "REPOSITORY")]
+        reference: Option<PathBuf>,
+        repository: PathBuf,
+        directory: Option<PathBuf>,
+    },
+    Fetch {
+        #[arg(short = 'f', long = "force")]
+        force: bool,
+        remote: Option<String>,
+        refspecs: Vec<String>,
+    },
+    Push {
+        #[arg(short = 'f', long = "force")]
+        force: bool,
+        #[arg(
+            long = "force-with-lease",
+            value_name = "REF[:EXPECT]",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = ""
+        )]
+        force_with_lease: Option<String>,
+        #[arg(long = "no-verify")]
+        no_verify: bool,
+        remote: Option<String>,
+        refspecs: Vec<String>,
+    },
+    Gc {
+        #[arg(long = "auto")]
+        auto: bool,
+        #[arg(long = "prune", value_name = "DATE")]
+        prune: Option<String>,
+    },
+    CommitGraph {
+        #[command(subcommand)]
+        action: CommitGraphAction,
     },
     // implement the git config user.name and user.email
 }
+#[derive(Subcommand, Debug)]
+enum CommitGraphAction {
+    Write {
+        #[arg(long = "reachable")]
+        reachable: bool,
+        #[arg(long = "changed-paths")]
+        changed_paths: bool,
+    },
+    Verify,
+}
+// "stash" alone (or with the options of push) is "stash push"
+#[derive(clap::Args, Debug)]
+struct StashPush {
+    #[arg(short = 'm', long = "message")]
+    message: Option<String>,
+    #[arg(short = 'u', long = "include-untracked")]
+    include_untracked: bool,
+    #[arg(short = 'q', long = "quiet")]
+    quiet: bool,
+    paths: Vec<String>,
+}
+#[derive(Subcommand, Debug)]
+enum StashAction {
+    Push(StashPush),
+    List,
+    Show {
+        #[arg(short = 'p', long = "patch")]
+        patch: bool,
+        #[arg(long = "stat")]
+        stat: bool,
+        stash: Option<String>,
+    },
+    Apply {
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+        stash: Option<String>,
+    },
+    Pop {
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+        stash: Option<String>,
+    },
+    Drop {
+        #[arg(short = 'q', long = "quiet")]
+        quiet: bool,
+        stash: Option<String>,
+    },
+    Branch {
+        name: String,
+        stash: Option<String>,
+    },
+    Clear,
+}
 fn main() -> anyhow::Result<()> {
     let args = Args::parse();
     match args.command {
-        Commands::Init => {
-            fs::create_dir(".git").unwrap();
-            fs::create_dir(".git/objects").unwrap();
-            fs::create_dir(".git/refs").unwrap();
-            fs::write(".git/HEAD", "ref: refs/heads/main\n").unwrap();
-            println!("Initialized git directory")
-        }
+        Commands::Init { object_format } => commands::init::invoke(object_format)?,
         Commands::CatFile {
             pretty_print,
+            show_type,
+            show_size,
             object_hash,
-        } => commands::cat_file::invoke(pretty_print, object_hash)?,
-        Commands::HashObject { write, file_path } => {
-            commands::hash_object::invoke(write, &file_path)?;
+        } => commands::cat_file::invoke(pretty_print, show_type, show_size, object_hash)?,
+        Commands::HashObject {
+            kind,
+            write,
+            stdin,
+            stdin_paths,
+            literally,
+            no_filters,
+            path,
+            file_paths,
+        } => {
+            let options = commands::hash_object::Options {
+                kind,
+                write,
+                stdin,
+                stdin_paths,
+                literally,
+                no_filters,
+                path,
+            };
+            commands::hash_object::invoke(options, &file_paths)?;
         }
         Commands::LsTree {
+            recursive,
+            show_trees,
+            trees_only,
+            long,
+            nul_terminated,
             name_only,
-            tree_object,
-        } => commands::ls_tree::invoke(name_only, tree_object)?,
+            object_only,
+            abbrev,
+            format,
+            tree_ish,
+            paths,
+        } => {
+            let options = commands::ls_tree::Options {
+                recursive,
+                show_trees,
+                trees_only,
+                long,
+                nul_terminated,
+                name_only,
+                object_only,
+                abbrev,
+                format,
+            };
+            commands::ls_tree::invoke(options, &tree_ish, &paths)?;
+        }
         Commands::WriteTree => {
             commands::write_tree::invoke(&PathBuf::from("."))?;
         }
+        Commands::Mktree {
+            nul_terminated,
+            missing,
+            batch,
+        } => commands::mktree::invoke(nul_terminated, missing, batch)?,
         Commands::CommitTree {
             tree_sha,
             parent_commit_sha,
             commit_message,
+            message_file,
         } => {
-            commands::commit_tree::invoke(tree_sha, parent_commit_sha, commit_message)?;
+            commands::commit_tree::invoke(
+                &tree_sha,
+                &parent_commit_sha,
+                &commit_message,
+                &message_file,
+            )?;
         }
         Commands::LsFiles { stage, cached } => commands::ls_file::invoke(stage, cached)?,
-        Commands::Commit { message } => commands::commit::invoke(&message)?,
+        Commands::Fsck {
+            unreachable,
+            lost_found,
+            connectivity_only,
+        } => commands::fsck::invoke(unreachable, lost_found, connectivity_only)?,
+        Commands::CountObjects {
+            verbose,
+            human_readable,
+        } => commands::count_objects::invoke(verbose, human_readable)?,
+        Commands::Prune {
+            dry_run,
+            verbose,
+            expire,
+        } => commands::prune::invoke(expire, dry_run, verbose)?,
+        Commands::Commit {
+            message,
+            file,
+            template,
+            edit,
+            no_edit,
+            amend,
+            all,
+            cleanup,
+            allow_empty,
+            author,
+            date,
+            signoff,
+            no_verify,
+            quiet,
+        } => {
+            let options = commands::commit::Options {
+                messages: message,
+                file,
+                template,
+                edit,
+                no_edit,
+                amend,
+                all,
+                cleanup,
+                allow_empty,
+                author,
+                date,
+                signoff,
+                no_verify,
+                quiet,
+                reflog_action: None,
+            };
+            commands::commit::invoke(options)?;
+        }
+        Commands::Merge {
+            ff_only,
+            no_ff,
+            no_commit,
+            message,
+            edit,
+            no_edit,
+            no_verify,
+            allow_unrelated_histories,
+            abort,
+            continue_merge,
+            commit,
+        } => {
+            let options = commands::merge::Options {
+                ff_only,
+                no_ff,
+                no_commit,
+                messages: message,
+                edit,
+                no_edit,
+                no_verify,
+                allow_unrelated_histories,
+                abort,
+                continue_merge,
+            };
+            commands::merge::invoke(options, commit.as_deref())?;
+        }
+        Commands::MergeBase {
+            all,
+            octopus,
+            is_ancestor,
+            fork_point,
+            commits,
+        } => {
+            let options = commands::merge_base::Options {
+                all,
+                octopus,
+                is_ancestor,
+                fork_point,
+            };
+            commands::merge_base::invoke(options, &commits)?;
+        }
+        Commands::CherryPick {
+            no_commit,
+            record_origin,
+            mainline,
+            edit,
+            continue_pick,
+            skip,
+            abort,
+            commits,
+        } => {
+            let options = sequencer::Options {
+                no_commit,
+                record_origin,
+                mainline,
+                edit,
+            };
+            let sequence = sequence(continue_pick, skip, abort);
+            commands::cherry_pick::invoke(options, sequence, &commits)?;
+        }
+        Commands::Revert {
+            no_commit,
+            mainline,
+            edit,
+            no_edit,
+            continue_revert,
+            skip,
+            abort,
+            commits,
+        } => {
+            let options = sequencer::Options {
+                no_commit,
+                record_origin: false,
+                mainline,
+                edit,
+            };
+            let sequence = sequence(continue_revert, skip, abort);
+            commands::revert::invoke(options, no_edit, sequence, &commits)?;
+        }
+        Commands::Rebase {
+            onto,
+            interactive,
+            autosquash,
+            exec,
+            reapply_cherry_picks,
+            continue_rebase,
+            skip,
+            abort,
+            upstream,
+            branch,
+        } => {
+            let options = commands::rebase::Options {
+                onto,
+                interactive,
+                autosquash,
+                exec,
+                reapply_cherry_picks,
+                continue_rebase,
+                skip,
+                abort,
+            };
+            commands::rebase::invoke(options, upstream.as_deref(), branch.as_deref())?;
+        }
         Commands::UpdateIndex { add, file_path } => commands::update_index::invoke(add, file_path)?,
+        Commands::Reset {
+            soft,
+            mixed,
+            hard,
+            keep,
+            merge,
+            quiet,
+            args,
+            paths,
+        } => {
+            let mode = reset_mode(soft, mixed, hard, keep, merge);
+            commands::reset::invoke(mode, quiet, &args, &paths)?;
+        }
+        Commands::Restore {
+            staged,
+            worktree,
+            source,
+            paths,
+        } => {
+            let options = commands::restore::Options {
+                staged,
+                worktree,
+                source,
+            };
+            commands::restore::invoke(options, &paths)?;
+        }
+        Commands::Rm {
+            cached,
+            recursive,
+            force,
+            dry_run,
+            quiet,
+            paths,
+        } => {
+            let options = commands::rm::Options {
+                cached,
+                recursive,
+                force,
+                dry_run,
+                quiet,
+            };
+            commands::rm::invoke(options, &paths)?;
+        }
+        Commands::Mv {
+            force,
+            skip_errors,
+            dry_run,
+            verbose,
+            paths,
+        } => {
+            let options = commands::mv::Options {
+                force,
+                skip_errors,
+                dry_run,
+                verbose,
+            };
+            commands::mv::invoke(options, &paths)?;
+        }
+        Commands::Stash { action, push } => match action.unwrap_or(StashAction::Push(push)) {
+            StashAction::Push(StashPush {
+                message,
+                include_untracked,
+                quiet,
+                paths,
+            }) => {
+                let options = commands::stash::PushOptions {
+                    message,
+                    include_untracked,
+                    quiet,
+                };
+                commands::stash::push(options, &paths)?;
+            }
+            StashAction::List => commands::stash::list()?,
+            StashAction::Show { patch, stat, stash } => {
+                commands::stash::show(patch, stat, stash.as_deref())?
+            }
+            StashAction::Apply { quiet, stash } => commands::stash::apply(stash.as_deref(), quiet)?,
+            StashAction::Pop { quiet, stash } => commands::stash::pop(stash.as_deref(), quiet)?,
+            StashAction::Drop { quiet, stash } => commands::stash::drop(stash.as_deref(), quiet)?,
+            StashAction::Branch { name, stash } => {
+                commands::stash::branch(&name, stash.as_deref())?
+            }
+            StashAction::Clear => commands::stash::clear()?,
+        },
+        Commands::Log {
+            oneline,
+            max_count,
+            revisions,
+            paths,
+        } => {
+            let options = commands::log::Options { oneline, max_count };
+            commands::log::invoke(options, &revisions, &paths)?;
+        }
+        Commands::Clone {
+            shared,
+            reference,
+            repository,
+            directory,
+        } => commands::clone::invoke(shared, reference, &repository, directory)?,
+        Commands::Fetch {
+            force,
+            remote,
+            refspecs,
+        } => commands::fetch::invoke(remote.as_deref(), &refspecs, force)?,
+        Commands::Push {
+            force,
+            force_with_lease,
+            no_verify,
+            remote,
+            refspecs,
+        } => commands::push::invoke(
+            remote.as_deref(),
+            &refspecs,
+            force,
+            force_with_lease.as_deref(),
+            no_verify,
+        )?,
+        Commands::Gc { auto, prune } => commands::gc::invoke(auto, prune)?,
+        Commands::CommitGraph { action } => match action {
+            CommitGraphAction::Write {
+                reachable,
+                changed_paths,
+            } => commands::commit_graph::write(reachable, changed_paths)?,
+            CommitGraphAction::Verify => commands::commit_graph::verify()?,
+        },
     }
     Ok(())
 }
 //
 // [u8; 20] is the real SHA-1 value
 // bS/sFN*% is just a human-readable representation of those bytes
+
+// --continue / --skip / --abort of cherry-pick and revert
+fn sequence(
+    continue_sequence: bool,
+    skip: bool,
+    abort: bool,
+) -> Option<commands::cherry_pick::Sequence> {
+    use commands::cherry_pick::Sequence;
+    match (continue_sequence, skip, abort) {
+        (true, _, _) => Some(Sequence::Continue),
+        (_, true, _) => Some(Sequence::Skip),
+        (_, _, true) => Some(Sequence::Abort),
+        _ => None,
+    }
+}
+
+// --soft / --mixed / --hard / --keep / --merge of reset, None is the default (--mixed)
+fn reset_mode(
+    soft: bool,
+    mixed: bool,
+    hard: bool,
+    keep: bool,
+    merge: bool,
+) -> Option<commands::reset::Mode> {
+    use commands::reset::Mode;
+    [
+        (soft, Mode::Soft),
+        (mixed, Mode::Mixed),
+        (hard, Mode::Hard),
+        (keep, Mode::Keep),
+        (merge, Mode::Merge),
+    ]
+    .into_iter()
+    .find_map(|(set, mode)| set.then_some(mode))
+}
@@ -0,0 +1,945 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{BufRead, Read};
+use std::str::FromStr;
+
+use anyhow::Context;
+
+use crate::ancestry::Ancestry;
+use crate::commit::{Commit, Signature};
+use crate::diff;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::index::{Index, IndexEntry};
+use crate::objects::{Kind, Object};
+use crate::odb::{Abbrev, MemoryDb, ObjectDatabase};
+use crate::tree::{self, Files, Tree};
+
+// NOTE: three-way merge, the same idea as git "ort"
+// every path is looked at in base, ours and theirs:
+// - changed on one side only -> that side wins
+// - changed the same way on both sides -> fine
+// - changed on both sides -> the lines are merged (merge_text), what can't be merged is a
+//   conflict with the markers in the worktree and the stages 1 (base) 2 (ours) 3 (theirs)
+//   in the index, same for modified on one side and deleted on the other
+// renames are found on each side (same content, or at least 50% of the lines) so a change
+// on the other side follows the file to its new name
+// when the two commits have more than one best common ancestor (criss-cross merge) the
+// ancestors are merged first into a virtual one, that merge never stop on a conflict
+// (the markers are just committed in it) and it only live in memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub(crate) enum ConflictStyle {
+    // <<<<<<< ours ======= theirs >>>>>>>, the lines both sides agree on are left out
+    #[default]
+    Merge,
+    // the base in between ||||||| and =======, the whole changed region
+    Diff3,
+    // diff3, but the lines at the start and the end both sides agree on are left out
+    Zdiff3,
+}
+
+impl FromStr for ConflictStyle {
+    type Err = anyhow::Error;
+    fn from_str(style: &str) -> anyhow::Result<Self> {
+        match style {
+            "merge" => Ok(ConflictStyle::Merge),
+            "diff3" => Ok(ConflictStyle::Diff3),
+            "zdiff3" => Ok(ConflictStyle::Zdiff3),
+            _ => anyhow::bail!("unknown style '{style}' given for 'merge.conflictstyle'"),
+        }
+    }
+}
+
+// what the markers say: <<<<<<< HEAD, ||||||| 1a2b3c4, >>>>>>> feature
+#[derive(Debug, Clone)]
+pub(crate) struct Labels {
+    pub(crate) ours: String,
+    pub(crate) base: String,
+    pub(crate) theirs: String,
+}
+
+const MARKER_SIZE: usize = 7;
+
+// NOTE: merge of the lines, the number of conflicts comes with the result
+// the changes of both sides are put on the base, the changes that touch (or overlap)
+// each other are one region, that region is a conflict unless both sides did the same
+pub(crate) fn merge_text(
+    base: &[u8],
+    ours: &[u8],
+    theirs: &[u8],
+    labels: &Labels,
+    style: ConflictStyle,
+) -> (Vec<u8>, usize) {
+    let base = diff::lines(base);
+    let ours = diff::lines(ours);
+    let theirs = diff::lines(theirs);
+    let ours_changes = diff::diff(&base, &ours);
+    let theirs_changes = diff::diff(&base, &theirs);
+
+    let mut out = Output {
+        text: Vec::new(),
+        conflicts: 0,
+        labels,
+        style,
+    };
+    // where the next base line is in ours and theirs
+    let (mut ours_delta, mut theirs_delta) = (0isize, 0isize);
+    let (mut i, mut j) = (0, 0);
+    let mut base_at = 0;
+    while i < ours_changes.len() || j < theirs_changes.len() {
+        let start = match (ours_changes.get(i), theirs_changes.get(j)) {
+            (Some(o), Some(t)) => o.old.start.min(t.old.start),
+            (Some(o), None) => o.old.start,
+            (None, Some(t)) => t.old.start,
+            (None, None) => break,
+        };
+        let mut end = start;
+        let (first_ours, first_theirs) = (i, j);
+        // a change touching the region (even just next to it) is part of it
+        loop {
+            if let Some(o) = ours_changes.get(i).filter(|o| o.old.start <= end) {
+                end = end.max(o.old.end);
+                i += 1;
+            } else if let Some(t) = theirs_changes.get(j).filter(|t| t.old.start <= end) {
+                end = end.max(t.old.end);
+                j += 1;
+            } else {
+                break;
+            }
+        }
+        for line in &base[base_at..start] {
+            out.text.extend(*line);
+        }
+        base_at = end;
+
+        let ours_region = side_region(&ours_changes[first_ours..i], start..end, ours_delta);
+        let theirs_region = side_region(&theirs_changes[first_theirs..j], start..end, theirs_delta);
+        ours_delta += delta(&ours_changes[first_ours..i]);
+        theirs_delta += delta(&theirs_changes[first_theirs..j]);
+        let ours_lines = &ours[ours_region];
+        let theirs_lines = &theirs[theirs_region];
+        if first_theirs == j || ours_lines == theirs_lines {
+            out.lines(ours_lines);
+        } else if first_ours == i {
+            out.lines(theirs_lines);
+        } else {
+            out.conflict(ours_lines, &base[start..end], theirs_lines);
+        }
+    }
+    for line in &base[base_at..] {
+        out.text.extend(*line);
+    }
+    (out.text, out.conflicts)
+}
+
+// the lines of one side for the base region, its changes inside the region are applied
+fn side_region(
+    changes: &[diff::Change],
+    base: std::ops::Range<usize>,
+    delta_before: isize,
+) -> std::ops::Range<usize> {
+    let start = (base.start as isize + delta_before) as usize;
+    let end = (base.end as isize + delta_before + delta(changes)) as usize;
+    start..end
+}
+
+fn delta(changes: &[diff::Change]) -> isize {
+    changes
+        .iter()
+        .map(|change| change.new.len() as isize - change.old.len() as isize)
+        .sum()
+}
+
+struct Output<'a> {
+    text: Vec<u8>,
+    conflicts: usize,
+    labels: &'a Labels,
+    style: ConflictStyle,
+}
+
+impl Output<'_> {
+    fn lines(&mut self, lines: &[&[u8]]) {
+        for line in lines {
+            self.text.extend(*line);
+        }
+    }
+
+    fn conflict(&mut self, ours: &[&[u8]], base: &[&[u8]], theirs: &[&[u8]]) {
+        // no sense to look for common lines when one side is empty
+        if self.style == ConflictStyle::Diff3 || ours.is_empty() || theirs.is_empty() {
+            return self.markers(ours, base, theirs);
+        }
+        let changes = diff::diff(ours, theirs);
+        let (Some(first), Some(last)) = (changes.first(), changes.last()) else {
+            return self.lines(ours);
+        };
+        if self.style == ConflictStyle::Zdiff3 {
+            self.lines(&ours[..first.old.start]);
+            self.markers(
+                &ours[first.old.start..last.old.end],
+                base,
+                &theirs[first.new.start..last.new.end],
+            );
+            return self.lines(&ours[last.old.end..]);
+        }
+        // every difference of ours and theirs is its own conflict
+        let mut at = 0;
+        for change in &changes {
+            self.lines(&ours[at..change.old.start]);
+            self.markers(&ours[change.old.clone()], &[], &theirs[change.new.clone()]);
+            at = change.old.end;
+        }
+        self.lines(&ours[at..]);
+    }
+
+    fn markers(&mut self, ours: &[&[u8]], base: &[&[u8]], theirs: &[&[u8]]) {
+        self.conflicts += 1;
+        self.marker(b'<', &self.labels.ours.clone());
+        self.side(ours);
+        if self.style != ConflictStyle::Merge {
+            self.marker(b'|', &self.labels.base.clone());
+            self.side(base);
+        }
+        self.marker(b'=', "");
+        self.side(theirs);
+        self.marker(b'>', &self.labels.theirs.clone());
+    }
+
+    fn marker(&mut self, marker: u8, label: &str) {
+        self.text.extend(std::iter::repeat_n(marker, MARKER_SIZE));
+        if !label.is_empty() {
+            self.text.push(b' ');
+            self.text.extend(label.as_bytes());
+        }
+        self.text.push(b'\n');
+    }
+
+    // the marker after a side need its own line, even when the file didn't end with one
+    fn side(&mut self, lines: &[&[u8]]) {
+        self.lines(lines);
+        if lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+            self.text.push(b'\n');
+        }
+    }
+}
+
+// NOTE: the merged index, the paths in conflict have the stages 1-3 instead of the stage 0
+// the worktree of a conflicted path get `contents` (the markers, or the side that is kept)
+#[derive(Debug, Default)]
+pub(crate) struct MergeResult {
+    pub(crate) entries: Vec<IndexEntry>,
+    pub(crate) contents: BTreeMap<String, (u32, Vec<u8>)>,
+    pub(crate) conflicts: BTreeSet<String>,
+    // "Auto-merging a.txt", "CONFLICT (content): Merge conflict in a.txt", ...
+    pub(crate) messages: Vec<String>,
+}
+
+impl MergeResult {
+    pub(crate) fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+
+    // NOTE: tree of the merge, only when there is no conflict left
+    pub(crate) fn tree(&self, db: &dyn ObjectDatabase) -> anyhow::Result<ObjectId> {
+        let index = Index {
+            format: db.format(),
+            entries: self.entries.clone(),
+        };
+        index.write_tree(db)
+    }
+}
+
+// NOTE: merge the commit theirs into ours, the base is found from the history
+pub(crate) fn merge_commits(
+    db: &dyn ObjectDatabase,
+    ours: ObjectId,
+    theirs: ObjectId,
+    ours_label: &str,
+    theirs_label: &str,
+    style: ConflictStyle,
+) -> anyhow::Result<MergeResult> {
+    let scratch = Scratch {
+        memory: MemoryDb::new(db.format()),
+        db,
+    };
+    let (base, base_label) = merge_base_tree(&scratch, ours, theirs, style)?;
+    let labels = Labels {
+        ours: ours_label.to_string(),
+        base: base_label,
+        theirs: theirs_label.to_string(),
+    };
+    let ours = Commit::read(db, &ours.to_string())?.tree;
+    let theirs = Commit::read(db, &theirs.to_string())?.tree;
+    let result = Merger::new(&scratch, db, labels, style, false).merge(base, ours, theirs)?;
+    // the base stage of a conflict can be a blob of the virtual ancestor, git need it
+    for entry in result.entries.iter().filter(|e| e.stage() == 1) {
+        let hash = entry.hash.to_string();
+        if !db.contains(&hash) {
+            let mut object = scratch.read(&hash)?;
+            db.write(Object {
+                kind: object.kind,
+                expected_size: object.expected_size,
+                reader: &mut object.reader,
+            })?;
+        }
+    }
+    Ok(result)
+}
+
+// NOTE: merge of three trees when the base is already known (cherry-pick, revert: the
+// parent of the commit is the base), no merge base is looked for
+pub(crate) fn merge_trees(
+    db: &dyn ObjectDatabase,
+    base: ObjectId,
+    ours: ObjectId,
+    theirs: ObjectId,
+    labels: Labels,
+    style: ConflictStyle,
+) -> anyhow::Result<MergeResult> {
+    Merger::new(db, db, labels, style, false).merge(base, ours, theirs)
+}
+
+// the tree to use as the base and its label
+// several best common ancestors are merged together, the oldest first like git
+fn merge_base_tree(
+    scratch: &Scratch,
+    ours: ObjectId,
+    theirs: ObjectId,
+    style: ConflictStyle,
+) -> anyhow::Result<(ObjectId, String)> {
+    let mut bases = Ancestry::new(scratch)?.merge_bases(ours, theirs)?;
+    if bases.is_empty() {
+        let empty = Tree::default().object().write_to(scratch)?;
+        return Ok((empty, "empty tree".to_string()));
+    }
+    if let [base] = bases.as_slice() {
+        let tree = Commit::read(scratch, &base.to_string())?.tree;
+        let hash = base.to_string();
+        let label = Abbrev::new(scratch, 7)?.abbrev(&hash).to_string();
+        return Ok((tree, label));
+    }
+    bases.reverse();
+    let mut current = bases[0];
+    for &next in &bases[1..] {
+        let (base, base_label) = merge_base_tree(scratch, current, next, style)?;
+        let labels = Labels {
+            ours: "Temporary merge branch 1".to_string(),
+            base: base_label,
+            theirs: "Temporary merge branch 2".to_string(),
+        };
+        let current_commit = Commit::read(scratch, &current.to_string())?;
+        let next_commit = Commit::read(scratch, &next.to_string())?;
+        let merged = Merger::new(scratch, scratch, labels, style, true).merge(
+            base,
+            current_commit.tree,
+            next_commit.tree,
+        )?;
+        // NOTE: the virtual commit only need to be walked by the next merge_bases
+        let time = current_commit
+            .committer
+            .time
+            .max(next_commit.committer.time);
+        let signature = Signature {
+            name: b"merge".to_vec(),
+            email: Vec::new(),
+            time,
+            timezone: "+0000".to_string(),
+        };
+        let virtual_commit = Commit {
+            tree: merged.tree(scratch)?,
+            parents: vec![current, next],
+            author: signature.clone(),
+            committer: signature,
+            encoding: None,
+            extra_headers: Vec::new(),
+            gpgsig: None,
+            message: b"merged common ancestors\n".to_vec(),
+        };
+        current = virtual_commit.object().write_to(scratch)?;
+    }
+    let tree = Commit::read(scratch, &current.to_string())?.tree;
+    Ok((tree, "merged common ancestors".to_string()))
+}
+
+// NOTE: the objects of the virtual ancestors go to memory, everything else is read from
+// the real database
+struct Scratch<'a> {
+    memory: MemoryDb,
+    db: &'a dyn ObjectDatabase,
+}
+
+impl ObjectDatabase for Scratch<'_> {
+    fn format(&self) -> ObjectFormat {
+        self.db.format()
+    }
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)> {
+        if self.memory.contains(hash) {
+            return self.memory.read_header(hash);
+        }
+        self.db.read_header(hash)
+    }
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>> {
+        if self.memory.contains(hash) {
+            return self.memory.read(hash);
+        }
+        self.db.read(hash)
+    }
+    fn contains(&self, hash: &str) -> bool {
+        self.memory.contains(hash) || self.db.contains(hash)
+    }
+    fn write(&self, object: Object<&mut dyn Read>) -> anyhow::Result<ObjectId> {
+        self.memory.write(object)
+    }
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        Ok(Box::new(self.db.iter()?.chain(self.memory.iter()?)))
+    }
+}
+
+type Entry = (u32, ObjectId);
+
+// one path seen from the 3 trees, with the path it has on each side (renames)
+struct Item {
+    base: Option<(String, Entry)>,
+    ours: Option<(String, Entry)>,
+    theirs: Option<(String, Entry)>,
+}
+
+struct Merger<'a> {
+    // objects are read from db and the merged blobs are written to out
+    db: &'a dyn ObjectDatabase,
+    out: &'a dyn ObjectDatabase,
+    labels: Labels,
+    style: ConflictStyle,
+    // merge of the ancestors: a conflict is resolved right away (markers or the base)
+    virtual_base: bool,
+    result: MergeResult,
+    messages: Vec<(String, String)>,
+}
+
+impl<'a> Merger<'a> {
+    fn new(
+        db: &'a dyn ObjectDatabase,
+        out: &'a dyn ObjectDatabase,
+        labels: Labels,
+        style: ConflictStyle,
+        virtual_base: bool,
+    ) -> Self {
+        Self {
+            db,
+            out,
+            labels,
+            style,
+            virtual_base,
+            result: MergeResult::default(),
+            messages: Vec::new(),
+        }
+    }
+
+    fn merge(
+        mut self,
+        base: ObjectId,
+        ours: ObjectId,
+        theirs: ObjectId,
+    ) -> anyhow::Result<MergeResult> {
+        let base = tree::files(self.db, base)?;
+        let ours = tree::files(self.db, ours)?;
+        let theirs = tree::files(self.db, theirs)?;
+        let mut ours_renames = self.renames(&base, &ours)?;
+        let mut theirs_renames = self.renames(&base, &theirs)?;
+        // a rename onto a path the other side also has (and didn't get by the same rename)
+        // is left as a delete and an add
+        let keep = |renames: &BTreeMap<String, String>,
+                    other: &Files,
+                    other_renames: &BTreeMap<String, String>| {
+            renames
+                .iter()
+                .filter(|(from, to)| {
+                    !other.contains_key(*to) || other_renames.get(*from) == Some(*to)
+                })
+                .map(|(from, to)| (from.clone(), to.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+        (ours_renames, theirs_renames) = (
+            keep(&ours_renames, &theirs, &theirs_renames),
+            keep(&theirs_renames, &ours, &ours_renames),
+        );
+
+        let mut items = Vec::new();
+        let mut seen_ours = HashSet::new();
+        let mut seen_theirs = HashSet::new();
+        for (path, &entry) in &base {
+            let side = |files: &Files, renames: &BTreeMap<String, String>| {
+                let path = renames.get(path).unwrap_or(path);
+                files.get(path).map(|&entry| (path.clone(), entry))
+            };
+            let ours_side = side(&ours, &ours_renames);
+            let theirs_side = side(&theirs, &theirs_renames);
+            seen_ours.extend(ours_side.as_ref().map(|(path, _)| path.clone()));
+            seen_theirs.extend(theirs_side.as_ref().map(|(path, _)| path.clone()));
+            items.push(Item {
+                base: Some((path.clone(), entry)),
+                ours: ours_side,
+                theirs: theirs_side,
+            });
+        }
+        let mut added: BTreeSet<&String> =
+            ours.keys().filter(|p| !seen_ours.contains(*p)).collect();
+        added.extend(theirs.keys().filter(|p| !seen_theirs.contains(*p)));
+        for path in added {
+            items.push(Item {
+                base: None,
+                ours: ours
+                    .get(path)
+                    .filter(|_| !seen_ours.contains(path))
+                    .map(|&entry| (path.clone(), entry)),
+                theirs: theirs
+                    .get(path)
+                    .filter(|_| !seen_theirs.contains(path))
+                    .map(|&entry| (path.clone(), entry)),
+            });
+        }
+        for item in items {
+            self.merge_item(item)?;
+        }
+        self.directory_conflicts(&ours)?;
+
+        self.result
+            .entries
+            .sort_by(|a, b| (a.path.as_bytes(), a.stage()).cmp(&(b.path.as_bytes(), b.stage())));
+        // messages come in the order of the paths, whatever order they were found
+        self.messages.sort_by(|a, b| a.0.cmp(&b.0));
+        self.result.messages = self.messages.into_iter().map(|(_, m)| m).collect();
+        Ok(self.result)
+    }
+
+    // where the item ends up, the renames of the two sides have to agree
+    fn merge_item(&mut self, item: Item) -> anyhow::Result<()> {
+        let Item { base, ours, theirs } = item;
+        let base_path = base.as_ref().map(|(path, _)| path.clone());
+        let (ours_path, theirs_path) = (
+            ours.as_ref().map(|(path, _)| path.clone()),
+            theirs.as_ref().map(|(path, _)| path.clone()),
+        );
+        let base = base.map(|(_, entry)| entry);
+        let (ours, theirs) = (ours.map(|(_, e)| e), theirs.map(|(_, e)| e));
+        let path = match (&base_path, &ours_path, &theirs_path) {
+            (_, Some(o), Some(t)) if o == t => o.clone(),
+            (Some(b), Some(o), Some(t)) if o == b => t.clone(),
+            (Some(b), Some(o), Some(t)) if t == b => o.clone(),
+            (Some(b), Some(o), Some(t)) => {
+                let message = format!(
+                    "CONFLICT (rename/rename): {b} renamed to {o} in {} and to {t} in {}.",
+                    self.labels.ours, self.labels.theirs
+                );
+                let (ours, theirs) = (ours.unwrap(), theirs.unwrap());
+                if self.virtual_base {
+                    self.stage(o, 0, ours);
+                    return Ok(());
+                }
+                self.conflict(o, base, Some(ours), None, ours, message.clone())?;
+                self.conflict(t, base, None, Some(theirs), theirs, message)?;
+                return Ok(());
+            }
+            (Some(b), Some(o), None) if o != b => {
+                let message = format!(
+                    "CONFLICT (rename/delete): {b} renamed to {o} in {}, but deleted in {}.",
+                    self.labels.ours, self.labels.theirs
+                );
+                return self.rename_delete(o, base, ours.unwrap(), 2, message);
+            }
+            (Some(b), None, Some(t)) if t != b => {
+                let message = format!(
+                    "CONFLICT (rename/delete): {b} renamed to {t} in {}, but deleted in {}.",
+                    self.labels.theirs, self.labels.ours
+                );
+                return self.rename_delete(t, base, theirs.unwrap(), 3, message);
+            }
+            (Some(b), _, _) => b.clone(),
+            (None, Some(o), _) => o.clone(),
+            (None, None, Some(t)) => t.clone(),
+            (None, None, None) => return Ok(()),
+        };
+        self.merge_path(&path, base, ours, theirs)
+    }
+
+    fn rename_delete(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        entry: Entry,
+        stage: u16,
+        message: String,
+    ) -> anyhow::Result<()> {
+        if self.virtual_base {
+            self.stage(path, 0, entry);
+            return Ok(());
+        }
+        let (ours, theirs) = match stage {
+            2 => (Some(entry), None),
+            _ => (None, Some(entry)),
+        };
+        self.conflict(path, base, ours, theirs, entry, message)
+    }
+
+    fn merge_path(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        ours: Option<Entry>,
+        theirs: Option<Entry>,
+    ) -> anyhow::Result<()> {
+        let taken = if ours == theirs || base == theirs {
+            Some(ours)
+        } else if base == ours {
+            Some(theirs)
+        } else {
+            None
+        };
+        if let Some(taken) = taken {
+            if let Some(entry) = taken {
+                self.stage(path, 0, entry);
+            }
+            return Ok(());
+        }
+        let (ours_entry, theirs_entry) = match (ours, theirs) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            (Some(ours), None) => {
+                let message = format!(
+                    "CONFLICT (modify/delete): {path} deleted in {} and modified in {}.  \
+                     Version {} of {path} left in tree.",
+                    self.labels.theirs, self.labels.ours, self.labels.ours
+                );
+                return self.modify_delete(path, base, ours, 2, message);
+            }
+            (None, Some(theirs)) => {
+                let message = format!(
+                    "CONFLICT (modify/delete): {path} deleted in {} and modified in {}.  \
+                     Version {} of {path} left in tree.",
+                    self.labels.ours, self.labels.theirs, self.labels.theirs
+                );
+                return self.modify_delete(path, base, theirs, 3, message);
+            }
+            (None, None) => return Ok(()),
+        };
+        self.merge_contents(path, base, ours_entry, theirs_entry)
+    }
+
+    fn modify_delete(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        entry: Entry,
+        stage: u16,
+        message: String,
+    ) -> anyhow::Result<()> {
+        if self.virtual_base {
+            if let Some(base) = base {
+                self.stage(path, 0, base);
+            }
+            return Ok(());
+        }
+        let (ours, theirs) = match stage {
+            2 => (Some(entry), None),
+            _ => (None, Some(entry)),
+        };
+        self.conflict(path, base, ours, theirs, entry, message)
+    }
+
+    // both sides changed the file
+    fn merge_contents(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        ours: Entry,
+        theirs: Entry,
+    ) -> anyhow::Result<()> {
+        let kind = |mode: u32| mode & 0o170000;
+        if kind(ours.0) != kind(theirs.0) {
+            let message =
+                format!("CONFLICT (distinct types): {path} had different types on each side.");
+            return self.unmergeable(path, base, ours, theirs, message);
+        }
+        let base_mode = base.map(|(mode, _)| mode);
+        let (mode, mode_clean) = if base_mode == Some(ours.0) || ours.0 == theirs.0 {
+            (theirs.0, true)
+        } else if base_mode == Some(theirs.0) {
+            (ours.0, true)
+        } else {
+            (ours.0, false)
+        };
+        let base_hash = base.map(|(_, hash)| hash);
+        let hash = if ours.1 == theirs.1 || base_hash == Some(theirs.1) {
+            ours.1
+        } else if base_hash == Some(ours.1) {
+            theirs.1
+        } else if kind(ours.0) == 0o100000 {
+            self.messages
+                .push((path.to_string(), format!("Auto-merging {path}")));
+            let base_content = match base_hash {
+                Some(hash) => self.read(hash)?,
+                None => Vec::new(),
+            };
+            let (ours_content, theirs_content) = (self.read(ours.1)?, self.read(theirs.1)?);
+            let binary = [&base_content, &ours_content, &theirs_content]
+                .iter()
+                .any(|content| diff::is_binary(content));
+            let kind = if base.is_some() { "content" } else { "add/add" };
+            let message = format!("CONFLICT ({kind}): Merge conflict in {path}");
+            if binary {
+                self.messages.push((
+                    path.to_string(),
+                    format!(
+                        "warning: Cannot merge binary files: {path} ({} vs. {})",
+                        self.labels.ours, self.labels.theirs
+                    ),
+                ));
+                return self.unmergeable(path, base, ours, theirs, message);
+            }
+            let (merged, conflicts) = merge_text(
+                &base_content,
+                &ours_content,
+                &theirs_content,
+                &self.labels,
+                self.style,
+            );
+            if conflicts > 0 && !self.virtual_base {
+                self.messages.push((path.to_string(), message));
+                self.record_conflict(path, base, Some(ours), Some(theirs), (mode, merged));
+                return Ok(());
+            }
+            Object::blob(merged).write_to(self.out)?
+        } else {
+            // symlinks and submodules have nothing to merge line by line
+            let kind = if kind(ours.0) == 0o160000 {
+                "submodule"
+            } else {
+                "content"
+            };
+            let message = format!("CONFLICT ({kind}): Merge conflict in {path}");
+            return self.unmergeable(path, base, ours, theirs, message);
+        };
+        if !mode_clean && !self.virtual_base {
+            let message = format!("CONFLICT (content): Merge conflict in {path}");
+            let content = self.read(hash)?;
+            self.messages.push((path.to_string(), message));
+            self.record_conflict(path, base, Some(ours), Some(theirs), (mode, content));
+            return Ok(());
+        }
+        self.stage(path, 0, (mode, hash));
+        Ok(())
+    }
+
+    // conflict where ours stays in the worktree (the base for the virtual ancestor)
+    fn unmergeable(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        ours: Entry,
+        theirs: Entry,
+        message: String,
+    ) -> anyhow::Result<()> {
+        if self.virtual_base {
+            self.stage(path, 0, base.unwrap_or(ours));
+            return Ok(());
+        }
+        self.conflict(path, base, Some(ours), Some(theirs), ours, message)
+    }
+
+    // conflict with the content of one of the entries in the worktree
+    fn conflict(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        ours: Option<Entry>,
+        theirs: Option<Entry>,
+        kept: Entry,
+        message: String,
+    ) -> anyhow::Result<()> {
+        let content = self.read(kept.1)?;
+        self.messages.push((path.to_string(), message));
+        self.record_conflict(path, base, ours, theirs, (kept.0, content));
+        Ok(())
+    }
+
+    fn record_conflict(
+        &mut self,
+        path: &str,
+        base: Option<Entry>,
+        ours: Option<Entry>,
+        theirs: Option<Entry>,
+        content: (u32, Vec<u8>),
+    ) {
+        for (stage, entry) in [(1, base), (2, ours), (3, theirs)] {
+            if let Some(entry) = entry {
+                self.stage(path, stage, entry);
+            }
+        }
+        self.result.contents.insert(path.to_string(), content);
+        self.result.conflicts.insert(path.to_string());
+    }
+
+    fn stage(&mut self, path: &str, stage: u16, (mode, hash): Entry) {
+        self.result
+            .entries
+            .push(IndexEntry::new(path, mode, hash, stage));
+    }
+
+    // NOTE: a file where the other side has a directory is moved to "<path>~<side>"
+    fn directory_conflicts(&mut self, ours: &Files) -> anyhow::Result<()> {
+        let mut dirs = HashSet::new();
+        for entry in &self.result.entries {
+            let mut path = entry.path.as_str();
+            while let Some((dir, _)) = path.rsplit_once('/') {
+                if !dirs.insert(dir.to_string()) {
+                    break;
+                }
+                path = dir;
+            }
+        }
+        let in_the_way: BTreeSet<String> = self
+            .result
+            .entries
+            .iter()
+            .filter(|e| dirs.contains(&e.path))
+            .map(|e| e.path.clone())
+            .collect();
+        for path in in_the_way {
+            let moved: Vec<IndexEntry> = self
+                .result
+                .entries
+                .iter()
+                .filter(|e| e.path == path)
+                .cloned()
+                .collect();
+            self.result.entries.retain(|e| e.path != path);
+            self.result.conflicts.remove(&path);
+            let content = self.result.contents.remove(&path);
+            for entry in moved {
+                let from_ours = ours.get(&path) == Some(&(entry.mode, entry.hash));
+                let label = if from_ours {
+                    &self.labels.ours
+                } else {
+                    &self.labels.theirs
+                };
+                let new_path = format!("{path}~{}", label.replace('/', "_"));
+                if self.virtual_base {
+                    self.stage(&new_path, entry.stage(), (entry.mode, entry.hash));
+                    continue;
+                }
+                let message = format!(
+                    "CONFLICT (file/directory): directory in the way of {path} from {label}; \
+                     moving it to {new_path} instead."
+                );
+                let stage = if entry.stage() != 0 {
+                    entry.stage()
+                } else if from_ours {
+                    2
+                } else {
+                    3
+                };
+                self.messages.push((path.clone(), message));
+                self.stage(&new_path, stage, (entry.mode, entry.hash));
+                let content = match &content {
+                    Some(content) => content.clone(),
+                    None => (entry.mode, self.read(entry.hash)?),
+                };
+                self.result.contents.insert(new_path.clone(), content);
+                self.result.conflicts.insert(new_path);
+            }
+        }
+        Ok(())
+    }
+
+    // NOTE: deleted path -> added path of the same side, exact same blob first and then
+    // the most similar (at least 50% of the bytes in common lines)
+    fn renames(&self, base: &Files, side: &Files) -> anyhow::Result<BTreeMap<String, String>> {
+        // only files and symlinks, a submodule is never renamed
+        let is_file = |mode: u32| mode & 0o170000 != 0o160000;
+        let deleted: Vec<&String> = base
+            .iter()
+            .filter(|(path, (mode, _))| !side.contains_key(*path) && is_file(*mode))
+            .map(|(path, _)| path)
+            .collect();
+        let mut added: Vec<&String> = side
+            .iter()
+            .filter(|(path, (mode, _))| !base.contains_key(*path) && is_file(*mode))
+            .map(|(path, _)| path)
+            .collect();
+        let mut renames = BTreeMap::new();
+        if deleted.is_empty() || added.is_empty() {
+            return Ok(renames);
+        }
+        let mut left = Vec::new();
+        for from in deleted {
+            let hash = base[from].1;
+            match added.iter().position(|to| side[*to].1 == hash) {
+                Some(i) => {
+                    renames.insert(from.clone(), added.remove(i).clone());
+                }
+                None => left.push(from),
+            }
+        }
+        // same limit idea as git diff.renameLimit, comparing everything with everything
+        // is too slow for big changes
+        if left.is_empty() || added.is_empty() || left.len() * added.len() > 1000 * 1000 {
+            return Ok(renames);
+        }
+        let mut contents: HashMap<ObjectId, Vec<u8>> = HashMap::new();
+        for path in left
+            .iter()
+            .map(|p| base[*p].1)
+            .chain(added.iter().map(|p| side[*p].1))
+        {
+            if let std::collections::hash_map::Entry::Vacant(entry) = contents.entry(path) {
+                entry.insert(self.read(path)?);
+            }
+        }
+        let mut scores = Vec::new();
+        for from in &left {
+            for to in &added {
+                let score = similarity(&contents[&base[*from].1], &contents[&side[*to].1]);
+                if score >= 50 {
+                    scores.push((std::cmp::Reverse(score), (*from).clone(), (*to).clone()));
+                }
+            }
+        }
+        scores.sort();
+        let mut taken = HashSet::new();
+        for (_, from, to) in scores {
+            if renames.contains_key(&from) || taken.contains(&to) {
+                continue;
+            }
+            taken.insert(to.clone());
+            renames.insert(from, to);
+        }
+        Ok(renames)
+    }
+
+    fn read(&self, hash: ObjectId) -> anyhow::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        self.db
+            .read(&hash.to_string())?
+            .reader
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read the blob {hash}"))?;
+        Ok(content)
+    }
+}
+
+// percent of the bytes of the bigger file that are in lines both files have
+// (empty files are never similar, they would all be renames of each other)
+fn similarity(a: &[u8], b: &[u8]) -> usize {
+    if a.is_empty() || b.is_empty() {
+        return 0;
+    }
+    let mut lines: HashMap<&[u8], usize> = HashMap::new();
+    for line in diff::lines(a) {
+        *lines.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in diff::lines(b) {
+        if let Some(count) = lines.get_mut(line).filter(|count| **count > 0) {
+            *count -= 1;
+            common += line.len();
+        }
+    }
+    common * 100 / a.len().max(b.len())
+}
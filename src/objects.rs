@@ -1,20 +1,20 @@
 use anyhow::Context;
 use core::fmt;
 use flate2::Compression;
-use flate2::{read::ZlibDecoder, write::ZlibEncoder};
-use sha1::{Digest, Sha1};
+use flate2::write::ZlibEncoder;
 use std::io::prelude::*;
+use std::os::unix::ffi::OsStringExt;
 use std::path::Path;
-use std::{
-    ffi::CStr,
-    fs,
-    io::{BufRead, BufReader, Read},
-};
-#[derive(Debug, PartialEq, Eq)]
+use std::{io::Read, str::FromStr};
+
+use crate::hash::{Hasher, ObjectFormat, ObjectId};
+use crate::odb::{self, ObjectDatabase};
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub(crate) enum Kind {
     Blob,
     Tree,
     Commit,
+    Tag,
 }
 impl fmt::Display for Kind {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -22,6 +22,19 @@ impl fmt::Display for Kind {
             Kind::Blob => write!(f, "blob"),
             Kind::Tree => write!(f, "tree"),
             Kind::Commit => write!(f, "commit"),
+            Kind::Tag => write!(f, "tag"),
+        }
+    }
+}
+impl FromStr for Kind {
+    type Err = anyhow::Error;
+    fn from_str(kind: &str) -> anyhow::Result<Self> {
+        match kind {
+            "blob" => Ok(Kind::Blob),
+            "tree" => Ok(Kind::Tree),
+            "commit" => Ok(Kind::Commit),
+            "tag" => Ok(Kind::Tag),
+            _ => anyhow::bail!("invalid object type \"{kind}\""),
         }
     }
 }
@@ -33,49 +46,36 @@ pub(crate) struct Object<R> {
 }
 
 impl Object<()> {
+    pub(crate) fn blob(content: Vec<u8>) -> Object<impl Read> {
+        Object {
+            kind: Kind::Blob,
+            expected_size: content.len() as u64,
+            reader: std::io::Cursor::new(content),
+        }
+    }
+    // NOTE: symlink is never followed, its blob is the link target ("../lib/a.so")
+    // and the tree/index give it the mode 120000
     pub(crate) fn blob_from_file(file: impl AsRef<Path>) -> anyhow::Result<Object<impl Read>> {
         let file = file.as_ref();
-        let stat = std::fs::metadata(file)
+        let stat = std::fs::symlink_metadata(file)
             .with_context(|| format!("Failed to read stat for :{}", file.display()))?;
+        if stat.file_type().is_symlink() {
+            let target = std::fs::read_link(file)
+                .with_context(|| format!("failed to read the symlink:{}", file.display()))?
+                .into_os_string()
+                .into_vec();
+            return Ok(Object {
+                kind: Kind::Blob,
+                expected_size: target.len() as u64,
+                reader: Box::new(std::io::Cursor::new(target)) as Box<dyn Read>,
+            });
+        }
         let file = std::fs::File::open(file)
             .with_context(|| format!("failed to open the file:{}", file.display()))?;
         Ok(Object {
             kind: Kind::Blob,
             expected_size: stat.len(),
-            reader: file,
-        })
-    }
-    pub(crate) fn read(hash: &str) -> anyhow::Result<Object<impl BufRead>> {
-        let f = fs::File::open(format!(".git/objects/{}/{}", &hash[..2], &hash[2..],))
-            .context("File does't exits")?;
-
-        let z = ZlibDecoder::new(f);
-        let mut z = BufReader::new(z);
-        let mut buf = Vec::new();
-        z.read_until(0, &mut buf)
-            .context("Reading header from .git/object")?;
-
-        let header = CStr::from_bytes_with_nul(&buf)
-            .expect("know there is exactly one nul, and it's at the end");
-        let header = header
-            .to_str()
-            .context(".git/objects file header isn't valid UTF-8")?;
-        let Some((kind, size)) = header.split_once(' ') else {
-            anyhow::bail!(".git/objects file header did not start with a known type: '{header}'");
-        };
-
-        let kind = match kind {
-            "blob" => Kind::Blob,
-            "tree" => Kind::Tree,
-            "commint" => Kind::Commit,
-            _ => anyhow::bail!("we do not yet know how to print a '{kind}'"),
-        };
-        let size = size.parse::<u64>().context("file size is not valid")?;
-        let z = z.take(size);
-        Ok(Object {
-            kind,
-            expected_size: size,
-            reader: z,
+            reader: Box::new(file),
         })
     }
 }
@@ -83,35 +83,60 @@ impl<R> Object<R>
 where
     R: Read,
 {
-    pub(crate) fn write(mut self, writer: impl Write) -> anyhow::Result<[u8; 20]> {
+    // NOTE: zlib compress "<kind> <size>\0<content>" into the writer
+    // and return the hash of the uncompressed bytes (sha1 or sha256 depend on the repo format)
+    pub(crate) fn write(
+        mut self,
+        writer: impl Write,
+        format: ObjectFormat,
+    ) -> anyhow::Result<ObjectId> {
         let z = ZlibEncoder::new(writer, Compression::default());
         let mut writer = HashWriter {
             writer: z,
-            hasher: Sha1::new(),
+            hasher: Hasher::new(format),
         };
         write!(writer, "{} {}\0", self.kind, self.expected_size)?;
-        std::io::copy(&mut self.reader, &mut writer).context("Failed to write into the file")?;
+        let n = std::io::copy(&mut self.reader, &mut writer)
+            .context("Failed to write into the file")?;
+        anyhow::ensure!(
+            n == self.expected_size,
+            "object was not expected size (expected :{}, actual: {n})",
+            self.expected_size
+        );
         let _ = writer.writer.finish()?;
-        let hash = writer.hasher.finalize();
-        Ok(hash.into())
+        writer.hasher.finalize()
+    }
+    // NOTE: only the hash, nothing get stored (and nothing to compress)
+    // the content is streamed, a file of any size is hashed in bounded memory
+    pub(crate) fn hash(mut self, format: ObjectFormat) -> anyhow::Result<ObjectId> {
+        let mut writer = HashWriter {
+            writer: std::io::sink(),
+            hasher: Hasher::new(format),
+        };
+        write!(writer, "{} {}\0", self.kind, self.expected_size)?;
+        let n =
+            std::io::copy(&mut self.reader, &mut writer).context("Failed to hash the object")?;
+        anyhow::ensure!(
+            n == self.expected_size,
+            "object was not expected size (expected :{}, actual: {n})",
+            self.expected_size
+        );
+        writer.hasher.finalize()
+    }
+    pub(crate) fn write_to_object(self) -> anyhow::Result<ObjectId> {
+        self.write_to(&odb::open()?)
     }
-    pub(crate) fn write_to_object(self) -> anyhow::Result<[u8; 20]> {
-        let tmp = "temporary";
-        let hash = self.write(std::fs::File::create(tmp).context("Failed to create File")?)?;
-        let raw_hash = hex::encode(hash);
-        std::fs::create_dir_all(format!(".git/objects/{}", &raw_hash[..2]))
-            .context("Failed to create parent dir for .git/objects/")?;
-        std::fs::rename(
-            tmp,
-            format!(".git/objects/{}/{}", &raw_hash[..2], &raw_hash[2..]),
-        )
-        .context("Failed to move tmp file into .git/objects")?;
-        Ok(hash)
+    pub(crate) fn write_to(mut self, db: &dyn ObjectDatabase) -> anyhow::Result<ObjectId> {
+        db.write(Object {
+            kind: self.kind,
+            expected_size: self.expected_size,
+            reader: &mut self.reader,
+        })
     }
 }
 pub(crate) struct HashWriter<W> {
     pub(crate) writer: W,
-    pub(crate) hasher: Sha1,
+    pub(crate) hasher: Hasher,
 }
 impl<W> Write for HashWriter<W>
 where
@@ -126,3 +151,66 @@ where
         self.writer.flush()
     }
 }
+
+// NOTE: reader side of the HashWriter
+// hash the "<kind> <size>\0" header and everything we read, and when the whole content
+// went through check it hash to the name of the object.
+// a corrupted object (or a sha1 collision) fail the read at the end instead of being trusted
+pub(crate) struct HashReader<R> {
+    reader: R,
+    hasher: Option<Hasher>,
+    expected: ObjectId,
+    remaining: u64,
+}
+impl<R> HashReader<R> {
+    pub(crate) fn new(
+        reader: R,
+        kind: Kind,
+        size: u64,
+        expected: ObjectId,
+        format: ObjectFormat,
+    ) -> Self {
+        let mut hasher = Hasher::new(format);
+        hasher.update(format!("{kind} {size}\0").as_bytes());
+        Self {
+            reader,
+            hasher: Some(hasher),
+            expected,
+            remaining: size,
+        }
+    }
+    fn verify(&mut self) -> std::io::Result<()> {
+        let Some(hasher) = self.hasher.take() else {
+            return Ok(());
+        };
+        let actual = hasher
+            .finalize()
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string()))?;
+        if self.remaining != 0 || actual != self.expected {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!(
+                    "hash mismatch for object {} (content hash to {actual})",
+                    self.expected
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+impl<R> Read for HashReader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        if let Some(hasher) = &mut self.hasher {
+            hasher.update(&buf[..n]);
+            self.remaining = self.remaining.saturating_sub(n as u64);
+        }
+        if n == 0 || self.remaining == 0 {
+            self.verify()?;
+        }
+        Ok(n)
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, Read};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::config::{Config, FSYNC_LOOSE_OBJECT};
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{Kind, Object};
+
+pub(crate) mod composite;
+pub(crate) mod loose;
+pub(crate) mod memory;
+pub(crate) mod pack;
+
+pub(crate) use composite::CompositeDb;
+pub(crate) use loose::LooseDb;
+pub(crate) use memory::MemoryDb;
+pub(crate) use pack::PackDb;
+
+// NOTE: the object database is anything that can hand out objects by their hash.
+// .git/objects/ab/cdef..   -> LooseDb   (one zlib file per object)
+// .git/objects/pack/*.pack -> PackDb    (many objects in one file, found through the .idx)
+// memory                   -> MemoryDb  (HashMap, nothing touches the disk)
+// all of them together     -> CompositeDb (asks every backend in order, writes to the first one)
+//
+// hash is always the hex string, same as the file name in .git/objects
+// (40 char for sha1 repo, 64 char for sha256 repo)
+pub(crate) trait ObjectDatabase {
+    /// sha1 or sha256, every object in the database use the same one
+    fn format(&self) -> ObjectFormat;
+    /// kind and size of the object without reading the content
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)>;
+    /// reader over the content of the object (header already stripped)
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>>;
+    fn contains(&self, hash: &str) -> bool;
+    /// store the object and return its hash
+    fn write(&self, object: Object<&mut dyn Read>) -> anyhow::Result<ObjectId>;
+    /// every object hash this database knows about
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>>;
+}
+
+// NOTE: default object database of the repository in the current dir
+// loose objects first (that's where the writes go) then all the packs, then the alternates.
+// GIT_OBJECT_DIRECTORY will point the whole thing to some other store, same as the real git
+// GIT_OBJECT_DIRECTORY=/tmp/store cargo run -- hash-object -w file.txt
+// GIT_ALTERNATE_OBJECT_DIRECTORIES=/a/objects:/b/objects add more stores only for reading
+pub(crate) fn open() -> anyhow::Result<CompositeDb> {
+    let objects_dir = objects_dir();
+    let config = Config::load()?;
+    let mut db = CompositeDb::new(ObjectFormat::from_config(&config)?);
+    let mut seen = HashSet::new();
+    add_objects_dir(&mut db, &objects_dir, &mut seen, 0, &config)?;
+    if let Some(dirs) = std::env::var_os("GIT_ALTERNATE_OBJECT_DIRECTORIES") {
+        for dir in std::env::split_paths(&dirs) {
+            if !dir.as_os_str().is_empty() {
+                add_objects_dir(&mut db, &dir, &mut seen, 1, &config)?;
+            }
+        }
+    }
+    Ok(db)
+}
+
+pub(crate) fn objects_dir() -> PathBuf {
+    std::env::var_os("GIT_OBJECT_DIRECTORY")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(".git/objects"))
+}
+
+// NOTE: objects dir of some other repository, its config is in <objects_dir>/../config
+pub(crate) fn open_at(objects_dir: &Path) -> anyhow::Result<CompositeDb> {
+    let config = Config::load_from(&objects_dir.join("../config"))?;
+    let mut db = CompositeDb::new(ObjectFormat::from_config(&config)?);
+    add_objects_dir(&mut db, objects_dir, &mut HashSet::new(), 0, &config)?;
+    Ok(db)
+}
+
+// NOTE: objects/info/alternates has one objects dir per line
+// relative path is relative to the objects dir that have the alternates file, not the cwd
+// alternate can have its own alternates, git stop after 5 levels
+// same dir twice (a -> b -> a) is skipped so the cycle does not loop forever
+const MAX_ALTERNATE_DEPTH: usize = 5;
+fn add_objects_dir(
+    db: &mut CompositeDb,
+    objects_dir: &Path,
+    seen: &mut HashSet<PathBuf>,
+    depth: usize,
+    config: &Config,
+) -> anyhow::Result<()> {
+    let canonical = fs::canonicalize(objects_dir).unwrap_or_else(|_| objects_dir.to_path_buf());
+    if !seen.insert(canonical) {
+        return Ok(());
+    }
+    if depth > 0 && !objects_dir.is_dir() {
+        eprintln!(
+            "error: object directory {} does not exist; check .git/objects/info/alternates",
+            objects_dir.display()
+        );
+        return Ok(());
+    }
+    let format = db.format();
+    let big_file_threshold = config.big_file_threshold()?;
+    db.push(
+        LooseDb::new(objects_dir, format)
+            .with_fsync(config.fsync(FSYNC_LOOSE_OBJECT)?)
+            .with_big_file_threshold(big_file_threshold),
+    );
+    for pack in PackDb::open_all(objects_dir, format)
+        .with_context(|| format!("Failed to open packs in {}", objects_dir.display()))?
+    {
+        db.push(pack.with_big_file_threshold(big_file_threshold));
+    }
+    let alternates = read_alternates(objects_dir)?;
+    if !alternates.is_empty() && depth >= MAX_ALTERNATE_DEPTH {
+        eprintln!(
+            "error: {}: ignoring alternate object stores, nesting too deep",
+            objects_dir.display()
+        );
+        return Ok(());
+    }
+    for alternate in alternates {
+        add_objects_dir(db, &alternate, seen, depth + 1, config)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn read_alternates(objects_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let alternates = match fs::read_to_string(objects_dir.join("info/alternates")) {
+        Ok(alternates) => alternates,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read objects/info/alternates"),
+    };
+    Ok(alternates
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| objects_dir.join(line))
+        .collect())
+}
+
+// NOTE: parse the "<kind> <size>\0" header that every object start with
+// (loose object after inflate, and also the thing we hash)
+pub(crate) fn read_object_header(reader: &mut impl BufRead) -> anyhow::Result<(Kind, u64)> {
+    let mut buf = Vec::new();
+    reader
+        .read_until(0, &mut buf)
+        .context("Reading header from .git/object")?;
+    anyhow::ensure!(
+        buf.last() == Some(&0),
+        ".git/objects file header is not nul terminated"
+    );
+    buf.pop();
+    let header = std::str::from_utf8(&buf).context(".git/objects file header isn't valid UTF-8")?;
+    let Some((kind, size)) = header.split_once(' ') else {
+        anyhow::bail!(".git/objects file header did not start with a known type: '{header}'");
+    };
+    let kind = kind.parse::<Kind>()?;
+    let size = size.parse::<u64>().context("file size is not valid")?;
+    Ok((kind, size))
+}
+
+// NOTE: short hash to the full one, same as "git cat-file -p 3b18e5"
+// git need at least 4 chars and the prefix have to match exactly one object
+pub(crate) fn resolve_prefix(db: &dyn ObjectDatabase, prefix: &str) -> anyhow::Result<String> {
+    let hex_len = db.format().hex_len();
+    anyhow::ensure!(
+        (4..=hex_len).contains(&prefix.len()) && prefix.bytes().all(|b| b.is_ascii_hexdigit()),
+        "not a valid object name: '{prefix}'"
+    );
+    let prefix = prefix.to_ascii_lowercase();
+    if prefix.len() == hex_len {
+        return Ok(prefix);
+    }
+    let mut found = db
+        .iter()?
+        .map(|hash| hash.to_string())
+        .filter(|hash| hash.starts_with(&prefix));
+    let Some(hash) = found.next() else {
+        anyhow::bail!("not a valid object name: '{prefix}'");
+    };
+    anyhow::ensure!(
+        found.next().is_none(),
+        "short object ID {prefix} is ambiguous"
+    );
+    Ok(hash)
+}
+
+// NOTE: short hash for display (log --oneline, ls-tree --abbrev)
+// shortest prefix of at least min_len chars that no other object in the database share
+// hashes are collected once, a lookup is a binary search in the sorted list
+pub(crate) struct Abbrev {
+    hashes: Vec<String>,
+    min_len: usize,
+}
+
+impl Abbrev {
+    pub(crate) fn new(db: &dyn ObjectDatabase, min_len: usize) -> anyhow::Result<Self> {
+        let mut hashes: Vec<String> = db.iter()?.map(|hash| hash.to_string()).collect();
+        hashes.sort_unstable();
+        hashes.dedup();
+        Ok(Self {
+            hashes,
+            min_len: min_len.clamp(4, db.format().hex_len()),
+        })
+    }
+
+    pub(crate) fn abbrev<'a>(&self, hash: &'a str) -> &'a str {
+        let common = |other: &String| {
+            other
+                .bytes()
+                .zip(hash.bytes())
+                .take_while(|(a, b)| a == b)
+                .count()
+        };
+        // the neighbours in sorted order are the ones sharing the longest prefix
+        let position = self.hashes.partition_point(|other| other.as_str() < hash);
+        let before = position.checked_sub(1).and_then(|i| self.hashes.get(i));
+        let after = self.hashes[position..]
+            .iter()
+            .find(|other| other.as_str() != hash);
+        let shared = before
+            .into_iter()
+            .chain(after)
+            .map(common)
+            .max()
+            .unwrap_or(0);
+        &hash[..(shared + 1).max(self.min_len).min(hash.len())]
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Read};
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{Kind, Object};
+use crate::odb::ObjectDatabase;
+
+// NOTE: list of object databases that act like one
+// read: first backend that has the object wins
+// write: always go to the first backend (for the repo that's the loose objects)
+pub(crate) struct CompositeDb {
+    format: ObjectFormat,
+    backends: Vec<Box<dyn ObjectDatabase>>,
+}
+
+impl CompositeDb {
+    pub(crate) fn new(format: ObjectFormat) -> Self {
+        Self {
+            format,
+            backends: Vec::new(),
+        }
+    }
+    pub(crate) fn push(&mut self, db: impl ObjectDatabase + 'static) {
+        self.backends.push(Box::new(db));
+    }
+    fn find(&self, hash: &str) -> anyhow::Result<&dyn ObjectDatabase> {
+        self.backends
+            .iter()
+            .find(|db| db.contains(hash))
+            .map(|db| db.as_ref())
+            .ok_or_else(|| anyhow::anyhow!("object {hash} not found"))
+    }
+}
+
+impl ObjectDatabase for CompositeDb {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)> {
+        self.find(hash)?.read_header(hash)
+    }
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>> {
+        self.find(hash)?.read(hash)
+    }
+    fn contains(&self, hash: &str) -> bool {
+        self.backends.iter().any(|db| db.contains(hash))
+    }
+    fn write(&self, object: Object<&mut dyn Read>) -> anyhow::Result<ObjectId> {
+        let Some(db) = self.backends.first() else {
+            anyhow::bail!("no object database to write into");
+        };
+        db.write(object)
+    }
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        // same object can be loose and packed at the same time, report it only once
+        let mut hashes = BTreeSet::new();
+        for db in &self.backends {
+            hashes.extend(db.iter()?);
+        }
+        Ok(Box::new(hashes.into_iter()))
+    }
+}
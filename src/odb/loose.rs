@@ -0,0 +1,256 @@
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::SystemTime;
+
+use anyhow::Context;
+use flate2::read::ZlibDecoder;
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{HashReader, Kind, Object};
+use crate::odb::{ObjectDatabase, read_object_header};
+
+// NOTE: one zlib compressed file per object
+// .git/objects/<hash - first 2>/<hash - rest>
+// content after inflate: <kind> <size>\0<content>
+pub(crate) struct LooseDb {
+    objects_dir: PathBuf,
+    format: ObjectFormat,
+    fsync: bool,
+    big_file_threshold: u64,
+}
+
+impl LooseDb {
+    pub(crate) fn new(objects_dir: impl Into<PathBuf>, format: ObjectFormat) -> Self {
+        Self {
+            objects_dir: objects_dir.into(),
+            format,
+            fsync: true,
+            big_file_threshold: u64::MAX,
+        }
+    }
+    // NOTE: core.fsync without loose-object, we still write into temp + rename
+    pub(crate) fn with_fsync(mut self, fsync: bool) -> Self {
+        self.fsync = fsync;
+        self
+    }
+    // NOTE: objects bigger than this are not held in memory to be hashed first, see write
+    pub(crate) fn with_big_file_threshold(mut self, threshold: u64) -> Self {
+        self.big_file_threshold = threshold;
+        self
+    }
+    pub(crate) fn object_path(&self, hash: &str) -> anyhow::Result<PathBuf> {
+        anyhow::ensure!(
+            hash.len() == self.format.hex_len() && hash.bytes().all(|b| b.is_ascii_hexdigit()),
+            "not a valid object name: '{hash}'"
+        );
+        Ok(self.objects_dir.join(&hash[..2]).join(&hash[2..]))
+    }
+    fn open(&self, hash: &str) -> anyhow::Result<BufReader<ZlibDecoder<fs::File>>> {
+        let f = fs::File::open(self.object_path(hash)?).context("File does't exits")?;
+        Ok(BufReader::new(ZlibDecoder::new(f)))
+    }
+}
+
+impl ObjectDatabase for LooseDb {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)> {
+        let mut z = self.open(hash)?;
+        read_object_header(&mut z)
+    }
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>> {
+        let mut z = self.open(hash)?;
+        let (kind, size) = read_object_header(&mut z)?;
+        let reader = HashReader::new(
+            z.take(size),
+            kind,
+            size,
+            ObjectId::from_hex(hash)?,
+            self.format,
+        );
+        Ok(Object {
+            kind,
+            expected_size: size,
+            reader: Box::new(BufReader::new(reader)),
+        })
+    }
+    fn contains(&self, hash: &str) -> bool {
+        self.object_path(hash).is_ok_and(|path| path.is_file())
+    }
+    // NOTE: the object is hashed first, when it is already there we keep the old file and
+    // only refresh its mtime (so prune don't think it's old garbage), nothing get compressed.
+    // a new one is written into objects/tmp_obj_XXXXXX (same dir, same filesystem so the
+    // rename is atomic), fsync'ed and only then renamed to its real name. two writers never
+    // share a temp file, and the final file is read only like git.
+    // an object bigger than core.bigFileThreshold is not read in memory, it is hashed while
+    // it is streamed into the temp file, which is dropped when the object was there
+    fn write(&self, object: Object<&mut dyn Read>) -> anyhow::Result<ObjectId> {
+        if object.expected_size > self.big_file_threshold {
+            let (tmp_path, hash) = self.write_temp(object)?;
+            if self.freshen(&hash) {
+                let _ = fs::remove_file(&tmp_path);
+                return Ok(hash);
+            }
+            return self.move_into_place(&tmp_path, hash);
+        }
+        let mut content = Vec::with_capacity(object.expected_size.min(1 << 20) as usize);
+        object
+            .reader
+            .read_to_end(&mut content)
+            .context("Failed to read the object content")?;
+        let hash = Object {
+            kind: object.kind,
+            expected_size: object.expected_size,
+            reader: &content[..],
+        }
+        .hash(self.format)?;
+        if self.freshen(&hash) {
+            return Ok(hash);
+        }
+        let (tmp_path, _) = self.write_temp(Object {
+            kind: object.kind,
+            expected_size: object.expected_size,
+            reader: &mut &content[..],
+        })?;
+        self.move_into_place(&tmp_path, hash)
+    }
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        let mut hashes = Vec::new();
+        let dirs = match fs::read_dir(&self.objects_dir) {
+            Ok(dirs) => dirs,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Box::new(hashes.into_iter()));
+            }
+            Err(e) => return Err(e).context("Reading the .git/objects dir"),
+        };
+        for dir in dirs {
+            let dir = dir.context("Reading the .git/objects dir")?;
+            let prefix = dir.file_name();
+            let Some(prefix) = prefix.to_str() else {
+                continue;
+            };
+            // skip pack/ and info/, only the 2 hex char fan-out dirs
+            if prefix.len() != 2 || !prefix.bytes().all(|b| b.is_ascii_hexdigit()) {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())
+                .with_context(|| format!("Reading .git/objects/{prefix}"))?
+            {
+                let file = file.with_context(|| format!("Reading .git/objects/{prefix}"))?;
+                let rest = file.file_name();
+                let Some(rest) = rest.to_str() else {
+                    continue;
+                };
+                let hash = format!("{prefix}{rest}");
+                if hash.len() != self.format.hex_len() {
+                    continue;
+                }
+                if let Ok(hash) = ObjectId::from_hex(&hash) {
+                    hashes.push(hash);
+                }
+            }
+        }
+        hashes.sort_unstable();
+        Ok(Box::new(hashes.into_iter()))
+    }
+}
+
+impl LooseDb {
+    // true when the object is already there, its mtime is now
+    fn freshen(&self, hash: &ObjectId) -> bool {
+        let Ok(path) = self.object_path(&hash.to_string()) else {
+            return false;
+        };
+        if !path.is_file() {
+            return false;
+        }
+        if let Ok(existing) = fs::File::open(&path) {
+            let _ = existing.set_modified(SystemTime::now());
+        }
+        true
+    }
+    // the compressed object in a new temp file, hashed while it is written
+    fn write_temp(&self, object: Object<&mut dyn Read>) -> anyhow::Result<(PathBuf, ObjectId)> {
+        fs::create_dir_all(&self.objects_dir)
+            .with_context(|| format!("Failed to create {}", self.objects_dir.display()))?;
+        let (tmp_path, file) = create_temp_file(&self.objects_dir, "tmp_obj_")?;
+        let written = (|| {
+            let mut file = BufWriter::new(file);
+            let hash = object.write(&mut file, self.format)?;
+            let file = file
+                .into_inner()
+                .context("Failed to flush the object file")?;
+            if self.fsync {
+                file.sync_all().context("Failed to fsync the object file")?;
+            }
+            Ok(hash)
+        })();
+        match written {
+            Ok(hash) => Ok((tmp_path, hash)),
+            Err(e) => {
+                let _ = fs::remove_file(&tmp_path);
+                Err(e)
+            }
+        }
+    }
+    fn move_into_place(&self, tmp_path: &Path, hash: ObjectId) -> anyhow::Result<ObjectId> {
+        let raw_hash = hash.to_string();
+        let path = self.object_path(&raw_hash)?;
+        let parent = self.objects_dir.join(&raw_hash[..2]);
+        let moved = fs::create_dir_all(&parent)
+            .context("Failed to create parent dir for .git/objects/")
+            .and_then(|_| {
+                fs::set_permissions(tmp_path, fs::Permissions::from_mode(0o444))
+                    .context("Failed to make the object file read only")
+            })
+            .and_then(|_| {
+                fs::rename(tmp_path, &path).context("Failed to move tmp file into .git/objects")
+            });
+        if let Err(e) = moved {
+            let _ = fs::remove_file(tmp_path);
+            return Err(e);
+        }
+        if self.fsync {
+            // rename is only durable after the dir itself is on the disk
+            fs::File::open(&parent)
+                .and_then(|dir| dir.sync_all())
+                .context("Failed to fsync the object dir")?;
+        }
+        Ok(hash)
+    }
+}
+
+// NOTE: <dir>/<prefix><pid>_<counter>_<nanos>, create_new fail if someone else got the same name
+// so we just try the next one
+pub(crate) fn create_temp_file(dir: &Path, prefix: &str) -> anyhow::Result<(PathBuf, fs::File)> {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    for _ in 0..100 {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+        let name = format!(
+            "{prefix}{}_{}_{nanos}",
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        );
+        let path = dir.join(name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+        {
+            Ok(file) => return Ok((path, file)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(e)
+                    .with_context(|| format!("Failed to create temp file in {}", dir.display()));
+            }
+        }
+    }
+    anyhow::bail!("Failed to find a free temp file name in {}", dir.display())
+}
@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io::{BufRead, Cursor, Read};
+
+use anyhow::Context;
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{Kind, Object};
+use crate::odb::ObjectDatabase;
+
+// NOTE: object database that only live in the memory
+// nothing get compressed, we keep the raw content next to its kind
+// usefull when you need a scratch store (test, dry run) without touching .git/objects
+// the merge keep its virtual merge bases in there
+pub(crate) struct MemoryDb {
+    format: ObjectFormat,
+    objects: RefCell<BTreeMap<ObjectId, Stored>>,
+}
+
+type Stored = (Kind, Vec<u8>);
+
+impl MemoryDb {
+    pub(crate) fn new(format: ObjectFormat) -> Self {
+        Self {
+            format,
+            objects: RefCell::default(),
+        }
+    }
+    fn get(&self, hash: &str) -> anyhow::Result<Stored> {
+        let raw = ObjectId::from_hex(hash)?;
+        self.objects
+            .borrow()
+            .get(&raw)
+            .cloned()
+            .with_context(|| format!("object {hash} not found in memory"))
+    }
+}
+
+impl ObjectDatabase for MemoryDb {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)> {
+        let (kind, content) = self.get(hash)?;
+        Ok((kind, content.len() as u64))
+    }
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>> {
+        let (kind, content) = self.get(hash)?;
+        Ok(Object {
+            kind,
+            expected_size: content.len() as u64,
+            reader: Box::new(Cursor::new(content)),
+        })
+    }
+    fn contains(&self, hash: &str) -> bool {
+        self.get(hash).is_ok()
+    }
+    fn write(&self, object: Object<&mut dyn Read>) -> anyhow::Result<ObjectId> {
+        let mut content = Vec::with_capacity(object.expected_size as usize);
+        object
+            .reader
+            .read_to_end(&mut content)
+            .context("Failed to read the object content")?;
+        anyhow::ensure!(
+            content.len() as u64 == object.expected_size,
+            "object was not expected size (expected :{}, actual: {})",
+            object.expected_size,
+            content.len()
+        );
+        let kind = object.kind;
+        // hash the same way the loose object does, just throw away the compressed bytes
+        let hash = Object {
+            kind,
+            expected_size: object.expected_size,
+            reader: &content[..],
+        }
+        .hash(self.format)?;
+        self.objects.borrow_mut().insert(hash, (kind, content));
+        Ok(hash)
+    }
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        let hashes: Vec<_> = self.objects.borrow().keys().copied().collect();
+        Ok(Box::new(hashes.into_iter()))
+    }
+}
@@ -0,0 +1,775 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use flate2::read::ZlibDecoder;
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{HashReader, Kind, Object};
+use crate::odb::ObjectDatabase;
+
+// NOTE: packed objects live in .git/objects/pack/pack-<hash>.pack
+// and every pack have a .idx file next to it to find the object offset in the pack.
+// .idx version 2 layout
+// | Field         | Size (bytes)   |
+// | ------------- | -------------- |
+// | magic         | 4  \377tOc     |
+// | version       | 4  (2)         |
+// | fanout        | 256 * 4        |  fanout[b] = number of objects whose first byte <= b
+// | names         | N * 20 (or 32) |  sorted, 32 bytes in the sha256 repo
+// | crc32         | N * 4          |
+// | offsets       | N * 4          |  MSB set -> index into the large offsets table
+// | large offsets | M * 8          |
+// version 1 has no magic, just the fanout followed by N * (4 bytes offset + 20 bytes name)
+const IDX_MAGIC: [u8; 4] = [0xff, b't', b'O', b'c'];
+
+// pack entry type (3 bits in the entry header)
+const OBJ_COMMIT: u8 = 1;
+const OBJ_TREE: u8 = 2;
+const OBJ_BLOB: u8 = 3;
+const OBJ_TAG: u8 = 4;
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+// NOTE: git makes chains of pack.depth deltas (50 by default, never more than 4095),
+// 10000 leaves room for other writers, a longer chain is a broken or crafted pack
+const MAX_DELTA_DEPTH: usize = 10000;
+
+// NOTE: sizes come from the pack (or the index) before the data is there, a lying one must
+// not allocate gigabytes up front. Vec grows past this when the data is really there
+const MAX_PREALLOC: u64 = 1 << 20;
+
+pub(crate) struct PackDb {
+    pack_path: PathBuf,
+    format: ObjectFormat,
+    hashes: Vec<ObjectId>,
+    offsets: Vec<u64>,
+    big_file_threshold: u64,
+}
+
+enum Entry {
+    Base(Kind),
+    OfsDelta(u64),
+    RefDelta(ObjectId),
+}
+
+impl PackDb {
+    // NOTE: open the pack from the path of its .idx file
+    pub(crate) fn open(idx_path: &Path, format: ObjectFormat) -> anyhow::Result<Self> {
+        let hash_len = format.raw_len();
+        let idx = fs::read(idx_path)
+            .with_context(|| format!("Failed to read pack index {}", idx_path.display()))?;
+        let (hashes, offsets) = if idx.starts_with(&IDX_MAGIC) {
+            let version = be_u32(&idx, 4)?;
+            anyhow::ensure!(version == 2, "unsupported pack index version {version}");
+            let n = be_u32(&idx, 8 + 255 * 4)? as usize;
+            let names_at = 8 + 256 * 4;
+            let offsets_at = names_at + n * hash_len + n * 4;
+            let large_at = offsets_at + n * 4;
+            anyhow::ensure!(idx.len() >= large_at, "pack index is truncated");
+            let mut hashes = Vec::with_capacity(n);
+            let mut offsets = Vec::with_capacity(n);
+            for i in 0..n {
+                hashes.push(be_hash(&idx, names_at + i * hash_len, hash_len)?);
+                let offset = be_u32(&idx, offsets_at + i * 4)?;
+                let offset = if offset & 0x8000_0000 != 0 {
+                    let at = large_at + (offset & 0x7fff_ffff) as usize * 8;
+                    let raw = idx.get(at..at + 8).context("pack index is truncated")?;
+                    u64::from_be_bytes(raw.try_into().unwrap())
+                } else {
+                    offset as u64
+                };
+                offsets.push(offset);
+            }
+            (hashes, offsets)
+        } else {
+            let n = be_u32(&idx, 255 * 4)? as usize;
+            let entries_at = 256 * 4;
+            let entry_len = 4 + hash_len;
+            anyhow::ensure!(
+                idx.len() >= entries_at + n * entry_len,
+                "pack index is truncated"
+            );
+            let mut hashes = Vec::with_capacity(n);
+            let mut offsets = Vec::with_capacity(n);
+            for i in 0..n {
+                offsets.push(be_u32(&idx, entries_at + i * entry_len)? as u64);
+                hashes.push(be_hash(&idx, entries_at + i * entry_len + 4, hash_len)?);
+            }
+            (hashes, offsets)
+        };
+        Ok(Self {
+            pack_path: idx_path.with_extension("pack"),
+            format,
+            hashes,
+            offsets,
+            big_file_threshold: u64::MAX,
+        })
+    }
+    // NOTE: base (not delta) entries bigger than this are inflated while the caller read them
+    // instead of all at once, see core.bigFileThreshold
+    pub(crate) fn with_big_file_threshold(mut self, threshold: u64) -> Self {
+        self.big_file_threshold = threshold;
+        self
+    }
+    pub(crate) fn open_all(objects_dir: &Path, format: ObjectFormat) -> anyhow::Result<Vec<Self>> {
+        let pack_dir = objects_dir.join("pack");
+        let dir = match fs::read_dir(&pack_dir) {
+            Ok(dir) => dir,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e).context("Reading .git/objects/pack"),
+        };
+        let mut packs = Vec::new();
+        for entry in dir {
+            let path = entry.context("Reading .git/objects/pack")?.path();
+            if path.extension().is_some_and(|ext| ext == "idx")
+                && path.with_extension("pack").is_file()
+            {
+                packs.push(Self::open(&path, format)?);
+            }
+        }
+        packs.sort_by(|a, b| a.pack_path.cmp(&b.pack_path));
+        Ok(packs)
+    }
+    pub(crate) fn pack_path(&self) -> &Path {
+        &self.pack_path
+    }
+    // number of objects in the pack
+    pub(crate) fn len(&self) -> usize {
+        self.hashes.len()
+    }
+    fn offset_of(&self, hash: &str) -> Option<u64> {
+        let raw = ObjectId::from_hex(hash).ok()?;
+        self.offset_of_raw(&raw)
+    }
+    fn offset_of_raw(&self, raw: &ObjectId) -> Option<u64> {
+        let i = self.hashes.binary_search(raw).ok()?;
+        Some(self.offsets[i])
+    }
+
+    // NOTE: entry header is variable length
+    // first byte: 1 bit continue | 3 bits type | 4 bits size
+    // next bytes: 1 bit continue | 7 bits size (little endian groups)
+    // ofs delta: base offset is a big endian varint, each continuation add 1 before shifting
+    // a size or an offset that doesn't fit in 64 bits is an error, not a wrap around
+    // ref delta: hash of the base (20 or 32 bytes)
+    fn read_entry_header(
+        &self,
+        file: &mut BufReader<fs::File>,
+        offset: u64,
+    ) -> anyhow::Result<(Entry, u64)> {
+        file.seek(SeekFrom::Start(offset))
+            .context("Seeking in the pack file")?;
+        let mut c = read_byte(file)?;
+        let kind = (c >> 4) & 0b111;
+        let mut size = (c & 0x0f) as u64;
+        let mut shift = 4;
+        while c & 0x80 != 0 {
+            c = read_byte(file)?;
+            size |= varint_bits(c, shift)
+                .with_context(|| format!("pack entry size is too big at offset {offset}"))?;
+            shift += 7;
+        }
+        let entry = match kind {
+            OBJ_COMMIT => Entry::Base(Kind::Commit),
+            OBJ_TREE => Entry::Base(Kind::Tree),
+            OBJ_BLOB => Entry::Base(Kind::Blob),
+            OBJ_TAG => Entry::Base(Kind::Tag),
+            OBJ_OFS_DELTA => {
+                let mut c = read_byte(file)?;
+                let mut back = (c & 0x7f) as u64;
+                while c & 0x80 != 0 {
+                    c = read_byte(file)?;
+                    back = back
+                        .checked_add(1)
+                        .and_then(|back| back.checked_mul(1 << 7))
+                        .and_then(|back| back.checked_add((c & 0x7f) as u64))
+                        .with_context(|| format!("ofs delta is too far at offset {offset}"))?;
+                }
+                let base = offset
+                    .checked_sub(back)
+                    .context("ofs delta point before the start of the pack")?;
+                Entry::OfsDelta(base)
+            }
+            OBJ_REF_DELTA => {
+                let mut base = vec![0; self.format.raw_len()];
+                file.read_exact(&mut base)
+                    .context("Reading ref delta base")?;
+                Entry::RefDelta(ObjectId::from_bytes(&base)?)
+            }
+            _ => anyhow::bail!("unknown pack entry type {kind} at offset {offset}"),
+        };
+        Ok((entry, size))
+    }
+    fn inflate(file: &mut BufReader<fs::File>, size: u64) -> anyhow::Result<Vec<u8>> {
+        let mut data = Vec::with_capacity(size.min(MAX_PREALLOC) as usize);
+        ZlibDecoder::new(file)
+            .take(size)
+            .read_to_end(&mut data)
+            .context("Inflating pack entry")?;
+        anyhow::ensure!(
+            data.len() as u64 == size,
+            "pack entry was not expected size (expected :{size}, actual: {})",
+            data.len()
+        );
+        Ok(data)
+    }
+    fn base_offset(&self, entry: &Entry) -> anyhow::Result<u64> {
+        match entry {
+            Entry::Base(_) => unreachable!("base entry has no delta base"),
+            Entry::OfsDelta(base) => Ok(*base),
+            Entry::RefDelta(base) => self
+                .offset_of_raw(base)
+                .with_context(|| format!("delta base {base} is not in the pack")),
+        }
+    }
+    // NOTE: a delta whose base chain comes back to an entry already seen (an ofs delta of 0,
+    // a ref delta on itself, two deltas on each other) would never reach a base object
+    fn next_base(&self, entry: &Entry, seen: &mut HashSet<u64>) -> anyhow::Result<u64> {
+        let base = self.base_offset(entry)?;
+        anyhow::ensure!(
+            seen.len() <= MAX_DELTA_DEPTH,
+            "delta chain is deeper than {MAX_DELTA_DEPTH}"
+        );
+        anyhow::ensure!(seen.insert(base), "delta chain loops at offset {base}");
+        Ok(base)
+    }
+    fn kind_at(&self, file: &mut BufReader<fs::File>, offset: u64) -> anyhow::Result<Kind> {
+        let mut offset = offset;
+        let mut seen = HashSet::from([offset]);
+        loop {
+            let (entry, _) = self.read_entry_header(file, offset)?;
+            match entry {
+                Entry::Base(kind) => return Ok(kind),
+                delta => offset = self.next_base(&delta, &mut seen)?,
+            }
+        }
+    }
+    // the deltas down to the base object, applied back up from the base
+    fn read_at(
+        &self,
+        file: &mut BufReader<fs::File>,
+        offset: u64,
+    ) -> anyhow::Result<(Kind, Vec<u8>)> {
+        let mut offset = offset;
+        let mut seen = HashSet::from([offset]);
+        let mut deltas: Vec<Vec<u8>> = Vec::new();
+        loop {
+            let (entry, size) = self.read_entry_header(file, offset)?;
+            let data = Self::inflate(file, size)?;
+            match entry {
+                Entry::Base(kind) => {
+                    let mut content = data;
+                    for delta in deltas.iter().rev() {
+                        content = apply_delta(&content, delta)?;
+                    }
+                    return Ok((kind, content));
+                }
+                delta => {
+                    deltas.push(data);
+                    offset = self.next_base(&delta, &mut seen)?;
+                }
+            }
+        }
+    }
+    fn open_pack(&self) -> anyhow::Result<BufReader<fs::File>> {
+        let f = fs::File::open(&self.pack_path)
+            .with_context(|| format!("Failed to open {}", self.pack_path.display()))?;
+        Ok(BufReader::new(f))
+    }
+}
+
+impl ObjectDatabase for PackDb {
+    fn format(&self) -> ObjectFormat {
+        self.format
+    }
+    fn read_header(&self, hash: &str) -> anyhow::Result<(Kind, u64)> {
+        let offset = self
+            .offset_of(hash)
+            .with_context(|| format!("object {hash} not found in the pack"))?;
+        let mut file = self.open_pack()?;
+        let (entry, size) = self.read_entry_header(&mut file, offset)?;
+        if let Entry::Base(kind) = entry {
+            return Ok((kind, size));
+        }
+        // delta data start with the base size and then the result size
+        let mut head = Vec::new();
+        ZlibDecoder::new(&mut file)
+            .take(20)
+            .read_to_end(&mut head)
+            .context("Inflating delta header")?;
+        let mut at = 0;
+        let _ = read_varint(&head, &mut at)?;
+        let size = read_varint(&head, &mut at)?;
+        let kind = self.kind_at(&mut file, offset)?;
+        Ok((kind, size))
+    }
+    fn read(&self, hash: &str) -> anyhow::Result<Object<Box<dyn BufRead>>> {
+        let offset = self
+            .offset_of(hash)
+            .with_context(|| format!("object {hash} not found in the pack"))?;
+        let mut file = self.open_pack()?;
+        if let (Entry::Base(kind), size) = self.read_entry_header(&mut file, offset)?
+            && size > self.big_file_threshold
+        {
+            let reader = HashReader::new(
+                ZlibDecoder::new(file).take(size),
+                kind,
+                size,
+                ObjectId::from_hex(hash)?,
+                self.format,
+            );
+            return Ok(Object {
+                kind,
+                expected_size: size,
+                reader: Box::new(BufReader::new(reader)),
+            });
+        }
+        let (kind, content) = self.read_at(&mut file, offset)?;
+        let size = content.len() as u64;
+        let reader = HashReader::new(
+            Cursor::new(content),
+            kind,
+            size,
+            ObjectId::from_hex(hash)?,
+            self.format,
+        );
+        Ok(Object {
+            kind,
+            expected_size: size,
+            reader: Box::new(BufReader::new(reader)),
+        })
+    }
+    fn contains(&self, hash: &str) -> bool {
+        self.offset_of(hash).is_some()
+    }
+    fn write(&self, _: Object<&mut dyn Read>) -> anyhow::Result<ObjectId> {
+        anyhow::bail!(
+            "{} is read only, objects can not be added to an existing pack",
+            self.pack_path.display()
+        )
+    }
+    fn iter(&self) -> anyhow::Result<Box<dyn Iterator<Item = ObjectId> + '_>> {
+        Ok(Box::new(self.hashes.iter().copied()))
+    }
+}
+
+// NOTE: delta format
+// <base size varint><result size varint><instruction>...
+// instruction first bit 1 -> copy from base
+//   bits 0-3 say which offset bytes follow, bits 4-6 which size bytes follow (size 0 means 0x10000)
+// instruction first bit 0 -> insert the next <instruction> bytes from the delta itself
+pub(crate) fn apply_delta(base: &[u8], delta: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut at = 0;
+    let base_size = read_varint(delta, &mut at)?;
+    anyhow::ensure!(
+        base_size == base.len() as u64,
+        "delta base was not expected size (expected :{base_size}, actual: {})",
+        base.len()
+    );
+    let result_size = read_varint(delta, &mut at)?;
+    let mut result = Vec::with_capacity(result_size.min(MAX_PREALLOC) as usize);
+    while at < delta.len() {
+        let op = delta[at];
+        at += 1;
+        if op & 0x80 != 0 {
+            let mut offset = 0usize;
+            let mut size = 0usize;
+            for i in 0..4 {
+                if op & (1 << i) != 0 {
+                    offset |= (*delta.get(at).context("delta is truncated")? as usize) << (i * 8);
+                    at += 1;
+                }
+            }
+            for i in 0..3 {
+                if op & (1 << (4 + i)) != 0 {
+                    size |= (*delta.get(at).context("delta is truncated")? as usize) << (i * 8);
+                    at += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let copy = base
+                .get(offset..offset + size)
+                .context("delta copy is out of the base object")?;
+            result.extend_from_slice(copy);
+        } else if op != 0 {
+            let insert = delta
+                .get(at..at + op as usize)
+                .context("delta is truncated")?;
+            result.extend_from_slice(insert);
+            at += op as usize;
+        } else {
+            anyhow::bail!("delta has a reserved 0 instruction");
+        }
+    }
+    anyhow::ensure!(
+        result.len() as u64 == result_size,
+        "delta result was not expected size (expected :{result_size}, actual: {})",
+        result.len()
+    );
+    Ok(result)
+}
+
+fn read_varint(data: &[u8], at: &mut usize) -> anyhow::Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        let c = *data.get(*at).context("delta is truncated")?;
+        *at += 1;
+        value |= varint_bits(c, shift).context("delta size is too big")?;
+        shift += 7;
+        if c & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+}
+
+// the 7 low bits of c moved to `shift`, none of them may fall off the u64
+fn varint_bits(c: u8, shift: u32) -> Option<u64> {
+    let bits = (c & 0x7f) as u64;
+    bits.checked_shl(shift)
+        .filter(|shifted| shifted >> shift == bits)
+}
+
+fn read_byte(r: &mut impl Read) -> anyhow::Result<u8> {
+    let mut buf = [0u8; 1];
+    r.read_exact(&mut buf).context("pack file is truncated")?;
+    Ok(buf[0])
+}
+
+fn be_u32(data: &[u8], at: usize) -> anyhow::Result<u32> {
+    let raw = data.get(at..at + 4).context("pack index is truncated")?;
+    Ok(u32::from_be_bytes(raw.try_into().unwrap()))
+}
+
+fn be_hash(data: &[u8], at: usize, len: usize) -> anyhow::Result<ObjectId> {
+    let raw = data.get(at..at + len).context("pack index is truncated")?;
+    ObjectId::from_bytes(raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+
+    use super::*;
+    use crate::odb::MemoryDb;
+
+    // one pack entry, written the way read_entry_header reads it
+    enum Raw {
+        Base(u8, Vec<u8>),
+        // the base is the entry at this index (its own index makes an ofs delta of 0)
+        Ofs(usize, Vec<u8>),
+        Ref(ObjectId, Vec<u8>),
+    }
+
+    // a .pack and its .idx in a fresh directory, removed on drop
+    struct TestPack {
+        dir: PathBuf,
+        db: PackDb,
+    }
+
+    impl Drop for TestPack {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    fn entry_header(kind: u8, size: u64) -> Vec<u8> {
+        let mut header = vec![(kind << 4) | (size & 0x0f) as u8];
+        let mut size = size >> 4;
+        while size != 0 {
+            *header.last_mut().unwrap() |= 0x80;
+            header.push((size & 0x7f) as u8);
+            size >>= 7;
+        }
+        header
+    }
+
+    fn ofs_bytes(mut back: u64) -> Vec<u8> {
+        let mut bytes = vec![(back & 0x7f) as u8];
+        back >>= 7;
+        while back != 0 {
+            back -= 1;
+            bytes.push(0x80 | (back & 0x7f) as u8);
+            back >>= 7;
+        }
+        bytes.reverse();
+        bytes
+    }
+
+    fn varint(mut value: u64) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let c = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                bytes.push(c);
+                return bytes;
+            }
+            bytes.push(c | 0x80);
+        }
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    // a delta that copies all of the base and adds `insert`
+    fn delta(base_len: usize, insert: &[u8]) -> Vec<u8> {
+        let mut delta = varint(base_len as u64);
+        delta.extend(varint((base_len + insert.len()) as u64));
+        if base_len > 0 {
+            delta.push(0x80 | 0x10 | 0x20);
+            delta.extend([(base_len & 0xff) as u8, (base_len >> 8) as u8]);
+        }
+        delta.push(insert.len() as u8);
+        delta.extend(insert);
+        delta
+    }
+
+    // the pack and the offset of every entry
+    fn pack_bytes(entries: &[Raw]) -> (Vec<u8>, Vec<u64>) {
+        let mut pack = b"PACK".to_vec();
+        pack.extend(2u32.to_be_bytes());
+        pack.extend((entries.len() as u32).to_be_bytes());
+        let mut offsets = Vec::new();
+        for entry in entries {
+            let offset = pack.len() as u64;
+            offsets.push(offset);
+            let data = match entry {
+                Raw::Base(kind, data) => {
+                    pack.extend(entry_header(*kind, data.len() as u64));
+                    data
+                }
+                Raw::Ofs(base, data) => {
+                    pack.extend(entry_header(OBJ_OFS_DELTA, data.len() as u64));
+                    pack.extend(ofs_bytes(offset - offsets[*base]));
+                    data
+                }
+                Raw::Ref(base, data) => {
+                    pack.extend(entry_header(OBJ_REF_DELTA, data.len() as u64));
+                    pack.extend(base.as_bytes());
+                    data
+                }
+            };
+            pack.extend(deflate(data));
+        }
+        pack.extend([0; 20]);
+        (pack, offsets)
+    }
+
+    fn idx_bytes(names: &[ObjectId], offsets: &[u64]) -> Vec<u8> {
+        let mut sorted: Vec<(ObjectId, u64)> =
+            names.iter().copied().zip(offsets.iter().copied()).collect();
+        sorted.sort();
+        let mut idx = IDX_MAGIC.to_vec();
+        idx.extend(2u32.to_be_bytes());
+        for b in 0..=255u8 {
+            let count = sorted
+                .iter()
+                .filter(|(name, _)| name.as_bytes()[0] <= b)
+                .count();
+            idx.extend((count as u32).to_be_bytes());
+        }
+        for (name, _) in &sorted {
+            idx.extend(name.as_bytes());
+        }
+        idx.extend(vec![0; sorted.len() * 4]);
+        for (_, offset) in &sorted {
+            idx.extend((*offset as u32).to_be_bytes());
+        }
+        idx
+    }
+
+    fn write_pack(test: &str, pack: &[u8], idx: &[u8]) -> TestPack {
+        let dir = std::env::temp_dir().join(format!("git-rs-pack-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pack-test.pack"), pack).unwrap();
+        fs::write(dir.join("pack-test.idx"), idx).unwrap();
+        let db = PackDb::open(&dir.join("pack-test.idx"), ObjectFormat::Sha1).unwrap();
+        TestPack { dir, db }
+    }
+
+    fn build(test: &str, entries: &[Raw], names: &[ObjectId]) -> TestPack {
+        let (pack, offsets) = pack_bytes(entries);
+        write_pack(test, &pack, &idx_bytes(names, &offsets))
+    }
+
+    fn read_all(db: &dyn ObjectDatabase, hash: ObjectId) -> anyhow::Result<(Kind, Vec<u8>)> {
+        let mut object = db.read(&hash.to_string())?;
+        let mut content = Vec::new();
+        object.reader.read_to_end(&mut content)?;
+        Ok((object.kind, content))
+    }
+
+    // an id for an entry whose content doesn't matter
+    fn fake_id(n: usize) -> ObjectId {
+        let mut raw = [0u8; 20];
+        raw[..8].copy_from_slice(&(n as u64).to_be_bytes());
+        ObjectId::from_bytes(&raw).unwrap()
+    }
+
+    // NOTE: the pack gives back the same objects as the MemoryDb they were written to
+    #[test]
+    fn reads_base_and_delta_objects() {
+        let memory = MemoryDb::new(ObjectFormat::Sha1);
+        let base = b"hello world\n".to_vec();
+        let contents = [
+            base.clone(),
+            b"hello world\nsecond\n".to_vec(),
+            b"hello world\nthird\n".to_vec(),
+        ];
+        let ids: Vec<ObjectId> = contents
+            .iter()
+            .map(|content| Object::blob(content.clone()).write_to(&memory).unwrap())
+            .collect();
+        let entries = [
+            Raw::Base(OBJ_BLOB, base.clone()),
+            Raw::Ofs(0, delta(base.len(), b"second\n")),
+            Raw::Ref(ids[0], delta(base.len(), b"third\n")),
+        ];
+        let test = build("deltas", &entries, &ids);
+
+        for id in &ids {
+            let hex = id.to_string();
+            assert_eq!(
+                read_all(&test.db, *id).unwrap(),
+                read_all(&memory, *id).unwrap()
+            );
+            assert_eq!(
+                test.db.read_header(&hex).unwrap(),
+                memory.read_header(&hex).unwrap()
+            );
+            assert!(test.db.contains(&hex) && memory.contains(&hex));
+        }
+        let mut listed: Vec<ObjectId> = test.db.iter().unwrap().collect();
+        let mut stored: Vec<ObjectId> = memory.iter().unwrap().collect();
+        listed.sort();
+        stored.sort();
+        assert_eq!(listed, stored);
+    }
+
+    #[test]
+    fn ofs_delta_on_itself_is_an_error() {
+        let test = build("self-ofs", &[Raw::Ofs(0, delta(0, b"x"))], &[fake_id(1)]);
+        let error = read_all(&test.db, fake_id(1)).unwrap_err();
+        assert!(format!("{error:#}").contains("loops"), "{error:#}");
+        let error = test.db.read_header(&fake_id(1).to_string()).unwrap_err();
+        assert!(format!("{error:#}").contains("loops"), "{error:#}");
+    }
+
+    #[test]
+    fn ref_deltas_on_each_other_are_an_error() {
+        let entries = [
+            Raw::Ref(fake_id(2), delta(1, b"a")),
+            Raw::Ref(fake_id(1), delta(1, b"b")),
+        ];
+        let test = build("ref-loop", &entries, &[fake_id(1), fake_id(2)]);
+        let error = read_all(&test.db, fake_id(1)).unwrap_err();
+        assert!(format!("{error:#}").contains("loops"), "{error:#}");
+    }
+
+    // NOTE: entry i is a delta on entry i - 1 that adds one byte
+    #[test]
+    fn too_deep_delta_chain_is_an_error() {
+        let mut entries = vec![Raw::Base(OBJ_BLOB, Vec::new())];
+        for depth in 0..=MAX_DELTA_DEPTH {
+            entries.push(Raw::Ofs(depth, delta(depth, b"x")));
+        }
+        let names: Vec<ObjectId> = (0..entries.len()).map(fake_id).collect();
+        let test = build("deep", &entries, &names);
+
+        let error = read_all(&test.db, names[MAX_DELTA_DEPTH + 1]).unwrap_err();
+        assert!(format!("{error:#}").contains("deeper"), "{error:#}");
+        // the ids are made up, read past the hash check
+        let mut file = test.db.open_pack().unwrap();
+        let offset = test.db.offset_of_raw(&names[MAX_DELTA_DEPTH]).unwrap();
+        let (_, content) = test.db.read_at(&mut file, offset).unwrap();
+        assert_eq!(content, vec![b'x'; MAX_DELTA_DEPTH]);
+    }
+
+    #[test]
+    fn truncated_pack_is_an_error() {
+        let memory = MemoryDb::new(ObjectFormat::Sha1);
+        let content = b"some content that is long enough\n".to_vec();
+        let id = Object::blob(content.clone()).write_to(&memory).unwrap();
+        let (pack, offsets) = pack_bytes(&[Raw::Base(OBJ_BLOB, content)]);
+        let idx = idx_bytes(&[id], &offsets);
+        for len in [12, 13, pack.len() - 30] {
+            let test = write_pack(&format!("truncated-{len}"), &pack[..len], &idx);
+            assert!(read_all(&test.db, id).is_err());
+        }
+    }
+
+    // NOTE: a header can claim any size, it is an error once the data is not there and
+    // a size that doesn't fit in 64 bits is an error right away
+    #[test]
+    fn huge_sizes_are_errors() {
+        let (pack, offsets) = pack_bytes(&[Raw::Base(OBJ_BLOB, b"tiny".to_vec())]);
+        let idx = idx_bytes(&[fake_id(1)], &offsets);
+        let start = offsets[0] as usize;
+
+        let mut lying = pack[..start].to_vec();
+        lying.extend(entry_header(OBJ_BLOB, 1 << 60));
+        lying.extend(deflate(b"tiny"));
+        let test = write_pack("huge", &lying, &idx);
+        assert!(read_all(&test.db, fake_id(1)).is_err());
+
+        let mut overflow = pack[..start].to_vec();
+        overflow.extend([0xb0]);
+        overflow.extend([0xff; 12]);
+        overflow.push(0x01);
+        let test = write_pack("overflow", &overflow, &idx);
+        let error = read_all(&test.db, fake_id(1)).unwrap_err();
+        assert!(format!("{error:#}").contains("too big"), "{error:#}");
+
+        let mut far = pack[..start].to_vec();
+        far.extend(entry_header(OBJ_OFS_DELTA, 1));
+        far.extend([0xff; 12]);
+        far.push(0x01);
+        let test = write_pack("far", &far, &idx);
+        let error = read_all(&test.db, fake_id(1)).unwrap_err();
+        assert!(format!("{error:#}").contains("too far"), "{error:#}");
+
+        // result size of 2^42, only 0 bytes come
+        assert!(apply_delta(b"", &[0x00, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f]).is_err());
+        let mut delta = vec![0x00];
+        delta.extend([0xff; 10]);
+        delta.push(0x01);
+        let error = apply_delta(b"", &delta).unwrap_err();
+        assert!(format!("{error:#}").contains("too big"), "{error:#}");
+    }
+
+    #[test]
+    fn truncated_index_is_an_error() {
+        let (pack, offsets) = pack_bytes(&[Raw::Base(OBJ_BLOB, b"a".to_vec())]);
+        let idx = idx_bytes(&[fake_id(1)], &offsets);
+        let dir = std::env::temp_dir().join(format!("git-rs-pack-idx-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("pack-test.pack"), &pack).unwrap();
+        let path = dir.join("pack-test.idx");
+
+        // the fanout says 2^32 - 1 objects, the names are not there
+        let mut lying = idx.clone();
+        let last = 8 + 255 * 4;
+        lying[last..last + 4].copy_from_slice(&u32::MAX.to_be_bytes());
+        fs::write(&path, &lying).unwrap();
+        assert!(PackDb::open(&path, ObjectFormat::Sha1).is_err());
+        for len in [3, 100, idx.len() - 1] {
+            fs::write(&path, &idx[..len]).unwrap();
+            assert!(PackDb::open(&path, ObjectFormat::Sha1).is_err(), "{len}");
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
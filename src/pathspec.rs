@@ -0,0 +1,17 @@
+// NOTE: the paths given to reset, restore, rm, ... (a small part of git pathspecs)
+// "f"      -> the file f
+// "dir"    -> every file under dir/ ("dir/" is the same)
+// "."      -> every file
+pub(crate) fn matches(pathspecs: &[String], path: &str) -> bool {
+    pathspecs.iter().any(|pathspec| matches_one(pathspec, path))
+}
+
+pub(crate) fn matches_one(pathspec: &str, path: &str) -> bool {
+    let pathspec = pathspec.trim_start_matches("./").trim_end_matches('/');
+    pathspec.is_empty()
+        || pathspec == "."
+        || path == pathspec
+        || path
+            .strip_prefix(pathspec)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
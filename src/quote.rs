@@ -0,0 +1,74 @@
+// NOTE: git print paths with unusual bytes in C style, between double quotes
+// "tab\there"  "new\nline"  "caf\303\251" (bytes above 0x7f as octal)
+// tools reading git output (mktree, update-index --index-info, ...) accept both forms
+pub(crate) fn unquote_c(text: &str) -> anyhow::Result<Vec<u8>> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        return Ok(text.as_bytes().to_vec());
+    };
+    let mut bytes = quoted.bytes();
+    let mut unquoted = Vec::new();
+    loop {
+        let Some(byte) = bytes.next() else {
+            anyhow::bail!("unterminated quoted path {text}");
+        };
+        match byte {
+            b'"' => break,
+            b'\\' => {
+                let escaped = bytes.next().unwrap_or(b'?');
+                unquoted.push(match escaped {
+                    b'a' => 7,
+                    b'b' => 8,
+                    b't' => b'\t',
+                    b'n' => b'\n',
+                    b'v' => 11,
+                    b'f' => 12,
+                    b'r' => b'\r',
+                    b'"' | b'\\' => escaped,
+                    b'0'..=b'3' => {
+                        let mut value = escaped - b'0';
+                        for _ in 0..2 {
+                            match bytes.next() {
+                                Some(digit @ b'0'..=b'7') => value = value * 8 + (digit - b'0'),
+                                _ => anyhow::bail!("bad octal escape in quoted path {text}"),
+                            }
+                        }
+                        // NOTE: a NUL can't be in a path, it would end the name in a tree
+                        anyhow::ensure!(value != 0, "NUL in quoted path {text}");
+                        value
+                    }
+                    _ => anyhow::bail!("bad escape in quoted path {text}"),
+                });
+            }
+            _ => unquoted.push(byte),
+        }
+    }
+    anyhow::ensure!(bytes.next().is_none(), "garbage after quoted path {text}");
+    Ok(unquoted)
+}
+
+// NOTE: the other way, names are quoted only when needed ("a b" stay a b)
+// control chars, '"', '\' and (core.quotePath, default on) every byte above 0x7f
+pub(crate) fn quote_c(name: &[u8]) -> String {
+    let needs_quote = |b: u8| b < 0x20 || b == b'"' || b == b'\\' || b >= 0x7f;
+    if !name.iter().any(|&b| needs_quote(b)) {
+        return String::from_utf8_lossy(name).into_owned();
+    }
+    let mut quoted = String::from("\"");
+    for &byte in name {
+        match byte {
+            7 => quoted.push_str("\\a"),
+            8 => quoted.push_str("\\b"),
+            b'\t' => quoted.push_str("\\t"),
+            b'\n' => quoted.push_str("\\n"),
+            11 => quoted.push_str("\\v"),
+            12 => quoted.push_str("\\f"),
+            b'\r' => quoted.push_str("\\r"),
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            _ if needs_quote(byte) => quoted.push_str(&format!("\\{byte:03o}")),
+            _ => quoted.push(byte as char),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
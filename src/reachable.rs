@@ -0,0 +1,127 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commit_graph::CommitGraph;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::index::Index;
+use crate::objects::Kind;
+use crate::odb::ObjectDatabase;
+use crate::refs::{self, Head};
+
+// NOTE: everything the user can still get back to, the same set git prune keep
+// HEAD, every ref, every reflog entry and every blob in the index
+// (git add write the blob before the commit, it must survive a prune)
+pub(crate) fn roots(git_dir: &Path) -> anyhow::Result<Vec<ObjectId>> {
+    let mut hashes: Vec<String> = refs::list(git_dir, "refs/")?
+        .into_iter()
+        .map(|(_, hash)| hash)
+        .collect();
+    if let Head::Detached(hash) = refs::read_head(git_dir)? {
+        hashes.push(hash);
+    }
+    hashes.extend(
+        refs::reflog_entries(git_dir)?
+            .into_iter()
+            .map(|(_, hash)| hash),
+    );
+    let mut roots = hashes
+        .iter()
+        .map(|hash| ObjectId::from_hex(hash))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let index = Index::read(&git_dir.join("index"))?;
+    roots.extend(
+        index
+            .entries
+            .iter()
+            // submodule commit live in the other repository
+            .filter(|entry| entry.mode & 0o170000 != 0o160000)
+            .map(|entry| entry.hash),
+    );
+    Ok(roots)
+}
+
+// NOTE: follow every link from the roots
+// commit -> tree + parents, tree -> entries, tag -> object
+// a missing object is an error, we can't know what it was keeping alive
+// the commits in the commit-graph are not inflated, the graph has their tree and parents
+pub(crate) fn mark(
+    db: &dyn ObjectDatabase,
+    graph: Option<&CommitGraph>,
+    roots: Vec<ObjectId>,
+) -> anyhow::Result<HashSet<ObjectId>> {
+    let mut reachable = HashSet::new();
+    let mut queue = roots;
+    while let Some(hash) = queue.pop() {
+        if !reachable.insert(hash) {
+            continue;
+        }
+        let hex = hash.to_string();
+        if let Some(commit) = graph.map(|graph| graph.commit(hash)).transpose()?.flatten() {
+            anyhow::ensure!(db.contains(&hex), "missing object {hex}, run fsck");
+            queue.push(commit.tree);
+            queue.extend(commit.parents);
+            continue;
+        }
+        let (kind, _) = db
+            .read_header(&hex)
+            .with_context(|| format!("missing object {hex}, run fsck"))?;
+        if kind == Kind::Blob {
+            continue;
+        }
+        let mut content = Vec::new();
+        db.read(&hex)?
+            .reader
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read {kind} {hex}"))?;
+        queue.extend(links(kind, &content, db.format())?);
+    }
+    Ok(reachable)
+}
+
+// NOTE: objects this one point to, no validation here (that's fsck job)
+pub(crate) fn links(
+    kind: Kind,
+    content: &[u8],
+    format: ObjectFormat,
+) -> anyhow::Result<Vec<ObjectId>> {
+    let mut links = Vec::new();
+    match kind {
+        Kind::Blob => {}
+        // <mode> <name>\0<hash>
+        Kind::Tree => {
+            let mut rest = content;
+            while !rest.is_empty() {
+                let Some(nul) = rest.iter().position(|&b| b == 0) else {
+                    anyhow::bail!("corrupt tree entry");
+                };
+                let hash_end = nul + 1 + format.raw_len();
+                anyhow::ensure!(hash_end <= rest.len(), "corrupt tree entry");
+                // gitlink (submodule) mode start with 160
+                if !rest.starts_with(b"160") {
+                    links.push(ObjectId::from_bytes(&rest[nul + 1..hash_end])?);
+                }
+                rest = &rest[hash_end..];
+            }
+        }
+        // tree <hash> / parent <hash> / object <hash> in the header
+        Kind::Commit | Kind::Tag => {
+            for line in content
+                .split(|&b| b == b'\n')
+                .take_while(|line| !line.is_empty())
+            {
+                let hash = line
+                    .strip_prefix(b"tree ")
+                    .or_else(|| line.strip_prefix(b"parent "))
+                    .or_else(|| line.strip_prefix(b"object "));
+                if let Some(hash) = hash {
+                    let hash = std::str::from_utf8(hash).context("hash is not UTF-8")?;
+                    links.push(ObjectId::from_hex(hash)?);
+                }
+            }
+        }
+    }
+    Ok(links)
+}
@@ -0,0 +1,415 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commit::Signature;
+use crate::config::{Config, FSYNC_REFERENCE};
+use crate::hooks::Hooks;
+use crate::lockfile::LockFile;
+
+// NOTE: refs are just files with the 40 char hash inside
+// .git/HEAD              -> "ref: refs/heads/main\n" (symbolic) or a hash (detached)
+// .git/refs/heads/main   -> hash of the last commit on main
+// .git/refs/tags/v1      -> hash of the tag/commit
+// .git/packed-refs       -> "<hash> <refname>" per line, written by git gc/clone
+//                           "^<hash>" line after a tag is the peeled commit, we skip it
+// loose ref always win over the packed one with the same name
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Head {
+    Symbolic(String),
+    Detached(String),
+}
+
+pub(crate) fn read_head(git_dir: &Path) -> anyhow::Result<Head> {
+    let head = fs::read_to_string(git_dir.join("HEAD")).context("Failed to read the head")?;
+    match head.strip_prefix("ref: ") {
+        Some(name) => Ok(Head::Symbolic(name.trim().to_string())),
+        None => Ok(Head::Detached(head.trim().to_string())),
+    }
+}
+
+// NOTE: the rules of git check-ref-format, a ref name is a path under .git so a name that
+// came from somewhere else (packed-refs of a remote, a refspec) could go anywhere
+// "refs/heads/../../../x" must never be joined to the git dir
+// no empty component (leading, trailing or double "/"), no component starting with "."
+// or ending with ".lock", no "..", "@{", control char, space or any of ~^:?*[\
+// a lone "@" is not a name either, one level names (HEAD, main) are fine
+pub(crate) fn check_refname_format(name: &str) -> anyhow::Result<()> {
+    let valid = name != "@"
+        && !name.contains("..")
+        && !name.contains("@{")
+        && !name.ends_with('.')
+        && !name
+            .bytes()
+            .any(|b| b < 0x20 || b == 0x7f || b" ~^:?*[\\".contains(&b))
+        && name.split('/').all(|component| {
+            !component.is_empty() && !component.starts_with('.') && !component.ends_with(".lock")
+        });
+    anyhow::ensure!(valid, "'{}' is not a valid ref name", name.escape_debug());
+    Ok(())
+}
+
+// NOTE: hash the ref point to, following the "ref: " chain
+// None when the ref does not exist yet (for example main before the first commit)
+// or can't exist (a name check_refname_format refuse)
+pub(crate) fn resolve(git_dir: &Path, name: &str) -> anyhow::Result<Option<String>> {
+    let mut name = name.to_string();
+    // same limit as git, symbolic refs pointing to each other in a loop would never end
+    for _ in 0..5 {
+        if check_refname_format(&name).is_err() {
+            return Ok(None);
+        }
+        let value = match fs::read_to_string(git_dir.join(&name)) {
+            Ok(value) => value,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(read_packed(git_dir)?
+                    .into_iter()
+                    .find(|(packed, _)| *packed == name)
+                    .map(|(_, hash)| hash));
+            }
+            Err(e) => return Err(e).with_context(|| format!("Failed to read the ref {name}")),
+        };
+        match value.strip_prefix("ref: ") {
+            Some(target) => name = target.trim().to_string(),
+            None => return Ok(Some(value.trim().to_string())),
+        }
+    }
+    anyhow::bail!("ref {name} is a symbolic ref pointing too deep")
+}
+
+// NOTE: the reference-transaction hook see the update before ("prepared", it can refuse it,
+// then it gets "aborted") and after ("committed") it is written, a new ref has the all zero hash as old value
+// the message goes to the reflog of the ref, and to the one of HEAD when HEAD points to it
+// ("commit: subject" is in both logs/HEAD and logs/refs/heads/main)
+// the new value goes to <ref>.lock first and is renamed into place
+pub(crate) fn update(git_dir: &Path, name: &str, hash: &str, message: &str) -> anyhow::Result<()> {
+    check_refname_format(name)
+        .with_context(|| format!("refusing to update ref with bad name '{name}'"))?;
+    let old = resolve(git_dir, name)?.unwrap_or_else(|| "0".repeat(hash.len()));
+    transaction(git_dir, name, &old, hash, || {
+        let path = git_dir.join(name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create dir for {name}"))?;
+        }
+        let fsync = Config::load_from(&git_dir.join("config"))?.fsync(FSYNC_REFERENCE)?;
+        LockFile::acquire(&path)?
+            .commit(format!("{hash}\n").as_bytes(), fsync)
+            .with_context(|| format!("Failed to update the ref {name}"))
+    })?;
+    append_reflog(git_dir, name, &old, hash, message)?;
+    if name != "HEAD" && matches!(read_head(git_dir), Ok(Head::Symbolic(head)) if head == name) {
+        append_reflog(git_dir, "HEAD", &old, hash, message)?;
+    }
+    Ok(())
+}
+
+// NOTE: the ref goes away (loose, packed and its reflog), the new value the hook see is all zero
+pub(crate) fn delete(git_dir: &Path, name: &str) -> anyhow::Result<()> {
+    check_refname_format(name)
+        .with_context(|| format!("refusing to delete ref with bad name '{name}'"))?;
+    let Some(old) = resolve(git_dir, name)? else {
+        return Ok(());
+    };
+    transaction(git_dir, name, &old, &"0".repeat(old.len()), || {
+        match fs::remove_file(git_dir.join(name)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e).with_context(|| format!("Failed to delete the ref {name}"));
+            }
+            _ => {}
+        }
+        let packed = read_packed(git_dir)?;
+        if packed.iter().any(|(packed, _)| packed == name) {
+            remove_packed(git_dir, name)?;
+        }
+        write_reflog(git_dir, name, &[])
+    })
+}
+
+fn transaction(
+    git_dir: &Path,
+    name: &str,
+    old: &str,
+    new: &str,
+    write: impl FnOnce() -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let hooks = Hooks::open(git_dir)?;
+    let transaction = format!("{old} {new} {name}\n");
+    if !hooks.run(
+        "reference-transaction",
+        &["prepared"],
+        transaction.as_bytes(),
+    )? {
+        hooks.run(
+            "reference-transaction",
+            &["aborted"],
+            transaction.as_bytes(),
+        )?;
+        anyhow::bail!("ref updates aborted by hook");
+    }
+    write()?;
+    hooks.run(
+        "reference-transaction",
+        &["committed"],
+        transaction.as_bytes(),
+    )?;
+    Ok(())
+}
+
+// NOTE: HEAD -> refs/heads/main, the reflog of HEAD gets the move to the commit of the target
+// ("checkout: moving from a to b"), nothing is logged while the target does not exist yet
+pub(crate) fn write_symbolic(
+    git_dir: &Path,
+    name: &str,
+    target: &str,
+    message: &str,
+) -> anyhow::Result<()> {
+    for name in [name, target] {
+        check_refname_format(name)
+            .with_context(|| format!("refusing to update ref with bad name '{name}'"))?;
+    }
+    let old = resolve(git_dir, name)?;
+    let path = git_dir.join(name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).with_context(|| format!("Failed to create dir for {name}"))?;
+    }
+    fs::write(&path, format!("ref: {target}\n"))
+        .with_context(|| format!("Failed to update the symbolic ref {name}"))?;
+    let Some(new) = resolve(git_dir, target)? else {
+        return Ok(());
+    };
+    let old = old.unwrap_or_else(|| "0".repeat(new.len()));
+    append_reflog(git_dir, name, &old, &new, message)
+}
+
+// NOTE: every ref under the prefix ("refs/" for all of them), loose and packed, sorted by name
+pub(crate) fn list(git_dir: &Path, prefix: &str) -> anyhow::Result<Vec<(String, String)>> {
+    let mut refs: Vec<(String, String)> = read_packed(git_dir)?
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(prefix))
+        .collect();
+    let mut loose = Vec::new();
+    collect_loose(git_dir, Path::new("refs"), &mut loose)?;
+    for name in loose {
+        if !name.starts_with(prefix) {
+            continue;
+        }
+        let Some(hash) = resolve(git_dir, &name)? else {
+            continue;
+        };
+        refs.retain(|(packed, _)| *packed != name);
+        refs.push((name, hash));
+    }
+    refs.sort();
+    Ok(refs)
+}
+
+fn collect_loose(git_dir: &Path, dir: &Path, refs: &mut Vec<String>) -> anyhow::Result<()> {
+    let entries = match fs::read_dir(git_dir.join(dir)) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", dir.display())),
+    };
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", dir.display()))?;
+        let name = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_loose(git_dir, &name, refs)?;
+        } else if let Some(name) = name.to_str() {
+            refs.push(name.to_string());
+        }
+    }
+    Ok(())
+}
+
+// the line of the ref and its "^<peeled>" line go, the rest of the file stays as it is
+fn remove_packed(git_dir: &Path, name: &str) -> anyhow::Result<()> {
+    let packed =
+        fs::read_to_string(git_dir.join("packed-refs")).context("Failed to read packed-refs")?;
+    let mut kept = String::new();
+    let mut removed = false;
+    for line in packed.lines() {
+        if line.starts_with('^') && removed {
+            continue;
+        }
+        removed = line
+            .split_once(' ')
+            .is_some_and(|(_, packed)| packed == name);
+        if !removed {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    fs::write(git_dir.join("packed-refs"), kept).context("Failed to write packed-refs")
+}
+
+fn read_packed(git_dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let packed = match fs::read_to_string(git_dir.join("packed-refs")) {
+        Ok(packed) => packed,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).context("Failed to read packed-refs"),
+    };
+    Ok(packed
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .map(|(hash, name)| (name.to_string(), hash.to_string()))
+        .collect())
+}
+
+// NOTE: .git/logs/HEAD and .git/logs/refs/... one line per update of the ref
+// <old hash> <new hash> <committer> <timestamp> <tz>\t<message>
+// old hash of the first entry is all zero, we skip it
+// return (ref name, hash) for every hash in every reflog
+pub(crate) fn reflog_entries(git_dir: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let mut hashes = Vec::new();
+    collect_reflogs(git_dir, Path::new(""), &mut hashes)?;
+    Ok(hashes)
+}
+
+// NOTE: one more line at the end of the reflog of the ref, the committer is who did it
+// core.logAllRefUpdates (true by default, false in a bare repo) starts a log for HEAD and the branches,
+// other refs only get a line when their log already exist, false never starts one
+// refs/stash always has one, the stash list is its reflog
+// the message is one line, runs of whitespace (tab, newline) become one space like git
+fn append_reflog(
+    git_dir: &Path,
+    name: &str,
+    old: &str,
+    new: &str,
+    message: &str,
+) -> anyhow::Result<()> {
+    let config = Config::load_from(&git_dir.join("config"))?;
+    let path = git_dir.join("logs").join(name);
+    let bare = config.get_bool("core.bare")?.unwrap_or(false);
+    let log_all = config.get_bool("core.logAllRefUpdates")?.unwrap_or(!bare);
+    let loggable = name == "HEAD"
+        || ["refs/heads/", "refs/remotes/", "refs/notes/"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix));
+    if !(path.exists() || log_all && loggable || name == "refs/stash") {
+        return Ok(());
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create dir for the reflog of {name}"))?;
+    }
+    let committer = Signature::committer(&config)?;
+    let mut log = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("Failed to open the reflog of {name}"))?;
+    let entry = ReflogEntry {
+        old: old.to_string(),
+        new: new.to_string(),
+        committer: committer.to_string(),
+        message: message
+            .split_ascii_whitespace()
+            .collect::<Vec<_>>()
+            .join(" "),
+    };
+    writeln!(log, "{entry}").with_context(|| format!("Failed to write the reflog of {name}"))
+}
+
+// NOTE: one update of a ref, a line of its reflog
+#[derive(Debug, Clone)]
+pub(crate) struct ReflogEntry {
+    pub(crate) old: String,
+    pub(crate) new: String,
+    // "Name <email> <time> <tz>"
+    pub(crate) committer: String,
+    pub(crate) message: String,
+}
+
+impl std::fmt::Display for ReflogEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {} {}\t{}",
+            self.old, self.new, self.committer, self.message
+        )
+    }
+}
+
+// NOTE: every update of one ref, the oldest first
+// no log for the ref is an empty list
+// the log is read as bytes, a name or a message that is not UTF-8 (an old git, another
+// encoding) is shown with U+FFFD and does not make the whole log unreadable
+pub(crate) fn reflog(git_dir: &Path, name: &str) -> anyhow::Result<Vec<ReflogEntry>> {
+    let log = match fs::read(git_dir.join("logs").join(name)) {
+        Ok(log) => log,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read the reflog of {name}")),
+    };
+    Ok(log
+        .split(|&b| b == b'\n')
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let line = String::from_utf8_lossy(line);
+            let (line, message) = line.split_once('\t').unwrap_or((&line, ""));
+            let mut fields = line.splitn(3, ' ');
+            Some(ReflogEntry {
+                old: fields.next()?.to_string(),
+                new: fields.next()?.to_string(),
+                committer: fields.next().unwrap_or("").to_string(),
+                message: message.to_string(),
+            })
+        })
+        .collect())
+}
+
+// NOTE: replace the whole reflog (stash drop), no entry left removes it
+pub(crate) fn write_reflog(
+    git_dir: &Path,
+    name: &str,
+    entries: &[ReflogEntry],
+) -> anyhow::Result<()> {
+    let path = git_dir.join("logs").join(name);
+    if entries.is_empty() {
+        return match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(e).with_context(|| format!("Failed to remove the reflog of {name}"))
+            }
+            _ => Ok(()),
+        };
+    }
+    let content: String = entries.iter().map(|entry| format!("{entry}\n")).collect();
+    fs::write(&path, content).with_context(|| format!("Failed to write the reflog of {name}"))
+}
+
+fn collect_reflogs(
+    git_dir: &Path,
+    dir: &Path,
+    hashes: &mut Vec<(String, String)>,
+) -> anyhow::Result<()> {
+    let logs = git_dir.join("logs").join(dir);
+    let entries = match fs::read_dir(&logs) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", logs.display())),
+    };
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read {}", logs.display()))?;
+        let name = dir.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            collect_reflogs(git_dir, &name, hashes)?;
+            continue;
+        }
+        let log = fs::read(entry.path())
+            .with_context(|| format!("Failed to read the reflog {}", name.display()))?;
+        for line in log.split(|&b| b == b'\n') {
+            for hash in line.split(|&b| b == b' ').take(2) {
+                if !hash.is_empty() && hash.iter().any(|&b| b != b'0') {
+                    hashes.push((
+                        name.display().to_string(),
+                        String::from_utf8_lossy(hash).into_owned(),
+                    ));
+                }
+            }
+        }
+    }
+    Ok(())
+}
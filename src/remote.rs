@@ -0,0 +1,214 @@
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use crate::ancestry::Ancestry;
+use crate::commands::clone::find_git_dir;
+use crate::config::Config;
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{Abbrev, ObjectDatabase};
+use crate::reachable;
+use crate::refs::{self, Head};
+
+// NOTE: the other repository is on the same disk, the url is a path or file://<path>
+// [remote "origin"]
+//     url = /some/where/project
+//     fetch = +refs/heads/*:refs/remotes/origin/*
+// a path given instead of a remote name works too, it just has no fetch refspec
+pub(crate) struct Remote {
+    pub(crate) name: String,
+    pub(crate) url: String,
+    pub(crate) git_dir: PathBuf,
+    pub(crate) fetch: Vec<Refspec>,
+}
+
+impl Remote {
+    pub(crate) fn open(config: &Config, name: &str) -> anyhow::Result<Self> {
+        let url = config.get(&format!("remote.{name}.url")).unwrap_or(name);
+        let fetch = config
+            .get_all(&format!("remote.{name}.fetch"))
+            .into_iter()
+            .map(Refspec::parse)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let path = url.strip_prefix("file://").unwrap_or(url);
+        let git_dir = find_git_dir(Path::new(path))
+            .map_err(|_| anyhow::anyhow!("'{name}' does not appear to be a git repository"))?;
+        Ok(Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            git_dir,
+            fetch,
+        })
+    }
+
+    // refs/heads/main -> refs/remotes/origin/main, through the fetch refspecs
+    pub(crate) fn tracking_ref(&self, name: &str) -> Option<String> {
+        self.fetch.iter().find_map(|refspec| refspec.map(name))
+    }
+}
+
+// NOTE: branch.<current>.remote, "origin" when the branch has none (or HEAD is detached)
+pub(crate) fn default_name(git_dir: &Path, config: &Config) -> anyhow::Result<String> {
+    if let Head::Symbolic(name) = refs::read_head(git_dir)?
+        && let Some(branch) = name.strip_prefix("refs/heads/")
+        && let Some(remote) = config.get(&format!("branch.{branch}.remote"))
+    {
+        return Ok(remote.to_string());
+    }
+    Ok("origin".to_string())
+}
+
+// NOTE: [+]<src>:<dst>
+// +refs/heads/*:refs/remotes/origin/*   every branch, the "*" part is kept, forced
+// main:refs/heads/x                     one ref to another name
+// main                                  no dst (fetch: only FETCH_HEAD, push: the same name)
+// :refs/heads/x                         empty src, push deletes the ref
+#[derive(Debug, Clone)]
+pub(crate) struct Refspec {
+    pub(crate) force: bool,
+    pub(crate) src: String,
+    pub(crate) dst: Option<String>,
+}
+
+impl Refspec {
+    pub(crate) fn parse(refspec: &str) -> anyhow::Result<Self> {
+        let (force, rest) = match refspec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, refspec),
+        };
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, dst)) => (src, Some(dst.to_string()).filter(|dst| !dst.is_empty())),
+            None => (rest, None),
+        };
+        let stars = |part: &str| part.matches('*').count();
+        anyhow::ensure!(
+            stars(src) <= 1
+                && dst.as_deref().is_none_or(|dst| stars(dst) == stars(src))
+                && (src.is_empty() || !src.contains("..")),
+            "invalid refspec '{refspec}'"
+        );
+        anyhow::ensure!(
+            !src.is_empty() || dst.is_some(),
+            "invalid refspec '{refspec}'"
+        );
+        // NOTE: dst (and a src pattern) are ref names, the "*" stands for a part of one
+        // a plain src can be any revision when pushing (HEAD~1), it's resolved later
+        let check = |part: &str| refs::check_refname_format(&part.replacen('*', "x", 1));
+        for part in dst
+            .iter()
+            .map(String::as_str)
+            .chain(src.contains('*').then_some(src))
+        {
+            check(part).with_context(|| format!("invalid refspec '{refspec}'"))?;
+        }
+        Ok(Self {
+            force,
+            src: src.to_string(),
+            dst,
+        })
+    }
+
+    pub(crate) fn is_pattern(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    // the dst name of a ref the src matches, None when it does not match (or there is no dst)
+    pub(crate) fn map(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_ref()?;
+        if !self.is_pattern() {
+            return (self.src == name).then(|| dst.clone());
+        }
+        let (prefix, suffix) = self.src.split_once('*')?;
+        let middle = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+        Some(dst.replacen('*', middle, 1))
+    }
+}
+
+// refs/heads/main -> main, refs/tags/v1 -> v1, refs/remotes/origin/main -> origin/main
+pub(crate) fn short_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+// "[new branch]" for a ref that did not exist before
+pub(crate) fn new_ref_kind(name: &str) -> &'static str {
+    if name.starts_with("refs/tags/") {
+        "[new tag]"
+    } else if name.starts_with("refs/heads/") || name.starts_with("refs/remotes/") {
+        "[new branch]"
+    } else {
+        "[new ref]"
+    }
+}
+
+// "1a2b3c4..5d6e7f8" for a fast-forward, three dots for a forced update
+pub(crate) fn range(abbrev: &Abbrev, old: &str, new: &str, forced: bool) -> String {
+    let dots = if forced { "..." } else { ".." };
+    format!("{}{dots}{}", abbrev.abbrev(old), abbrev.abbrev(new))
+}
+
+// NOTE: the old commit is in the history of the new one, anything that is not a commit
+// (an annotated tag for example) can only be forced
+pub(crate) fn is_fast_forward(
+    db: &dyn ObjectDatabase,
+    old: &str,
+    new: &str,
+) -> anyhow::Result<bool> {
+    for hash in [old, new] {
+        if !matches!(db.read_header(hash), Ok((Kind::Commit, _))) {
+            return Ok(false);
+        }
+    }
+    Ancestry::new(db)?.is_ancestor(ObjectId::from_hex(old)?, ObjectId::from_hex(new)?)
+}
+
+// NOTE: every object the wanted ones need that the destination does not have yet
+// the walk stops at an object the destination already has, a repository that has a
+// commit has its whole history too, so nothing under it can be missing
+pub(crate) fn missing_objects(
+    from: &dyn ObjectDatabase,
+    to: &dyn ObjectDatabase,
+    wants: &[ObjectId],
+) -> anyhow::Result<Vec<ObjectId>> {
+    let mut missing = Vec::new();
+    let mut seen = HashSet::new();
+    let mut queue = wants.to_vec();
+    while let Some(hash) = queue.pop() {
+        let hex = hash.to_string();
+        if !seen.insert(hash) || to.contains(&hex) {
+            continue;
+        }
+        let mut object = from
+            .read(&hex)
+            .with_context(|| format!("missing object {hex}"))?;
+        if object.kind != Kind::Blob {
+            let mut content = Vec::new();
+            object
+                .reader
+                .read_to_end(&mut content)
+                .with_context(|| format!("Failed to read {} {hex}", object.kind))?;
+            queue.extend(reachable::links(object.kind, &content, from.format())?);
+        }
+        missing.push(hash);
+    }
+    Ok(missing)
+}
+
+pub(crate) fn copy_objects(
+    from: &dyn ObjectDatabase,
+    to: &dyn ObjectDatabase,
+    objects: &[ObjectId],
+) -> anyhow::Result<()> {
+    for hash in objects {
+        let hex = hash.to_string();
+        from.read(&hex)?
+            .write_to(to)
+            .with_context(|| format!("Failed to copy object {hex}"))?;
+    }
+    Ok(())
+}
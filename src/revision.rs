@@ -0,0 +1,214 @@
+use std::io::BufRead;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commit::Commit;
+use crate::hash::ObjectId;
+use crate::objects::Kind;
+use crate::odb::{self, ObjectDatabase};
+use crate::refs;
+use crate::tree::Tree;
+
+// NOTE: revision names, a subset of git rev-parse
+// 3b18e512dba7...            -> full or short hash (at least 4 chars)
+// HEAD, main, v1, origin/main -> refs, tried in this order like git:
+//                              <name>, refs/<name>, refs/tags/<name>, refs/heads/<name>,
+//                              refs/remotes/<name>, refs/remotes/<name>/HEAD
+// @                          -> HEAD
+// rev^ rev^2 rev~3           -> first parent, second parent, third first-parent ancestor
+// rev^{tree} rev^{commit}    -> peel tags/commits until that kind, rev^{} peel tags only
+// rev:path/in/tree           -> the object at that path in the tree of rev
+// main@{1} HEAD@{2} @{1}     -> the value the ref had before, from its reflog
+pub(crate) fn resolve(
+    db: &dyn ObjectDatabase,
+    git_dir: &Path,
+    revision: &str,
+) -> anyhow::Result<ObjectId> {
+    resolve_inner(db, git_dir, revision)
+        .with_context(|| format!("Not a valid object name {revision}"))
+}
+
+fn resolve_inner(
+    db: &dyn ObjectDatabase,
+    git_dir: &Path,
+    revision: &str,
+) -> anyhow::Result<ObjectId> {
+    if let Some((revision, path)) = revision.split_once(':') {
+        let tree = peel(db, resolve_inner(db, git_dir, revision)?, Kind::Tree)?;
+        return lookup_path(db, tree, path);
+    }
+
+    let base_end = revision.find(['^', '~']).unwrap_or(revision.len());
+    let mut id = resolve_name(db, git_dir, &revision[..base_end])?;
+    let mut rest = &revision[base_end..];
+    while !rest.is_empty() {
+        if let Some(peeled) = rest.strip_prefix("^{") {
+            let Some((kind, after)) = peeled.split_once('}') else {
+                anyhow::bail!("missing '}}' in {revision}");
+            };
+            id = match kind {
+                "" => peel_tags(db, id)?,
+                "object" => id,
+                kind => peel(db, id, kind.parse()?)?,
+            };
+            rest = after;
+            continue;
+        }
+        let operator = rest.as_bytes()[0];
+        rest = &rest[1..];
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let number = match &rest[..digits] {
+            "" => 1,
+            number => number.parse::<usize>().context("number too big")?,
+        };
+        rest = &rest[digits..];
+        if operator == b'^' {
+            // rev^0 is the commit itself
+            id = peel(db, id, Kind::Commit)?;
+            if number > 0 {
+                let parents = parents(db, id)?;
+                let Some(parent) = parents.get(number - 1) else {
+                    anyhow::bail!("{id} has no parent number {number}");
+                };
+                id = *parent;
+            }
+        } else {
+            for _ in 0..number {
+                id = peel(db, id, Kind::Commit)?;
+                let Some(parent) = parents(db, id)?.first().copied() else {
+                    anyhow::bail!("{id} has no parent");
+                };
+                id = parent;
+            }
+        }
+    }
+    Ok(id)
+}
+
+fn resolve_name(db: &dyn ObjectDatabase, git_dir: &Path, name: &str) -> anyhow::Result<ObjectId> {
+    if let Some((name, nth)) = name
+        .strip_suffix('}')
+        .and_then(|name| name.rsplit_once("@{"))
+    {
+        return reflog_entry(git_dir, name, nth);
+    }
+    let name = if name.is_empty() || name == "@" {
+        "HEAD"
+    } else {
+        name
+    };
+    let is_hex = name.bytes().all(|b| b.is_ascii_hexdigit());
+    if is_hex && name.len() == db.format().hex_len() {
+        return ObjectId::from_hex(name);
+    }
+    if let Some(full_name) = dwim_ref(git_dir, name)?
+        && let Some(hash) = refs::resolve(git_dir, &full_name)?
+    {
+        return ObjectId::from_hex(&hash);
+    }
+    anyhow::ensure!(is_hex, "unknown revision {name}");
+    ObjectId::from_hex(&odb::resolve_prefix(db, name)?)
+}
+
+// NOTE: main@{2} is what main was two updates ago (the new value of that reflog line),
+// @{2} is about the current branch, HEAD@{2} about HEAD itself
+fn reflog_entry(git_dir: &Path, name: &str, nth: &str) -> anyhow::Result<ObjectId> {
+    let nth: usize = nth
+        .parse()
+        .with_context(|| format!("only @{{<number>}} is supported, not @{{{nth}}}"))?;
+    let full_name = match name {
+        "" => match refs::read_head(git_dir)? {
+            refs::Head::Symbolic(branch) => branch,
+            refs::Head::Detached(_) => "HEAD".to_string(),
+        },
+        name => dwim_ref(git_dir, name)?.with_context(|| format!("unknown revision {name}"))?,
+    };
+    let log = refs::reflog(git_dir, &full_name)?;
+    let Some(entry) = log.iter().rev().nth(nth) else {
+        anyhow::bail!("log for '{full_name}' only has {} entries", log.len());
+    };
+    ObjectId::from_hex(&entry.new)
+}
+
+// NOTE: full ref name of a short one ("main" -> "refs/heads/main"), None when nothing match
+pub(crate) fn dwim_ref(git_dir: &Path, name: &str) -> anyhow::Result<Option<String>> {
+    let candidates = if name.starts_with("refs/") {
+        vec![name.to_string()]
+    } else {
+        // HEAD, ORIG_HEAD, MERGE_HEAD, ... live right in .git, other names there are not refs
+        let is_pseudo_ref = name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_');
+        let mut candidates: Vec<String> = is_pseudo_ref
+            .then(|| name.to_string())
+            .into_iter()
+            .collect();
+        candidates.extend([
+            format!("refs/{name}"),
+            format!("refs/tags/{name}"),
+            format!("refs/heads/{name}"),
+            format!("refs/remotes/{name}"),
+            format!("refs/remotes/{name}/HEAD"),
+        ]);
+        candidates
+    };
+    for candidate in candidates {
+        if refs::resolve(git_dir, &candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+// NOTE: tag -> its object, commit -> its tree, until the object is of the wanted kind
+pub(crate) fn peel(db: &dyn ObjectDatabase, id: ObjectId, kind: Kind) -> anyhow::Result<ObjectId> {
+    let mut current = id;
+    loop {
+        let hex = current.to_string();
+        let (actual, _) = db.read_header(&hex)?;
+        if actual == kind {
+            return Ok(current);
+        }
+        current = match (actual, kind) {
+            (Kind::Tag, _) => tag_object(db, current)?,
+            (Kind::Commit, Kind::Tree) => Commit::read(db, &hex)?.tree,
+            _ => anyhow::bail!("object {id} does not point to a {kind}"),
+        };
+    }
+}
+
+fn peel_tags(db: &dyn ObjectDatabase, id: ObjectId) -> anyhow::Result<ObjectId> {
+    let mut current = id;
+    while db.read_header(&current.to_string())?.0 == Kind::Tag {
+        current = tag_object(db, current)?;
+    }
+    Ok(current)
+}
+
+fn parents(db: &dyn ObjectDatabase, commit: ObjectId) -> anyhow::Result<Vec<ObjectId>> {
+    Ok(Commit::read(db, &commit.to_string())?.parents)
+}
+
+// NOTE: first line of the tag object is "object <hash>"
+fn tag_object(db: &dyn ObjectDatabase, tag: ObjectId) -> anyhow::Result<ObjectId> {
+    let mut line = String::new();
+    db.read(&tag.to_string())?
+        .reader
+        .read_line(&mut line)
+        .with_context(|| format!("Failed to read the tag {tag}"))?;
+    let Some(object) = line.trim_end().strip_prefix("object ") else {
+        anyhow::bail!("tag {tag} does not start with an object");
+    };
+    ObjectId::from_hex(object)
+}
+
+fn lookup_path(db: &dyn ObjectDatabase, tree: ObjectId, path: &str) -> anyhow::Result<ObjectId> {
+    let mut id = tree;
+    for component in path.split('/').filter(|component| !component.is_empty()) {
+        let tree = Tree::read(db, &id.to_string())?;
+        let Some(entry) = tree.find(component.as_bytes()) else {
+            anyhow::bail!("path '{path}' does not exist");
+        };
+        id = entry.hash;
+    }
+    Ok(id)
+}
@@ -0,0 +1,75 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+use crate::ancestry::Ancestry;
+use crate::hash::ObjectId;
+use crate::odb::ObjectDatabase;
+
+// NOTE: walk of the history the way git log does it without --topo-order:
+// the newest commit of the ones waiting is the next one (committer time), same time is
+// the one pushed first. the caller push the parents it want to follow, every commit
+// comes out once. parents and times come from the commit-graph when there is one
+// (through Ancestry) so only the commits that get printed are inflated
+pub(crate) struct RevWalk<'a> {
+    ancestry: Ancestry<'a>,
+    queue: BinaryHeap<(i64, Reverse<usize>, ObjectId)>,
+    seen: HashSet<ObjectId>,
+    pushed: usize,
+}
+
+impl<'a> RevWalk<'a> {
+    pub(crate) fn new(db: &'a dyn ObjectDatabase) -> anyhow::Result<Self> {
+        Ok(Self {
+            ancestry: Ancestry::new(db)?,
+            queue: BinaryHeap::new(),
+            seen: HashSet::new(),
+            pushed: 0,
+        })
+    }
+
+    pub(crate) fn push(&mut self, commit: ObjectId) -> anyhow::Result<()> {
+        if self.seen.insert(commit) {
+            let time = self.ancestry.time(commit)?;
+            self.queue.push((time, Reverse(self.pushed), commit));
+            self.pushed += 1;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<ObjectId> {
+        self.queue.pop().map(|(_, _, commit)| commit)
+    }
+
+    pub(crate) fn ancestry(&mut self) -> &mut Ancestry<'a> {
+        &mut self.ancestry
+    }
+}
+
+// NOTE: "from..to", the commits of to that are not in from, newest first
+// (every ancestor of from is marked first, the walk does not go through them)
+pub(crate) fn range(
+    db: &dyn ObjectDatabase,
+    from: ObjectId,
+    to: ObjectId,
+) -> anyhow::Result<Vec<ObjectId>> {
+    let mut walk = RevWalk::new(db)?;
+    let mut excluded = HashSet::new();
+    let mut stack = vec![from];
+    while let Some(commit) = stack.pop() {
+        if excluded.insert(commit) {
+            stack.extend(walk.ancestry().parents(commit)?);
+        }
+    }
+    let mut commits = Vec::new();
+    walk.push(to)?;
+    while let Some(commit) = walk.pop() {
+        if excluded.contains(&commit) {
+            continue;
+        }
+        commits.push(commit);
+        for parent in walk.ancestry().parents(commit)? {
+            walk.push(parent)?;
+        }
+    }
+    Ok(commits)
+}
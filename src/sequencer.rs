@@ -0,0 +1,595 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::commands;
+use crate::commands::merge::{entries, move_head};
+use crate::commit::{self, Commit};
+use crate::config::Config;
+use crate::diff;
+use crate::hash::ObjectId;
+use crate::index::Index;
+use crate::merge::{self, ConflictStyle, Labels, MergeResult};
+use crate::objects::Kind;
+use crate::odb::{self, Abbrev, CompositeDb};
+use crate::refs;
+use crate::revision;
+use crate::revwalk;
+use crate::tree::{self, Tree};
+use crate::worktree;
+
+// NOTE: cherry-pick and revert, the commits are applied one after the other like the
+// git sequencer. every one of them is a three-way merge (crate::merge) with a known base:
+// pick    base = the parent of the commit, theirs = the commit
+// revert  base = the commit, theirs = its parent, so the change is undone
+// -m N    the parent N of a merge is the parent to use
+// the state is kept in .git/sequencer so the rest can go on after a conflict
+//   head          HEAD before the first commit, --abort goes back to it
+//   abort-safety  HEAD after the last commit made, --abort does not rewind when it moved
+//   todo          "pick <abbrev> <subject>" per line, the first one is the current one
+//   opts          [options] no-commit, record-origin, mainline, edit
+// a single commit has no sequencer, only the files of the conflict
+// a conflict stops with the markers in the files, .git/CHERRY_PICK_HEAD (REVERT_HEAD) and
+// .git/MERGE_MSG, fix the files, update-index them and --continue (or commit)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Action {
+    Pick,
+    Revert,
+}
+
+impl Action {
+    // the word in the todo file
+    fn name(self) -> &'static str {
+        match self {
+            Action::Pick => "pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    fn command(self) -> &'static str {
+        match self {
+            Action::Pick => "cherry-pick",
+            Action::Revert => "revert",
+        }
+    }
+
+    fn head_file(self) -> &'static str {
+        match self {
+            Action::Pick => "CHERRY_PICK_HEAD",
+            Action::Revert => "REVERT_HEAD",
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Options {
+    pub(crate) no_commit: bool,
+    pub(crate) record_origin: bool,
+    pub(crate) mainline: Option<usize>,
+    pub(crate) edit: bool,
+}
+
+pub(crate) fn start(action: Action, options: Options, revisions: &[String]) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let dir = git_dir.join("sequencer");
+    let command = action.command();
+    anyhow::ensure!(
+        !dir.exists(),
+        "{command} is already in progress\nhint: try \"git {command} (--continue | --abort)\""
+    );
+    anyhow::ensure!(
+        options.mainline != Some(0),
+        "option 'mainline' expects a number greater than zero"
+    );
+    let db = odb::open()?;
+    let head = revision::resolve(&db, git_dir, "HEAD")
+        .with_context(|| format!("can't {command} into an empty head"))?;
+    let commits = commits(&db, git_dir, action, revisions)?;
+    anyhow::ensure!(!commits.is_empty(), "empty commit set passed");
+    // NOTE: one commit does not need the sequencer, CHERRY_PICK_HEAD is enough to go on
+    if let [commit] = commits.as_slice() {
+        return apply(&db, git_dir, action, *commit, options);
+    }
+
+    fs::create_dir_all(&dir).context("Failed to create .git/sequencer")?;
+    fs::write(dir.join("head"), format!("{head}\n")).context("Failed to write sequencer head")?;
+    fs::write(dir.join("abort-safety"), format!("{head}\n"))
+        .context("Failed to write sequencer abort-safety")?;
+    write_opts(&dir, options)?;
+    let todo: Vec<(Action, ObjectId)> = commits.into_iter().map(|c| (action, c)).collect();
+    write_todo(&db, &dir, &todo)?;
+    let result = run(&db, git_dir);
+    // nothing was done (dirty index, merge without -m...), no state to leave behind
+    if result.is_err()
+        && !git_dir.join("MERGE_MSG").exists()
+        && read_todo(&db, git_dir, &dir)?.len() == todo.len()
+    {
+        fs::remove_dir_all(&dir).context("Failed to remove .git/sequencer")?;
+    }
+    result
+}
+
+// NOTE: --continue, the stopped commit is committed first when it is still waiting
+// (CHERRY_PICK_HEAD), it's skipped from the todo when it was committed by hand
+pub(crate) fn resume() -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let dir = git_dir.join("sequencer");
+    let stopped = stopped(git_dir);
+    anyhow::ensure!(
+        dir.exists() || stopped.is_some(),
+        "no cherry-pick or revert in progress"
+    );
+    let db = odb::open()?;
+    if let Some(action) = stopped {
+        commands::commit::invoke(commands::commit::Options {
+            reflog_action: Some(action.command().to_string()),
+            ..Default::default()
+        })?;
+        summary(&db, git_dir, true)?;
+    }
+    if !dir.exists() {
+        return Ok(());
+    }
+    let head = revision::resolve(&db, git_dir, "HEAD")?;
+    let mut todo = read_todo(&db, git_dir, &dir)?;
+    // not committed and nothing done (it failed before the merge): tried again
+    let done =
+        stopped.is_some() || head != abort_safety(&dir)? || git_dir.join("MERGE_MSG").exists();
+    if done && !todo.is_empty() {
+        todo.remove(0);
+    }
+    write_todo(&db, &dir, &todo)?;
+    fs::write(dir.join("abort-safety"), format!("{head}\n"))
+        .context("Failed to write sequencer abort-safety")?;
+    run(&db, git_dir)
+}
+
+// NOTE: --skip, the index and the files go back to HEAD (the files the commit did not
+// touch keep their changes) and the next commits are applied
+pub(crate) fn skip(action: Action) -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let dir = git_dir.join("sequencer");
+    anyhow::ensure!(
+        dir.exists() || git_dir.join(action.head_file()).exists(),
+        "no {} in progress",
+        action.command()
+    );
+    let db = odb::open()?;
+    let head = revision::resolve(&db, git_dir, "HEAD")?;
+    reset_merge(&db, git_dir, head)?;
+    remove_stopped(git_dir);
+    if !dir.exists() {
+        return Ok(());
+    }
+    let mut todo = read_todo(&db, git_dir, &dir)?;
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    write_todo(&db, &dir, &todo)?;
+    run(&db, git_dir)
+}
+
+// NOTE: --abort, back to the HEAD before the first commit, unless HEAD was moved by
+// something else since the last commit we made (then only the state is removed)
+// a single commit just goes back to HEAD
+pub(crate) fn abort() -> anyhow::Result<()> {
+    let git_dir = Path::new(".git");
+    let dir = git_dir.join("sequencer");
+    anyhow::ensure!(
+        dir.exists() || stopped(git_dir).is_some(),
+        "no cherry-pick or revert in progress"
+    );
+    let db = odb::open()?;
+    let head = revision::resolve(&db, git_dir, "HEAD")?;
+    if !dir.exists() {
+        reset_merge(&db, git_dir, head)?;
+        remove_stopped(git_dir);
+        return Ok(());
+    }
+    let original = fs::read_to_string(dir.join("head")).context("Failed to read sequencer head")?;
+    let original = ObjectId::from_hex(original.trim())?;
+    if head != abort_safety(&dir)? {
+        eprintln!("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!");
+    } else {
+        reset_merge(&db, git_dir, original)?;
+        let message = format!("reset: moving to {original}");
+        move_head(git_dir, &refs::read_head(git_dir)?, &original, &message)?;
+    }
+    remove_stopped(git_dir);
+    fs::remove_dir_all(&dir).context("Failed to remove .git/sequencer")
+}
+
+// a commit is waiting for its conflicts to be resolved, the command that stopped on it
+fn stopped(git_dir: &Path) -> Option<Action> {
+    [Action::Pick, Action::Revert]
+        .into_iter()
+        .find(|action| git_dir.join(action.head_file()).exists())
+}
+
+fn remove_stopped(git_dir: &Path) {
+    for file in ["CHERRY_PICK_HEAD", "REVERT_HEAD", "MERGE_MSG"] {
+        let _ = fs::remove_file(git_dir.join(file));
+    }
+}
+
+// the todo, one commit at a time, the state is removed when it's all done
+fn run(db: &CompositeDb, git_dir: &Path) -> anyhow::Result<()> {
+    let dir = git_dir.join("sequencer");
+    let options = read_opts(&dir)?;
+    loop {
+        let mut todo = read_todo(db, git_dir, &dir)?;
+        let Some(&(action, commit)) = todo.first() else {
+            return fs::remove_dir_all(&dir).context("Failed to remove .git/sequencer");
+        };
+        apply(db, git_dir, action, commit, options)?;
+        todo.remove(0);
+        write_todo(db, &dir, &todo)?;
+        let head = revision::resolve(db, git_dir, "HEAD")?;
+        fs::write(dir.join("abort-safety"), format!("{head}\n"))
+            .context("Failed to write sequencer abort-safety")?;
+    }
+}
+
+// NOTE: one pick (or revert), the commit is made with the author of the picked commit
+// with -n the result is only in the index and the files, .git/MERGE_MSG has the message
+fn apply(
+    db: &CompositeDb,
+    git_dir: &Path,
+    action: Action,
+    id: ObjectId,
+    options: Options,
+) -> anyhow::Result<()> {
+    let command = action.command();
+    let hex = id.to_string();
+    let Merged {
+        commit,
+        short,
+        subject,
+        parent,
+        head_tree,
+        result,
+    } = merge_commit(db, git_dir, action, id, options)?;
+    for message in &result.messages {
+        println!("{message}");
+    }
+
+    let mut message = match action {
+        Action::Pick if options.record_origin => commit::add_signoff(
+            &commit.message_text(),
+            &format!("(cherry picked from commit {hex})"),
+        ),
+        Action::Pick => commit.message_text(),
+        Action::Revert => {
+            let mut message = format!("Revert \"{subject}\"\n\nThis reverts commit {hex}");
+            if let (true, Some(parent)) = (commit.parents.len() > 1, parent) {
+                message.push_str(&format!(", reversing\nchanges made to {parent}"));
+            }
+            message.push_str(".\n");
+            message
+        }
+    };
+    let head_file = git_dir.join(action.head_file());
+    if !result.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in &result.conflicts {
+            message.push_str(&format!("#\t{path}\n"));
+        }
+        fs::write(git_dir.join("MERGE_MSG"), &message).context("Failed to write MERGE_MSG")?;
+        if !options.no_commit {
+            fs::write(&head_file, format!("{hex}\n"))
+                .with_context(|| format!("Failed to write {}", action.head_file()))?;
+        }
+        let verb = match action {
+            Action::Pick => "apply",
+            Action::Revert => "revert",
+        };
+        anyhow::bail!(
+            "could not {verb} {short}... {subject}\n\
+             hint: After resolving the conflicts, mark them with\n\
+             hint: \"git add/rm <pathspec>\", then run\n\
+             hint: \"git {command} --continue\".\n\
+             hint: You can instead skip this commit with \"git {command} --skip\".\n\
+             hint: To abort and get back to the state before \"git {command}\",\n\
+             hint: run \"git {command} --abort\"."
+        );
+    }
+    if options.no_commit {
+        fs::write(git_dir.join("MERGE_MSG"), &message).context("Failed to write MERGE_MSG")?;
+        return Ok(());
+    }
+    let tree = result.tree(db)?;
+    if tree == head_tree {
+        fs::write(git_dir.join("MERGE_MSG"), &message).context("Failed to write MERGE_MSG")?;
+        fs::write(&head_file, format!("{hex}\n"))
+            .with_context(|| format!("Failed to write {}", action.head_file()))?;
+        anyhow::bail!(
+            "The previous {command} is now empty, possibly due to conflict resolution.\n\
+             If you wish to commit it anyway, use:\n\n    \
+             git commit --allow-empty\n\n\
+             Otherwise, please use 'git {command} --skip'"
+        );
+    }
+
+    let author = &commit.author;
+    let (author, date) = match action {
+        Action::Pick => (
+            Some(format!(
+                "{} <{}>",
+                commit.decode(&author.name),
+                commit.decode(&author.email)
+            )),
+            Some(format!("@{} {}", author.time, author.timezone)),
+        ),
+        Action::Revert => (None, None),
+    };
+    commands::commit::invoke(commands::commit::Options {
+        messages: vec![message],
+        author,
+        date,
+        edit: options.edit,
+        no_edit: !options.edit,
+        no_verify: true,
+        reflog_action: Some(action.command().to_string()),
+        ..Default::default()
+    })?;
+    summary(db, git_dir, true)
+}
+
+// NOTE: the three-way merge of a pick (or revert) with HEAD, the index and the files get
+// the result (with the conflicts), nothing is committed and nothing printed. rebase use it too
+pub(crate) struct Merged {
+    pub(crate) commit: Commit,
+    pub(crate) short: String,
+    pub(crate) subject: String,
+    // the parent used as the base (-m), None for a root commit
+    pub(crate) parent: Option<ObjectId>,
+    pub(crate) head_tree: ObjectId,
+    pub(crate) result: MergeResult,
+}
+
+pub(crate) fn merge_commit(
+    db: &CompositeDb,
+    git_dir: &Path,
+    action: Action,
+    id: ObjectId,
+    options: Options,
+) -> anyhow::Result<Merged> {
+    let config = Config::load()?;
+    let command = action.command();
+    let hex = id.to_string();
+    let short = Abbrev::new(db, 7)?.abbrev(&hex).to_string();
+    let commit = Commit::read(db, &hex)?;
+    let subject = commit.subject().to_string();
+    let parent = match (commit.parents.len(), options.mainline) {
+        (count, None) if count > 1 => {
+            anyhow::bail!("commit {hex} is a merge but no -m option was given.")
+        }
+        (count, Some(_)) if count <= 1 => {
+            anyhow::bail!("mainline was specified but commit {hex} is not a merge.")
+        }
+        (count, Some(mainline)) if mainline > count => {
+            anyhow::bail!("commit {hex} does not have parent {mainline}")
+        }
+        (_, Some(mainline)) => Some(commit.parents[mainline - 1]),
+        (_, None) => commit.parents.first().copied(),
+    };
+    let parent_tree = match parent {
+        Some(parent) => Commit::read(db, &parent.to_string())?.tree,
+        None => Tree::default().object().write_to(db)?,
+    };
+
+    let head = revision::resolve(db, git_dir, "HEAD")?;
+    let head_tree = Commit::read(db, &head.to_string())?.tree;
+    let head_files = tree::files(db, head_tree)?;
+    let index = Index::read(&git_dir.join("index"))?;
+    anyhow::ensure!(
+        index.entries.iter().all(|e| e.stage() == 0),
+        "{} is not possible because you have unmerged files.",
+        match action {
+            Action::Pick => "Cherry-picking",
+            Action::Revert => "Reverting",
+        }
+    );
+    let current = index.files();
+    // NOTE: with -n the staged changes are kept and the commit goes on top of them
+    let ours = if options.no_commit {
+        index.write_tree(db)?
+    } else {
+        anyhow::ensure!(
+            current == head_files,
+            "your local changes would be overwritten by {command}.\n\
+             hint: commit your changes or stash them to proceed."
+        );
+        head_tree
+    };
+
+    let this = format!("{short} ({subject})");
+    let parent_of = format!("parent of {short} ({subject})");
+    let (base, theirs, labels) = match action {
+        Action::Pick => (
+            parent_tree,
+            commit.tree,
+            Labels {
+                ours: "HEAD".to_string(),
+                base: parent_of,
+                theirs: this,
+            },
+        ),
+        Action::Revert => (
+            commit.tree,
+            parent_tree,
+            Labels {
+                ours: "HEAD".to_string(),
+                base: this,
+                theirs: parent_of,
+            },
+        ),
+    };
+    let style = match config.get("merge.conflictStyle") {
+        Some(style) => style.parse()?,
+        None => ConflictStyle::default(),
+    };
+    let result = merge::merge_trees(db, base, ours, theirs, labels, style)?;
+    worktree::update(db, &current, &result.entries, &result.contents, "merge")?;
+    Ok(Merged {
+        commit,
+        short,
+        subject,
+        parent,
+        head_tree,
+        result,
+    })
+}
+
+// NOTE: after "[main 1a2b3c4] subject" of the commit, its author date and the short stat
+pub(crate) fn summary(db: &CompositeDb, git_dir: &Path, date: bool) -> anyhow::Result<()> {
+    let made = Commit::read(db, &revision::resolve(db, git_dir, "HEAD")?.to_string())?;
+    if date {
+        println!(
+            " Date: {}",
+            commit::format_date(made.author.time, &made.author.timezone)
+        );
+    }
+    let parent_files = match made.parents.first() {
+        Some(parent) => tree::files(db, Commit::read(db, &parent.to_string())?.tree)?,
+        None => tree::Files::new(),
+    };
+    print!(
+        "{}",
+        diff::short_stat(db, &parent_files, &tree::files(db, made.tree)?)?
+    );
+    Ok(())
+}
+
+// NOTE: the commits of the arguments, "A..B" is every commit of B that is not in A,
+// the oldest first for a pick and the newest first for a revert (like git)
+fn commits(
+    db: &CompositeDb,
+    git_dir: &Path,
+    action: Action,
+    revisions: &[String],
+) -> anyhow::Result<Vec<ObjectId>> {
+    let resolve = |name: &str| {
+        let name = if name.is_empty() { "HEAD" } else { name };
+        revision::resolve(db, git_dir, name)
+            .and_then(|id| revision::peel(db, id, Kind::Commit))
+            .with_context(|| format!("bad revision '{name}'"))
+    };
+    let mut commits = Vec::new();
+    for name in revisions {
+        let Some((from, to)) = name.split_once("..") else {
+            commits.push(resolve(name)?);
+            continue;
+        };
+        let mut range = revwalk::range(db, resolve(from)?, resolve(to)?)?;
+        if action == Action::Pick {
+            range.reverse();
+        }
+        commits.extend(range);
+    }
+    Ok(commits)
+}
+
+// same as git reset --merge
+pub(crate) fn reset_merge(
+    db: &CompositeDb,
+    git_dir: &Path,
+    commit: ObjectId,
+) -> anyhow::Result<()> {
+    let files = tree::files(db, Commit::read(db, &commit.to_string())?.tree)?;
+    let index = Index::read(&git_dir.join("index"))?;
+    worktree::update(
+        db,
+        &index.files(),
+        &entries(&files),
+        &BTreeMap::new(),
+        "merge",
+    )
+}
+
+fn abort_safety(dir: &Path) -> anyhow::Result<ObjectId> {
+    let safety = fs::read_to_string(dir.join("abort-safety"))
+        .context("Failed to read sequencer abort-safety")?;
+    ObjectId::from_hex(safety.trim())
+}
+
+fn write_todo(db: &CompositeDb, dir: &Path, todo: &[(Action, ObjectId)]) -> anyhow::Result<()> {
+    let abbrev = Abbrev::new(db, 7)?;
+    let mut content = String::new();
+    for (action, commit) in todo {
+        let hex = commit.to_string();
+        let subject = Commit::read(db, &hex)?.subject();
+        content.push_str(&format!(
+            "{} {} {subject}\n",
+            action.name(),
+            abbrev.abbrev(&hex),
+        ));
+    }
+    fs::write(dir.join("todo"), content).context("Failed to write sequencer todo")
+}
+
+fn read_todo(
+    db: &CompositeDb,
+    git_dir: &Path,
+    dir: &Path,
+) -> anyhow::Result<Vec<(Action, ObjectId)>> {
+    let content = fs::read_to_string(dir.join("todo")).context("Failed to read sequencer todo")?;
+    let mut todo = Vec::new();
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let action = match words.next() {
+            Some("pick" | "p") => Action::Pick,
+            Some("revert") => Action::Revert,
+            _ => anyhow::bail!("invalid line {}: {line}", number + 1),
+        };
+        let commit = words
+            .next()
+            .and_then(|name| revision::resolve(db, git_dir, name).ok())
+            .and_then(|id| revision::peel(db, id, Kind::Commit).ok())
+            .with_context(|| format!("invalid line {}: {line}", number + 1))?;
+        todo.push((action, commit));
+    }
+    Ok(todo)
+}
+
+// no file at all without options, like git
+fn write_opts(dir: &Path, options: Options) -> anyhow::Result<()> {
+    let mut content = String::new();
+    if options.no_commit {
+        content.push_str("\tno-commit = true\n");
+    }
+    if options.record_origin {
+        content.push_str("\trecord-origin = true\n");
+    }
+    if let Some(mainline) = options.mainline {
+        content.push_str(&format!("\tmainline = {mainline}\n"));
+    }
+    if options.edit {
+        content.push_str("\tedit = true\n");
+    }
+    if content.is_empty() {
+        return Ok(());
+    }
+    content.insert_str(0, "[options]\n");
+    fs::write(dir.join("opts"), content).context("Failed to write sequencer opts")
+}
+
+fn read_opts(dir: &Path) -> anyhow::Result<Options> {
+    let opts = Config::load_from(&dir.join("opts"))?;
+    Ok(Options {
+        no_commit: opts.get_bool("options.no-commit")?.unwrap_or(false),
+        record_origin: opts.get_bool("options.record-origin")?.unwrap_or(false),
+        mainline: opts
+            .get("options.mainline")
+            .map(str::parse)
+            .transpose()
+            .context("bad mainline in sequencer opts")?,
+        edit: opts.get_bool("options.edit")?.unwrap_or(false),
+    })
+}
@@ -0,0 +1,343 @@
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{Cursor, Read};
+
+use anyhow::Context;
+
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::{Kind, Object};
+use crate::odb::ObjectDatabase;
+
+// NOTE: tree object content
+// <mode> <name>\0<raw hash>
+// <mode> <name>\0<raw hash>
+// (the raw hash is 20 bytes, 32 bytes in the sha256 repo)
+// mode is octal without leading zero: 40000 tree, 100644 file, 100755 executable,
+// 120000 symlink, 160000 submodule commit
+// entries are sorted by name bytes, but a tree sort as if its name ended with '/'
+// ("a.txt" < "a/" < "a0"), no name can be there twice
+// parse is strict (it's for reading trees we are going to use), fsck use the lenient
+// checks in validate.rs to report what is wrong instead
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TreeEntry {
+    pub(crate) mode: u32,
+    pub(crate) name: Vec<u8>,
+    pub(crate) hash: ObjectId,
+}
+
+impl TreeEntry {
+    pub(crate) fn is_tree(&self) -> bool {
+        self.mode == 0o40000
+    }
+
+    // NOTE: the kind comes from the mode, no need to read the object (it may not even be here)
+    pub(crate) fn kind(&self) -> Kind {
+        kind_of_mode(self.mode)
+    }
+
+    pub(crate) fn name_str(&self) -> anyhow::Result<&str> {
+        std::str::from_utf8(&self.name).context("tree entry name is not UTF-8")
+    }
+}
+
+pub(crate) fn kind_of_mode(mode: u32) -> Kind {
+    match mode {
+        0o40000 => Kind::Tree,
+        0o160000 => Kind::Commit,
+        _ => Kind::Blob,
+    }
+}
+
+// 100664 was written by very old git, still accepted
+pub(crate) fn is_valid_mode(mode: u32) -> bool {
+    matches!(
+        mode,
+        0o40000 | 0o100644 | 0o100755 | 0o100664 | 0o120000 | 0o160000
+    )
+}
+
+// NOTE: git order of the entries (base_name_compare)
+pub(crate) fn compare(a: &TreeEntry, b: &TreeEntry) -> Ordering {
+    let a_name = a.name.iter().chain(a.is_tree().then_some(&b'/'));
+    let b_name = b.name.iter().chain(b.is_tree().then_some(&b'/'));
+    a_name.cmp(b_name)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub(crate) struct Tree {
+    pub(crate) entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub(crate) fn read(db: &dyn ObjectDatabase, hash: &str) -> anyhow::Result<Tree> {
+        let mut object = db.read(hash)?;
+        anyhow::ensure!(
+            object.kind == Kind::Tree,
+            "{hash} is a {} not a tree",
+            object.kind
+        );
+        let mut content = Vec::with_capacity(object.expected_size as usize);
+        object
+            .reader
+            .read_to_end(&mut content)
+            .with_context(|| format!("Failed to read the tree {hash}"))?;
+        Tree::parse(&content, db.format()).with_context(|| format!("bad tree object {hash}"))
+    }
+
+    pub(crate) fn parse(content: &[u8], format: ObjectFormat) -> anyhow::Result<Tree> {
+        let mut entries: Vec<TreeEntry> = Vec::new();
+        let mut names = HashSet::new();
+        let mut rest = content;
+        while !rest.is_empty() {
+            let Some(space) = rest.iter().position(|&b| b == b' ') else {
+                anyhow::bail!("malformed mode in tree entry");
+            };
+            let Some(nul) = rest.iter().position(|&b| b == 0) else {
+                anyhow::bail!("truncated tree entry");
+            };
+            anyhow::ensure!(space < nul, "malformed mode in tree entry");
+            let hash_end = nul + 1 + format.raw_len();
+            anyhow::ensure!(hash_end <= rest.len(), "too-short tree object");
+
+            let mode_text = &rest[..space];
+            let mode = std::str::from_utf8(mode_text)
+                .ok()
+                .filter(|mode| !mode.starts_with('0'))
+                .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+                .filter(|&mode| is_valid_mode(mode));
+            let Some(mode) = mode else {
+                anyhow::bail!(
+                    "malformed mode in tree entry: {}",
+                    String::from_utf8_lossy(mode_text)
+                );
+            };
+            let name = &rest[space + 1..nul];
+            check_name(name)?;
+            let entry = TreeEntry {
+                mode,
+                name: name.to_vec(),
+                hash: ObjectId::from_bytes(&rest[nul + 1..hash_end])?,
+            };
+            rest = &rest[hash_end..];
+
+            anyhow::ensure!(
+                names.insert(name),
+                "duplicate entry '{}' in tree",
+                String::from_utf8_lossy(name)
+            );
+            if let Some(previous) = entries.last() {
+                anyhow::ensure!(
+                    compare(previous, &entry) == Ordering::Less,
+                    "tree entries not sorted: '{}' after '{}'",
+                    String::from_utf8_lossy(name),
+                    String::from_utf8_lossy(&previous.name)
+                );
+            }
+            entries.push(entry);
+        }
+        Ok(Tree { entries })
+    }
+
+    pub(crate) fn find(&self, name: &[u8]) -> Option<&TreeEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut content = Vec::new();
+        for entry in &self.entries {
+            content.extend(format!("{:o} ", entry.mode).as_bytes());
+            content.extend(&entry.name);
+            content.push(0);
+            content.extend(entry.hash.as_bytes());
+        }
+        content
+    }
+
+    pub(crate) fn object(&self) -> Object<impl Read> {
+        let content = self.to_bytes();
+        Object {
+            kind: Kind::Tree,
+            expected_size: content.len() as u64,
+            reader: Cursor::new(content),
+        }
+    }
+}
+
+// NOTE: every file of a tree with the sub trees flattened, by full path
+// "src/main.rs" -> (100644, hash), the trees themselves are not in it
+pub(crate) type Files = BTreeMap<String, (u32, ObjectId)>;
+
+pub(crate) fn files(db: &dyn ObjectDatabase, tree: ObjectId) -> anyhow::Result<Files> {
+    let mut files = Files::new();
+    collect_files(db, tree, "", &mut files)?;
+    Ok(files)
+}
+
+// NOTE: paths of the files that are not the same in the two trees (None = empty tree),
+// same list as git diff-tree -r --name-only, a sub tree with the same hash is skipped
+// without being read
+pub(crate) fn changed_paths(
+    db: &dyn ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+) -> anyhow::Result<Vec<String>> {
+    let mut paths = Vec::new();
+    collect_changes(db, old, new, "", &mut paths)?;
+    paths.sort_unstable();
+    Ok(paths)
+}
+
+fn collect_changes(
+    db: &dyn ObjectDatabase,
+    old: Option<ObjectId>,
+    new: Option<ObjectId>,
+    prefix: &str,
+    paths: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    if old == new {
+        return Ok(());
+    }
+    let read = |tree: Option<ObjectId>| -> anyhow::Result<BTreeMap<Vec<u8>, TreeEntry>> {
+        let Some(tree) = tree else {
+            return Ok(BTreeMap::new());
+        };
+        Ok(Tree::read(db, &tree.to_string())?
+            .entries
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect())
+    };
+    let (old, mut new) = (read(old)?, read(new)?);
+    let mut changes: Vec<(Option<TreeEntry>, Option<TreeEntry>)> = Vec::new();
+    for (name, old_entry) in old {
+        changes.push((Some(old_entry), new.remove(&name)));
+    }
+    changes.extend(new.into_values().map(|new_entry| (None, Some(new_entry))));
+    for (old_entry, new_entry) in changes {
+        let entry = new_entry.as_ref().or(old_entry.as_ref()).unwrap();
+        let path = format!("{prefix}{}", entry.name_str()?);
+        if old_entry == new_entry {
+            continue;
+        }
+        // a file that became a directory (or the other way) is one path removed and
+        // the files under the directory added
+        let subtree =
+            |entry: &Option<TreeEntry>| entry.as_ref().filter(|e| e.is_tree()).map(|e| e.hash);
+        let is_file = |entry: &Option<TreeEntry>| entry.as_ref().is_some_and(|e| !e.is_tree());
+        if is_file(&old_entry) || is_file(&new_entry) {
+            paths.push(path.clone());
+        }
+        let (old_tree, new_tree) = (subtree(&old_entry), subtree(&new_entry));
+        if old_tree.is_some() || new_tree.is_some() {
+            collect_changes(db, old_tree, new_tree, &format!("{path}/"), paths)?;
+        }
+    }
+    Ok(())
+}
+
+// NOTE: mode and hash of what is at "dir/file" (or "dir") in the tree
+pub(crate) fn entry_at(
+    db: &dyn ObjectDatabase,
+    tree: ObjectId,
+    path: &str,
+) -> anyhow::Result<Option<(u32, ObjectId)>> {
+    let mut current = (0o40000, tree);
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        if current.0 != 0o40000 {
+            return Ok(None);
+        }
+        let tree = Tree::read(db, &current.1.to_string())?;
+        let Some(entry) = tree.find(name.as_bytes()) else {
+            return Ok(None);
+        };
+        current = (entry.mode, entry.hash);
+    }
+    Ok(Some(current))
+}
+
+fn collect_files(
+    db: &dyn ObjectDatabase,
+    tree: ObjectId,
+    prefix: &str,
+    files: &mut Files,
+) -> anyhow::Result<()> {
+    for entry in Tree::read(db, &tree.to_string())?.entries {
+        let path = format!("{prefix}{}", entry.name_str()?);
+        if entry.is_tree() {
+            collect_files(db, entry.hash, &format!("{path}/"), files)?;
+        } else {
+            files.insert(path, (entry.mode, entry.hash));
+        }
+    }
+    Ok(())
+}
+
+fn check_name(name: &[u8]) -> anyhow::Result<()> {
+    anyhow::ensure!(!name.is_empty(), "empty filename in tree entry");
+    anyhow::ensure!(
+        !name.contains(&b'/'),
+        "path {} contains slash",
+        String::from_utf8_lossy(name)
+    );
+    // NOTE: the name ends at the first NUL in the raw tree, one inside it corrupts the entry
+    anyhow::ensure!(
+        !name.contains(&0),
+        "path {} contains NUL",
+        String::from_utf8_lossy(name)
+    );
+    anyhow::ensure!(
+        name != b"." && name != b"..",
+        "invalid path '{}' in tree entry",
+        String::from_utf8_lossy(name)
+    );
+    // NOTE: checked out it would land inside the real .git (hooks, config), any case
+    // because .GIT is the same dir on a case-insensitive filesystem
+    anyhow::ensure!(
+        !name.eq_ignore_ascii_case(b".git"),
+        "invalid path '{}' in tree entry",
+        String::from_utf8_lossy(name)
+    );
+    Ok(())
+}
+
+// NOTE: entries can be added in any order, build() sort them the git way
+// let mut builder = TreeBuilder::default();
+// builder.insert(0o100644, "b.txt", hash)?;
+// builder.insert(0o40000, "a", subtree)?;
+// let tree = builder.build();   -> a, b.txt
+#[derive(Default)]
+pub(crate) struct TreeBuilder {
+    entries: Vec<TreeEntry>,
+    names: HashSet<Vec<u8>>,
+}
+
+impl TreeBuilder {
+    pub(crate) fn insert(
+        &mut self,
+        mode: u32,
+        name: impl Into<Vec<u8>>,
+        hash: ObjectId,
+    ) -> anyhow::Result<()> {
+        let name = name.into();
+        anyhow::ensure!(is_valid_mode(mode), "invalid mode {mode:o}");
+        check_name(&name)?;
+        anyhow::ensure!(
+            self.names.insert(name.clone()),
+            "duplicate entry '{}' in tree",
+            String::from_utf8_lossy(&name)
+        );
+        self.entries.push(TreeEntry { mode, name, hash });
+        Ok(())
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn build(mut self) -> Tree {
+        self.entries.sort_unstable_by(compare);
+        Tree {
+            entries: self.entries,
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::objects::Kind;
+
+// NOTE: syntax check of the tree, commit and tag objects, the same checks git fsck run
+// (fsck report every problem, hash-object refuse to write an object with an error)
+// problem id and message are the git ones so the output can be compared
+// error in tree <hash>: treeNotSorted: not properly sorted
+// also return the links of the object: every object it point to, with the kind it must be
+pub(crate) fn object(
+    kind: Kind,
+    content: &[u8],
+    format: ObjectFormat,
+) -> (Vec<(Kind, ObjectId)>, Vec<Problem>) {
+    let mut problems = Vec::new();
+    let links = match kind {
+        Kind::Blob => Vec::new(),
+        Kind::Tree => check_tree(content, format, &mut problems),
+        Kind::Commit => check_commit(content, format, &mut problems),
+        Kind::Tag => check_tag(content, format, &mut problems),
+    };
+    (links, problems)
+}
+
+#[derive(PartialEq)]
+pub(crate) struct Problem {
+    pub(crate) warning: bool,
+    pub(crate) id: &'static str,
+    pub(crate) message: &'static str,
+}
+
+fn error(id: &'static str, message: &'static str) -> Problem {
+    Problem {
+        warning: false,
+        id,
+        message,
+    }
+}
+
+fn warning(id: &'static str, message: &'static str) -> Problem {
+    Problem {
+        warning: true,
+        id,
+        message,
+    }
+}
+
+fn report(problems: &mut Vec<Problem>, problem: Problem) {
+    // same problem in many entries of one tree is reported once, like git
+    if !problems.contains(&problem) {
+        problems.push(problem);
+    }
+}
+
+// hash in the text objects is always the full lowercase hex
+fn parse_hex(hex: &[u8], format: ObjectFormat) -> Option<ObjectId> {
+    if hex.len() != format.hex_len() || !hex.iter().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+    {
+        return None;
+    }
+    ObjectId::from_hex(std::str::from_utf8(hex).ok()?).ok()
+}
+
+// NOTE: tree entries have to be sorted the way git sort them, by name bytes
+// but a tree sort as if its name ended with '/' ("a.txt" < "a/" < "a0")
+// the mode string is octal without leading zero (40000, not 040000)
+fn check_tree(
+    content: &[u8],
+    format: ObjectFormat,
+    problems: &mut Vec<Problem>,
+) -> Vec<(Kind, ObjectId)> {
+    let mut links = Vec::new();
+    let mut previous: Option<(&[u8], Vec<u8>)> = None;
+    let mut rest = content;
+    while !rest.is_empty() {
+        let bad_tree = error("badTree", "cannot be parsed as a tree");
+        let (Some(space), Some(nul)) = (
+            rest.iter().position(|&b| b == b' '),
+            rest.iter().position(|&b| b == 0),
+        ) else {
+            report(problems, bad_tree);
+            return links;
+        };
+        let hash_end = nul + 1 + format.raw_len();
+        if space > nul || hash_end > rest.len() {
+            report(problems, bad_tree);
+            return links;
+        }
+        let Some(mode) = std::str::from_utf8(&rest[..space])
+            .ok()
+            .and_then(|mode| u32::from_str_radix(mode, 8).ok())
+        else {
+            report(problems, bad_tree);
+            return links;
+        };
+        let mode_text = &rest[..space];
+        let name = &rest[space + 1..nul];
+        let hash = ObjectId::from_bytes(&rest[nul + 1..hash_end]).expect("raw_len bytes");
+        rest = &rest[hash_end..];
+
+        if mode_text.len() > 1 && mode_text[0] == b'0' {
+            report(
+                problems,
+                warning("zeroPaddedFilemode", "contains zero-padded file modes"),
+            );
+        }
+        // 100664 was written by very old git, still accepted
+        if !matches!(
+            mode,
+            0o40000 | 0o100644 | 0o100755 | 0o100664 | 0o120000 | 0o160000
+        ) {
+            report(problems, warning("badFilemode", "contains bad file modes"));
+        }
+        if name.is_empty() {
+            report(problems, warning("emptyName", "contains empty pathname"));
+        }
+        if name.contains(&b'/') {
+            report(problems, warning("fullPathname", "contains full pathnames"));
+        }
+        match name {
+            b"." => report(problems, warning("hasDot", "contains '.'")),
+            b".." => report(problems, warning("hasDotdot", "contains '..'")),
+            _ if name.eq_ignore_ascii_case(b".git") => {
+                report(problems, error("hasDotgit", "contains '.git'"))
+            }
+            _ => {}
+        }
+        if hash.as_bytes().iter().all(|&b| b == 0) {
+            report(
+                problems,
+                error("nullSha1", "contains entries pointing to null sha1"),
+            );
+        }
+
+        let is_tree = mode & 0o170000 == 0o040000;
+        let mut key = name.to_vec();
+        if is_tree {
+            key.push(b'/');
+        }
+        if let Some((previous_name, previous_key)) = &previous {
+            if *previous_name == name {
+                report(
+                    problems,
+                    error("duplicateEntries", "contains duplicate file entries"),
+                );
+            } else if *previous_key > key {
+                report(problems, error("treeNotSorted", "not properly sorted"));
+            }
+        }
+        previous = Some((name, key));
+
+        match mode & 0o170000 {
+            0o040000 => links.push((Kind::Tree, hash)),
+            // submodule, the commit is not in this repository
+            0o160000 => {}
+            _ => links.push((Kind::Blob, hash)),
+        }
+    }
+    links
+}
+
+// NOTE: commit header, in this order
+// tree <hash>
+// parent <hash>        (zero or more)
+// author <ident>
+// committer <ident>
+// then other headers (encoding, gpgsig, ...) we don't check
+fn check_commit(
+    content: &[u8],
+    format: ObjectFormat,
+    problems: &mut Vec<Problem>,
+) -> Vec<(Kind, ObjectId)> {
+    let mut links = Vec::new();
+    let mut lines = header_lines(content);
+    let mut line = lines.next();
+
+    let Some(tree) = line.and_then(|line| line.strip_prefix(b"tree ")) else {
+        report(
+            problems,
+            error("missingTree", "invalid format - expected 'tree' line"),
+        );
+        return links;
+    };
+    let Some(tree) = parse_hex(tree, format) else {
+        report(
+            problems,
+            error("badTreeSha1", "invalid 'tree' line format - bad sha1"),
+        );
+        return links;
+    };
+    links.push((Kind::Tree, tree));
+
+    line = lines.next();
+    while let Some(parent) = line.and_then(|line| line.strip_prefix(b"parent ")) {
+        let Some(parent) = parse_hex(parent, format) else {
+            report(
+                problems,
+                error("badParentSha1", "invalid 'parent' line format - bad sha1"),
+            );
+            return links;
+        };
+        links.push((Kind::Commit, parent));
+        line = lines.next();
+    }
+
+    let Some(author) = line.and_then(|line| line.strip_prefix(b"author ")) else {
+        report(
+            problems,
+            error("missingAuthor", "invalid format - expected 'author' line"),
+        );
+        return links;
+    };
+    if let Some(problem) = check_ident(author) {
+        report(problems, problem);
+        return links;
+    }
+
+    let Some(committer) = lines
+        .next()
+        .and_then(|line| line.strip_prefix(b"committer "))
+    else {
+        report(
+            problems,
+            error(
+                "missingCommitter",
+                "invalid format - expected 'committer' line",
+            ),
+        );
+        return links;
+    };
+    if let Some(problem) = check_ident(committer) {
+        report(problems, problem);
+    }
+    links
+}
+
+// NOTE: tag header
+// object <hash>
+// type <kind>
+// tag <name>
+// tagger <ident>       (very old tags don't have it)
+fn check_tag(
+    content: &[u8],
+    format: ObjectFormat,
+    problems: &mut Vec<Problem>,
+) -> Vec<(Kind, ObjectId)> {
+    let mut lines = header_lines(content);
+
+    let Some(object) = lines.next().and_then(|line| line.strip_prefix(b"object ")) else {
+        report(
+            problems,
+            error("missingObject", "invalid format - expected 'object' line"),
+        );
+        return Vec::new();
+    };
+    let Some(object) = parse_hex(object, format) else {
+        report(
+            problems,
+            error("badObjectSha1", "invalid 'object' line format - bad sha1"),
+        );
+        return Vec::new();
+    };
+
+    let Some(kind) = lines.next().and_then(|line| line.strip_prefix(b"type ")) else {
+        report(
+            problems,
+            error("missingTypeEntry", "invalid format - expected 'type' line"),
+        );
+        return Vec::new();
+    };
+    let Some(kind) = std::str::from_utf8(kind)
+        .ok()
+        .and_then(|kind| kind.parse::<Kind>().ok())
+    else {
+        report(problems, error("badType", "invalid 'type' value"));
+        return Vec::new();
+    };
+    let links = vec![(kind, object)];
+
+    if !lines.next().is_some_and(|line| line.starts_with(b"tag ")) {
+        report(
+            problems,
+            error("missingTagEntry", "invalid format - expected 'tag' line"),
+        );
+        return links;
+    }
+    if let Some(tagger) = lines.next().and_then(|line| line.strip_prefix(b"tagger "))
+        && let Some(problem) = check_ident(tagger)
+    {
+        report(problems, problem);
+    }
+    links
+}
+
+// lines before the empty line that start the message
+fn header_lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .split(|&b| b == b'\n')
+        .take_while(|line| !line.is_empty())
+}
+
+// NOTE: "Name <email> 1700000000 +0100"
+fn check_ident(ident: &[u8]) -> Option<Problem> {
+    if ident.first() == Some(&b'<') {
+        return Some(error(
+            "missingNameBeforeEmail",
+            "invalid author/committer line - missing name before email",
+        ));
+    }
+    let Some(open) = ident.iter().position(|&b| b == b'<' || b == b'>') else {
+        return Some(error(
+            "missingEmail",
+            "invalid author/committer line - missing email",
+        ));
+    };
+    if ident[open] == b'>' {
+        return Some(error("badName", "invalid author/committer line - bad name"));
+    }
+    if ident[open - 1] != b' ' {
+        return Some(error(
+            "missingSpaceBeforeEmail",
+            "invalid author/committer line - missing space before email",
+        ));
+    }
+    let rest = &ident[open + 1..];
+    let Some(close) = rest.iter().position(|&b| b == b'<' || b == b'>') else {
+        return Some(error(
+            "badEmail",
+            "invalid author/committer line - bad email",
+        ));
+    };
+    if rest[close] == b'<' {
+        return Some(error(
+            "badEmail",
+            "invalid author/committer line - bad email",
+        ));
+    }
+    let Some(rest) = rest[close + 1..].strip_prefix(b" ") else {
+        return Some(error(
+            "missingSpaceBeforeDate",
+            "invalid author/committer line - missing space before date",
+        ));
+    };
+    let Some(space) = rest.iter().position(|&b| b == b' ') else {
+        return Some(error("badDate", "invalid author/committer line - bad date"));
+    };
+    let (date, zone) = (&rest[..space], &rest[space + 1..]);
+    if date.is_empty() || !date.iter().all(u8::is_ascii_digit) {
+        return Some(error("badDate", "invalid author/committer line - bad date"));
+    }
+    if date.len() > 1 && date[0] == b'0' {
+        return Some(error(
+            "zeroPaddedDate",
+            "invalid author/committer line - zero-padded date",
+        ));
+    }
+    if std::str::from_utf8(date)
+        .ok()
+        .and_then(|date| date.parse::<u64>().ok())
+        .is_none()
+    {
+        return Some(error(
+            "badDateOverflow",
+            "invalid author/committer line - date causes integer overflow",
+        ));
+    }
+    let valid_zone = zone.len() == 5
+        && matches!(zone[0], b'+' | b'-')
+        && zone[1..].iter().all(u8::is_ascii_digit);
+    if !valid_zone {
+        return Some(error(
+            "badTimezone",
+            "invalid author/committer line - bad time zone",
+        ));
+    }
+    None
+}
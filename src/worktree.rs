@@ -0,0 +1,389 @@
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::Read;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::config::Config;
+use crate::hash::{ObjectFormat, ObjectId};
+use crate::index::{Index, IndexEntry};
+use crate::objects::Object;
+use crate::odb::ObjectDatabase;
+use crate::tree::{Files, Tree};
+
+// NOTE: write the content of the tree object into the working tree
+// and add every file to the index (stage 0) so `git status` is clean right after
+// 40000  -> directory, we go inside
+// 100644 -> normal file
+// 100755 -> executable file
+// 120000 -> symlink, the blob content is the link target
+// 160000 -> submodule commit, only create the empty dir
+// core.symlinks = false (filesystem without symlinks) write the link target into a plain file,
+// the index still say 120000 so the next commit keep it a symlink
+pub(crate) fn checkout_tree(
+    db: &dyn ObjectDatabase,
+    tree_hash: &str,
+    worktree: &Path,
+    index: &mut Index,
+) -> anyhow::Result<()> {
+    let config = Config::load_from(&worktree.join(".git/config"))?;
+    let symlinks = config.get_bool("core.symlinks")?.unwrap_or(true);
+    checkout_tree_at(db, tree_hash, worktree, "", index, symlinks)
+}
+
+fn checkout_tree_at(
+    db: &dyn ObjectDatabase,
+    tree_hash: &str,
+    worktree: &Path,
+    prefix: &str,
+    index: &mut Index,
+    symlinks: bool,
+) -> anyhow::Result<()> {
+    for tree_entry in Tree::read(db, tree_hash)?.entries {
+        let (mode, hash) = (tree_entry.mode, tree_entry.hash);
+        let name = tree_entry.name_str()?;
+        let rel_path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{prefix}/{name}")
+        };
+        let path = worktree.join(&rel_path);
+        match mode {
+            0o40000 => {
+                verify_path(&rel_path)?;
+                fs::create_dir_all(&path)
+                    .with_context(|| format!("Failed to create dir {}", path.display()))?;
+                checkout_tree_at(db, &hash.to_string(), worktree, &rel_path, index, symlinks)?;
+            }
+            0o160000 => {
+                fs::create_dir_all(&path)
+                    .with_context(|| format!("Failed to create dir {}", path.display()))?;
+            }
+            _ => index.add(checkout_file(
+                db, worktree, &rel_path, mode, hash, symlinks,
+            )?),
+        }
+    }
+    Ok(())
+}
+
+// NOTE: one blob of a tree into the worktree, the stage 0 index entry of it is returned
+// the dirs on the way are created, whatever file is there is replaced
+pub(crate) fn checkout_file(
+    db: &dyn ObjectDatabase,
+    worktree: &Path,
+    rel_path: &str,
+    mode: u32,
+    hash: ObjectId,
+    symlinks: bool,
+) -> anyhow::Result<IndexEntry> {
+    verify_path(rel_path)?;
+    let path = worktree.join(rel_path);
+    if mode == 0o160000 {
+        fs::create_dir_all(&path)
+            .with_context(|| format!("Failed to create dir {}", path.display()))?;
+        return Ok(IndexEntry::new(rel_path, mode, hash, 0));
+    }
+    let mut object = db.read(&hash.to_string())?;
+    write_file(worktree, rel_path, mode, &mut object.reader, symlinks)?;
+    let metadata = fs::symlink_metadata(&path)
+        .with_context(|| format!("Failed to read stat for :{}", path.display()))?;
+    let mut entry = IndexEntry::from_metadata(rel_path, &metadata, hash, 0);
+    if mode == 0o120000 {
+        entry.mode = 0o120000;
+    }
+    Ok(entry)
+}
+
+// 100644 -> normal file, 100755 -> executable file, 120000 -> symlink to the content
+pub(crate) fn write_file(
+    worktree: &Path,
+    rel_path: &str,
+    mode: u32,
+    content: &mut dyn Read,
+    symlinks: bool,
+) -> anyhow::Result<()> {
+    verify_path(rel_path)?;
+    let path = &worktree.join(rel_path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create dir {}", parent.display()))?;
+    }
+    remove_file_at(path)?;
+    match mode {
+        0o120000 => {
+            let mut target = Vec::new();
+            content
+                .read_to_end(&mut target)
+                .context("Failed to read the symlink target")?;
+            if symlinks {
+                std::os::unix::fs::symlink(OsStr::from_bytes(&target), path)
+                    .with_context(|| format!("Failed to create symlink {}", path.display()))?;
+            } else {
+                fs::write(path, &target)
+                    .with_context(|| format!("Failed to write {}", path.display()))?;
+            }
+        }
+        0o100644 | 0o100755 | 0o100664 => {
+            let mut file = fs::File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            std::io::copy(content, &mut file)
+                .with_context(|| format!("Failed to write {}", path.display()))?;
+            if mode == 0o100755 {
+                let mut permissions = file.metadata()?.permissions();
+                permissions.set_mode(permissions.mode() | 0o111);
+                fs::set_permissions(path, permissions)?;
+            }
+        }
+        _ => anyhow::bail!("unknown mode {mode:o} for {}", path.display()),
+    }
+    Ok(())
+}
+
+// NOTE: the paths come from trees (maybe of somebody else's repository), none of them may
+// reach into .git or out of the worktree, same rules as git verify_path:
+// no empty, "." or ".." component (so no leading "/" either), no .git in any case, no NUL
+pub(crate) fn verify_path(rel_path: &str) -> anyhow::Result<()> {
+    let valid = !rel_path.contains('\0')
+        && rel_path.split('/').all(|component| {
+            !matches!(component, "" | "." | "..") && !component.eq_ignore_ascii_case(".git")
+        });
+    anyhow::ensure!(valid, "invalid path '{rel_path}'");
+    Ok(())
+}
+
+// NOTE: the file goes and then every dir above it that is left empty
+pub(crate) fn remove_path(worktree: &Path, rel_path: &str) -> anyhow::Result<()> {
+    verify_path(rel_path)?;
+    let path = worktree.join(rel_path);
+    remove_file_at(&path)?;
+    let mut dir = Path::new(rel_path).parent();
+    while let Some(parent) = dir.filter(|parent| !parent.as_os_str().is_empty()) {
+        if fs::remove_dir(worktree.join(parent)).is_err() {
+            break;
+        }
+        dir = parent.parent();
+    }
+    Ok(())
+}
+
+// NOTE: the file is not what the index entry say, the stat is checked first
+// and the content is hashed only when the stat changed
+pub(crate) fn is_modified(
+    worktree: &Path,
+    entry: &IndexEntry,
+    format: ObjectFormat,
+) -> anyhow::Result<bool> {
+    let path = worktree.join(&entry.path);
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+        Err(e) => {
+            return Err(e).with_context(|| format!("Failed to read stat for :{}", entry.path));
+        }
+    };
+    if entry.mode == 0o160000 {
+        return Ok(false);
+    }
+    let fresh = IndexEntry::from_metadata(&entry.path, &metadata, entry.hash, 0);
+    // core.symlinks = false keeps the link as a plain file
+    let same_mode = fresh.mode == entry.mode || (entry.mode == 0o120000 && metadata.is_file());
+    if !same_mode {
+        return Ok(true);
+    }
+    if (fresh.mtime, fresh.ctime, fresh.size, fresh.ino)
+        == (entry.mtime, entry.ctime, entry.size, entry.ino)
+    {
+        return Ok(false);
+    }
+    Ok(Object::blob_from_file(&path)?.hash(format)? != entry.hash)
+}
+
+// NOTE: entries without stat (new in the index) get the stat of the file when it has
+// the same content, the next status does not need to hash it again
+pub(crate) fn refresh(index: &mut Index) -> anyhow::Result<()> {
+    let worktree = Path::new(".");
+    let format = index.format;
+    for entry in index
+        .entries
+        .iter_mut()
+        .filter(|entry| entry.mtime == (0, 0))
+    {
+        if entry.mode == 0o160000 || is_modified(worktree, entry, format)? {
+            continue;
+        }
+        let metadata = fs::symlink_metadata(worktree.join(&entry.path))
+            .with_context(|| format!("Failed to read stat for :{}", entry.path))?;
+        let mode = entry.mode;
+        *entry = IndexEntry::from_metadata(&entry.path, &metadata, entry.hash, 0);
+        entry.mode = mode;
+    }
+    Ok(())
+}
+
+// NOTE: move the worktree and the index from the files of `from` (what the index has now)
+// to the entries of `to` (a tree, or a merge result with its conflict stages)
+// only the paths that change are written, the others keep their local changes
+// `contents` is what the worktree get for a conflicted path (content with the markers)
+// nothing is written when one of the paths to change
+// - has changes in the worktree or in the index that are not committed
+// - is not tracked but there is a file in the way
+// action is what is refused: "merge", "checkout", ...
+pub(crate) fn update(
+    db: &dyn ObjectDatabase,
+    from: &Files,
+    to: &[IndexEntry],
+    contents: &BTreeMap<String, (u32, Vec<u8>)>,
+    action: &str,
+) -> anyhow::Result<()> {
+    let worktree = Path::new(".");
+    let index_path = Path::new(".git/index");
+    let (mut index, lock) = Index::lock(index_path)?;
+    let config = Config::load()?;
+    let symlinks = config.get_bool("core.symlinks")?.unwrap_or(true);
+
+    let mut wanted: BTreeMap<&str, Vec<&IndexEntry>> = BTreeMap::new();
+    for entry in to {
+        wanted.entry(&entry.path).or_default().push(entry);
+    }
+    let mut paths: Vec<&str> = from.keys().map(String::as_str).collect();
+    paths.extend(wanted.keys());
+    paths.sort_unstable();
+    paths.dedup();
+    // a conflict in the index is always written again (the worktree has the markers)
+    let touched: Vec<&str> = paths
+        .into_iter()
+        .filter(|path| {
+            let wanted = match wanted.get(path).map(Vec::as_slice) {
+                Some([entry]) if entry.stage() == 0 => Some((entry.mode, entry.hash)),
+                Some(_) => return true,
+                None => None,
+            };
+            wanted != from.get(*path).copied() || index.is_unmerged(path)
+        })
+        .collect();
+
+    let mut local_changes = Vec::new();
+    let mut untracked = Vec::new();
+    for &path in &touched {
+        if index.is_unmerged(path) {
+            continue;
+        }
+        match index.entries.iter().find(|e| e.path == path) {
+            Some(entry) => {
+                let staged = Some((entry.mode, entry.hash)) != from.get(path).copied();
+                if staged || is_modified(worktree, entry, index.format)? {
+                    local_changes.push(path);
+                }
+            }
+            None => {
+                let in_the_way = fs::symlink_metadata(worktree.join(path))
+                    .is_ok_and(|metadata| !metadata.is_dir());
+                if in_the_way && wanted.contains_key(path) {
+                    untracked.push(path);
+                }
+            }
+        }
+    }
+    if !local_changes.is_empty() {
+        anyhow::bail!(
+            "Your local changes to the following files would be overwritten by {action}:\n\t{}\n\
+             Please commit your changes or stash them before you {action}.\nAborting",
+            local_changes.join("\n\t")
+        );
+    }
+    if !untracked.is_empty() {
+        anyhow::bail!(
+            "The following untracked working tree files would be overwritten by {action}:\n\t{}\n\
+             Please move or remove them before you {action}.\nAborting",
+            untracked.join("\n\t")
+        );
+    }
+
+    // removed first, a file can be replaced by a dir of the same name
+    for &path in touched.iter().filter(|path| !wanted.contains_key(*path)) {
+        remove_path(worktree, path)?;
+        index.remove(path);
+    }
+    for &path in touched.iter().filter(|path| wanted.contains_key(*path)) {
+        index.remove(path);
+        match (wanted[path].as_slice(), contents.get(path)) {
+            ([entry], None) if entry.stage() == 0 => {
+                let entry = checkout_file(db, worktree, path, entry.mode, entry.hash, symlinks)?;
+                index.add(entry);
+                continue;
+            }
+            (_, Some((mode, content))) => {
+                write_file(worktree, path, *mode, &mut &content[..], symlinks)?;
+            }
+            (_, None) => {}
+        }
+        for entry in &wanted[path] {
+            index.add((*entry).clone());
+        }
+    }
+    index.write_locked(lock)
+}
+
+// NOTE: every file (or symlink) of the worktree that the index does not have, sorted
+// .git and the dirs of nested repositories are skipped, ignore files are not read (yet)
+pub(crate) fn untracked(worktree: &Path, index: &Index) -> anyhow::Result<Vec<String>> {
+    let tracked: HashSet<&str> = index
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_str())
+        .collect();
+    let mut files = Vec::new();
+    collect_untracked(worktree, "", &tracked, &mut files)?;
+    files.sort();
+    Ok(files)
+}
+
+fn collect_untracked(
+    worktree: &Path,
+    prefix: &str,
+    tracked: &HashSet<&str>,
+    files: &mut Vec<String>,
+) -> anyhow::Result<()> {
+    let dir = worktree.join(prefix);
+    let entries =
+        fs::read_dir(&dir).with_context(|| format!("Failed to read dir {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.with_context(|| format!("Failed to read dir {}", dir.display()))?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name == ".git" {
+            continue;
+        }
+        let path = if prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{prefix}/{name}")
+        };
+        if entry.file_type()?.is_dir() {
+            if !entry.path().join(".git").exists() {
+                collect_untracked(worktree, &path, tracked, files)?;
+            }
+        } else if !tracked.contains(path.as_str()) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// NOTE: whatever file or symlink is in the way go first
+// File::create on an existing symlink would write into the file it point to
+fn remove_file_at(path: &Path) -> anyhow::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.is_dir() => {
+            fs::remove_file(path).with_context(|| format!("Failed to remove {}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
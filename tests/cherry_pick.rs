@@ -0,0 +1,208 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, head, ok, read_object};
+
+fn object(dir: &Path, hex: &str) -> String {
+    String::from_utf8(read_object(dir, hex)).unwrap()
+}
+
+// refs/heads/side gets the commits, main stays where it was
+fn side(dir: &Path, commits: &[(&str, &str, &str)]) -> Vec<String> {
+    let base = head(dir);
+    let hashes = commits
+        .iter()
+        .map(|(name, content, message)| commit_file(dir, name, content, message))
+        .collect::<Vec<_>>();
+    fs::write(dir.join(".git/refs/heads/side"), format!("{}\n", head(dir))).unwrap();
+    ok(dir, &["reset", "--hard", &base]);
+    hashes
+}
+
+#[test]
+fn range_is_picked_oldest_first() {
+    let scratch = Scratch::new("cherry-pick-range");
+    let dir = scratch.repo("repo");
+    side(&dir, &[("a", "a\n", "a"), ("b", "b\n", "b")]);
+    let base = head(&dir);
+
+    ok(&dir, &["cherry-pick", "-x", "main..side"]);
+    let b = object(&dir, &head(&dir));
+    assert!(b.contains("\n\nb\n\n(cherry picked from commit "), "{b}");
+    let parent = b.lines().find_map(|l| l.strip_prefix("parent ")).unwrap();
+    let a = object(&dir, parent);
+    assert!(a.contains(&format!("parent {base}\n")), "{a}");
+    assert_eq!(fs::read_to_string(dir.join("b")).unwrap(), "b\n");
+    assert!(!dir.join(".git/sequencer").exists());
+}
+
+#[test]
+fn author_is_kept() {
+    let scratch = Scratch::new("cherry-pick-author");
+    let dir = scratch.repo("repo");
+    let base = head(&dir);
+    fs::write(dir.join("a"), "a\n").unwrap();
+    ok(&dir, &["update-index", "--add", "a"]);
+    ok(
+        &dir,
+        &[
+            "commit",
+            "-m",
+            "a",
+            "--author",
+            "Other <o@e.x>",
+            "--date",
+            "@1600000000 +0200",
+        ],
+    );
+    let picked = head(&dir);
+    ok(&dir, &["reset", "--hard", &base]);
+    commit_file(&dir, "b", "b\n", "b");
+
+    ok(&dir, &["cherry-pick", &picked]);
+    let commit = object(&dir, &head(&dir));
+    assert!(
+        commit.contains("author Other <o@e.x> 1600000000 +0200\n"),
+        "{commit}"
+    );
+    assert!(
+        commit.contains("committer T <t@e.x> 1700000000 +0000\n"),
+        "{commit}"
+    );
+}
+
+#[test]
+fn no_commit_only_stages() {
+    let scratch = Scratch::new("cherry-pick-no-commit");
+    let dir = scratch.repo("repo");
+    let picked = side(&dir, &[("a", "a\n", "a")]);
+    let base = head(&dir);
+
+    ok(&dir, &["cherry-pick", "-n", &picked[0]]);
+    assert_eq!(head(&dir), base);
+    assert!(ok(&dir, &["ls-files"]).contains("a\n"));
+    assert_eq!(
+        fs::read_to_string(dir.join(".git/MERGE_MSG")).unwrap(),
+        "a\n"
+    );
+}
+
+#[test]
+fn conflict_continue_skip_and_abort() {
+    let scratch = Scratch::new("cherry-pick-conflict");
+    let dir = scratch.repo("repo");
+    side(
+        &dir,
+        &[
+            ("file", "side\n", "conflicts"),
+            ("b", "b\n", "b"),
+            ("c", "c\n", "c"),
+        ],
+    );
+    let ours = commit_file(&dir, "file", "ours\n", "ours");
+
+    let out = git(&dir, &["cherry-pick", "main..side"], b"");
+    assert!(!out.status.success());
+    assert!(dir.join(".git/CHERRY_PICK_HEAD").exists());
+    assert!(dir.join(".git/sequencer").exists());
+    // a second one can't start
+    let out = git(&dir, &["cherry-pick", "side"], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("cherry-pick is already in progress"));
+
+    ok(&dir, &["cherry-pick", "--abort"]);
+    assert_eq!(head(&dir), ours);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "ours\n");
+    assert!(!dir.join(".git/sequencer").exists());
+
+    assert!(
+        !git(&dir, &["cherry-pick", "main..side"], b"")
+            .status
+            .success()
+    );
+    ok(&dir, &["cherry-pick", "--skip"]);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "ours\n");
+    assert_eq!(fs::read_to_string(dir.join("c")).unwrap(), "c\n");
+    assert!(!dir.join(".git/sequencer").exists());
+
+    ok(&dir, &["reset", "--hard", &ours]);
+    assert!(
+        !git(&dir, &["cherry-pick", "main..side"], b"")
+            .status
+            .success()
+    );
+    fs::write(dir.join("file"), "both\n").unwrap();
+    ok(&dir, &["update-index", "file"]);
+    ok(&dir, &["cherry-pick", "--continue"]);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "both\n");
+    assert_eq!(fs::read_to_string(dir.join("c")).unwrap(), "c\n");
+    assert!(!dir.join(".git/CHERRY_PICK_HEAD").exists());
+    assert!(!dir.join(".git/sequencer").exists());
+}
+
+#[test]
+fn revert_undoes_the_change() {
+    let scratch = Scratch::new("revert");
+    let dir = scratch.repo("repo");
+    let changed = commit_file(&dir, "file", "two\n", "two");
+
+    ok(&dir, &["revert", "--no-edit", &changed]);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "one\n");
+    let commit = object(&dir, &head(&dir));
+    assert!(
+        commit.ends_with(&format!(
+            "\n\nRevert \"two\"\n\nThis reverts commit {changed}.\n"
+        )),
+        "{commit}"
+    );
+}
+
+#[test]
+fn merges_need_a_mainline() {
+    let scratch = Scratch::new("cherry-pick-mainline");
+    let dir = scratch.repo("repo");
+    let base = head(&dir);
+    let picked = side(&dir, &[("a", "a\n", "a")]);
+    ok(&dir, &["merge", "--no-ff", "-m", "merge", "side"]);
+    let merge = head(&dir);
+    ok(&dir, &["reset", "--hard", &base]);
+
+    let out = git(&dir, &["cherry-pick", &merge], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is a merge but no -m option was given"));
+    let out = git(&dir, &["cherry-pick", "-m", "1", &picked[0]], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("is not a merge"));
+    let out = git(&dir, &["cherry-pick", "-m", "3", &merge], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("does not have parent 3"));
+    assert_eq!(head(&dir), base);
+
+    ok(&dir, &["cherry-pick", "-m", "1", &merge]);
+    assert_eq!(fs::read_to_string(dir.join("a")).unwrap(), "a\n");
+}
+
+#[test]
+fn bad_input_is_refused() {
+    let scratch = Scratch::new("cherry-pick-bad-input");
+    let dir = scratch.repo("repo");
+    for (args, error) in [
+        (
+            &["cherry-pick", "--continue"][..],
+            "no cherry-pick or revert in progress",
+        ),
+        (
+            &["revert", "--abort"],
+            "no cherry-pick or revert in progress",
+        ),
+        (&["cherry-pick", "main..main"], "empty commit set passed"),
+        (
+            &["cherry-pick", "-m", "0", "main"],
+            "expects a number greater than zero",
+        ),
+        (&["cherry-pick", "nope"], "nope"),
+    ] {
+        let out = git(&dir, args, b"");
+        assert!(!out.status.success(), "{args:?}");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{args:?}: {stderr}");
+    }
+}
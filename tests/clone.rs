@@ -0,0 +1,128 @@
+mod common;
+
+use std::fs;
+
+use common::{Scratch, git, hash, ok, tree_entry};
+
+// NOTE: a tree with .git/hooks/post-checkout must not install the hook, and clone must not
+// run it
+#[test]
+fn clone_refuses_dot_git_hooks_entry() {
+    let scratch = Scratch::new("clone-dotgit");
+    let root = scratch.0.clone();
+    let source = root.join("source");
+    fs::create_dir_all(&source).unwrap();
+    assert!(git(&source, &["init"], b"").status.success());
+
+    let marker = root.join("pwned");
+    let script = format!("#!/bin/sh\ntouch {}\n", marker.display());
+    let blob = hash(git(
+        &source,
+        &["hash-object", "-w", "--stdin"],
+        script.as_bytes(),
+    ));
+    let literal_tree = |content: Vec<u8>| {
+        hash(git(
+            &source,
+            &["hash-object", "-w", "-t", "tree", "--literally", "--stdin"],
+            &content,
+        ))
+    };
+    let hooks = literal_tree(tree_entry("100755", "post-checkout", &blob));
+    let dot_git = literal_tree(tree_entry("40000", "hooks", &hooks));
+    let top = literal_tree(tree_entry("40000", ".git", &dot_git));
+    let commit = hash(git(&source, &["commit-tree", &top, "-m", "evil"], b""));
+    fs::create_dir_all(source.join(".git/refs/heads")).unwrap();
+    fs::write(source.join(".git/refs/heads/main"), format!("{commit}\n")).unwrap();
+
+    let output = git(&root, &["clone", "source", "copy"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!root.join("copy/.git/hooks/post-checkout").exists());
+    assert!(!marker.exists());
+}
+
+// NOTE: the HEAD branch goes in .git/config, a newline in it could add any key
+// (core.hooksPath) there
+#[test]
+fn clone_refuses_head_branch_that_breaks_the_config() {
+    let scratch = Scratch::new("clone-config");
+    let source = scratch.repo("source");
+    let head = fs::read_to_string(source.join(".git/refs/heads/main")).unwrap();
+    let evil = "refs/heads/x\"]\n[core]\n\thooksPath = /tmp";
+    fs::write(
+        source.join(".git/packed-refs"),
+        format!("{} {evil}\n", head.trim()),
+    )
+    .unwrap();
+    fs::write(source.join(".git/HEAD"), format!("ref: {evil}\n")).unwrap();
+
+    let output = git(&scratch.0, &["clone", "source", "copy"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!scratch.0.join("copy/.git/config").exists());
+}
+
+#[test]
+fn clone_refuses_ref_names_that_leave_the_repository() {
+    let scratch = Scratch::new("clone-traversal");
+    let source = scratch.repo("source");
+    let head = fs::read_to_string(source.join(".git/refs/heads/main")).unwrap();
+    fs::write(
+        source.join(".git/packed-refs"),
+        format!("{} refs/heads/../../../../../escaped\n", head.trim()),
+    )
+    .unwrap();
+
+    let output = git(&scratch.0, &["clone", "source", "copy"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!scratch.0.join("escaped").exists());
+}
+
+// NOTE: a quote or a backslash in the branch or the path is escaped, git and we read the
+// same config back
+#[test]
+fn clone_escapes_config_values() {
+    let scratch = Scratch::new("clone-escape");
+    let source = scratch.repo("sr\"c\\x");
+    fs::rename(
+        source.join(".git/refs/heads/main"),
+        source.join(".git/refs/heads/x\"]"),
+    )
+    .unwrap();
+    fs::write(source.join(".git/HEAD"), "ref: refs/heads/x\"]\n").unwrap();
+
+    ok(&scratch.0, &["clone", "sr\"c\\x", "copy"]);
+    let config = fs::read_to_string(scratch.0.join("copy/.git/config")).unwrap();
+    assert!(config.contains("[branch \"x\\\"]\"]\n"), "{config}");
+    assert!(config.contains("\tmerge = refs/heads/x\\\"]\n"), "{config}");
+    assert!(config.contains("sr\\\"c\\\\x\n"), "{config}");
+    ok(&scratch.0.join("copy"), &["fetch"]);
+}
+
+// the object of `file` is gone, the clone fails after .git is made
+fn broken_source(scratch: &Scratch) {
+    let source = scratch.repo("source");
+    let blob = hash(git(&source, &["hash-object", "file"], b""));
+    fs::remove_file(source.join(format!(".git/objects/{}/{}", &blob[..2], &blob[2..]))).unwrap();
+}
+
+#[test]
+fn failed_clone_removes_the_directory() {
+    let scratch = Scratch::new("clone-cleanup");
+    broken_source(&scratch);
+
+    let output = git(&scratch.0, &["clone", "source", "copy"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!scratch.0.join("copy").exists());
+}
+
+// NOTE: an empty directory that was there before stays, empty
+#[test]
+fn failed_clone_into_existing_directory_empties_it() {
+    let scratch = Scratch::new("clone-cleanup-existing");
+    broken_source(&scratch);
+    fs::create_dir(scratch.0.join("copy")).unwrap();
+
+    let output = git(&scratch.0, &["clone", "source", "copy"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert_eq!(fs::read_dir(scratch.0.join("copy")).unwrap().count(), 0);
+}
@@ -0,0 +1,124 @@
+mod common;
+
+use std::fs;
+
+use common::{Scratch, git, hash, ok};
+
+// a commit git made with i18n.commitEncoding = ISO-8859-1, name and message are latin-1
+fn latin1_commit(dir: &std::path::Path) -> String {
+    let tree = ok(dir, &["write-tree"]);
+    let mut content = format!("tree {}\n", tree.trim()).into_bytes();
+    content.extend(b"author J\xfcrgen <j@e.x> 1700000000 +0100\n");
+    content.extend(b"committer J\xfcrgen <j@e.x> 1700000000 +0100\n");
+    content.extend(b"encoding ISO-8859-1\n\ncaf\xe9\n\nbody \xe9\n");
+    let commit = hash(git(
+        dir,
+        &["hash-object", "-w", "-t", "commit", "--stdin"],
+        &content,
+    ));
+    fs::write(dir.join(".git/refs/heads/main"), format!("{commit}\n")).unwrap();
+    commit
+}
+
+#[test]
+fn latin1_commit_is_readable() {
+    let scratch = Scratch::new("commit-latin1");
+    let repo = scratch.repo("repo");
+    let commit = latin1_commit(&repo);
+
+    let log = ok(&repo, &["log"]);
+    assert!(log.contains(&format!("commit {commit}\n")), "{log}");
+    assert!(log.contains("Author: Jürgen <j@e.x>\n"), "{log}");
+    assert!(log.contains("    café\n"), "{log}");
+    assert!(log.contains("    body é\n"), "{log}");
+    ok(&repo, &["fsck"]);
+    ok(&repo, &["commit-graph", "write", "--reachable"]);
+    ok(&repo, &["commit-graph", "verify"]);
+}
+
+// NOTE: the new commit is UTF-8 and its parent is the latin-1 one, untouched
+#[test]
+fn commit_on_top_of_latin1_commit() {
+    let scratch = Scratch::new("commit-latin1-parent");
+    let repo = scratch.repo("repo");
+    let parent = latin1_commit(&repo);
+
+    fs::write(repo.join("file"), "two\n").unwrap();
+    ok(&repo, &["update-index", "file"]);
+    ok(&repo, &["commit", "-q", "-m", "two"]);
+    let log = ok(&repo, &["log", "--oneline"]);
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(lines.len(), 2, "{log}");
+    assert!(lines[0].ends_with(" two"), "{log}");
+    assert_eq!(lines[1], format!("{} café", &parent[..7]));
+    ok(&repo, &["fsck"]);
+}
+
+// NOTE: a rebuilt commit (amend) is re-encoded, the author keeps the same bytes
+#[test]
+fn amend_latin1_commit_reencodes_message() {
+    let scratch = Scratch::new("commit-latin1-amend");
+    let repo = scratch.repo("repo");
+    latin1_commit(&repo);
+
+    ok(
+        &repo,
+        &["commit", "-q", "--amend", "--no-edit", "--allow-empty"],
+    );
+    let log = ok(&repo, &["log", "-n", "1"]);
+    assert!(log.contains("    café\n"), "{log}");
+    assert!(log.contains("    body é\n"), "{log}");
+}
+
+#[test]
+fn reflog_with_latin1_bytes() {
+    let scratch = Scratch::new("reflog-latin1");
+    let repo = scratch.repo("repo");
+    let mut log = fs::read(repo.join(".git/logs/HEAD")).unwrap();
+    let last = log.split(|&b| b == b'\n').next().unwrap().to_vec();
+    let tab = last.iter().position(|&b| b == b'\t').unwrap();
+    log.extend(&last[..tab]);
+    log.extend(b"\tcommit: caf\xe9\n");
+    fs::write(repo.join(".git/logs/HEAD"), &log).unwrap();
+
+    fs::write(repo.join("file"), "changed\n").unwrap();
+    ok(&repo, &["stash"]);
+    let stash = fs::read(repo.join(".git/logs/refs/stash")).unwrap();
+    let tab = stash.iter().position(|&b| b == b'\t').unwrap();
+    let mut stash = stash[..tab].to_vec();
+    stash.extend(b"\tWIP on main: caf\xe9\n");
+    fs::write(repo.join(".git/logs/refs/stash"), &stash).unwrap();
+    let list = ok(&repo, &["stash", "list"]);
+    assert_eq!(list, "stash@{0}: WIP on main: caf\u{fffd}\n");
+
+    ok(&repo, &["prune"]);
+    ok(&repo, &["gc"]);
+    ok(&repo, &["fsck"]);
+}
+
+// NOTE: the commit is made before post-commit runs, a hook that can't start is a warning and
+// the summary line is still printed
+#[test]
+fn post_commit_hook_that_fails_to_run_is_a_warning() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let scratch = Scratch::new("commit-post-commit");
+    let repo = scratch.repo("repo");
+    let hook = repo.join(".git/hooks/post-commit");
+    fs::create_dir_all(hook.parent().unwrap()).unwrap();
+    fs::write(&hook, "#!/nonexistent/interpreter\n").unwrap();
+    fs::set_permissions(&hook, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let output = git(&repo, &["commit", "--allow-empty", "-m", "two"], b"");
+    assert!(output.status.success(), "{output:?}");
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.starts_with("[main ") && stdout.ends_with("] two\n"),
+        "{stdout}"
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("warning: post-commit hook failed"),
+        "{stderr}"
+    );
+}
@@ -0,0 +1,106 @@
+#![allow(dead_code)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+pub fn git(dir: &Path, args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_codecrafters-git"))
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "T")
+        .env("GIT_AUTHOR_EMAIL", "t@e.x")
+        .env("GIT_AUTHOR_DATE", "1700000000 +0000")
+        .env("GIT_COMMITTER_NAME", "T")
+        .env("GIT_COMMITTER_EMAIL", "t@e.x")
+        .env("GIT_COMMITTER_DATE", "1700000000 +0000")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+// runs git and wants it to succeed, gives back stdout
+pub fn ok(dir: &Path, args: &[&str]) -> String {
+    let output = git(dir, args, b"");
+    assert!(output.status.success(), "git {args:?}: {output:?}");
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+pub fn hash(output: Output) -> String {
+    assert!(output.status.success(), "{output:?}");
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+// <mode> <name>\0<raw hash>
+pub fn tree_entry(mode: &str, name: &str, hex: &str) -> Vec<u8> {
+    let mut entry = format!("{mode} {name}\0").into_bytes();
+    entry.extend(hex::decode(hex).unwrap());
+    entry
+}
+
+// an empty directory for one test, removed again when it is dropped
+pub struct Scratch(pub PathBuf);
+
+impl Scratch {
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("git-rs-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        Self(root)
+    }
+
+    // a fresh repository with one commit of `file`
+    pub fn repo(&self, name: &str) -> PathBuf {
+        let dir = self.0.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        ok(&dir, &["init"]);
+        fs::write(dir.join("file"), "one\n").unwrap();
+        ok(&dir, &["update-index", "--add", "file"]);
+        ok(&dir, &["commit", "-m", "one"]);
+        dir
+    }
+}
+
+// writes `name`, stages and commits it, gives back the new main
+pub fn commit_file(dir: &Path, name: &str, content: &str, message: &str) -> String {
+    fs::write(dir.join(name), content).unwrap();
+    ok(dir, &["update-index", "--add", name]);
+    ok(dir, &["commit", "-m", message]);
+    head(dir)
+}
+
+pub fn head(dir: &Path) -> String {
+    fs::read_to_string(dir.join(".git/refs/heads/main"))
+        .unwrap()
+        .trim()
+        .to_string()
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+// the content of a loose object, header cut off (cat-file -p only prints blobs)
+pub fn read_object(dir: &Path, hex: &str) -> Vec<u8> {
+    use std::io::Read;
+    let path = dir.join(".git/objects").join(&hex[..2]).join(&hex[2..]);
+    let mut data = Vec::new();
+    flate2::read::ZlibDecoder::new(fs::File::open(path).unwrap())
+        .read_to_end(&mut data)
+        .unwrap();
+    let nul = data.iter().position(|&b| b == 0).unwrap();
+    data.split_off(nul + 1)
+}
+
+// the sha1 that closes .git/index and the packs
+pub fn sha1(data: &[u8]) -> Vec<u8> {
+    use sha1_checked::Digest;
+    sha1_checked::Sha1::digest(data).to_vec()
+}
@@ -0,0 +1,22 @@
+mod common;
+
+use std::fs;
+
+use common::{Scratch, ok};
+
+#[test]
+fn garbage_is_counted_by_its_length() {
+    let scratch = Scratch::new("count-objects-garbage");
+    let dir = scratch.repo("repo");
+    fs::create_dir_all(dir.join(".git/objects/ab")).unwrap();
+    fs::write(dir.join(".git/objects/ab/not-an-object"), [0u8; 100]).unwrap();
+    fs::create_dir_all(dir.join(".git/objects/pack")).unwrap();
+    fs::write(dir.join(".git/objects/pack/tmp_pack_x"), [0u8; 3000]).unwrap();
+
+    let out = ok(&dir, &["count-objects", "-v", "-H"]);
+    assert!(out.contains("count: 3\n"), "{out}");
+    assert!(out.contains("garbage: 2\n"), "{out}");
+    assert!(out.contains("size-garbage: 3.03 KiB\n"), "{out}");
+    let out = ok(&dir, &["count-objects", "-v"]);
+    assert!(out.contains("size-garbage: 3\n"), "{out}");
+}
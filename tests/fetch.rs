@@ -0,0 +1,153 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, head, ok};
+
+// NOTE: the other repository decides the ref names, one with ".." in it must not turn into a
+// path outside of .git
+#[test]
+fn fetch_ignores_ref_names_that_leave_the_repository() {
+    let scratch = Scratch::new("fetch-traversal");
+    let source = scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+
+    let head = fs::read_to_string(source.join(".git/refs/heads/main")).unwrap();
+    assert!(!head.is_empty());
+    fs::write(
+        source.join(".git/packed-refs"),
+        format!("{} refs/heads/../../../../../escaped\n", head.trim()),
+    )
+    .unwrap();
+
+    let output = git(&copy, &["fetch"], b"");
+    assert!(output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("ignoring ref with broken name"), "{stderr}");
+    for dir in [&scratch.0, &copy, &copy.join(".git")] {
+        assert!(!dir.join("escaped").exists());
+    }
+}
+
+#[test]
+fn fetch_refuses_refspec_with_bad_destination() {
+    let scratch = Scratch::new("fetch-refspec");
+    scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+
+    let output = git(&copy, &["fetch", "origin", "main:refs/heads/../../x"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    let output = git(&copy, &["fetch", "origin", "main:refs/heads/a.lock"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!copy.join(".git/refs/heads/a.lock").exists());
+}
+
+#[test]
+fn push_refuses_bad_destination() {
+    let scratch = Scratch::new("push-refspec");
+    let source = scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+
+    let output = git(
+        &copy,
+        &["push", "origin", "main:refs/heads/../../../escaped"],
+        b"",
+    );
+    assert!(!output.status.success(), "{output:?}");
+    assert!(!scratch.0.join("escaped").exists());
+    assert!(!source.join("escaped").exists());
+}
+
+#[test]
+fn refspecs_map_and_force() {
+    let scratch = Scratch::new("fetch-refspec-map");
+    let source = scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+    let first = head(&source);
+
+    ok(
+        &copy,
+        &["fetch", "origin", "refs/heads/*:refs/remotes/mirror/*"],
+    );
+    assert_eq!(read_ref(&copy, "refs/remotes/mirror/main"), first);
+    ok(&copy, &["fetch", "origin", "main:refs/heads/copy"]);
+    assert_eq!(read_ref(&copy, "refs/heads/copy"), first);
+    let fetch_head = fs::read_to_string(copy.join(".git/FETCH_HEAD")).unwrap();
+    assert!(fetch_head.starts_with(&first), "{fetch_head}");
+
+    // main of the source is rewritten, the copy only follows with a "+"
+    ok(&source, &["commit", "--amend", "-m", "rewritten"]);
+    let rewritten = head(&source);
+    let out = git(&copy, &["fetch", "origin", "main:refs/heads/copy"], b"");
+    assert!(!out.status.success());
+    assert!(
+        String::from_utf8_lossy(&out.stderr).contains("non-fast-forward"),
+        "{out:?}"
+    );
+    assert_eq!(read_ref(&copy, "refs/heads/copy"), first);
+    ok(&copy, &["fetch", "origin", "+main:refs/heads/copy"]);
+    assert_eq!(read_ref(&copy, "refs/heads/copy"), rewritten);
+}
+
+#[test]
+fn bad_refspecs_are_refused() {
+    let scratch = Scratch::new("fetch-refspec-bad");
+    scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+
+    for refspec in [
+        "refs/heads/*/*:refs/x/*/*",
+        "refs/heads/*:refs/x",
+        "main:refs/x/*",
+        "a..b:refs/x",
+        ":",
+        "+",
+        "main:refs/heads/x y",
+    ] {
+        for command in ["fetch", "push"] {
+            let out = git(&copy, &[command, "origin", refspec], b"");
+            assert!(!out.status.success(), "{command} {refspec}");
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(
+                stderr.contains(&format!("invalid refspec '{refspec}'")),
+                "{command} {refspec}: {stderr}"
+            );
+        }
+    }
+    let out = git(&copy, &["fetch", "origin", "nope"], b"");
+    assert!(String::from_utf8_lossy(&out.stderr).contains("couldn't find remote ref nope"));
+}
+
+#[test]
+fn push_creates_and_deletes() {
+    let scratch = Scratch::new("push-refspec-map");
+    let source = scratch.repo("source");
+    ok(&scratch.0, &["clone", "source", "copy"]);
+    let copy = scratch.0.join("copy");
+    let pushed = commit_file(&copy, "new", "new\n", "new");
+
+    ok(&copy, &["push", "origin", "main:refs/heads/side"]);
+    assert_eq!(read_ref(&source, "refs/heads/side"), pushed);
+    assert!(
+        source
+            .join(format!(".git/objects/{}", &pushed[..2]))
+            .exists()
+    );
+    ok(&copy, &["push", "origin", ":refs/heads/side"]);
+    assert!(!source.join(".git/refs/heads/side").exists());
+    let out = git(&copy, &["push", "origin", "nope:refs/heads/x"], b"");
+    assert!(!out.status.success());
+}
+
+fn read_ref(dir: &Path, name: &str) -> String {
+    fs::read_to_string(dir.join(".git").join(name))
+        .unwrap()
+        .trim()
+        .to_string()
+}
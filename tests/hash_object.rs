@@ -0,0 +1,53 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use common::{Scratch, git, hash, ok};
+
+fn tmp_files(objects: &Path) -> Vec<String> {
+    fs::read_dir(objects)
+        .unwrap()
+        .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+        .filter(|name| name.starts_with("tmp_"))
+        .collect()
+}
+
+// writes the blob twice, the second write must only freshen the file that is there
+fn rewrite_freshens(dir: &Path) {
+    let blob = hash(git(dir, &["hash-object", "-w", "--stdin"], b"same\n"));
+    let path = dir.join(".git/objects").join(&blob[..2]).join(&blob[2..]);
+    let old = SystemTime::now() - Duration::from_secs(30 * 24 * 3600);
+    fs::File::open(&path).unwrap().set_modified(old).unwrap();
+    let before = fs::read(&path).unwrap();
+
+    assert_eq!(
+        hash(git(dir, &["hash-object", "-w", "--stdin"], b"same\n")),
+        blob
+    );
+    let meta = fs::metadata(&path).unwrap();
+    assert!(meta.modified().unwrap() > old + Duration::from_secs(24 * 3600));
+    assert_eq!(meta.permissions().mode() & 0o777, 0o444);
+    assert_eq!(fs::read(&path).unwrap(), before);
+    assert!(tmp_files(&dir.join(".git/objects")).is_empty());
+    assert_eq!(ok(dir, &["cat-file", "-p", &blob]), "same\n");
+}
+
+#[test]
+fn writing_an_existing_object_freshens_it() {
+    let scratch = Scratch::new("hash-object-freshen");
+    let dir = scratch.repo("repo");
+    rewrite_freshens(&dir);
+}
+
+#[test]
+fn writing_an_existing_big_object_freshens_it() {
+    let scratch = Scratch::new("hash-object-freshen-big");
+    let dir = scratch.repo("repo");
+    let mut config = fs::read_to_string(dir.join(".git/config")).unwrap();
+    config.push_str("[core]\n\tbigFileThreshold = 1\n");
+    fs::write(dir.join(".git/config"), config).unwrap();
+    rewrite_freshens(&dir);
+}
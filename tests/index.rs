@@ -0,0 +1,144 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, git, hash, ok, sha1};
+
+// one entry: path, blob, extended flags (0x4000 skip-worktree, 0x2000 intent-to-add)
+fn index_bytes(version: u32, entries: &[(&str, &str, u16)], extensions: &[u8]) -> Vec<u8> {
+    let mut buf = b"DIRC".to_vec();
+    buf.extend(version.to_be_bytes());
+    buf.extend((entries.len() as u32).to_be_bytes());
+    for (path, blob, extended) in entries {
+        let start = buf.len();
+        buf.extend([0; 24]);
+        buf.extend(0o100644u32.to_be_bytes());
+        buf.extend([0; 12]);
+        buf.extend(hex::decode(blob).unwrap());
+        let mut flags = path.len() as u16;
+        if *extended != 0 {
+            flags |= 0x4000;
+        }
+        buf.extend(flags.to_be_bytes());
+        if *extended != 0 {
+            buf.extend(extended.to_be_bytes());
+        }
+        buf.extend(path.as_bytes());
+        let len = buf.len() - start;
+        buf.resize(start + ((len + 8) & !7), 0);
+    }
+    buf.extend(extensions);
+    buf
+}
+
+fn write_index(repo: &Path, content: &[u8]) {
+    let mut data = content.to_vec();
+    data.extend(sha1(content));
+    fs::write(repo.join(".git/index"), data).unwrap();
+}
+
+fn blob(repo: &Path, path: &str) -> String {
+    hash(git(repo, &["hash-object", "-w", path], b""))
+}
+
+// NOTE: skip-worktree set by git (sparse checkout) survives an index we write again
+#[test]
+fn extended_flags_are_kept() {
+    let scratch = Scratch::new("index-extended");
+    let repo = scratch.repo("repo");
+    let file = blob(&repo, "file");
+    write_index(&repo, &index_bytes(3, &[("file", &file, 0x4000)], b""));
+
+    fs::write(repo.join("other"), "other\n").unwrap();
+    ok(&repo, &["update-index", "--add", "other"]);
+    let index = fs::read(repo.join(".git/index")).unwrap();
+    assert_eq!(&index[4..8], &3u32.to_be_bytes());
+    let other = blob(&repo, "other");
+    // the stat of "other" is whatever the file has, compare everything after it
+    let expected = index_bytes(3, &[("file", &file, 0x4000), ("other", &other, 0)], b"");
+    let end = 12 + ((62 + 2 + "file".len() + 8) & !7);
+    assert_eq!(index[..end], expected[..end]);
+    let ls = ok(&repo, &["ls-files", "-s"]);
+    assert!(ls.contains(&format!("100644 {file} 0\tfile\n")), "{ls}");
+}
+
+#[test]
+fn unknown_required_extension_is_refused() {
+    let scratch = Scratch::new("index-extension");
+    let repo = scratch.repo("repo");
+    let file = blob(&repo, "file");
+
+    // an optional extension (uppercase) is only a cache
+    let mut tree = b"TREE".to_vec();
+    tree.extend(3u32.to_be_bytes());
+    tree.extend(b"abc");
+    write_index(&repo, &index_bytes(2, &[("file", &file, 0)], &tree));
+    ok(&repo, &["ls-files"]);
+
+    let mut link = b"link".to_vec();
+    link.extend(20u32.to_be_bytes());
+    link.extend([0; 20]);
+    write_index(&repo, &index_bytes(2, &[("file", &file, 0)], &link));
+    let output = git(&repo, &["ls-files"], b"");
+    assert!(!output.status.success(), "{output:?}");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("link extension"), "{stderr}");
+}
+
+// NOTE: every cut of a good index (with a good checksum) is an error, never a panic,
+// but the one right before the extension that is a good index itself
+#[test]
+fn truncated_index_is_an_error() {
+    let scratch = Scratch::new("index-truncated");
+    let repo = scratch.repo("repo");
+    let file = blob(&repo, "file");
+    let mut tree = b"TREE".to_vec();
+    tree.extend(3u32.to_be_bytes());
+    tree.extend(b"abc");
+    let content = index_bytes(3, &[("file", &file, 0x4000), ("zz", &file, 0)], &tree);
+
+    for len in (0..content.len()).filter(|&len| len != content.len() - tree.len()) {
+        write_index(&repo, &content[..len]);
+        let output = git(&repo, &["ls-files"], b"");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(!output.status.success(), "{len}: {output:?}");
+        assert!(!stderr.contains("panicked"), "{len}: {stderr}");
+    }
+
+    // the count says more entries than the file can hold
+    let mut lying = content.clone();
+    lying[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    write_index(&repo, &lying);
+    let output = git(&repo, &["ls-files"], b"");
+    assert!(!output.status.success(), "{output:?}");
+}
+
+// NOTE: index.lock is taken before the index is read, a second writer stops right away and
+// the lock of the other one is left alone
+#[test]
+fn index_is_locked_before_it_is_read() {
+    let scratch = Scratch::new("index-lock");
+    let repo = scratch.repo("repo");
+    let before = fs::read(repo.join(".git/index")).unwrap();
+    fs::write(repo.join(".git/index.lock"), "").unwrap();
+
+    fs::write(repo.join("other"), "other\n").unwrap();
+    for args in [
+        &["update-index", "--add", "other"][..],
+        &["rm", "--cached", "file"],
+        &["reset", "-q"],
+        &["restore", "--staged", "file"],
+    ] {
+        let output = git(&repo, args, b"");
+        assert!(!output.status.success(), "{args:?}: {output:?}");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("index.lock"), "{args:?}: {stderr}");
+    }
+    assert!(repo.join(".git/index.lock").exists());
+    assert_eq!(fs::read(repo.join(".git/index")).unwrap(), before);
+
+    fs::remove_file(repo.join(".git/index.lock")).unwrap();
+    ok(&repo, &["update-index", "--add", "other"]);
+    assert!(!repo.join(".git/index.lock").exists());
+}
@@ -0,0 +1,145 @@
+mod common;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, head, ok, read_object};
+
+// main at `one`, refs/heads/side one commit ahead with `side`
+fn side_branch(dir: &Path) -> (String, String) {
+    let base = head(dir);
+    let side = commit_file(dir, "side", "side\n", "side");
+    fs::write(dir.join(".git/refs/heads/side"), format!("{side}\n")).unwrap();
+    ok(dir, &["reset", "--hard", &base]);
+    (base, side)
+}
+
+fn hook(dir: &Path, name: &str, script: &str) {
+    let path = dir.join(".git/hooks").join(name);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, script).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn fast_forward() {
+    let scratch = Scratch::new("merge-ff");
+    let dir = scratch.repo("repo");
+    let (base, side) = side_branch(&dir);
+
+    let out = ok(&dir, &["merge", "side"]);
+    assert!(out.contains("Fast-forward\n"), "{out}");
+    assert_eq!(head(&dir), side);
+    assert_eq!(fs::read_to_string(dir.join("side")).unwrap(), "side\n");
+    assert_eq!(
+        fs::read_to_string(dir.join(".git/ORIG_HEAD")).unwrap(),
+        format!("{base}\n")
+    );
+    assert!(ok(&dir, &["merge", "side"]).contains("Already up to date."));
+}
+
+#[test]
+fn true_merge_has_both_parents() {
+    let scratch = Scratch::new("merge-true");
+    let dir = scratch.repo("repo");
+    let (_, side) = side_branch(&dir);
+    let ours = commit_file(&dir, "ours", "ours\n", "ours");
+
+    let out = ok(&dir, &["merge", "-m", "both", "side"]);
+    assert!(out.contains("Merge made by the 'ort' strategy."), "{out}");
+    let merge = String::from_utf8(read_object(&dir, &head(&dir))).unwrap();
+    assert!(
+        merge.contains(&format!("parent {ours}\nparent {side}\n")),
+        "{merge}"
+    );
+    assert!(merge.ends_with("\nboth\n"), "{merge}");
+    assert!(!dir.join(".git/MERGE_HEAD").exists());
+}
+
+#[test]
+fn conflict_stops_and_abort_goes_back() {
+    let scratch = Scratch::new("merge-conflict");
+    let dir = scratch.repo("repo");
+    let base = head(&dir);
+    let side = commit_file(&dir, "file", "theirs\n", "theirs");
+    fs::write(dir.join(".git/refs/heads/side"), format!("{side}\n")).unwrap();
+    ok(&dir, &["reset", "--hard", &base]);
+    let ours = commit_file(&dir, "file", "ours\n", "ours");
+
+    let out = git(&dir, &["merge", "side"], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("CONFLICT (content)"));
+    let file = fs::read_to_string(dir.join("file")).unwrap();
+    assert!(
+        file.starts_with("<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> side\n"),
+        "{file}"
+    );
+    assert!(dir.join(".git/MERGE_HEAD").exists());
+    // nothing new can start before this one is done
+    assert!(!git(&dir, &["merge", "side"], b"").status.success());
+
+    ok(&dir, &["merge", "--abort"]);
+    assert_eq!(head(&dir), ours);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "ours\n");
+    assert!(!dir.join(".git/MERGE_HEAD").exists());
+}
+
+#[test]
+fn ff_only_refuses_a_true_merge() {
+    let scratch = Scratch::new("merge-ff-only");
+    let dir = scratch.repo("repo");
+    side_branch(&dir);
+    let ours = commit_file(&dir, "ours", "ours\n", "ours");
+
+    let out = git(&dir, &["merge", "--ff-only", "side"], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("Not possible to fast-forward"));
+    assert_eq!(head(&dir), ours);
+}
+
+#[test]
+fn no_verify_skips_the_commit_msg_hook() {
+    let scratch = Scratch::new("merge-no-verify");
+    let dir = scratch.repo("repo");
+    side_branch(&dir);
+    let ours = commit_file(&dir, "ours", "ours\n", "ours");
+    hook(&dir, "commit-msg", "#!/bin/sh\nexit 1\n");
+
+    assert!(
+        !git(&dir, &["merge", "-m", "m", "side"], b"")
+            .status
+            .success()
+    );
+    assert_eq!(head(&dir), ours);
+    ok(&dir, &["merge", "--abort"]);
+
+    ok(&dir, &["merge", "--no-verify", "-m", "m", "side"]);
+    assert_ne!(head(&dir), ours);
+}
+
+// NOTE: git merge -n is --no-stat, it must not turn the hooks off
+#[test]
+fn short_n_is_not_no_verify() {
+    let scratch = Scratch::new("merge-short-n");
+    let dir = scratch.repo("repo");
+    side_branch(&dir);
+    let ours = commit_file(&dir, "ours", "ours\n", "ours");
+    hook(&dir, "commit-msg", "#!/bin/sh\nexit 1\n");
+
+    assert!(
+        !git(&dir, &["merge", "-n", "-m", "m", "side"], b"")
+            .status
+            .success()
+    );
+    assert_eq!(head(&dir), ours);
+}
+
+#[test]
+fn unknown_commit_is_refused() {
+    let scratch = Scratch::new("merge-unknown");
+    let dir = scratch.repo("repo");
+    let out = git(&dir, &["merge", "nope"], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("nope - not something we can merge"));
+}
@@ -0,0 +1,142 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, hash, head, ok};
+
+fn commit(dir: &Path, tree: &str, parents: &[&str], message: &str) -> String {
+    let mut args = vec!["commit-tree", tree, "-m", message];
+    for parent in parents {
+        args.extend(["-p", parent]);
+    }
+    hash(git(dir, &args, b""))
+}
+
+fn lines(out: String) -> Vec<String> {
+    let mut lines: Vec<String> = out.lines().map(str::to_string).collect();
+    lines.sort();
+    lines
+}
+
+// root - a - c (merge a b)
+//      \ b - d (merge b a)        u unrelated
+struct History {
+    root: String,
+    a: String,
+    b: String,
+    c: String,
+    d: String,
+    u: String,
+}
+
+fn criss_cross(dir: &Path) -> History {
+    let root = head(dir);
+    let tree = ok(dir, &["write-tree"]).trim().to_string();
+    let a = commit(dir, &tree, &[&root], "a");
+    let b = commit(dir, &tree, &[&root], "b");
+    let c = commit(dir, &tree, &[&a, &b], "c");
+    let d = commit(dir, &tree, &[&b, &a], "d");
+    let u = commit(dir, &tree, &[], "u");
+    for (name, hex) in [("c", &c), ("d", &d), ("u", &u)] {
+        fs::write(dir.join(".git/refs/heads").join(name), format!("{hex}\n")).unwrap();
+    }
+    History {
+        root,
+        a,
+        b,
+        c,
+        d,
+        u,
+    }
+}
+
+// the same answers with and without the commit-graph
+fn check(dir: &Path, h: &History) {
+    let mut both = vec![h.a.clone(), h.b.clone()];
+    both.sort();
+    assert_eq!(lines(ok(dir, &["merge-base", "--all", &h.c, &h.d])), both);
+    let one = ok(dir, &["merge-base", &h.c, &h.d]);
+    assert!(both.contains(&one.trim().to_string()), "{one}");
+    assert_eq!(ok(dir, &["merge-base", &h.a, &h.b]).trim(), h.root);
+    assert_eq!(ok(dir, &["merge-base", &h.a, &h.c]).trim(), h.a);
+    assert_eq!(
+        ok(dir, &["merge-base", "--octopus", &h.a, &h.b, &h.c]).trim(),
+        h.root
+    );
+
+    assert!(
+        git(dir, &["merge-base", "--is-ancestor", &h.root, &h.c], b"")
+            .status
+            .success()
+    );
+    let out = git(dir, &["merge-base", "--is-ancestor", &h.c, &h.root], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+
+    let out = git(dir, &["merge-base", &h.c, &h.u], b"");
+    assert_eq!(out.status.code(), Some(1));
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn criss_cross_without_and_with_commit_graph() {
+    let scratch = Scratch::new("merge-base");
+    let dir = scratch.repo("repo");
+    let history = criss_cross(&dir);
+    check(&dir, &history);
+    ok(&dir, &["commit-graph", "write", "--reachable"]);
+    assert!(dir.join(".git/objects/info/commit-graph").exists());
+    check(&dir, &history);
+}
+
+// NOTE: main had a and was reset back, the fork point is still a (it is in main's reflog)
+#[test]
+fn fork_point_uses_the_reflog() {
+    let scratch = Scratch::new("merge-base-fork-point");
+    let dir = scratch.repo("repo");
+    let root = head(&dir);
+    let a = commit_file(&dir, "a", "a\n", "a");
+    let tree = ok(&dir, &["write-tree"]).trim().to_string();
+    let topic = commit(&dir, &tree, &[&a], "topic");
+    ok(&dir, &["reset", "--hard", &root]);
+    commit_file(&dir, "b", "b\n", "b");
+
+    assert_eq!(ok(&dir, &["merge-base", "main", &topic]).trim(), root);
+    assert_eq!(
+        ok(&dir, &["merge-base", "--fork-point", "main", &topic]).trim(),
+        a
+    );
+}
+
+#[test]
+fn bad_input_is_refused() {
+    let scratch = Scratch::new("merge-base-bad-input");
+    let dir = scratch.repo("repo");
+    let main = head(&dir);
+    let tree = ok(&dir, &["write-tree"]).trim().to_string();
+    for (args, error) in [
+        (
+            vec!["merge-base", main.as_str()],
+            "needs at least two commits",
+        ),
+        (
+            vec!["merge-base", "--is-ancestor", &main],
+            "exactly two commits",
+        ),
+        (
+            vec!["merge-base", "--fork-point", "nope"],
+            "No such ref: 'nope'",
+        ),
+        (
+            vec!["merge-base", &main, "nope"],
+            "Not a valid commit name nope",
+        ),
+        (vec!["merge-base", &main, &tree], "Not a valid commit name"),
+    ] {
+        let out = git(&dir, &args, b"");
+        assert!(!out.status.success(), "{args:?}");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{args:?}: {stderr}");
+    }
+}
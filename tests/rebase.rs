@@ -0,0 +1,181 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, head, ok, read_object};
+
+fn subjects(dir: &Path) -> Vec<String> {
+    let mut subjects = Vec::new();
+    let mut commit = head(dir);
+    loop {
+        let content = String::from_utf8(read_object(dir, &commit)).unwrap();
+        let (headers, message) = content.split_once("\n\n").unwrap();
+        subjects.push(message.lines().next().unwrap().to_string());
+        match headers
+            .lines()
+            .find_map(|line| line.strip_prefix("parent "))
+        {
+            Some(parent) => commit = parent.to_string(),
+            None => return subjects,
+        }
+    }
+}
+
+// refs/heads/up gets `up` on top of the first commit, main gets `topic` and goes back to it
+fn diverged(dir: &Path, up: &[(&str, &str, &str)], topic: &[(&str, &str, &str)]) {
+    let base = head(dir);
+    for (name, content, message) in up {
+        commit_file(dir, name, content, message);
+    }
+    fs::write(dir.join(".git/refs/heads/up"), format!("{}\n", head(dir))).unwrap();
+    ok(dir, &["reset", "--hard", &base]);
+    for (name, content, message) in topic {
+        commit_file(dir, name, content, message);
+    }
+}
+
+#[test]
+fn commits_are_replayed_on_upstream() {
+    let scratch = Scratch::new("rebase-replay");
+    let dir = scratch.repo("repo");
+    diverged(
+        &dir,
+        &[("up", "up\n", "up")],
+        &[("a", "a\n", "a"), ("b", "b\n", "b")],
+    );
+    let orig = head(&dir);
+
+    let out = git(&dir, &["rebase", "up"], b"");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("Successfully rebased and updated refs/heads/main."),
+        "{stderr}"
+    );
+    assert_eq!(subjects(&dir), ["b", "a", "up", "one"]);
+    assert_eq!(fs::read_to_string(dir.join("up")).unwrap(), "up\n");
+    assert_eq!(
+        fs::read_to_string(dir.join(".git/ORIG_HEAD")).unwrap(),
+        format!("{orig}\n")
+    );
+    assert!(!dir.join(".git/rebase-merge").exists());
+    assert!(ok(&dir, &["rebase", "up"]).contains("Current branch main is up to date."));
+}
+
+#[test]
+fn commits_already_upstream_are_left_out() {
+    let scratch = Scratch::new("rebase-cherry-picked");
+    let dir = scratch.repo("repo");
+    // the same change as `picked`, on another base and with another message
+    diverged(
+        &dir,
+        &[("up", "up\n", "up"), ("a", "a\n", "a upstream")],
+        &[("a", "a\n", "a"), ("b", "b\n", "b")],
+    );
+
+    let out = git(&dir, &["rebase", "up"], b"");
+    assert!(out.status.success(), "{out:?}");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("warning: skipped previously applied commit"),
+        "{stderr}"
+    );
+    assert_eq!(subjects(&dir), ["b", "a upstream", "up", "one"]);
+}
+
+#[test]
+fn reapply_cherry_picks_keeps_them_in_the_todo() {
+    let scratch = Scratch::new("rebase-reapply");
+    let dir = scratch.repo("repo");
+    diverged(
+        &dir,
+        &[("a", "a\n", "a upstream")],
+        &[("a", "a\n", "a"), ("b", "b\n", "b")],
+    );
+
+    let out = git(&dir, &["rebase", "--reapply-cherry-picks", "up"], b"");
+    assert!(out.status.success(), "{out:?}");
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("skipped"));
+    // picked again it is empty, so it is dropped all the same
+    assert_eq!(subjects(&dir), ["b", "a upstream", "one"]);
+}
+
+#[test]
+fn conflict_stops_and_continue_finishes() {
+    let scratch = Scratch::new("rebase-conflict");
+    let dir = scratch.repo("repo");
+    diverged(
+        &dir,
+        &[("file", "up\n", "up")],
+        &[("file", "topic\n", "topic"), ("b", "b\n", "b")],
+    );
+
+    let out = git(&dir, &["rebase", "up"], b"");
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert_eq!(stderr.matches("ould not apply").count(), 1, "{stderr}");
+    assert!(dir.join(".git/REBASE_HEAD").exists());
+    // a second one can't start
+    assert!(!git(&dir, &["rebase", "up"], b"").status.success());
+
+    fs::write(dir.join("file"), "both\n").unwrap();
+    ok(&dir, &["update-index", "file"]);
+    ok(&dir, &["rebase", "--continue"]);
+    assert_eq!(subjects(&dir), ["b", "topic", "up", "one"]);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "both\n");
+    assert!(!dir.join(".git/rebase-merge").exists());
+}
+
+#[test]
+fn conflict_skip_and_abort() {
+    let scratch = Scratch::new("rebase-skip-abort");
+    let dir = scratch.repo("repo");
+    diverged(
+        &dir,
+        &[("file", "up\n", "up")],
+        &[("file", "topic\n", "topic"), ("b", "b\n", "b")],
+    );
+    let orig = head(&dir);
+
+    assert!(!git(&dir, &["rebase", "up"], b"").status.success());
+    ok(&dir, &["rebase", "--abort"]);
+    assert_eq!(head(&dir), orig);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "topic\n");
+    assert!(!dir.join(".git/rebase-merge").exists());
+
+    assert!(!git(&dir, &["rebase", "up"], b"").status.success());
+    ok(&dir, &["rebase", "--skip"]);
+    assert_eq!(subjects(&dir), ["b", "up", "one"]);
+    assert_eq!(fs::read_to_string(dir.join("file")).unwrap(), "up\n");
+}
+
+#[test]
+fn failed_exec_stops() {
+    let scratch = Scratch::new("rebase-exec");
+    let dir = scratch.repo("repo");
+    diverged(&dir, &[("up", "up\n", "up")], &[("a", "a\n", "a")]);
+
+    let out = git(&dir, &["rebase", "-x", "false", "up"], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("execution failed: false"));
+    assert!(dir.join(".git/rebase-merge").exists());
+    ok(&dir, &["rebase", "--continue"]);
+    assert!(!dir.join(".git/rebase-merge").exists());
+    assert_eq!(subjects(&dir), ["a", "up", "one"]);
+}
+
+#[test]
+fn bad_input_is_refused() {
+    let scratch = Scratch::new("rebase-bad-input");
+    let dir = scratch.repo("repo");
+    for (args, error) in [
+        (&["rebase", "nope"][..], "invalid upstream 'nope'"),
+        (&["rebase", "--continue"], "No rebase in progress?"),
+        (&["rebase", "--abort"], "No rebase in progress?"),
+    ] {
+        let out = git(&dir, args, b"");
+        assert!(!out.status.success());
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{args:?}: {stderr}");
+    }
+}
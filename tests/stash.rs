@@ -0,0 +1,165 @@
+mod common;
+
+use std::fs;
+use std::path::Path;
+
+use common::{Scratch, commit_file, git, head, ok};
+
+fn read(dir: &Path, name: &str) -> String {
+    fs::read_to_string(dir.join(name)).unwrap()
+}
+
+fn stderr(dir: &Path, args: &[&str]) -> String {
+    let out = git(dir, args, b"");
+    assert!(!out.status.success(), "{args:?}");
+    String::from_utf8_lossy(&out.stderr).into_owned()
+}
+
+#[test]
+fn push_and_pop() {
+    let scratch = Scratch::new("stash-push-pop");
+    let dir = scratch.repo("repo");
+    fs::write(dir.join("file"), "staged\n").unwrap();
+    ok(&dir, &["update-index", "file"]);
+    fs::write(dir.join("file"), "worktree\n").unwrap();
+
+    ok(&dir, &["stash"]);
+    assert_eq!(read(&dir, "file"), "one\n");
+    let list = ok(&dir, &["stash", "list"]);
+    assert!(list.starts_with("stash@{0}: WIP on main: "), "{list}");
+    assert!(list.ends_with(" one\n"), "{list}");
+
+    ok(&dir, &["stash", "pop"]);
+    assert_eq!(read(&dir, "file"), "worktree\n");
+    assert_eq!(ok(&dir, &["stash", "list"]), "");
+    assert!(!dir.join(".git/refs/stash").exists());
+}
+
+#[test]
+fn untracked_files_and_paths() {
+    let scratch = Scratch::new("stash-untracked");
+    let dir = scratch.repo("repo");
+    commit_file(&dir, "other", "other\n", "other");
+    fs::write(dir.join("file"), "changed\n").unwrap();
+    fs::write(dir.join("other"), "changed\n").unwrap();
+    fs::write(dir.join("new"), "new\n").unwrap();
+
+    ok(&dir, &["stash", "push", "-m", "only file", "--", "file"]);
+    assert_eq!(read(&dir, "file"), "one\n");
+    assert_eq!(read(&dir, "other"), "changed\n");
+    assert!(dir.join("new").exists());
+    ok(&dir, &["stash", "push", "-u"]);
+    assert_eq!(read(&dir, "other"), "other\n");
+    assert!(!dir.join("new").exists());
+
+    let list = ok(&dir, &["stash", "list"]);
+    let lines: Vec<&str> = list.lines().collect();
+    assert_eq!(lines.len(), 2, "{list}");
+    assert_eq!(lines[1], "stash@{1}: On main: only file");
+
+    ok(&dir, &["stash", "apply", "1"]);
+    assert_eq!(read(&dir, "file"), "changed\n");
+    ok(&dir, &["stash", "drop", "stash@{1}"]);
+    ok(&dir, &["stash", "pop"]);
+    assert_eq!(read(&dir, "other"), "changed\n");
+    assert_eq!(read(&dir, "new"), "new\n");
+    assert_eq!(ok(&dir, &["stash", "list"]), "");
+}
+
+#[test]
+fn conflict_keeps_the_stash() {
+    let scratch = Scratch::new("stash-conflict");
+    let dir = scratch.repo("repo");
+    fs::write(dir.join("file"), "stashed\n").unwrap();
+    ok(&dir, &["stash"]);
+    let stash = read(&dir, ".git/refs/stash");
+    commit_file(&dir, "file", "committed\n", "two");
+
+    let out = git(&dir, &["stash", "pop"], b"");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stdout).contains("CONFLICT (content)"));
+    assert!(read(&dir, "file").contains("<<<<<<<"));
+    assert_eq!(read(&dir, ".git/refs/stash"), stash);
+    assert!(stderr(&dir, &["stash", "apply"]).contains("in the middle of a merge"));
+}
+
+#[test]
+fn branch_from_a_stash() {
+    let scratch = Scratch::new("stash-branch");
+    let dir = scratch.repo("repo");
+    let base = head(&dir);
+    fs::write(dir.join("file"), "stashed\n").unwrap();
+    ok(&dir, &["stash"]);
+    commit_file(&dir, "file", "committed\n", "two");
+
+    ok(&dir, &["stash", "branch", "side"]);
+    assert_eq!(read(&dir, ".git/HEAD"), "ref: refs/heads/side\n");
+    assert_eq!(read(&dir, ".git/refs/heads/side").trim(), base);
+    assert_eq!(read(&dir, "file"), "stashed\n");
+    assert_eq!(ok(&dir, &["stash", "list"]), "");
+}
+
+// NOTE: the list is the reflog of refs/stash, a line that is not one is left out and the
+// message is kept on one line
+#[test]
+fn reflog_lines_are_parsed() {
+    let scratch = Scratch::new("stash-reflog");
+    let dir = scratch.repo("repo");
+    fs::write(dir.join("file"), "a\n").unwrap();
+    ok(&dir, &["stash", "push", "-m", "tab\tand\n\nnewline "]);
+    fs::write(dir.join("file"), "b\n").unwrap();
+    ok(&dir, &["stash", "push", "-m", "second"]);
+
+    let log = dir.join(".git/logs/refs/stash");
+    let content = read(&dir, ".git/logs/refs/stash");
+    assert_eq!(content.lines().count(), 2, "{content}");
+    assert!(
+        content.contains("\tOn main: tab and newline\n"),
+        "{content}"
+    );
+    fs::write(&log, format!("garbage\n\n{content}")).unwrap();
+
+    assert_eq!(
+        ok(&dir, &["stash", "list"]),
+        "stash@{0}: On main: second\nstash@{1}: On main: tab and newline\n"
+    );
+    ok(&dir, &["stash", "drop"]);
+    assert_eq!(
+        ok(&dir, &["stash", "list"]),
+        "stash@{0}: On main: tab and newline\n"
+    );
+    ok(&dir, &["stash", "pop"]);
+    assert_eq!(read(&dir, "file"), "a\n");
+}
+
+#[test]
+fn bad_input_is_refused() {
+    let scratch = Scratch::new("stash-bad-input");
+    let dir = scratch.repo("repo");
+    assert_eq!(ok(&dir, &["stash"]), "No local changes to save\n");
+    assert!(stderr(&dir, &["stash", "pop"]).contains("No stash entries found."));
+
+    fs::write(dir.join("file"), "two\n").unwrap();
+    ok(&dir, &["stash"]);
+    let main = head(&dir);
+    let stash = read(&dir, ".git/refs/stash").trim().to_string();
+    for (args, error) in [
+        (
+            &["stash", "drop", "5"][..],
+            "log for 'refs/stash' only has 1 entries",
+        ),
+        (&["stash", "apply", "nope"], "nope is not a valid reference"),
+        (&["stash", "drop", &stash], "is not a stash reference"),
+        (&["stash", "apply", &main], "is not a stash-like commit"),
+        (&["stash", "push", "nosuch"], "nosuch"),
+    ] {
+        let error_out = stderr(&dir, args);
+        assert!(error_out.contains(error), "{args:?}: {error_out}");
+    }
+
+    let scratch = Scratch::new("stash-unborn");
+    let dir = scratch.0.join("unborn");
+    fs::create_dir_all(&dir).unwrap();
+    ok(&dir, &["init"]);
+    assert!(stderr(&dir, &["stash"]).contains("You do not have the initial commit yet"));
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use std::path::Path;
+
+use common::{Scratch, git, hash, ok, tree_entry};
+
+fn blob(dir: &Path) -> String {
+    hash(git(dir, &["hash-object", "-w", "--stdin"], b"blob\n"))
+}
+
+fn literal_tree(dir: &Path, content: &[u8]) -> String {
+    hash(git(
+        dir,
+        &["hash-object", "-w", "-t", "tree", "--literally", "--stdin"],
+        content,
+    ))
+}
+
+#[test]
+fn mktree_sorts_like_git() {
+    let scratch = Scratch::new("tree-mktree");
+    let dir = scratch.repo("repo");
+    let blob = blob(&dir);
+    let sub = hash(git(
+        &dir,
+        &["mktree"],
+        format!("100644 blob {blob}\tinner\n").as_bytes(),
+    ));
+    let input =
+        format!("100644 blob {blob}\ta0\n040000 tree {sub}\ta\n100755 blob {blob}\ta.txt\n");
+    let tree = hash(git(&dir, &["mktree"], input.as_bytes()));
+
+    assert_eq!(
+        ok(&dir, &["ls-tree", &tree]),
+        format!("100755 blob {blob}\ta.txt\n040000 tree {sub}\ta\n100644 blob {blob}\ta0\n")
+    );
+    assert_eq!(
+        ok(&dir, &["ls-tree", "-r", "--name-only", &tree]),
+        "a.txt\na/inner\na0\n"
+    );
+    // the same entries written by hand hash the same
+    let mut raw = tree_entry("100755", "a.txt", &blob);
+    raw.extend(tree_entry("40000", "a", &sub));
+    raw.extend(tree_entry("100644", "a0", &blob));
+    assert_eq!(literal_tree(&dir, &raw), tree);
+}
+
+#[test]
+fn mktree_bad_input_is_refused() {
+    let scratch = Scratch::new("tree-mktree-bad");
+    let dir = scratch.repo("repo");
+    let blob = blob(&dir);
+    let missing = "1".repeat(40);
+    for (input, error) in [
+        (
+            "100644 blob nothex\tname\n".to_string(),
+            "input format error",
+        ),
+        (format!("100644 blob {blob} name\n"), "input format error"),
+        (
+            format!("100644 tree {blob}\tname\n"),
+            "doesn't match mode type",
+        ),
+        (format!("100644 blob {blob}\ta/b\n"), "contains slash"),
+        (format!("100644 blob {missing}\tname\n"), "is unavailable"),
+        (
+            format!("100644 blob {blob}\tx\n\n"),
+            "blank line only valid in batch mode",
+        ),
+        (
+            format!("100644 blob {blob}\tx\n100644 blob {blob}\tx\n"),
+            "duplicate entry 'x' in tree",
+        ),
+        (format!("100645 blob {blob}\tx\n"), "invalid mode 100645"),
+    ] {
+        let out = git(&dir, &["mktree"], input.as_bytes());
+        assert!(!out.status.success(), "{input:?}");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{input:?}: {stderr}");
+    }
+}
+
+#[test]
+fn malformed_trees_are_refused() {
+    let scratch = Scratch::new("tree-malformed");
+    let dir = scratch.repo("repo");
+    let blob = blob(&dir);
+    let entry = |mode: &str, name: &str| tree_entry(mode, name, &blob);
+    let cases: Vec<(Vec<u8>, &str)> = vec![
+        (entry("0100644", "a"), "malformed mode in tree entry"),
+        (entry("100645", "a"), "malformed mode in tree entry"),
+        (b"100644 a".to_vec(), "truncated tree entry"),
+        (entry("100644", "a")[..20].to_vec(), "too-short tree object"),
+        (
+            [entry("100644", "b"), entry("100644", "a")].concat(),
+            "not sorted",
+        ),
+        (
+            [entry("100644", "a"), entry("100644", "a")].concat(),
+            "duplicate entry 'a'",
+        ),
+        // "a/" sorts before "a0", so the tree a0 then a is out of order
+        (
+            [entry("100644", "a0"), entry("40000", "a")].concat(),
+            "not sorted",
+        ),
+        (entry("100644", ".."), "invalid path '..'"),
+        (entry("100644", ""), "empty filename"),
+    ];
+    for (content, error) in cases {
+        let tree = literal_tree(&dir, &content);
+        let out = git(&dir, &["ls-tree", &tree], b"");
+        assert!(!out.status.success(), "{content:?}");
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.contains(error), "{content:?}: {stderr}");
+    }
+}